@@ -0,0 +1,92 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Discover the databases and the tables.
+
+use crate::{model::sql_query::Response, DbClient, Result, RpcContext, SqlQueryRequest};
+
+/// List the tables of the database of the `ctx` by `SHOW TABLES`, which are
+/// filtered by the `prefix` if set, in the order of the names.
+pub async fn list_tables<C: DbClient + ?Sized>(
+    client: &C,
+    ctx: &RpcContext,
+    prefix: Option<&str>,
+) -> Result<Vec<String>> {
+    let resp = show(client, ctx, "SHOW TABLES").await?;
+    let mut tables = names(resp);
+    if let Some(prefix) = prefix {
+        tables.retain(|table| table.starts_with(prefix));
+    }
+    Ok(tables)
+}
+
+/// List the databases by `SHOW DATABASES` in the order of the names.
+pub async fn list_databases<C: DbClient + ?Sized>(
+    client: &C,
+    ctx: &RpcContext,
+) -> Result<Vec<String>> {
+    let resp = show(client, ctx, "SHOW DATABASES").await?;
+    Ok(names(resp))
+}
+
+async fn show<C: DbClient + ?Sized>(client: &C, ctx: &RpcContext, sql: &str) -> Result<Response> {
+    let req = SqlQueryRequest {
+        tables: Vec::new(),
+        sql: sql.to_string(),
+    };
+    client.sql_query(ctx, &req).await
+}
+
+/// The names in the only column of the rows.
+fn names(resp: Response) -> Vec<String> {
+    let mut names: Vec<_> = resp
+        .rows
+        .iter()
+        .filter_map(|row| row.columns().first())
+        .filter_map(|column| column.value().as_str())
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod test {
+    use super::names;
+    use crate::{
+        model::{
+            sql_query::row::{Column, Row},
+            value::Value,
+        },
+        SqlQueryResponse,
+    };
+
+    #[test]
+    fn test_names() {
+        let rows = ["t2", "t1"]
+            .iter()
+            .map(|name| {
+                let value = Value::String(name.to_string());
+                Row::new(vec![Column::new("Tables".to_string(), value)])
+            })
+            .collect();
+        let resp = SqlQueryResponse {
+            affected_rows: 0,
+            rows,
+        };
+        assert_eq!(names(resp), vec!["t1".to_string(), "t2".to_string()]);
+    }
+}
@@ -0,0 +1,204 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Destructive operations guarded by an explicit [`Confirmation`].
+
+use crate::{
+    model::value::TimestampMs, util::quote_ident, DbClient, Error, Result, RpcContext,
+    SqlQueryRequest,
+};
+
+/// Explicit confirmation required by the destructive operations.
+///
+/// The confirmation is bound to one table, and the operation fails if it is
+/// performed on any other table, which prevents scripts from deleting the
+/// wrong table by accident.
+#[derive(Clone, Debug)]
+pub struct Confirmation {
+    table: String,
+}
+
+impl Confirmation {
+    /// Confirm that the data of the `table` can be deleted.
+    pub fn for_table(table: impl Into<String>) -> Self {
+        Self {
+            table: table.into(),
+        }
+    }
+
+    fn check(&self, table: &str) -> Result<()> {
+        if self.table != table {
+            return Err(Error::Client(format!(
+                "destructive operation on table:{table} is not confirmed, confirmed table:{}",
+                self.table
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// The progress of [`delete_time_range`], reported after every chunk.
+#[derive(Clone, Debug)]
+pub struct DeleteProgress {
+    /// The number of the finished chunks.
+    pub finished_chunks: usize,
+    /// The number of all the chunks.
+    pub total_chunks: usize,
+    /// The time range `[start, end)` of the finished chunk.
+    pub chunk_range: (TimestampMs, TimestampMs),
+    /// The number of rows deleted so far.
+    pub affected_rows: u64,
+}
+
+/// The time range to delete by [`delete_time_range`].
+#[derive(Clone, Debug)]
+pub struct DeleteRange {
+    /// The name of the timestamp column of the table.
+    pub timestamp_column: String,
+    /// The inclusive start of the range.
+    pub start: TimestampMs,
+    /// The exclusive end of the range.
+    pub end: TimestampMs,
+    /// The max time span of the range deleted by one statement.
+    pub chunk_ms: i64,
+}
+
+/// Delete the rows of the `table` in the `range`, in chunks bounded by the
+/// `range.chunk_ms`.
+///
+/// The `on_progress` will be called after every chunk is deleted, and the
+/// total number of the deleted rows is returned.
+pub async fn delete_time_range<C: DbClient + ?Sized>(
+    client: &C,
+    ctx: &RpcContext,
+    table: &str,
+    range: &DeleteRange,
+    confirmation: &Confirmation,
+    mut on_progress: impl FnMut(&DeleteProgress),
+) -> Result<u64> {
+    confirmation.check(table)?;
+    let chunks = split_time_range(range.start, range.end, range.chunk_ms)?;
+
+    let mut progress = DeleteProgress {
+        finished_chunks: 0,
+        total_chunks: chunks.len(),
+        chunk_range: (range.start, range.start),
+        affected_rows: 0,
+    };
+    for (chunk_start, chunk_end) in chunks {
+        let sql = format!(
+            "DELETE FROM {} WHERE {col} >= {chunk_start} AND {col} < {chunk_end}",
+            quote_ident(table),
+            col = quote_ident(&range.timestamp_column),
+        );
+        let req = SqlQueryRequest {
+            tables: vec![table.to_string()],
+            sql,
+        };
+        let resp = client.sql_query(ctx, &req).await?;
+
+        progress.finished_chunks += 1;
+        progress.chunk_range = (chunk_start, chunk_end);
+        progress.affected_rows += resp.affected_rows as u64;
+        on_progress(&progress);
+    }
+
+    Ok(progress.affected_rows)
+}
+
+/// Drop the `table`.
+pub async fn drop_table_confirmed<C: DbClient + ?Sized>(
+    client: &C,
+    ctx: &RpcContext,
+    table: &str,
+    confirmation: &Confirmation,
+) -> Result<()> {
+    drop_table(client, ctx, table, false, confirmation).await
+}
+
+/// Drop the `table`, and don't fail if it doesn't exist when `if_exists` is
+/// set.
+pub async fn drop_table<C: DbClient + ?Sized>(
+    client: &C,
+    ctx: &RpcContext,
+    table: &str,
+    if_exists: bool,
+    confirmation: &Confirmation,
+) -> Result<()> {
+    confirmation.check(table)?;
+
+    let sql = match if_exists {
+        true => format!("DROP TABLE IF EXISTS {}", quote_ident(table)),
+        false => format!("DROP TABLE {}", quote_ident(table)),
+    };
+    let req = SqlQueryRequest {
+        tables: vec![table.to_string()],
+        sql,
+    };
+    client.sql_query(ctx, &req).await.map(|_| ())
+}
+
+fn split_time_range(
+    start: TimestampMs,
+    end: TimestampMs,
+    chunk_ms: i64,
+) -> Result<Vec<(TimestampMs, TimestampMs)>> {
+    if chunk_ms <= 0 {
+        return Err(Error::Client(format!(
+            "chunk of time range must be positive, chunk_ms:{chunk_ms}"
+        )));
+    }
+    if start >= end {
+        return Err(Error::Client(format!(
+            "invalid time range, start:{start}, end:{end}"
+        )));
+    }
+
+    let mut chunks = Vec::new();
+    let mut chunk_start = start;
+    while chunk_start < end {
+        let chunk_end = chunk_start.saturating_add(chunk_ms).min(end);
+        chunks.push((chunk_start, chunk_end));
+        chunk_start = chunk_end;
+    }
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_time_range() {
+        assert_eq!(
+            split_time_range(0, 25, 10).unwrap(),
+            vec![(0, 10), (10, 20), (20, 25)]
+        );
+        assert_eq!(split_time_range(0, 10, 10).unwrap(), vec![(0, 10)]);
+        assert!(split_time_range(0, 10, 0).is_err());
+        assert!(split_time_range(10, 10, 1).is_err());
+    }
+
+    #[test]
+    fn test_confirmation() {
+        let confirmation = Confirmation::for_table("t1");
+        assert!(confirmation.check("t1").is_ok());
+        assert!(confirmation.check("t2").is_err());
+    }
+}
@@ -0,0 +1,31 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Helpers for administrating the tables in HoraeDB on top of
+//! [`DbClient::sql_query`](crate::DbClient::sql_query).
+
+mod catalog;
+mod destructive;
+mod schema;
+
+pub use catalog::{list_databases, list_tables};
+pub use destructive::{
+    delete_time_range, drop_table, drop_table_confirmed, Confirmation, DeleteProgress, DeleteRange,
+};
+pub use schema::{add_columns, create_table, table_exists, TableSchemaBuilder};
+
+pub use crate::model::schema::ColumnSchema;
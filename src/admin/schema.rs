@@ -0,0 +1,310 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Create the tables by the typed schemas instead of writing the DDL by hand.
+
+use std::collections::HashSet;
+
+use crate::{
+    model::{
+        schema::{validate_columns, ColumnSchema, CreateTableOptions, TableSchema},
+        value::DataType,
+    },
+    util::quote_ident,
+    DbClient, Error, Result, RpcContext, SqlQueryRequest,
+};
+
+/// The builder of the `CREATE TABLE` DDL of HoraeDB, which is generated by
+/// [`TableSchema::to_create_sql`].
+///
+/// ```
+/// use horaedb_client::{admin::TableSchemaBuilder, model::value::DataType};
+///
+/// let ddl = TableSchemaBuilder::new("cpu", "t")
+///     .tag("host", DataType::String)
+///     .field("value", DataType::Double)
+///     .option("enable_ttl", "false")
+///     .ddl()
+///     .unwrap();
+/// assert_eq!(
+///     ddl,
+///     "CREATE TABLE `cpu` (`host` string TAG, `value` double, `t` timestamp NOT NULL, \
+///      TIMESTAMP KEY(`t`)) ENGINE=Analytic WITH (enable_ttl='false')"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct TableSchemaBuilder {
+    table: String,
+    schema: TableSchema,
+    options: CreateTableOptions,
+}
+
+impl TableSchemaBuilder {
+    /// The schema of the `table` with the timestamp key `timestamp_column`,
+    /// which is created by the `Analytic` engine by default.
+    pub fn new(table: impl Into<String>, timestamp_column: impl Into<String>) -> Self {
+        Self {
+            table: table.into(),
+            schema: TableSchema::default(),
+            options: CreateTableOptions {
+                timestamp_column: timestamp_column.into(),
+                if_not_exists: false,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn column(mut self, column: ColumnSchema) -> Self {
+        self.schema.columns.push(column);
+        self
+    }
+
+    pub fn tag(self, name: impl Into<String>, data_type: DataType) -> Self {
+        self.column(ColumnSchema::tag(name, data_type))
+    }
+
+    pub fn field(self, name: impl Into<String>, data_type: DataType) -> Self {
+        self.column(ColumnSchema::field(name, data_type))
+    }
+
+    /// The primary key of the table, which is decided by the server from the
+    /// tags and the timestamp key if not set.
+    pub fn primary_key(mut self, columns: Vec<String>) -> Self {
+        self.schema.primary_key = columns;
+        self
+    }
+
+    pub fn engine(mut self, engine: impl Into<String>) -> Self {
+        self.options.engine = engine.into();
+        self
+    }
+
+    /// Set the table option, e.g. `ttl` or `enable_ttl`.
+    pub fn option(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.table_options.insert(key.into(), value.into());
+        self
+    }
+
+    /// Don't fail if the table exists already.
+    pub fn if_not_exists(mut self, if_not_exists: bool) -> Self {
+        self.options.if_not_exists = if_not_exists;
+        self
+    }
+
+    /// Generate the `CREATE TABLE` DDL, and fail if the schema is invalid.
+    pub fn ddl(&self) -> Result<String> {
+        self.schema.to_create_sql(&self.table, &self.options)
+    }
+}
+
+/// Create the table by the DDL generated from the `schema`.
+pub async fn create_table<C: DbClient + ?Sized>(
+    client: &C,
+    ctx: &RpcContext,
+    schema: &TableSchemaBuilder,
+) -> Result<()> {
+    let req = SqlQueryRequest {
+        tables: vec![schema.table.clone()],
+        sql: schema.ddl()?,
+    };
+    client.sql_query(ctx, &req).await.map(|_| ())
+}
+
+/// Add the `columns` to the `table` by the `ALTER TABLE` statement.
+///
+/// It fails with [`Error::ColumnExists`] if any of the columns exists already.
+pub async fn add_columns<C: DbClient + ?Sized>(
+    client: &C,
+    ctx: &RpcContext,
+    table: &str,
+    columns: &[ColumnSchema],
+) -> Result<()> {
+    if columns.is_empty() {
+        return Ok(());
+    }
+    if let Err(msg) = validate_columns(&mut HashSet::new(), columns) {
+        return Err(Error::Client(format!(
+            "invalid columns to add to table:{table}, {msg}"
+        )));
+    }
+
+    let defs: Vec<_> = columns.iter().map(ColumnSchema::def).collect();
+    let req = SqlQueryRequest {
+        tables: vec![table.to_string()],
+        sql: format!(
+            "ALTER TABLE {} ADD COLUMN ({})",
+            quote_ident(table),
+            defs.join(", ")
+        ),
+    };
+    match client.sql_query(ctx, &req).await {
+        Ok(_) => Ok(()),
+        Err(Error::Server(err)) if err.msg.contains("exist") => {
+            match columns.iter().find(|column| err.msg.contains(&column.name)) {
+                Some(column) => Err(Error::ColumnExists {
+                    table: table.to_string(),
+                    column: column.name.clone(),
+                }),
+                None => Err(Error::Server(err)),
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Check whether the `table` exists by the `EXISTS TABLE` statement.
+pub async fn table_exists<C: DbClient + ?Sized>(
+    client: &C,
+    ctx: &RpcContext,
+    table: &str,
+) -> Result<bool> {
+    let req = SqlQueryRequest {
+        tables: vec![table.to_string()],
+        sql: format!("EXISTS TABLE {}", quote_ident(table)),
+    };
+    let resp = client.sql_query(ctx, &req).await?;
+    resp.rows
+        .first()
+        .and_then(|row| row.column("result"))
+        .and_then(|column| column.value().as_u8())
+        .map(|result| result != 0)
+        .ok_or_else(|| {
+            Error::Unknown(format!(
+                "unexpected result of checking the existence of table:{table}"
+            ))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use async_trait::async_trait;
+
+    use super::{add_columns, table_exists, TableSchemaBuilder};
+    use crate::{
+        db_client::PausePolicy,
+        errors::ServerError,
+        model::{
+            schema::ColumnSchema,
+            sql_query::row::{Column, Row},
+            value::{DataType, Value},
+        },
+        DbClient, Error, Result, RpcContext, SqlQueryRequest, SqlQueryResponse, WriteRequest,
+        WriteResponse,
+    };
+
+    /// Client of the tables named `t*`, which have the column `host`.
+    struct CatalogClient;
+
+    #[async_trait]
+    impl DbClient for CatalogClient {
+        async fn sql_query(
+            &self,
+            _: &RpcContext,
+            req: &SqlQueryRequest,
+        ) -> Result<SqlQueryResponse> {
+            if req.sql.starts_with("ALTER TABLE") {
+                if req.sql.contains("`host`") {
+                    return Err(Error::Server(ServerError {
+                        code: 500,
+                        msg: "column host already exists".to_string(),
+                    }));
+                }
+                return Ok(SqlQueryResponse::default());
+            }
+
+            let exists = req.sql.starts_with("EXISTS TABLE `t");
+            let column = Column::new("result".to_string(), Value::UInt8(exists as u8));
+            Ok(SqlQueryResponse {
+                affected_rows: 0,
+                rows: vec![Row::new(vec![column])],
+            })
+        }
+
+        async fn write(&self, _: &RpcContext, _: &WriteRequest) -> Result<WriteResponse> {
+            unimplemented!()
+        }
+
+        async fn health_check(&self, _: &RpcContext) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn pause(&self, _: PausePolicy) {}
+
+        fn resume(&self) {}
+    }
+
+    #[tokio::test]
+    async fn test_table_exists() {
+        let ctx = RpcContext::default();
+        assert!(table_exists(&CatalogClient, &ctx, "t1").await.unwrap());
+        assert!(!table_exists(&CatalogClient, &ctx, "x1").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_add_columns() {
+        let ctx = RpcContext::default();
+        let columns = vec![ColumnSchema::field("v2", DataType::Double)];
+        add_columns(&CatalogClient, &ctx, "t1", &columns)
+            .await
+            .unwrap();
+
+        let columns = vec![
+            ColumnSchema::field("v2", DataType::Double),
+            ColumnSchema::tag("host", DataType::String),
+        ];
+        let err = add_columns(&CatalogClient, &ctx, "t1", &columns)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ColumnExists { column, .. } if column == "host"));
+
+        let columns = vec![ColumnSchema::field("v2", DataType::Double); 2];
+        let err = add_columns(&CatalogClient, &ctx, "t1", &columns)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Client(_)));
+    }
+
+    #[test]
+    fn test_ddl() {
+        let ddl = TableSchemaBuilder::new("cpu", "ts")
+            .tag("host", DataType::String)
+            .column(ColumnSchema::tag("region`x", DataType::String).not_null())
+            .field("usage", DataType::UInt32)
+            .primary_key(vec!["host".to_string(), "ts".to_string()])
+            .option("ttl", "7d")
+            .option("enable_ttl", "true")
+            .if_not_exists(true)
+            .ddl()
+            .unwrap();
+        assert_eq!(
+            ddl,
+            "CREATE TABLE IF NOT EXISTS `cpu` (`host` string TAG, `region``x` string TAG NOT NULL, \
+             `usage` uint32, `ts` timestamp NOT NULL, TIMESTAMP KEY(`ts`), \
+             PRIMARY KEY(`host`, `ts`)) ENGINE=Analytic WITH (enable_ttl='true', ttl='7d')"
+        );
+
+        let schema = TableSchemaBuilder::new("cpu", "ts");
+        assert!(schema.clone().field("ts", DataType::Double).ddl().is_err());
+        assert!(schema.clone().field("v", DataType::Null).ddl().is_err());
+        assert!(schema
+            .clone()
+            .primary_key(vec!["host".to_string()])
+            .ddl()
+            .is_err());
+        assert!(schema.option("ttl='1d'", "1d").ddl().is_err());
+    }
+}
@@ -0,0 +1,65 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Compile-time assertions that the public types can be shared across threads,
+//! e.g. held by tower services or axum handlers.
+
+use std::{collections::BTreeMap, future::Future, sync::Arc};
+
+use crate::{
+    model::{
+        sql_query::{row::Row, series::TimeRange},
+        value::Value,
+    },
+    Builder, DbClient, Error, FailoverClient, QueryOptions, RpcConfig, RpcContext, SqlQueryRequest,
+    SqlQueryResponse, WriteOptions, WriteRequest, WriteResponse,
+};
+
+const fn assert_send_sync<T: Send + Sync + 'static>() {}
+
+fn assert_send_future<T: Future + Send>(_: &T) {}
+
+const _: () = {
+    assert_send_sync::<Arc<dyn DbClient>>();
+    assert_send_sync::<Builder>();
+    assert_send_sync::<FailoverClient>();
+    assert_send_sync::<RpcConfig>();
+    assert_send_sync::<RpcContext>();
+    assert_send_sync::<WriteOptions>();
+    assert_send_sync::<QueryOptions>();
+    assert_send_sync::<Error>();
+    assert_send_sync::<SqlQueryRequest>();
+    assert_send_sync::<SqlQueryResponse>();
+    assert_send_sync::<WriteRequest>();
+    assert_send_sync::<WriteResponse>();
+    assert_send_sync::<Row>();
+};
+
+#[allow(dead_code)]
+fn assert_send_futures(
+    client: &dyn DbClient,
+    ctx: &RpcContext,
+    query_req: &SqlQueryRequest,
+    write_req: &WriteRequest,
+    tags: &BTreeMap<String, Value>,
+    time_range: &TimeRange,
+) {
+    assert_send_future(&client.sql_query(ctx, query_req));
+    assert_send_future(&client.write(ctx, write_req));
+    assert_send_future(&client.sql_query_stream(ctx, query_req));
+    assert_send_future(&client.get_series(ctx, "", tags, time_range));
+}
@@ -0,0 +1,209 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The blocking client for the programs without an async runtime, e.g. the
+//! batch tools.
+//!
+//! ```rust,no_run
+//! use horaedb_client::{blocking, Builder, Mode, RpcContext, SqlQueryRequest};
+//!
+//! let client =
+//!     blocking::DbClient::new(Builder::new("127.0.0.1:8831".to_string(), Mode::Direct)).unwrap();
+//! let ctx = RpcContext::default().database("public".to_string());
+//! let req = SqlQueryRequest {
+//!     tables: vec!["horaedb".to_string()],
+//!     sql: "SELECT * FROM horaedb".to_string(),
+//! };
+//! let resp = client.sql_query(&ctx, &req).unwrap();
+//! println!("{:?}", resp.rows);
+//! ```
+
+use std::{future::Future, sync::Arc, time::Duration};
+
+use tokio::runtime::{Builder as RuntimeBuilder, Runtime};
+
+use crate::{
+    db_client::{Builder, DbClient as AsyncDbClient},
+    model::{
+        sql_query::{Request as SqlQueryRequest, Response as SqlQueryResponse},
+        write::{Request as WriteRequest, Response as WriteResponse},
+    },
+    rpc_client::RpcContext,
+    Error, Result,
+};
+
+const THREAD_NAME: &str = "horaedb-blocking";
+
+/// The blocking wrapper of the async [`DbClient`](crate::DbClient), which owns
+/// the runtime driving the calls.
+///
+/// The calls block the current thread, so they must not be made in an async
+/// context.
+pub struct DbClient {
+    client: Arc<dyn AsyncDbClient>,
+    runtime: Option<Runtime>,
+}
+
+impl DbClient {
+    /// Build the client by the `builder`, and the connection is established
+    /// lazily by the first request.
+    pub fn new(builder: Builder) -> Result<Self> {
+        let runtime = Self::build_runtime()?;
+        let client = {
+            let _guard = runtime.enter();
+            builder.try_build()?
+        };
+        Ok(Self {
+            client,
+            runtime: Some(runtime),
+        })
+    }
+
+    /// Build the client by the `builder` and connect to the endpoint eagerly,
+    /// see [`Builder::connect`].
+    pub fn connect(builder: Builder) -> Result<Self> {
+        let runtime = Self::build_runtime()?;
+        let client = runtime.block_on(builder.connect())?;
+        Ok(Self {
+            client,
+            runtime: Some(runtime),
+        })
+    }
+
+    /// Wrap the async `client`, e.g. a
+    /// [`FailoverClient`](crate::FailoverClient).
+    pub fn from_async(client: Arc<dyn AsyncDbClient>) -> Result<Self> {
+        Ok(Self {
+            client,
+            runtime: Some(Self::build_runtime()?),
+        })
+    }
+
+    fn build_runtime() -> Result<Runtime> {
+        RuntimeBuilder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name(THREAD_NAME)
+            .enable_all()
+            .build()
+            .map_err(|e| Error::Client(format!("failed to build the blocking runtime, err:{e}")))
+    }
+
+    fn block_on<T>(&self, fut: impl Future<Output = T>) -> T {
+        // The runtime is only taken when dropped.
+        self.runtime.as_ref().unwrap().block_on(fut)
+    }
+
+    /// The wrapped async client.
+    pub fn as_async(&self) -> &Arc<dyn AsyncDbClient> {
+        &self.client
+    }
+
+    /// See [`DbClient::sql_query`](crate::DbClient::sql_query).
+    pub fn sql_query(&self, ctx: &RpcContext, req: &SqlQueryRequest) -> Result<SqlQueryResponse> {
+        self.block_on(self.client.sql_query(ctx, req))
+    }
+
+    /// See [`DbClient::write`](crate::DbClient::write).
+    pub fn write(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+        self.block_on(self.client.write(ctx, req))
+    }
+
+    /// See [`DbClient::health_check`](crate::DbClient::health_check).
+    pub fn health_check(&self, ctx: &RpcContext) -> Result<()> {
+        self.block_on(self.client.health_check(ctx))
+    }
+
+    /// See [`DbClient::shutdown`](crate::DbClient::shutdown).
+    pub fn shutdown(&self, timeout: Duration) -> Result<()> {
+        self.block_on(self.client.shutdown(timeout))
+    }
+
+    /// See [`DbClient::close`](crate::DbClient::close).
+    pub fn close(&self) {
+        self.block_on(self.client.close())
+    }
+}
+
+impl Drop for DbClient {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+
+    use super::DbClient;
+    use crate::{
+        model::{
+            sql_query::{Request as SqlQueryRequest, Response as SqlQueryResponse},
+            write::{Request as WriteRequest, Response as WriteResponse},
+        },
+        rpc_client::RpcContext,
+        DbClient as AsyncDbClient, Error, PausePolicy, Result,
+    };
+
+    struct EchoClient;
+
+    #[async_trait]
+    impl AsyncDbClient for EchoClient {
+        async fn sql_query(
+            &self,
+            _ctx: &RpcContext,
+            req: &SqlQueryRequest,
+        ) -> Result<SqlQueryResponse> {
+            Ok(SqlQueryResponse {
+                affected_rows: req.tables.len() as u32,
+                rows: Vec::new(),
+            })
+        }
+
+        async fn write(&self, _ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+            // The calls are driven by the runtime of the blocking client.
+            tokio::task::yield_now().await;
+            Ok(WriteResponse::new(req.point_groups.len() as u32, 0))
+        }
+
+        async fn health_check(&self, _ctx: &RpcContext) -> Result<()> {
+            Err(Error::Client("unhealthy".to_string()))
+        }
+
+        fn pause(&self, _policy: PausePolicy) {}
+
+        fn resume(&self) {}
+    }
+
+    #[test]
+    fn test_blocking_client() {
+        let client = DbClient::from_async(Arc::new(EchoClient)).unwrap();
+        let ctx = RpcContext::default();
+
+        let req = SqlQueryRequest {
+            tables: vec!["t".to_string()],
+            sql: "select 1".to_string(),
+        };
+        assert_eq!(client.sql_query(&ctx, &req).unwrap().affected_rows, 1);
+        let resp = client.write(&ctx, &WriteRequest::default()).unwrap();
+        assert_eq!((resp.success, resp.failed), (0, 0));
+        assert!(matches!(client.health_check(&ctx), Err(Error::Client(_))));
+    }
+}
@@ -15,20 +15,36 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::time::Duration;
+use std::{borrow::Cow, collections::HashMap, net::IpAddr, time::Duration};
+
+use crate::{errors::Error, util::StatusCode};
 
 /// Config for the underlying grpc client
 #[derive(Debug, Clone)]
 pub struct RpcConfig {
-    /// Thread num used by the grpc client.
+    /// The number of the worker threads of the dedicated runtime, see
+    /// [`dedicated_runtime`](RpcConfig::dedicated_runtime).
     ///
     /// The number of cpu cores will be used if not set.
     pub thread_num: Option<usize>,
-    /// The max length of the message sent to server.
+    /// Drive the grpc connections by an internal multi-thread runtime of
+    /// `thread_num` worker threads instead of the runtime of the caller, so
+    /// the client isn't starved by the busy or small runtimes of the embedding
+    /// applications.
+    ///
+    /// It is ignored by the channels supplied by the channel provider, and is
+    /// disabled by default.
+    pub dedicated_runtime: bool,
+    /// The max length of the message sent to server, and the request exceeding
+    /// it fails without being sent.
     ///
     /// -1 means unlimited, and the default value is 20MB.
     pub max_send_msg_len: i32,
-    /// The max length of the message received from server.
+    /// The max length of the message received from server, and the response
+    /// exceeding it fails the call.
+    ///
+    /// It's checked after the response is decoded, as tonic doesn't limit the
+    /// decoded messages, so it doesn't bound the memory of receiving them.
     ///
     /// -1 means unlimited, and the default value is 1GB.
     pub max_recv_msg_len: i32,
@@ -45,6 +61,15 @@ pub struct RpcConfig {
     ///
     /// It is enabled by default.
     pub keep_alive_while_idle: bool,
+    /// Disable the Nagle's algorithm on the connections or not, which reduces
+    /// the latency of the small writes.
+    ///
+    /// It is enabled by default.
+    pub tcp_nodelay: bool,
+    /// The idle time before sending the TCP keepalive probes if set.
+    ///
+    /// It is disabled by default.
+    pub tcp_keepalive: Option<Duration>,
     /// Timeout for write operation.
     ///
     /// Default value is 5s.
@@ -57,6 +82,418 @@ pub struct RpcConfig {
     ///
     /// Default value is 3s.
     pub connect_timeout: Duration,
+    /// Connect to the servers by `https://` if set.
+    ///
+    /// It requires the `tls` feature, and it is disabled by default.
+    pub tls: Option<TlsConfig>,
+    /// Compress the requests sent to server if set.
+    ///
+    /// It is disabled by default.
+    pub send_compressed: Option<Compression>,
+    /// Accept the responses compressed by the server if set.
+    ///
+    /// It is disabled by default.
+    pub accept_compressed: Option<Compression>,
+    /// Rebuild the broken connection and retry the call by the policy if set.
+    ///
+    /// The calls already being the retries of the callers, e.g. by the
+    /// [`RetryConfig`] of the [`WriteOptions`], only rebuild the connection
+    /// and are not retried again. It is disabled by default.
+    ///
+    /// [`WriteOptions`]: crate::WriteOptions
+    pub reconnect: Option<ReconnectPolicy>,
+    /// The grpc status codes of the transient failures, and the call failed by
+    /// them is retried once on a fresh connection before surfacing the error,
+    /// or retried by the [`ReconnectPolicy`] if set.
+    ///
+    /// The writes are only re-sent with the request id of
+    /// [`WriteRetryMode::RequestId`], and otherwise only the connection is
+    /// rebuilt.
+    ///
+    /// Empty codes disable the retry unless the [`ReconnectPolicy`] is set,
+    /// which retries `Unavailable` then, and they are empty by default.
+    pub transport_retry_codes: Vec<tonic::Code>,
+    /// Re-resolve the host names of the endpoints on the interval if set, and
+    /// rebuild the connections once the resolved addresses change.
+    ///
+    /// The host names are also re-resolved when the servers are unavailable,
+    /// and it is disabled by default.
+    pub dns_refresh_interval: Option<Duration>,
+    /// Overrides of the keepalive and timeout configs for the endpoints in the
+    /// form: `{host}:{port}`, e.g. for the slower cross-region data nodes.
+    pub endpoint_overrides: HashMap<String, EndpointConfig>,
+    /// The max number of the concurrent rpcs to one endpoint if set, and the
+    /// extra ones wait for the permits within their timeouts.
+    ///
+    /// It's shared by all the clients built by the same factory, so a burst
+    /// of writes can't overload a single server, and it is unlimited by
+    /// default.
+    pub max_in_flight_per_endpoint: Option<usize>,
+    /// The local address the connections are bound to if set, which pins the
+    /// outbound network interface of the multi-homed hosts.
+    ///
+    /// It is ignored by the channels supplied by the channel provider.
+    pub local_address: Option<IpAddr>,
+    /// Send the [`Priority`](crate::Priority) of the calls to the server as
+    /// the `x-horaedb-priority` metadata.
+    ///
+    /// It is enabled by default.
+    pub forward_priority: bool,
+}
+
+impl RpcConfig {
+    /// Override the configs for the `endpoint` in the form: `{host}:{port}`.
+    pub fn endpoint_config(mut self, endpoint: impl Into<String>, config: EndpointConfig) -> Self {
+        self.endpoint_overrides.insert(endpoint.into(), config);
+        self
+    }
+
+    /// The config applied to the `endpoint` with the overrides.
+    pub(crate) fn for_endpoint(&self, endpoint: &str) -> Cow<'_, RpcConfig> {
+        let Some(overrides) = self.endpoint_overrides.get(endpoint) else {
+            return Cow::Borrowed(self);
+        };
+
+        let mut config = self.clone();
+        config.endpoint_overrides = HashMap::new();
+        if let Some(v) = overrides.keep_alive_interval {
+            config.keep_alive_interval = v;
+        }
+        if let Some(v) = overrides.keep_alive_timeout {
+            config.keep_alive_timeout = v;
+        }
+        if let Some(v) = overrides.keep_alive_while_idle {
+            config.keep_alive_while_idle = v;
+        }
+        if let Some(v) = overrides.default_write_timeout {
+            config.default_write_timeout = v;
+        }
+        if let Some(v) = overrides.default_sql_query_timeout {
+            config.default_sql_query_timeout = v;
+        }
+        if let Some(v) = overrides.connect_timeout {
+            config.connect_timeout = v;
+        }
+        Cow::Owned(config)
+    }
+}
+
+/// Overrides of the [`RpcConfig`] for an endpoint, and the unset ones follow
+/// the [`RpcConfig`].
+#[derive(Debug, Clone, Default)]
+pub struct EndpointConfig {
+    pub keep_alive_interval: Option<Duration>,
+    pub keep_alive_timeout: Option<Duration>,
+    pub keep_alive_while_idle: Option<bool>,
+    pub default_write_timeout: Option<Duration>,
+    pub default_sql_query_timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+}
+
+/// The policy to rebuild the broken connection to a server, with the
+/// exponential backoff between the attempts.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// The max number of the attempts to rebuild the connection for one call.
+    ///
+    /// Default value is 3.
+    pub max_attempts: u32,
+    /// The backoff before the first attempt, which is doubled for each of the
+    /// following attempts.
+    ///
+    /// Default value is 100ms.
+    pub initial_backoff: Duration,
+    /// The max backoff between the attempts.
+    ///
+    /// Default value is 3s.
+    pub max_backoff: Duration,
+    /// Randomize the backoff within `[backoff / 2, backoff]` to avoid the
+    /// clients reconnecting at the same time.
+    ///
+    /// It is enabled by default.
+    pub jitter: bool,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(3),
+            jitter: true,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Retry once without the backoff.
+    pub(crate) fn once() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            jitter: false,
+        }
+    }
+
+    /// The backoff before the `attempt` (starting from 0).
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let strategy = BackoffStrategy::Exponential {
+            initial: self.initial_backoff,
+            max: self.max_backoff,
+            jitter: self.jitter,
+        };
+        strategy.backoff(attempt, Duration::ZERO)
+    }
+}
+
+/// The strategy of the backoff between the retries.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackoffStrategy {
+    /// The same backoff before every retry.
+    Fixed(Duration),
+    /// The backoff starting from `initial` and doubled for each of the
+    /// following retries up to `max`, which is randomized within
+    /// `[backoff / 2, backoff]` if `jitter` is set.
+    Exponential {
+        initial: Duration,
+        max: Duration,
+        jitter: bool,
+    },
+    /// The backoff randomized within `[base, previous backoff * 3]` up to
+    /// `max`, which spreads the retries of a large number of clients failed
+    /// at the same time better than the exponential one.
+    DecorrelatedJitter { base: Duration, max: Duration },
+}
+
+impl Default for BackoffStrategy {
+    fn default() -> Self {
+        Self::Fixed(Duration::ZERO)
+    }
+}
+
+impl BackoffStrategy {
+    /// The backoff before the `attempt` (starting from 0) of the retries,
+    /// following the `prev` backoff.
+    pub(crate) fn backoff(&self, attempt: u32, prev: Duration) -> Duration {
+        match self {
+            Self::Fixed(backoff) => *backoff,
+            Self::Exponential {
+                initial,
+                max,
+                jitter,
+            } => {
+                let backoff = initial.saturating_mul(1 << attempt.min(16)).min(*max);
+                if !jitter {
+                    return backoff;
+                }
+
+                let half = backoff / 2;
+                half + half.mul_f64(crate::util::random_fraction())
+            }
+            Self::DecorrelatedJitter { base, max } => {
+                let upper = prev.saturating_mul(3).max(*base);
+                let backoff = *base + (upper - *base).mul_f64(crate::util::random_fraction());
+                backoff.min(*max)
+            }
+        }
+    }
+}
+
+/// The classes of the errors retried by the [`RetryConfig`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryableError {
+    /// The servers are unavailable, see [`Error::is_unavailable`].
+    Unavailable,
+    /// The server is overloaded, i.e. the server code 429 or the
+    /// `ResourceExhausted` status.
+    Throttled,
+    /// The server fails internally, i.e. the server code 500 or the `Internal`
+    /// status.
+    ServerInternal,
+}
+
+impl RetryableError {
+    pub(crate) fn matches(&self, err: &Error) -> bool {
+        let (code, status) = match self {
+            Self::Unavailable => return err.is_unavailable(),
+            Self::Throttled => (StatusCode::TooManyRequests, tonic::Code::ResourceExhausted),
+            Self::ServerInternal => (StatusCode::InternalError, tonic::Code::Internal),
+        };
+        match err {
+            Error::Server(e) => e.code == code.as_u32(),
+            Error::Rpc(s) => s.code() == status,
+            Error::RetryExhausted { source, .. } => self.matches(source),
+            _ => false,
+        }
+    }
+}
+
+/// How the failed writes are retried.
+///
+/// Only the writes failed before reaching the server are retried by default,
+/// as retrying the ones reaching the server may duplicate the rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteRetryMode {
+    /// Retry the writes failed by any of the retryable errors, which may
+    /// duplicate the rows if the failed write has reached the server.
+    Always,
+    /// Only retry the writes failed before reaching the server, e.g. failing
+    /// to connect, which never duplicates the rows.
+    #[default]
+    Unsent,
+    /// Retry the writes with a request id generated by the client, which is
+    /// sent in the `x-horaedb-request-id` metadata and kept across the
+    /// retries, so the server supporting the deduplication can drop the
+    /// duplicated writes.
+    ///
+    /// The id identifies the whole write, and is shared by its rpcs of the
+    /// disjoint tables sent to the different servers.
+    RequestId,
+}
+
+/// The backpressure of the calls throttled by the server, i.e. failed by the
+/// server code 429 or the `ResourceExhausted` status, which are resent after
+/// the backoff instead of failing immediately.
+///
+/// The backoff hinted by the server in the `retry-after` metadata (in
+/// seconds) is honored, and the call fails with [`Error::Throttled`] once the
+/// next backoff would exceed the budget or the deadline of the call.
+#[derive(Debug, Clone)]
+pub struct ThrottlePolicy {
+    /// The max time of waiting for the server to accept the call.
+    ///
+    /// Default value is 10s.
+    pub budget: Duration,
+    /// The backoff if the server doesn't hint it.
+    ///
+    /// Default value is exponential from 100ms to 5s with jitter.
+    pub backoff: BackoffStrategy,
+}
+
+impl Default for ThrottlePolicy {
+    fn default() -> Self {
+        Self {
+            budget: Duration::from_secs(10),
+            backoff: BackoffStrategy::Exponential {
+                initial: Duration::from_millis(100),
+                max: Duration::from_secs(5),
+                jitter: true,
+            },
+        }
+    }
+}
+
+impl ThrottlePolicy {
+    /// The backoff before resending the call throttled by the `err` for the
+    /// `throttled` (starting from 0) time, following the `prev` backoff.
+    pub(crate) fn backoff(&self, err: &Error, throttled: u32, prev: Duration) -> Duration {
+        err.retry_after()
+            .unwrap_or_else(|| self.backoff.backoff(throttled, prev))
+    }
+}
+
+/// Config of the retries of the calls failed by the transient errors.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// The max times to retry the call.
+    ///
+    /// It is not retried by default.
+    pub max_retries: u32,
+    /// The backoff between the retries, which is zero by default.
+    pub backoff: BackoffStrategy,
+    /// The classes of the errors to retry.
+    ///
+    /// Only the unavailable servers are retried by default.
+    pub retryable: Vec<RetryableError>,
+    /// How the failed writes are retried.
+    ///
+    /// Only the writes failed before reaching the server are retried by
+    /// default.
+    pub write_mode: WriteRetryMode,
+    /// Wait and resend the calls throttled by the server by the
+    /// [`ThrottlePolicy`] if set, which is independent of the `max_retries`.
+    ///
+    /// It is disabled by default.
+    pub throttle: Option<ThrottlePolicy>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: BackoffStrategy::default(),
+            retryable: vec![RetryableError::Unavailable],
+            write_mode: WriteRetryMode::default(),
+            throttle: None,
+        }
+    }
+}
+
+impl RetryConfig {
+    pub fn new(max_retries: u32, backoff: BackoffStrategy) -> Self {
+        Self {
+            max_retries,
+            backoff,
+            ..Default::default()
+        }
+    }
+
+    pub fn retryable(mut self, retryable: Vec<RetryableError>) -> Self {
+        self.retryable = retryable;
+        self
+    }
+
+    pub fn write_mode(mut self, write_mode: WriteRetryMode) -> Self {
+        self.write_mode = write_mode;
+        self
+    }
+
+    pub fn throttle(mut self, throttle: ThrottlePolicy) -> Self {
+        self.throttle = Some(throttle);
+        self
+    }
+
+    /// Whether the `err` should be retried.
+    pub(crate) fn is_retryable(&self, err: &Error) -> bool {
+        self.retryable.iter().any(|class| class.matches(err))
+    }
+}
+
+/// The grpc compression algorithm.
+///
+/// Only gzip is supported by the underlying grpc library for now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Compression {
+    Gzip,
+}
+
+/// Config for the TLS connections to the servers.
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    /// The domain name used for SNI and verifying the server certificate.
+    ///
+    /// The host of the endpoint will be used if not set, which is useful when
+    /// the servers are behind a TLS-terminating gateway.
+    pub domain_name: Option<String>,
+    /// The PEM encoded root CA certificate trusted in addition to the native
+    /// ones.
+    ///
+    /// The self-signed certificate of the server can be set here to pin it.
+    pub ca_pem: Option<Vec<u8>>,
+}
+
+impl TlsConfig {
+    pub fn domain_name(mut self, domain_name: impl Into<String>) -> Self {
+        self.domain_name = Some(domain_name.into());
+        self
+    }
+
+    pub fn with_ca_pem(mut self, ca_pem: impl AsRef<[u8]>) -> Self {
+        self.ca_pem = Some(ca_pem.as_ref().to_vec());
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -69,6 +506,7 @@ impl Default for RpcConfig {
     fn default() -> Self {
         Self {
             thread_num: None,
+            dedicated_runtime: false,
             // 20MB
             max_send_msg_len: 20 * (1 << 20),
             // 1GB
@@ -76,9 +514,109 @@ impl Default for RpcConfig {
             keep_alive_interval: Duration::from_secs(60 * 10),
             keep_alive_timeout: Duration::from_secs(3),
             keep_alive_while_idle: true,
+            tcp_nodelay: true,
+            tcp_keepalive: None,
             default_write_timeout: Duration::from_secs(5),
             default_sql_query_timeout: Duration::from_secs(60),
             connect_timeout: Duration::from_secs(3),
+            tls: None,
+            send_compressed: None,
+            accept_compressed: None,
+            reconnect: None,
+            transport_retry_codes: Vec::new(),
+            dns_refresh_interval: None,
+            endpoint_overrides: HashMap::new(),
+            max_in_flight_per_endpoint: None,
+            local_address: None,
+            forward_priority: true,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{BackoffStrategy, EndpointConfig, RetryConfig, RetryableError, RpcConfig};
+    use crate::errors::{Error, ServerError};
+
+    #[test]
+    fn test_endpoint_overrides() {
+        let config = RpcConfig::default().endpoint_config(
+            "10.0.0.1:8831",
+            EndpointConfig {
+                connect_timeout: Some(Duration::from_secs(10)),
+                default_sql_query_timeout: Some(Duration::from_secs(120)),
+                ..Default::default()
+            },
+        );
+
+        let overridden = config.for_endpoint("10.0.0.1:8831");
+        assert_eq!(overridden.connect_timeout, Duration::from_secs(10));
+        assert_eq!(
+            overridden.default_sql_query_timeout,
+            Duration::from_secs(120)
+        );
+        assert_eq!(
+            overridden.default_write_timeout,
+            config.default_write_timeout
+        );
+
+        let other = config.for_endpoint("10.0.0.2:8831");
+        assert_eq!(other.connect_timeout, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_backoff_strategies() {
+        let fixed = BackoffStrategy::Fixed(Duration::from_millis(10));
+        assert_eq!(fixed.backoff(5, Duration::ZERO), Duration::from_millis(10));
+
+        let exponential = BackoffStrategy::Exponential {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(50),
+            jitter: false,
+        };
+        let backoffs: Vec<_> = (0..4)
+            .map(|attempt| exponential.backoff(attempt, Duration::ZERO))
+            .collect();
+        assert_eq!(
+            backoffs,
+            [10, 20, 40, 50].map(Duration::from_millis).to_vec()
+        );
+
+        let base = Duration::from_millis(10);
+        let max = Duration::from_millis(100);
+        let decorrelated = BackoffStrategy::DecorrelatedJitter { base, max };
+        let mut prev = Duration::ZERO;
+        for attempt in 0..20 {
+            let backoff = decorrelated.backoff(attempt, prev);
+            assert!(backoff >= base && backoff <= max);
+            assert!(backoff <= (prev * 3).max(base));
+            prev = backoff;
+        }
+    }
+
+    #[test]
+    fn test_retryable_errors() {
+        let throttled = Error::Server(ServerError {
+            code: 429,
+            msg: "too many requests".to_string(),
+        });
+        let internal = Error::Rpc(tonic::Status::internal("panicked"));
+        let unavailable = Error::Rpc(tonic::Status::unavailable("down"));
+
+        let config = RetryConfig::default();
+        assert!(config.is_retryable(&unavailable));
+        assert!(!config.is_retryable(&throttled));
+        assert!(!config.is_retryable(&internal));
+
+        let config = RetryConfig::default().retryable(vec![
+            RetryableError::Throttled,
+            RetryableError::ServerInternal,
+        ]);
+        assert!(!config.is_retryable(&unavailable));
+        assert!(config.is_retryable(&throttled));
+        assert!(config.is_retryable(&internal));
+        assert!(!config.is_retryable(&Error::Client("bad request".to_string())));
+    }
+}
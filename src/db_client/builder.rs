@@ -15,12 +15,35 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::sync::Arc;
+use std::{fmt, sync::Arc, time::Duration};
 
+use async_trait::async_trait;
+
+#[cfg(feature = "http")]
+use crate::rpc_client::HttpRpcClientFactory;
 use crate::{
-    db_client::{raw::RawImpl, route_based::RouteBasedImpl, DbClient},
-    rpc_client::RpcClientImplFactory,
-    Authorization, RpcConfig,
+    db_client::{
+        cache::QueryCache, concurrency::ConcurrencyLimiter, rate_limit::WriteRateLimiter,
+        raw::RawImpl, route_based::RouteBasedImpl, slow::SlowRequestLog, CircuitBreakerConfig,
+        ClientOptions, ClientQuota, DbClient, HedgingConfig, QueryCacheConfig, SlowRequestConfig,
+        SlowRequestHook, WriteRateLimit,
+    },
+    model::{
+        sql_query::{
+            transform::RowTransformer, Request as SqlQueryRequest, Response as SqlQueryResponse,
+        },
+        write::{
+            sampling::WriteSampler, stats::WriteStats, Request as WriteRequest,
+            Response as WriteResponse,
+        },
+    },
+    router::{LoadBalancePolicy, RouteRefreshConfig, Router},
+    rpc_client::{
+        AuthProvider, BasicAuth, ChannelProvider, DedicatedRuntime, EndpointHook,
+        RequestInterceptor, RequestObserver, RpcClientImplFactory, RpcContext, SessionSettings,
+        TokenCache, TokenProvider,
+    },
+    Authorization, Error, Result, RetryConfig, RpcConfig, TlsConfig,
 };
 
 /// Access mode to HoraeDB server(s).
@@ -28,7 +51,8 @@ use crate::{
 pub enum Mode {
     /// When accessing HoraeDB cluster by `Direct` mode, the requests will be
     /// sent directly to the right HoraeDB instance determined by routing
-    /// information.
+    /// information. The query of the tables on different instances is sent to
+    /// the one serving the most of them.
     Direct,
     /// When accessing HoraeDB by `Proxy` mode, the requests are just sent to
     /// any one HoraeDB instance, which takes the responsibilities for
@@ -36,14 +60,67 @@ pub enum Mode {
     Proxy,
 }
 
+/// The transport to HoraeDB server(s).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transport {
+    /// The grpc services of the servers.
+    #[default]
+    Grpc,
+    /// The HTTP sql and InfluxDB write endpoints of the servers, for the
+    /// environments blocking grpc.
+    ///
+    /// It requires the `http` feature and only works in the `Proxy` mode, and
+    /// the endpoint should be the HTTP one of the server.
+    Http,
+}
+
 /// The builder for building [`DbClient`](DbClient).
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Builder {
     mode: Mode,
+    transport: Transport,
     endpoint: String,
     default_database: Option<String>,
     rpc_config: RpcConfig,
     authorization: Option<Authorization>,
+    auth_provider: Option<Arc<dyn AuthProvider>>,
+    write_sampler: Option<Arc<WriteSampler>>,
+    connect_budget: Option<Duration>,
+    row_transformers: Vec<Arc<dyn RowTransformer>>,
+    max_tables_per_write: Option<usize>,
+    require_default_database: bool,
+    interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    session_settings: SessionSettings,
+    observers: Vec<Arc<dyn RequestObserver>>,
+    write_stats: Option<Arc<WriteStats>>,
+    channel_provider: Option<Arc<dyn ChannelProvider>>,
+    endpoint_hook: Option<EndpointHook>,
+    load_balance_policy: Option<Arc<dyn LoadBalancePolicy>>,
+    route_ttl: Option<Duration>,
+    route_refresh: Option<RouteRefreshConfig>,
+    router: Option<Arc<dyn Router>>,
+    circuit_breaker: Option<CircuitBreakerConfig>,
+    hedging_config: Option<HedgingConfig>,
+    retry: Option<RetryConfig>,
+    fallback_endpoints: Vec<String>,
+    write_rate_limit: Option<WriteRateLimit>,
+    max_concurrent_calls: Option<usize>,
+    quota: ClientQuota,
+    slow_request_config: Option<SlowRequestConfig>,
+    slow_request_hook: Option<Arc<dyn SlowRequestHook>>,
+    query_cache_config: Option<QueryCacheConfig>,
+}
+
+impl fmt::Debug for Builder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("mode", &self.mode)
+            .field("transport", &self.transport)
+            .field("endpoint", &self.endpoint)
+            .field("default_database", &self.default_database)
+            .field("rpc_config", &self.rpc_config)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Builder {
@@ -51,10 +128,37 @@ impl Builder {
     pub fn new(endpoint: String, mode: Mode) -> Self {
         Self {
             mode,
+            transport: Transport::Grpc,
             endpoint,
             rpc_config: RpcConfig::default(),
             default_database: None,
             authorization: None,
+            auth_provider: None,
+            write_sampler: None,
+            connect_budget: None,
+            row_transformers: Vec::new(),
+            max_tables_per_write: None,
+            require_default_database: false,
+            interceptors: Vec::new(),
+            session_settings: SessionSettings::default(),
+            observers: Vec::new(),
+            write_stats: None,
+            channel_provider: None,
+            endpoint_hook: None,
+            load_balance_policy: None,
+            route_ttl: None,
+            route_refresh: None,
+            router: None,
+            circuit_breaker: None,
+            hedging_config: None,
+            retry: None,
+            fallback_endpoints: Vec::new(),
+            write_rate_limit: None,
+            max_concurrent_calls: None,
+            quota: ClientQuota::default(),
+            slow_request_config: None,
+            slow_request_hook: None,
+            query_cache_config: None,
         }
     }
 
@@ -64,35 +168,664 @@ impl Builder {
         self
     }
 
+    /// Talk to the servers by the [`Transport`], which is grpc by default.
+    #[inline]
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
     #[inline]
     pub fn rpc_config(mut self, rpc_config: RpcConfig) -> Self {
         self.rpc_config = rpc_config;
         self
     }
 
+    /// Connect to the servers by TLS, which requires the `tls` feature.
+    #[inline]
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.rpc_config.tls = Some(tls);
+        self
+    }
+
+    /// Use the channels supplied by the [`ChannelProvider`] instead of building
+    /// them by the [`RpcConfig`].
+    #[inline]
+    pub fn with_channel_provider(mut self, provider: impl ChannelProvider + 'static) -> Self {
+        self.channel_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Customize the tonic [`Endpoint`](tonic::transport::Endpoint) of every
+    /// connection after the [`RpcConfig`] is applied, e.g. to tune the http2
+    /// window sizes or the user agent which are not exposed by the
+    /// [`RpcConfig`].
+    ///
+    /// It's ignored if the channels are supplied by the [`ChannelProvider`].
+    #[inline]
+    pub fn configure_endpoint(
+        mut self,
+        hook: impl Fn(tonic::transport::Endpoint) -> tonic::transport::Endpoint + Send + Sync + 'static,
+    ) -> Self {
+        self.endpoint_hook = Some(Arc::new(hook));
+        self
+    }
+
     #[inline]
     pub fn authorization(mut self, authorization: Authorization) -> Self {
         self.authorization = Some(authorization);
         self
     }
 
+    /// Authenticate by the bearer tokens of the [`TokenProvider`], which are
+    /// refreshed once expired or rejected by the server.
+    ///
+    /// It overrides the basic [`Authorization`] if both are set.
+    #[inline]
+    pub fn token_provider(mut self, provider: impl TokenProvider + 'static) -> Self {
+        self.auth_provider = Some(Arc::new(TokenCache::new(Arc::new(provider))));
+        self
+    }
+
+    /// Attach the credentials of every rpc by the [`AuthProvider`], e.g. the
+    /// signatures required by the custom gateways.
+    ///
+    /// It overrides the basic [`Authorization`] and the [`TokenProvider`].
+    #[inline]
+    pub fn auth_provider(mut self, provider: impl AuthProvider + 'static) -> Self {
+        self.auth_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Append a [`RequestInterceptor`] called before every rpc is sent, and the
+    /// interceptors are called in the order of appending.
+    #[inline]
+    pub fn interceptor(mut self, interceptor: impl RequestInterceptor + 'static) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Forward the [`SessionSettings`] with every rpc, which are attached
+    /// before the [`RequestInterceptor`]s are called.
+    #[inline]
+    pub fn session_settings(mut self, settings: SessionSettings) -> Self {
+        self.session_settings = settings;
+        self
+    }
+
+    /// Append a [`RequestObserver`] notified around every rpc, and the
+    /// observers are notified in the order of appending.
+    #[inline]
+    pub fn observer(mut self, observer: impl RequestObserver + 'static) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Sample the points by the [`WriteSampler`] before writing them.
+    #[inline]
+    pub fn write_sampler(mut self, write_sampler: WriteSampler) -> Self {
+        self.write_sampler = Some(Arc::new(write_sampler));
+        self
+    }
+
+    /// Record the per-table point counts and bytes of every write into the
+    /// shared [`WriteStats`], which can be used to report the skewed tables.
+    #[inline]
+    pub fn write_stats(mut self, write_stats: Arc<WriteStats>) -> Self {
+        self.write_stats = Some(write_stats);
+        self
+    }
+
+    /// Connect to all the data nodes involved in a write in parallel, and
+    /// fail the tables on the nodes which can't be connected within the
+    /// `budget`, while the others are still written.
+    ///
+    /// It only works in the `Direct` mode.
+    #[inline]
+    pub fn connect_budget(mut self, budget: Duration) -> Self {
+        self.connect_budget = Some(budget);
+        self
+    }
+
+    /// Choose among the replicas of a table by the [`LoadBalancePolicy`]
+    /// instead of always taking the routed one.
+    ///
+    /// The replicas are only reported by the custom [`Router`] set by
+    /// [`Builder::router`], as the route response of the server carries one
+    /// endpoint per table, and it only works in the `Direct` mode.
+    #[inline]
+    pub fn load_balance_policy(mut self, policy: impl LoadBalancePolicy + 'static) -> Self {
+        self.load_balance_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Expire the cached routes of the tables after the `ttl`, so the tables
+    /// migrated to other nodes are routed again proactively instead of after
+    /// the requests to the stale nodes fail.
+    ///
+    /// The routes never expire by default, and it only works in the `Direct`
+    /// mode.
+    #[inline]
+    pub fn route_ttl(mut self, ttl: Duration) -> Self {
+        self.route_ttl = Some(ttl);
+        self
+    }
+
+    /// Refresh the cached routes of the hot tables in the background by the
+    /// [`RouteRefreshConfig`], so the requests don't wait for the route rpcs
+    /// even after the topology changes.
+    ///
+    /// It's disabled by default, and it only works in the `Direct` mode.
+    #[inline]
+    pub fn route_refresh(mut self, config: RouteRefreshConfig) -> Self {
+        self.route_refresh = Some(config);
+        self
+    }
+
+    /// Route the tables by the custom [`Router`], e.g. a static routing table
+    /// or another discovery mechanism, instead of asking the server at the
+    /// `endpoint`.
+    ///
+    /// The [`Builder::route_ttl`] and [`Builder::route_refresh`] don't apply
+    /// to the custom router, and it only works in the `Direct` mode.
+    #[inline]
+    pub fn router(mut self, router: impl Router + 'static) -> Self {
+        self.router = Some(Arc::new(router));
+        self
+    }
+
+    /// Stop calling the data nodes which keep failing by the circuit breakers
+    /// of the [`CircuitBreakerConfig`].
+    ///
+    /// It only works in the `Direct` mode.
+    #[inline]
+    pub fn circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.circuit_breaker = Some(config);
+        self
+    }
+
+    /// Hedge the read-only queries to another replica of the table by the
+    /// [`HedgingConfig`] to cut the tail latency.
+    ///
+    /// Only the queries opting in by [`RpcContext::hedge`] are hedged to the
+    /// replicas reported by the custom [`Router`] set by [`Builder::router`],
+    /// and it only works in the `Direct` mode.
+    #[inline]
+    pub fn hedging_config(mut self, config: HedgingConfig) -> Self {
+        self.hedging_config = Some(config);
+        self
+    }
+
+    /// Retry the queries and the writes failed by the transient errors by the
+    /// [`RetryConfig`], and the writes are retried for each server separately
+    /// following its [`WriteRetryMode`].
+    ///
+    /// [`WriteRetryMode`]: crate::WriteRetryMode
+    ///
+    /// The retries of the per-call options are made on top of them.
+    #[inline]
+    pub fn retry_config(mut self, config: RetryConfig) -> Self {
+        self.retry = Some(config);
+        self
+    }
+
+    /// Limit the rate of the writes of the client by the [`WriteRateLimit`].
+    #[inline]
+    pub fn write_rate_limit(mut self, limit: WriteRateLimit) -> Self {
+        self.write_rate_limit = Some(limit);
+        self
+    }
+
+    /// Limit the concurrent queries and writes of the client to all the
+    /// servers, and the extra ones wait for the permits within their timeouts
+    /// and deadlines if set, which bounds the outstanding calls of the
+    /// embedding service.
+    ///
+    /// The waiting calls of the higher [`Priority`](crate::Priority) are let
+    /// through first, so the bulk calls can't starve the interactive ones.
+    ///
+    /// It's unlimited by default.
+    #[inline]
+    pub fn max_concurrent_calls(mut self, permits: usize) -> Self {
+        self.max_concurrent_calls = Some(permits);
+        self
+    }
+
+    /// Enforce the [`ClientQuota`] on the calls of the client, e.g. by the
+    /// wrapper shared by the tenants of the cluster.
+    #[inline]
+    pub fn quota(mut self, quota: ClientQuota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Report the requests slower than the thresholds of the
+    /// [`SlowRequestConfig`] to the hook set by
+    /// [`Builder::slow_request_hook`], or as the `tracing` events with the
+    /// `tracing` feature if no hook is set.
+    #[inline]
+    pub fn slow_request_config(mut self, config: SlowRequestConfig) -> Self {
+        self.slow_request_config = Some(config);
+        self
+    }
+
+    /// Call the `hook` with every slow request, and the default
+    /// [`SlowRequestConfig`] is used if it's not set.
+    #[inline]
+    pub fn slow_request_hook(mut self, hook: impl SlowRequestHook + 'static) -> Self {
+        self.slow_request_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Cache the results of the queries in memory by the [`QueryCacheConfig`],
+    /// which is shared by the queries and the writes of the client only.
+    #[inline]
+    pub fn query_cache_config(mut self, config: QueryCacheConfig) -> Self {
+        self.query_cache_config = Some(config);
+        self
+    }
+
+    /// The endpoints of the other routers in `Direct` mode or proxies in
+    /// `Proxy` mode, failed over to in order once the endpoint is down, i.e.
+    /// it can't be connected or is unavailable.
+    ///
+    /// It only works with the [`Transport::Grpc`].
+    #[inline]
+    pub fn fallback_endpoints(mut self, endpoints: Vec<String>) -> Self {
+        self.fallback_endpoints = endpoints;
+        self
+    }
+
+    /// Append a [`RowTransformer`] applied to the rows of every query
+    /// response, and the transformers are applied in the order of appending.
+    #[inline]
+    pub fn row_transformer(mut self, transformer: impl RowTransformer + 'static) -> Self {
+        self.row_transformers.push(Arc::new(transformer));
+        self
+    }
+
+    /// Split a write into multiple rpcs each of which contains
+    /// `max_tables_per_write` tables at most, for the servers limiting the
+    /// number of tables per write request.
+    #[inline]
+    pub fn max_tables_per_write(mut self, max_tables_per_write: usize) -> Self {
+        self.max_tables_per_write = Some(max_tables_per_write);
+        self
+    }
+
+    /// Require the default database to be set when building a client of the
+    /// `Direct` mode, instead of failing every request without the database
+    /// set in its [`RpcContext`](crate::RpcContext).
+    #[inline]
+    pub fn require_default_database(mut self) -> Self {
+        self.require_default_database = true;
+        self
+    }
+
+    /// Build the client.
+    ///
+    /// The invalid configs, including the missing default database required by
+    /// [`Builder::require_default_database`], fail all the requests, see
+    /// [`Builder::try_build`] for failing on building instead.
     pub fn build(self) -> Arc<dyn DbClient> {
-        let rpc_client_factory = Arc::new(RpcClientImplFactory::new(
-            self.rpc_config,
-            self.authorization,
-        ));
-
-        match self.mode {
-            Mode::Direct => Arc::new(RouteBasedImpl::new(
-                rpc_client_factory,
-                self.endpoint,
-                self.default_database,
-            )),
-            Mode::Proxy => Arc::new(RawImpl::new(
-                rpc_client_factory,
+        match self.try_build() {
+            Ok(client) => client,
+            Err(e) => Arc::new(InvalidClient(e)),
+        }
+    }
+
+    /// Build the client, and fail if the default database is required but
+    /// missing.
+    ///
+    /// The connection is established lazily by the first request, see
+    /// [`Builder::connect`] for the eager one.
+    pub fn try_build(self) -> Result<Arc<dyn DbClient>> {
+        let client: Arc<dyn DbClient> = match self.build_impl()? {
+            ClientImpl::Direct(client) => Arc::new(client),
+            ClientImpl::Proxy(client) => Arc::new(client),
+            #[cfg(feature = "http")]
+            ClientImpl::Http(client) => Arc::new(client),
+        };
+        Ok(client)
+    }
+
+    /// Build the client and connect to the endpoint eagerly, which fails fast
+    /// if the endpoint can't be connected, e.g. at the startup of the service.
+    pub async fn connect(self) -> Result<Arc<dyn DbClient>> {
+        let client: Arc<dyn DbClient> = match self.build_impl()? {
+            ClientImpl::Direct(client) => {
+                client.connect().await?;
+                Arc::new(client)
+            }
+            ClientImpl::Proxy(client) => {
+                client.connect().await?;
+                Arc::new(client)
+            }
+            #[cfg(feature = "http")]
+            ClientImpl::Http(client) => {
+                client.connect().await?;
+                Arc::new(client)
+            }
+        };
+        Ok(client)
+    }
+
+    fn build_impl(self) -> Result<ClientImpl> {
+        if self.require_default_database
+            && matches!(self.mode, Mode::Direct)
+            && self.default_database.is_none()
+        {
+            return Err(Error::NoDatabase);
+        }
+
+        let auth_provider = match (self.auth_provider, &self.authorization) {
+            (Some(provider), _) => Some(provider),
+            (None, Some(authorization)) => {
+                Some(Arc::new(BasicAuth::new(authorization)?) as Arc<dyn AuthProvider>)
+            }
+            (None, None) => None,
+        };
+        let mut interceptors = self.interceptors;
+        if !self.session_settings.is_empty() {
+            interceptors.insert(0, Arc::new(self.session_settings));
+        }
+        let options = ClientOptions {
+            default_database: self.default_database,
+            write_sampler: self.write_sampler,
+            connect_budget: self.connect_budget,
+            row_transformers: self.row_transformers,
+            max_tables_per_write: self.max_tables_per_write,
+            write_stats: self.write_stats,
+            ingestion_gate: Default::default(),
+            load_balance_policy: self.load_balance_policy,
+            route_ttl: self.route_ttl,
+            route_refresh: self.route_refresh,
+            circuit_breaker: self.circuit_breaker,
+            hedging_config: self.hedging_config,
+            retry: self.retry,
+            capabilities: Default::default(),
+            max_send_msg_len: usize::try_from(self.rpc_config.max_send_msg_len).ok(),
+            write_rate_limiter: self
+                .write_rate_limit
+                .as_ref()
+                .map(|limit| Arc::new(WriteRateLimiter::new(limit))),
+            concurrency_limit: self
+                .max_concurrent_calls
+                .map(|permits| Arc::new(ConcurrencyLimiter::new(permits))),
+            query_concurrency_limit: self
+                .quota
+                .max_concurrent_queries
+                .map(|permits| Arc::new(ConcurrencyLimiter::new(permits))),
+            quota: self.quota,
+            shutdown_gate: Default::default(),
+            metrics: Default::default(),
+            query_cache: self
+                .query_cache_config
+                .map(|config| Arc::new(QueryCache::new(config))),
+            slow_request_log: match (self.slow_request_config, self.slow_request_hook) {
+                (None, None) => None,
+                (config, hook) => Some(Arc::new(SlowRequestLog::new(
+                    config.unwrap_or_default(),
+                    hook,
+                ))),
+            },
+        };
+        if self.transport == Transport::Http {
+            return Self::build_http(
+                self.mode,
                 self.endpoint,
-                self.default_database,
-            )),
+                self.rpc_config,
+                auth_provider,
+                interceptors,
+                self.observers,
+                options,
+            );
+        }
+
+        let runtime = match self.rpc_config.dedicated_runtime {
+            true => Some(DedicatedRuntime::new(self.rpc_config.thread_num)?),
+            false => None,
+        };
+        let mut rpc_client_factory =
+            RpcClientImplFactory::new(self.rpc_config, auth_provider, interceptors)
+                .with_observers(self.observers);
+        if let Some(runtime) = runtime {
+            rpc_client_factory = rpc_client_factory.with_runtime(Arc::new(runtime));
         }
+        if let Some(provider) = self.channel_provider {
+            rpc_client_factory = rpc_client_factory.with_channel_provider(provider);
+        }
+        if let Some(hook) = self.endpoint_hook {
+            rpc_client_factory = rpc_client_factory.with_endpoint_hook(hook);
+        }
+        if !self.fallback_endpoints.is_empty() {
+            rpc_client_factory = rpc_client_factory
+                .with_fallback_endpoints(self.endpoint.clone(), self.fallback_endpoints);
+        }
+        let rpc_client_factory = Arc::new(rpc_client_factory);
+
+        let client = match self.mode {
+            Mode::Direct => {
+                let client = RouteBasedImpl::new(rpc_client_factory, self.endpoint, options);
+                match self.router {
+                    Some(router) => ClientImpl::Direct(client.with_router(Box::new(router))),
+                    None => ClientImpl::Direct(client),
+                }
+            }
+            Mode::Proxy => {
+                ClientImpl::Proxy(RawImpl::new(rpc_client_factory, self.endpoint, options))
+            }
+        };
+        Ok(client)
+    }
+
+    #[cfg(feature = "http")]
+    fn build_http(
+        mode: Mode,
+        endpoint: String,
+        rpc_config: RpcConfig,
+        auth_provider: Option<Arc<dyn AuthProvider>>,
+        interceptors: Vec<Arc<dyn RequestInterceptor>>,
+        observers: Vec<Arc<dyn RequestObserver>>,
+        options: ClientOptions,
+    ) -> Result<ClientImpl> {
+        if matches!(mode, Mode::Direct) {
+            return Err(Error::Client(
+                "http transport only works in the Proxy mode".to_string(),
+            ));
+        }
+
+        let factory = HttpRpcClientFactory::new(rpc_config, auth_provider, interceptors)
+            .with_observers(observers);
+        Ok(ClientImpl::Http(RawImpl::new(
+            Arc::new(factory),
+            endpoint,
+            options,
+        )))
+    }
+
+    #[cfg(not(feature = "http"))]
+    fn build_http(
+        _mode: Mode,
+        _endpoint: String,
+        _rpc_config: RpcConfig,
+        _auth_provider: Option<Arc<dyn AuthProvider>>,
+        _interceptors: Vec<Arc<dyn RequestInterceptor>>,
+        _observers: Vec<Arc<dyn RequestObserver>>,
+        _options: ClientOptions,
+    ) -> Result<ClientImpl> {
+        Err(Error::Client(
+            "http transport requires the `http` feature".to_string(),
+        ))
+    }
+}
+
+/// The client built by the invalid configs, which fails all the requests.
+struct InvalidClient(Error);
+
+#[async_trait]
+impl DbClient for InvalidClient {
+    async fn sql_query(
+        &self,
+        _ctx: &RpcContext,
+        _req: &SqlQueryRequest,
+    ) -> Result<SqlQueryResponse> {
+        Err(self.error())
+    }
+
+    async fn write(&self, _ctx: &RpcContext, _req: &WriteRequest) -> Result<WriteResponse> {
+        Err(self.error())
+    }
+
+    async fn health_check(&self, _ctx: &RpcContext) -> Result<()> {
+        Err(self.error())
+    }
+}
+
+impl InvalidClient {
+    fn error(&self) -> Error {
+        match &self.0 {
+            Error::NoDatabase => Error::NoDatabase,
+            e => Error::Client(format!("invalid client builder, err:{e}")),
+        }
+    }
+}
+
+enum ClientImpl {
+    Direct(RouteBasedImpl<RpcClientImplFactory>),
+    Proxy(RawImpl<RpcClientImplFactory>),
+    #[cfg(feature = "http")]
+    Http(RawImpl<HttpRpcClientFactory>),
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+    use tonic::transport::Endpoint;
+
+    use super::{Builder, Mode, Transport};
+    use crate::{
+        model::{route::Endpoint as RouteEndpoint, write::Request as WriteRequest},
+        router::Router,
+        rpc_client::RpcContext,
+        Error, Result,
+    };
+
+    #[test]
+    fn test_require_default_database() {
+        let endpoint = "127.0.0.1:8831".to_string();
+        let err = Builder::new(endpoint.clone(), Mode::Direct)
+            .require_default_database()
+            .try_build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::NoDatabase));
+        assert!(err.to_string().contains("Builder::default_database"));
+
+        assert!(Builder::new(endpoint.clone(), Mode::Direct)
+            .require_default_database()
+            .default_database("public")
+            .try_build()
+            .is_ok());
+        assert!(Builder::new(endpoint, Mode::Proxy)
+            .require_default_database()
+            .try_build()
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_build_invalid() {
+        let endpoint = "127.0.0.1:8831".to_string();
+        let ctx = RpcContext::default();
+        let req = WriteRequest::default();
+
+        // The missing default database fails even the requests with the database.
+        let client = Builder::new(endpoint.clone(), Mode::Direct)
+            .require_default_database()
+            .build();
+        let err = client.write(&ctx, &req).await.unwrap_err();
+        assert!(matches!(err, Error::NoDatabase));
+        let err = client
+            .write(&RpcContext::default().database("public".to_string()), &req)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NoDatabase));
+
+        let client = Builder::new(endpoint, Mode::Direct)
+            .transport(Transport::Http)
+            .build();
+        let err = client.write(&ctx, &req).await.unwrap_err();
+        assert!(matches!(err, Error::Client(_)));
+        assert!(client.health_check(&ctx).await.is_err());
+    }
+
+    #[test]
+    fn test_http_transport() {
+        let endpoint = "127.0.0.1:5440".to_string();
+        let err = Builder::new(endpoint.clone(), Mode::Direct)
+            .transport(Transport::Http)
+            .try_build()
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Client(_)));
+
+        let result = Builder::new(endpoint, Mode::Proxy)
+            .transport(Transport::Http)
+            .try_build();
+        assert_eq!(result.is_ok(), cfg!(feature = "http"));
+    }
+
+    #[tokio::test]
+    async fn test_eager_connect() {
+        for mode in [Mode::Direct, Mode::Proxy] {
+            let err = Builder::new("127.0.0.1:1".to_string(), mode)
+                .connect()
+                .await
+                .err()
+                .unwrap();
+            assert!(matches!(err, Error::Connect { .. }));
+        }
+
+        let endpoint = "127.0.0.1:8831".to_string();
+        let channel = Endpoint::from_static("http://127.0.0.1:8831").connect_lazy();
+        let channels = HashMap::from([(endpoint.clone(), channel)]);
+        assert!(Builder::new(endpoint, Mode::Proxy)
+            .with_channel_provider(channels)
+            .connect()
+            .await
+            .is_ok());
+    }
+
+    /// The router of the tables in a static routing table.
+    struct StaticRouter(HashMap<String, RouteEndpoint>);
+
+    #[async_trait]
+    impl Router for StaticRouter {
+        async fn route(
+            &self,
+            tables: &[String],
+            _ctx: &RpcContext,
+        ) -> Result<Vec<Option<RouteEndpoint>>> {
+            Ok(tables.iter().map(|t| self.0.get(t).cloned()).collect())
+        }
+
+        fn evict(&self, _tables: &[String]) {}
+    }
+
+    #[tokio::test]
+    async fn test_custom_router() {
+        let endpoint = RouteEndpoint::new("192.168.0.1".to_string(), 8831);
+        let router = StaticRouter(HashMap::from([("t".to_string(), endpoint.clone())]));
+        let client = Builder::new("127.0.0.1:8831".to_string(), Mode::Direct)
+            .default_database("public")
+            .router(router)
+            .try_build()
+            .unwrap();
+
+        let tables = ["t".to_string(), "unknown".to_string()];
+        let endpoints = client.route(&RpcContext::default(), &tables).await.unwrap();
+        assert_eq!(endpoints, vec![Some(endpoint), None]);
     }
 }
@@ -0,0 +1,272 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Client-side cache of the query results, which serves the repeated queries
+//! (e.g. of the dashboards) from memory.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    db_client::hedge,
+    model::sql_query::{Request as SqlQueryRequest, Response as SqlQueryResponse},
+    rpc_client::RpcContext,
+    Result,
+};
+
+/// Config of the cache of the query results, see
+/// [`Builder::query_cache_config`](crate::Builder::query_cache_config).
+///
+/// Only the results of the read-only sqls are cached, keyed by the database
+/// and the sql, and the cached results of the tables are invalidated once
+/// they are written by the client.
+#[derive(Debug, Clone)]
+pub struct QueryCacheConfig {
+    /// Whether the results are cached by default, which is overridden by
+    /// [`RpcContext::cache`] of every query.
+    ///
+    /// Default value is false.
+    pub enabled: bool,
+    /// The time to live of the cached results.
+    ///
+    /// Default value is 10s.
+    pub ttl: Duration,
+    /// The max number of the cached results, and the oldest one is evicted
+    /// once it's full.
+    ///
+    /// Default value is 1024.
+    pub max_entries: usize,
+}
+
+impl Default for QueryCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl: Duration::from_secs(10),
+            max_entries: 1024,
+        }
+    }
+}
+
+type CacheKey = (Option<String>, String);
+
+struct CachedResult {
+    tables: Vec<String>,
+    resp: SqlQueryResponse,
+    expires_at: Instant,
+}
+
+pub(crate) struct QueryCache {
+    config: QueryCacheConfig,
+    entries: Mutex<HashMap<CacheKey, CachedResult>>,
+    /// Bumped by every invalidation, so that the results of the queries
+    /// racing with the writes are not cached.
+    generation: AtomicU64,
+}
+
+impl QueryCache {
+    pub fn new(config: QueryCacheConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Serve the query from the cache if enabled, or run the `query` and cache
+    /// its result.
+    ///
+    /// The `ctx` should have been resolved with the default database, and the
+    /// tables queried by the sql not read-only are invalidated instead.
+    pub async fn query<Fut>(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+        query: Fut,
+    ) -> Result<SqlQueryResponse>
+    where
+        Fut: Future<Output = Result<SqlQueryResponse>>,
+    {
+        if !hedge::is_idempotent(&req.sql) {
+            let _invalidation = self.invalidate_on_drop(ctx, req.tables.iter());
+            return query.await;
+        }
+        if !ctx.cache.unwrap_or(self.config.enabled) {
+            return query.await;
+        }
+
+        let key = (ctx.database.clone(), req.sql.clone());
+        if let Some(resp) = self.get(&key) {
+            return Ok(resp);
+        }
+        let generation = self.generation.load(Ordering::Acquire);
+        let resp = query.await?;
+        self.put(key, req.tables.clone(), resp.clone(), generation);
+        Ok(resp)
+    }
+
+    /// Invalidate the cached results of the `tables` in the database of the
+    /// `ctx` once the returned guard is dropped, i.e. the write is done.
+    pub fn invalidate_on_drop<'a>(
+        &self,
+        ctx: &RpcContext,
+        tables: impl Iterator<Item = &'a String>,
+    ) -> CacheInvalidation<'_> {
+        CacheInvalidation {
+            cache: self,
+            database: ctx.database.clone(),
+            tables: tables.cloned().collect(),
+        }
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<SqlQueryResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(cached) if cached.expires_at > Instant::now() => Some(cached.resp.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn put(&self, key: CacheKey, tables: Vec<String>, resp: SqlQueryResponse, generation: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        // Invalidated by a write during the query.
+        if self.generation.load(Ordering::Acquire) != generation {
+            return;
+        }
+
+        let now = Instant::now();
+        if entries.len() >= self.config.max_entries && !entries.contains_key(&key) {
+            entries.retain(|_, cached| cached.expires_at > now);
+            if entries.len() >= self.config.max_entries {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, cached)| cached.expires_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        if self.config.max_entries > 0 {
+            let expires_at = now + self.config.ttl;
+            entries.insert(
+                key,
+                CachedResult {
+                    tables,
+                    resp,
+                    expires_at,
+                },
+            );
+        }
+    }
+
+    fn invalidate(&self, database: &Option<String>, tables: &[String]) {
+        let mut entries = self.entries.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        entries.retain(|(cached_database, _), cached| {
+            cached_database != database || !cached.tables.iter().any(|t| tables.contains(t))
+        });
+    }
+}
+
+/// Guard invalidating the cached results of the written tables on drop.
+pub(crate) struct CacheInvalidation<'a> {
+    cache: &'a QueryCache,
+    database: Option<String>,
+    tables: Vec<String>,
+}
+
+impl Drop for CacheInvalidation<'_> {
+    fn drop(&mut self) {
+        self.cache.invalidate(&self.database, &self.tables);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{QueryCache, QueryCacheConfig};
+    use crate::{
+        model::sql_query::{Request as SqlQueryRequest, Response as SqlQueryResponse},
+        rpc_client::RpcContext,
+    };
+
+    async fn query(
+        cache: &QueryCache,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+        affected_rows: u32,
+    ) -> u32 {
+        let resp = SqlQueryResponse {
+            affected_rows,
+            rows: Vec::new(),
+        };
+        let resp = cache.query(ctx, req, async { Ok(resp) }).await;
+        resp.unwrap().affected_rows
+    }
+
+    #[tokio::test]
+    async fn test_query_cache() {
+        let cache = QueryCache::new(QueryCacheConfig {
+            enabled: true,
+            ttl: Duration::from_secs(60),
+            max_entries: 1,
+        });
+        let ctx = RpcContext::default().database("public".to_string());
+        let req = SqlQueryRequest {
+            tables: vec!["t1".to_string()],
+            sql: "SELECT * FROM t1".to_string(),
+        };
+        // Served from the cache.
+        assert_eq!(query(&cache, &ctx, &req, 1).await, 1);
+        assert_eq!(query(&cache, &ctx, &req, 2).await, 1);
+        // Not cached if disabled by the context.
+        assert_eq!(query(&cache, &ctx.clone().cache(false), &req, 3).await, 3);
+        // Keyed by the database.
+        let other_ctx = RpcContext::default().database("other".to_string());
+        assert_eq!(query(&cache, &other_ctx, &req, 4).await, 4);
+        // The oldest result is evicted once full.
+        assert_eq!(query(&cache, &ctx, &req, 5).await, 5);
+
+        // Invalidated by the writes.
+        let tables = ["t1".to_string()];
+        drop(cache.invalidate_on_drop(&other_ctx, tables.iter()));
+        assert_eq!(query(&cache, &ctx, &req, 6).await, 5);
+        drop(cache.invalidate_on_drop(&ctx, tables.iter()));
+        assert_eq!(query(&cache, &ctx, &req, 7).await, 7);
+
+        // The sqls not read-only are not cached.
+        let insert = SqlQueryRequest {
+            tables: vec!["t1".to_string()],
+            sql: "INSERT INTO t1 VALUES (1)".to_string(),
+        };
+        assert_eq!(query(&cache, &ctx, &insert, 8).await, 8);
+        assert_eq!(query(&cache, &ctx, &req, 9).await, 9);
+    }
+}
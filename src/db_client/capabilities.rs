@@ -0,0 +1,128 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Probe of the capabilities of the server.
+
+use horaedbproto::storage::{
+    RequestContext as RequestContextPb, SqlQueryRequest as QueryRequestPb,
+};
+
+use crate::{
+    model::sql_query::Response as SqlQueryResponse,
+    rpc_client::{RpcClient, RpcContext},
+    Error, Result,
+};
+
+/// The sql probing the version of the server.
+const VERSION_SQL: &str = "SELECT version()";
+
+/// The capabilities of the server, see [`DbClient::capabilities`].
+///
+/// [`DbClient::capabilities`]: crate::DbClient::capabilities
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServerCapabilities {
+    /// The version reported by the `version()` sql function of the server, if
+    /// it is supported.
+    pub version: Option<String>,
+    /// Whether the server-streaming query rpc is supported, otherwise the
+    /// streaming queries should fall back to the unary ones.
+    pub streaming_query: bool,
+}
+
+/// Probe the capabilities of the server by the `client`.
+///
+/// Only the unavailable server fails the probe, and the other failures are
+/// regarded as the missing capabilities.
+pub(crate) async fn probe(client: &dyn RpcClient, ctx: &RpcContext) -> Result<ServerCapabilities> {
+    let make_request = || QueryRequestPb {
+        context: Some(RequestContextPb {
+            database: ctx.database.clone().unwrap_or_default(),
+        }),
+        tables: Vec::new(),
+        sql: VERSION_SQL.to_string(),
+    };
+
+    let version = match client.sql_query(ctx, make_request()).await {
+        Ok(resp) => SqlQueryResponse::try_from(resp)
+            .ok()
+            .and_then(|resp| resp.rows.into_iter().next())
+            .and_then(|row| row.columns().first().and_then(|c| c.value().as_str())),
+        Err(e) if e.is_unavailable() => return Err(e),
+        Err(_) => None,
+    };
+    // The failures of the sql still mean the rpc is implemented.
+    let streaming_query = match client.sql_query_stream(ctx, make_request()).await {
+        Err(Error::Rpc(status)) if status.code() == tonic::Code::Unimplemented => false,
+        Err(e) if e.is_unavailable() => return Err(e),
+        _ => true,
+    };
+
+    Ok(ServerCapabilities {
+        version,
+        streaming_query,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use async_trait::async_trait;
+    use horaedbproto::storage::{
+        RouteRequest as RouteRequestPb, RouteResponse as RouteResponsePb,
+        SqlQueryRequest as QueryRequestPb, SqlQueryResponse as QueryResponsePb,
+        WriteRequest as WriteRequestPb, WriteResponse as WriteResponsePb,
+    };
+
+    use super::probe;
+    use crate::{
+        rpc_client::{RpcClient, RpcContext, SqlQueryStream},
+        Error, Result,
+    };
+
+    /// Client of the legacy server supporting neither `version()` nor the
+    /// streaming query.
+    struct LegacyClient;
+
+    #[async_trait]
+    impl RpcClient for LegacyClient {
+        async fn sql_query(&self, _: &RpcContext, _: QueryRequestPb) -> Result<QueryResponsePb> {
+            Err(Error::Rpc(tonic::Status::internal("unknown function")))
+        }
+
+        async fn write(&self, _: &RpcContext, _: WriteRequestPb) -> Result<WriteResponsePb> {
+            unimplemented!()
+        }
+
+        async fn route(&self, _: &RpcContext, _: RouteRequestPb) -> Result<RouteResponsePb> {
+            unimplemented!()
+        }
+
+        async fn sql_query_stream(
+            &self,
+            _: &RpcContext,
+            _: QueryRequestPb,
+        ) -> Result<SqlQueryStream> {
+            Err(Error::Rpc(tonic::Status::unimplemented("")))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_probe_legacy_server() {
+        let capabilities = probe(&LegacyClient, &RpcContext::default()).await.unwrap();
+        assert_eq!(capabilities.version, None);
+        assert!(!capabilities.streaming_query);
+    }
+}
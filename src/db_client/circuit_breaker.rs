@@ -0,0 +1,220 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Circuit breakers stopping the calls to the failing data nodes.
+
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::{Error, Result};
+
+/// Config of the circuit breaker of every data node.
+///
+/// The circuit is opened once the failure rate of the latest calls reaches the
+/// threshold, and the calls to the node fail fast with
+/// [`Error::CircuitOpen`] then. After `open_duration`, one call is let through
+/// as the probe, which closes the circuit if it succeeds, or opens it again
+/// otherwise.
+///
+/// Only the failures caused by the unavailable servers are counted, see
+/// [`Error::is_unavailable`].
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    /// The number of the latest calls to compute the failure rate.
+    ///
+    /// Default value is 20.
+    pub window_size: usize,
+    /// The min number of the calls in the window before the circuit can be
+    /// opened.
+    ///
+    /// Default value is 10.
+    pub min_calls: usize,
+    /// The failure rate within `(0, 1]` opening the circuit.
+    ///
+    /// Default value is 0.5.
+    pub failure_rate_threshold: f64,
+    /// How long the circuit keeps open before the probe.
+    ///
+    /// Default value is 10s.
+    pub open_duration: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            window_size: 20,
+            min_calls: 10,
+            failure_rate_threshold: 0.5,
+            open_duration: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Closed,
+    Open {
+        until: Instant,
+    },
+    /// The probe has been let through since `probe_started`.
+    HalfOpen {
+        probe_started: Instant,
+    },
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: State,
+    /// Whether the latest calls failed.
+    outcomes: VecDeque<bool>,
+    failures: usize,
+}
+
+impl Inner {
+    fn reset(&mut self, state: State) {
+        self.state = state;
+        self.outcomes.clear();
+        self.failures = 0;
+    }
+}
+
+/// The circuit breaker of one data node.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner {
+                state: State::Closed,
+                outcomes: VecDeque::new(),
+                failures: 0,
+            }),
+        }
+    }
+
+    /// Check whether the call to the `endpoint` can be made.
+    pub fn acquire(&self, endpoint: &str) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        match inner.state {
+            State::Closed => return Ok(()),
+            State::Open { until } if now >= until => {}
+            // Let another probe through if the last one is lost, e.g. the call
+            // is cancelled.
+            State::HalfOpen { probe_started }
+                if now >= probe_started + self.config.open_duration => {}
+            _ => return Err(Error::CircuitOpen(endpoint.to_string())),
+        }
+
+        inner.state = State::HalfOpen { probe_started: now };
+        Ok(())
+    }
+
+    /// Record the result of the call made after [`CircuitBreaker::acquire`].
+    pub fn record<T>(&self, result: &Result<T>) {
+        let failed = matches!(result, Err(e) if e.is_unavailable());
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            State::Closed => {
+                inner.outcomes.push_back(failed);
+                inner.failures += failed as usize;
+                if inner.outcomes.len() > self.config.window_size.max(1) {
+                    let evicted = inner.outcomes.pop_front().unwrap();
+                    inner.failures -= evicted as usize;
+                }
+
+                let calls = inner.outcomes.len();
+                let failure_rate = inner.failures as f64 / calls as f64;
+                if calls >= self.config.min_calls
+                    && failure_rate >= self.config.failure_rate_threshold
+                {
+                    let until = Instant::now() + self.config.open_duration;
+                    inner.reset(State::Open { until });
+                }
+            }
+            State::HalfOpen { .. } if failed => {
+                let until = Instant::now() + self.config.open_duration;
+                inner.reset(State::Open { until });
+            }
+            State::HalfOpen { .. } => inner.reset(State::Closed),
+            // The calls made before the circuit is opened.
+            State::Open { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{CircuitBreaker, CircuitBreakerConfig};
+    use crate::{Error, Result};
+
+    fn unavailable() -> Result<()> {
+        Err(Error::Rpc(tonic::Status::unavailable("down")))
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(CircuitBreakerConfig {
+            window_size: 4,
+            min_calls: 4,
+            failure_rate_threshold: 0.5,
+            open_duration: Duration::from_millis(20),
+        });
+        let endpoint = "127.0.0.1:8831";
+
+        // The errors of the requests themselves are not counted.
+        for _ in 0..4 {
+            breaker.acquire(endpoint).unwrap();
+            breaker.record::<()>(&Err(Error::Client("bad request".to_string())));
+        }
+        breaker.acquire(endpoint).unwrap();
+        breaker.record(&Ok(()));
+        breaker.record(&Ok(()));
+        breaker.record(&unavailable());
+        breaker.acquire(endpoint).unwrap();
+        breaker.record(&unavailable());
+        let err = breaker.acquire(endpoint).unwrap_err();
+        assert!(matches!(err, Error::CircuitOpen(_)));
+        assert!(err.is_unavailable());
+
+        // Only one probe is let through, and the failed probe opens the circuit
+        // again.
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        breaker.acquire(endpoint).unwrap();
+        assert!(breaker.acquire(endpoint).is_err());
+        breaker.record(&unavailable());
+        assert!(breaker.acquire(endpoint).is_err());
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        breaker.acquire(endpoint).unwrap();
+        breaker.record(&Ok(()));
+        for _ in 0..3 {
+            breaker.acquire(endpoint).unwrap();
+            breaker.record(&unavailable());
+        }
+        // The window is cleared once the circuit is closed.
+        breaker.acquire(endpoint).unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The client-wide limit of the concurrent calls, which lets the waiting calls
+//! of the higher [`Priority`] through first.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::oneshot;
+
+use crate::rpc_client::Priority;
+
+const PRIORITIES: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+#[derive(Debug)]
+struct LimiterState {
+    available: usize,
+    /// The waiters of every priority in the order of [`PRIORITIES`].
+    waiters: [VecDeque<oneshot::Sender<ConcurrencyPermit>>; PRIORITIES.len()],
+}
+
+/// The limiter of the concurrent calls, and the permits released are handed
+/// to the waiters of the highest priority in the FIFO order.
+#[derive(Debug)]
+pub(crate) struct ConcurrencyLimiter {
+    state: Mutex<LimiterState>,
+}
+
+/// The permit of a call, which is released when dropped.
+#[derive(Debug)]
+pub(crate) struct ConcurrencyPermit {
+    /// The limiter is taken if the permit is handed to another waiter.
+    limiter: Option<Arc<ConcurrencyLimiter>>,
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.limiter.take() {
+            limiter.release();
+        }
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(permits: usize) -> Self {
+        Self {
+            state: Mutex::new(LimiterState {
+                available: permits,
+                waiters: Default::default(),
+            }),
+        }
+    }
+
+    /// Wait for a permit, which is granted after the ones of the waiters with
+    /// the same or higher `priority`.
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> ConcurrencyPermit {
+        let waiter = {
+            let mut state = self.state.lock().unwrap();
+            if state.available > 0 {
+                state.available -= 1;
+                return ConcurrencyPermit {
+                    limiter: Some(self.clone()),
+                };
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let index = PRIORITIES.iter().position(|p| *p == priority).unwrap();
+            state.waiters[index].push_back(tx);
+            rx
+        };
+
+        // The sender is only dropped after the permit is sent.
+        waiter.await.expect("waiter is dropped without the permit")
+    }
+
+    fn release(self: Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(waiter) = state.waiters.iter_mut().find_map(VecDeque::pop_front) {
+            let permit = ConcurrencyPermit {
+                limiter: Some(self.clone()),
+            };
+            match waiter.send(permit) {
+                Ok(()) => return,
+                // The waiting is cancelled, and the permit is handed to the
+                // next waiter.
+                Err(mut permit) => {
+                    permit.limiter = None;
+                }
+            }
+        }
+        state.available += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use super::ConcurrencyLimiter;
+    use crate::rpc_client::Priority;
+
+    #[tokio::test]
+    async fn test_concurrency_limiter() {
+        let limiter = Arc::new(ConcurrencyLimiter::new(1));
+        let permit = limiter.acquire(Priority::Normal).await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut waiters = Vec::new();
+        for priority in [Priority::Low, Priority::Normal, Priority::High] {
+            let (limiter, tx) = (limiter.clone(), tx.clone());
+            waiters.push(tokio::spawn(async move {
+                let _permit = limiter.acquire(priority).await;
+                tx.send(priority).unwrap();
+            }));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // The cancelled waiting doesn't take the permit.
+        let cancelled = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(Priority::High).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        cancelled.abort();
+        assert!(cancelled.await.unwrap_err().is_cancelled());
+
+        drop(permit);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        let mut order = Vec::new();
+        while let Ok(priority) = rx.try_recv() {
+            order.push(priority);
+        }
+        assert_eq!(order, vec![Priority::High, Priority::Normal, Priority::Low]);
+
+        // The permit is returned at last.
+        drop(limiter.acquire(Priority::Low).await);
+    }
+}
@@ -0,0 +1,454 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Failover of the writes to a secondary cluster during the outage of the
+//! primary one.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use futures::future::{join, try_join};
+
+use crate::{
+    db_client::{
+        ConnectionState, DbClient, MetricsSnapshot, PausePolicy, RowBatchStream, ServerCapabilities,
+    },
+    model::{
+        route::Endpoint,
+        sql_query::{row::Row, Request as SqlQueryRequest, Response as SqlQueryResponse},
+        value::TimestampMs,
+        write::{Request as WriteRequest, Response as WriteResponse},
+    },
+    rpc_client::{RpcClient, RpcContext},
+    Error, Result,
+};
+
+/// The marker of the data of one table written to the secondary cluster, which
+/// tells the operators the time range to catch up into the primary cluster.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FailoverMarker {
+    pub table: String,
+    /// The min timestamp of the points written to the secondary cluster.
+    pub min_timestamp: TimestampMs,
+    /// The max timestamp of the points written to the secondary cluster.
+    pub max_timestamp: TimestampMs,
+    /// The wall time of the first failover write.
+    pub first_failover_at: SystemTime,
+    /// The wall time of the last failover write.
+    pub last_failover_at: SystemTime,
+}
+
+/// A [`DbClient`] writing to the primary cluster, and redirecting the writes
+/// to the secondary cluster once the primary one has been unavailable for
+/// longer than the threshold.
+///
+/// The primary cluster is always tried first, so the writes go back to it
+/// automatically once it recovers. The queries are always served by the
+/// primary cluster.
+pub struct FailoverClient {
+    primary: Arc<dyn DbClient>,
+    secondary: Arc<dyn DbClient>,
+    threshold: Duration,
+    // The time when the primary cluster is found unavailable.
+    outage_since: Mutex<Option<Instant>>,
+    markers: Mutex<BTreeMap<String, FailoverMarker>>,
+}
+
+impl FailoverClient {
+    pub fn new(
+        primary: Arc<dyn DbClient>,
+        secondary: Arc<dyn DbClient>,
+        threshold: Duration,
+    ) -> Self {
+        Self {
+            primary,
+            secondary,
+            threshold,
+            outage_since: Mutex::new(None),
+            markers: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// The markers of the tables written to the secondary cluster so far.
+    pub fn markers(&self) -> Vec<FailoverMarker> {
+        self.markers.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Take the markers, e.g. after the data has been caught up.
+    pub fn take_markers(&self) -> Vec<FailoverMarker> {
+        let markers = std::mem::take(&mut *self.markers.lock().unwrap());
+        markers.into_values().collect()
+    }
+
+    /// Whether the writes should fail over, and the outage is recorded if
+    /// it's the first failure.
+    fn should_failover(&self) -> bool {
+        let mut outage_since = self.outage_since.lock().unwrap();
+        let since = outage_since.get_or_insert_with(Instant::now);
+        since.elapsed() >= self.threshold
+    }
+
+    fn record_markers(&self, req: &WriteRequest) {
+        let now = SystemTime::now();
+        let mut markers = self.markers.lock().unwrap();
+        for (table, points) in &req.point_groups {
+            for point in points {
+                let marker = markers
+                    .entry(table.clone())
+                    .or_insert_with(|| FailoverMarker {
+                        table: table.clone(),
+                        min_timestamp: point.timestamp,
+                        max_timestamp: point.timestamp,
+                        first_failover_at: now,
+                        last_failover_at: now,
+                    });
+                marker.min_timestamp = marker.min_timestamp.min(point.timestamp);
+                marker.max_timestamp = marker.max_timestamp.max(point.timestamp);
+                marker.last_failover_at = now;
+            }
+        }
+    }
+
+    async fn write_secondary(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+        let resp = self.secondary.write(ctx, req).await?;
+        self.record_markers(req);
+        Ok(resp)
+    }
+}
+
+#[async_trait]
+impl DbClient for FailoverClient {
+    async fn sql_query(&self, ctx: &RpcContext, req: &SqlQueryRequest) -> Result<SqlQueryResponse> {
+        self.primary.sql_query(ctx, req).await
+    }
+
+    async fn sql_query_for_each(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+        on_row: &mut (dyn FnMut(Row) + Send),
+    ) -> Result<u32> {
+        self.primary.sql_query_for_each(ctx, req, on_row).await
+    }
+
+    async fn sql_query_stream(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+    ) -> Result<RowBatchStream> {
+        self.primary.sql_query_stream(ctx, req).await
+    }
+
+    async fn health_check(&self, ctx: &RpcContext) -> Result<()> {
+        self.primary.health_check(ctx).await
+    }
+
+    /// Warm up both the clients, so that the failover doesn't pay the latency
+    /// either.
+    async fn warm_up(&self, ctx: &RpcContext, tables: &[String]) -> Result<()> {
+        try_join(
+            self.primary.warm_up(ctx, tables),
+            self.secondary.warm_up(ctx, tables),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    async fn prefetch(&self, ctx: &RpcContext, tables: &[String]) -> Result<()> {
+        try_join(
+            self.primary.prefetch(ctx, tables),
+            self.secondary.prefetch(ctx, tables),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    fn clear_routes(&self) {
+        self.primary.clear_routes();
+        self.secondary.clear_routes();
+    }
+
+    /// The raw client of the primary, which is not failed over.
+    async fn route(&self, ctx: &RpcContext, tables: &[String]) -> Result<Vec<Option<Endpoint>>> {
+        self.primary.route(ctx, tables).await
+    }
+
+    async fn raw(&self, ctx: &RpcContext, table: Option<&str>) -> Result<Arc<dyn RpcClient>> {
+        self.primary.raw(ctx, table).await
+    }
+
+    async fn capabilities(&self, ctx: &RpcContext) -> Result<ServerCapabilities> {
+        self.primary.capabilities(ctx).await
+    }
+
+    /// The states of the connections of both the clients.
+    fn connection_states(&self) -> Vec<ConnectionState> {
+        let mut states = self.primary.connection_states();
+        states.extend(self.secondary.connection_states());
+        states
+    }
+
+    /// The metrics of the primary and secondary clients are merged.
+    fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.primary
+            .metrics_snapshot()
+            .merge(self.secondary.metrics_snapshot())
+    }
+
+    fn pause(&self, policy: PausePolicy) {
+        self.primary.pause(policy);
+        self.secondary.pause(policy);
+    }
+
+    fn resume(&self) {
+        self.primary.resume();
+        self.secondary.resume();
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> Result<()> {
+        let (primary, secondary) = join(
+            self.primary.shutdown(timeout),
+            self.secondary.shutdown(timeout),
+        )
+        .await;
+        primary.and(secondary)
+    }
+
+    /// The write is checked by the primary.
+    async fn dry_run_write(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+        self.primary.dry_run_write(ctx, req).await
+    }
+
+    async fn write(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+        let err = match self.primary.write(ctx, req).await {
+            Ok(resp) => {
+                *self.outage_since.lock().unwrap() = None;
+                return Ok(resp);
+            }
+            Err(e) => e,
+        };
+
+        match err {
+            e if e.is_unavailable() => {
+                if !self.should_failover() {
+                    return Err(e);
+                }
+                self.write_secondary(ctx, req).await
+            }
+            // Only the tables failed for the unavailable servers are redirected.
+            Error::RouteBasedWriteError(mut e)
+                if e.errors.iter().any(|(_, err)| err.is_unavailable()) =>
+            {
+                if !self.should_failover() {
+                    return Err(Error::RouteBasedWriteError(e));
+                }
+
+                let (unavailable, others): (Vec<_>, Vec<_>) = e
+                    .errors
+                    .into_iter()
+                    .partition(|(_, err)| err.is_unavailable());
+                e.errors = others;
+
+                let failover_tables = unavailable
+                    .into_iter()
+                    .flat_map(|(tables, _)| tables)
+                    .collect::<Vec<_>>();
+                let failover_req = req.select_tables(&failover_tables);
+
+                let mut results = vec![(e.ok.0, Ok(e.ok.1))];
+                results.extend(e.errors.into_iter().map(|(tables, err)| (tables, Err(err))));
+                results.push((
+                    failover_tables,
+                    self.write_secondary(ctx, &failover_req).await,
+                ));
+                crate::db_client::merge_write_results(results)
+            }
+            e => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+
+    use super::FailoverClient;
+    use crate::{
+        db_client::{DbClient, PausePolicy},
+        errors::RouteBasedWriteError,
+        model::{value::Value, write::point::PointBuilder},
+        Error, Result, RpcContext, SqlQueryRequest, SqlQueryResponse, WriteRequest, WriteResponse,
+    };
+
+    /// Client returning the scripted write results in order, and succeeding
+    /// once no result is left.
+    #[derive(Default)]
+    struct ScriptedClient {
+        results: Mutex<VecDeque<Result<WriteResponse>>>,
+        writes: Mutex<Vec<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl DbClient for ScriptedClient {
+        async fn sql_query(&self, _: &RpcContext, _: &SqlQueryRequest) -> Result<SqlQueryResponse> {
+            unimplemented!()
+        }
+
+        async fn health_check(&self, _: &RpcContext) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn pause(&self, _: PausePolicy) {
+            unimplemented!()
+        }
+
+        fn resume(&self) {
+            unimplemented!()
+        }
+
+        async fn write(&self, _: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+            let mut tables = req.point_groups.keys().cloned().collect::<Vec<_>>();
+            tables.sort();
+            self.writes.lock().unwrap().push(tables);
+            let points = req.point_groups.values().map(Vec::len).sum::<usize>();
+            self.results
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or(Ok(WriteResponse::new(points as u32, 0)))
+        }
+    }
+
+    fn unavailable() -> Error {
+        Error::Rpc(tonic::Status::unavailable("down"))
+    }
+
+    fn make_request(tables: &[(&str, i64)]) -> WriteRequest {
+        let mut req = WriteRequest::default();
+        for (table, ts) in tables {
+            req.add_point(
+                PointBuilder::new(*table)
+                    .timestamp(*ts)
+                    .field("value", Value::Int64(0))
+                    .build()
+                    .unwrap(),
+            );
+        }
+        req
+    }
+
+    #[tokio::test]
+    async fn test_failover_after_threshold() {
+        let primary = Arc::new(ScriptedClient::default());
+        let secondary = Arc::new(ScriptedClient::default());
+        let client = FailoverClient::new(primary.clone(), secondary.clone(), Duration::ZERO);
+        let ctx = RpcContext::default();
+
+        primary.results.lock().unwrap().extend([
+            Err(unavailable()),
+            Err(unavailable()),
+            Err(Error::Client("bad request".to_string())),
+        ]);
+        client
+            .write(&ctx, &make_request(&[("t1", 100), ("t1", 300)]))
+            .await
+            .unwrap();
+        client
+            .write(&ctx, &make_request(&[("t1", 200), ("t1", 500)]))
+            .await
+            .unwrap();
+        // The errors unrelated to the availability are returned as is.
+        let err = client
+            .write(&ctx, &make_request(&[("t2", 100)]))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Client(_)));
+
+        let markers = client.take_markers();
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].table, "t1");
+        assert_eq!(
+            (markers[0].min_timestamp, markers[0].max_timestamp),
+            (100, 500)
+        );
+        assert_eq!(secondary.writes.lock().unwrap().len(), 2);
+        assert!(client.markers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failover_within_threshold() {
+        let primary = Arc::new(ScriptedClient::default());
+        let secondary = Arc::new(ScriptedClient::default());
+        let client = FailoverClient::new(
+            primary.clone(),
+            secondary.clone(),
+            Duration::from_secs(3600),
+        );
+
+        primary
+            .results
+            .lock()
+            .unwrap()
+            .push_back(Err(unavailable()));
+        let err = client
+            .write(&RpcContext::default(), &make_request(&[("t1", 100)]))
+            .await
+            .unwrap_err();
+        assert!(err.is_unavailable());
+        assert!(secondary.writes.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failover_partial_tables() {
+        let primary = Arc::new(ScriptedClient::default());
+        let secondary = Arc::new(ScriptedClient::default());
+        let client = FailoverClient::new(primary.clone(), secondary.clone(), Duration::ZERO);
+
+        let partial = RouteBasedWriteError::from(vec![
+            (vec!["t1".to_string()], Ok(WriteResponse::new(1, 0))),
+            (vec!["t2".to_string()], Err(unavailable())),
+        ]);
+        primary
+            .results
+            .lock()
+            .unwrap()
+            .push_back(Err(Error::RouteBasedWriteError(partial)));
+        let resp = client
+            .write(
+                &RpcContext::default(),
+                &make_request(&[("t1", 100), ("t2", 100), ("t2", 200)]),
+            )
+            .await
+            .unwrap();
+        assert_eq!((resp.success, resp.failed), (3, 0));
+        assert_eq!(
+            *secondary.writes.lock().unwrap(),
+            vec![vec!["t2".to_string()]]
+        );
+        assert_eq!(client.markers()[0].table, "t2");
+    }
+}
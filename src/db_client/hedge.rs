@@ -0,0 +1,161 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Hedging of the idempotent queries to cut the tail latency.
+
+use std::{future::Future, time::Duration};
+
+use futures::{
+    future::{self, Either},
+    pin_mut,
+};
+
+use crate::Result;
+
+/// Config of firing a second identical query to another replica of the table
+/// if the first one doesn't finish within the `delay`, and the first
+/// successful response of them is returned.
+///
+/// Only the read-only queries (e.g. `SELECT`) opting in by
+/// [`RpcContext::hedge`](crate::RpcContext::hedge) are hedged, as the others
+/// are not idempotent. The replicas are only reported by the custom router
+/// set by [`Builder::router`](crate::Builder::router), as the route response
+/// of the server carries one endpoint per table, so the queries are never
+/// hedged without it.
+#[derive(Debug, Clone)]
+pub struct HedgingConfig {
+    /// The delay before firing the hedged query, which is usually set to the
+    /// high percentile (e.g. p95) of the query latency.
+    pub delay: Duration,
+}
+
+impl HedgingConfig {
+    pub fn new(delay: Duration) -> Self {
+        Self { delay }
+    }
+}
+
+/// Whether the `sql` is read-only, and can be sent multiple times safely.
+pub(crate) fn is_idempotent(sql: &str) -> bool {
+    const READ_ONLY_KEYWORDS: [&str; 6] = ["SELECT", "WITH", "SHOW", "DESCRIBE", "DESC", "EXPLAIN"];
+
+    let first_word = skip_comments(sql)
+        .split(|c: char| c.is_whitespace() || c == '(')
+        .next()
+        .unwrap_or_default();
+    READ_ONLY_KEYWORDS
+        .iter()
+        .any(|keyword| first_word.eq_ignore_ascii_case(keyword))
+}
+
+/// The `sql` without the leading whitespaces and comments.
+fn skip_comments(mut sql: &str) -> &str {
+    loop {
+        sql = sql.trim_start();
+        if let Some(rest) = sql.strip_prefix("--") {
+            sql = rest.split_once('\n').map_or("", |(_, rest)| rest);
+        } else if let Some(rest) = sql.strip_prefix("/*") {
+            sql = rest.split_once("*/").map_or("", |(_, rest)| rest);
+        } else {
+            return sql;
+        }
+    }
+}
+
+/// Run the `primary`, and race it with the `hedge` if it's not finished within
+/// the `delay`.
+///
+/// The error of the `primary` is returned if both of them fail, and the
+/// `primary` failing before the `delay` is not hedged.
+pub(crate) async fn hedge<T, P, H>(delay: Duration, primary: P, hedge: H) -> Result<T>
+where
+    P: Future<Output = Result<T>>,
+    H: Future<Output = Result<T>>,
+{
+    pin_mut!(primary);
+    let timer = tokio::time::sleep(delay);
+    pin_mut!(timer);
+    let primary = match future::select(primary, timer).await {
+        Either::Left((result, _)) => return result,
+        Either::Right((_, primary)) => primary,
+    };
+
+    pin_mut!(hedge);
+    match future::select(primary, hedge).await {
+        Either::Left((Ok(resp), _)) | Either::Right((Ok(resp), _)) => Ok(resp),
+        Either::Left((Err(e), hedge)) => hedge.await.map_err(|_| e),
+        Either::Right((Err(_), primary)) => primary.await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{hedge, is_idempotent};
+    use crate::{Error, Result};
+
+    async fn respond(delay_ms: u64, result: Result<&'static str>) -> Result<&'static str> {
+        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+        result
+    }
+
+    fn unavailable() -> Result<&'static str> {
+        Err(Error::Rpc(tonic::Status::unavailable("down")))
+    }
+
+    #[test]
+    fn test_is_idempotent() {
+        assert!(is_idempotent("select * from t"));
+        assert!(is_idempotent("  SHOW CREATE TABLE t"));
+        assert!(is_idempotent("SELECT(1)"));
+        assert!(is_idempotent("WITH t1 AS (SELECT 1) SELECT * FROM t1"));
+        assert!(is_idempotent("-- top n\n/* hosts */ SELECT * FROM t"));
+        assert!(!is_idempotent("/* SELECT */ DELETE FROM t"));
+        assert!(!is_idempotent("-- SELECT"));
+        assert!(!is_idempotent("INSERT INTO t (v) VALUES (1)"));
+        assert!(!is_idempotent("selected"));
+        assert!(!is_idempotent(""));
+    }
+
+    #[tokio::test]
+    async fn test_hedge() {
+        let delay = Duration::from_millis(20);
+
+        // The fast primary is not hedged.
+        let resp = hedge(delay, respond(0, Ok("primary")), async {
+            panic!("hedge should not be polled")
+        })
+        .await;
+        assert_eq!(resp.unwrap(), "primary");
+
+        // The faster hedge wins.
+        let resp = hedge(delay, respond(500, Ok("primary")), respond(0, Ok("hedge"))).await;
+        assert_eq!(resp.unwrap(), "hedge");
+
+        // The failed hedge falls back to the primary.
+        let resp = hedge(delay, respond(50, Ok("primary")), respond(0, unavailable())).await;
+        assert_eq!(resp.unwrap(), "primary");
+
+        // The error of the primary is returned if both fail.
+        let primary = respond(30, Err(Error::Client("primary".to_string())));
+        let err = hedge(delay, primary, respond(50, unavailable()))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Client(msg) if msg == "primary"));
+    }
+}
@@ -15,18 +15,35 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::sync::Arc;
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
+use futures::{stream::BoxStream, StreamExt};
 use horaedbproto::storage;
-use tokio::sync::OnceCell;
+use prost::Message;
+use tokio::{sync::OnceCell, time::Instant};
 
 use crate::{
+    db_client::{
+        concurrency::{ConcurrencyLimiter, ConcurrencyPermit},
+        metrics::ClientMetrics,
+        rate_limit::WriteRateLimiter,
+        shutdown::ShutdownGate,
+        slow::{RequestSubject, SlowRequestLog},
+        state::{ConnectionState, ConnectionTracker},
+    },
     model::{
-        sql_query::{Request as SqlQueryRequest, Response as SqlQueryResponse},
+        sql_query::{
+            response::for_each_row, row::Row, Request as SqlQueryRequest,
+            Response as SqlQueryResponse,
+        },
         write::{Request as WriteRequest, Response as WriteResponse, WriteTableRequestPbsBuilder},
     },
-    rpc_client::{RpcClient, RpcClientFactory, RpcContext},
-    Result,
+    rpc_client::{Priority, RpcClient, RpcClientFactory, RpcContext},
+    util::traced,
+    Error, Result,
 };
 
 /// Inner client for both standalone and route based modes.
@@ -35,7 +52,18 @@ use crate::{
 pub(crate) struct InnerClient<F: RpcClientFactory> {
     factory: Arc<F>,
     endpoint: String,
-    inner_client: OnceCell<Arc<dyn RpcClient>>,
+    /// The cell of the rpc client, which is replaced by an empty one on close
+    /// to release the connection.
+    inner_client: RwLock<Arc<OnceCell<Arc<dyn RpcClient>>>>,
+    tracker: ConnectionTracker,
+    rate_limiter: Option<Arc<WriteRateLimiter>>,
+    /// The client-wide limit of the concurrent queries and writes.
+    concurrency_limit: Option<Arc<ConcurrencyLimiter>>,
+    /// The client-wide limit of the concurrent queries.
+    query_concurrency_limit: Option<Arc<ConcurrencyLimiter>>,
+    shutdown_gate: Arc<ShutdownGate>,
+    metrics: Arc<ClientMetrics>,
+    slow_request_log: Option<Arc<SlowRequestLog>>,
 }
 
 impl<F: RpcClientFactory> InnerClient<F> {
@@ -43,13 +71,185 @@ impl<F: RpcClientFactory> InnerClient<F> {
         InnerClient {
             factory,
             endpoint,
-            inner_client: OnceCell::new(),
+            inner_client: RwLock::default(),
+            tracker: ConnectionTracker::default(),
+            rate_limiter: None,
+            concurrency_limit: None,
+            query_concurrency_limit: None,
+            shutdown_gate: Arc::default(),
+            metrics: Arc::default(),
+            slow_request_log: None,
+        }
+    }
+
+    /// Limit the rate of the writes by the `rate_limiter`, which may be shared
+    /// by the clients of the other endpoints.
+    pub fn with_rate_limiter(mut self, rate_limiter: Option<Arc<WriteRateLimiter>>) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// Limit the concurrent queries and writes by the permits of the
+    /// `concurrency_limit`, which may be shared by the clients of the other
+    /// endpoints.
+    pub fn with_concurrency_limit(
+        mut self,
+        concurrency_limit: Option<Arc<ConcurrencyLimiter>>,
+    ) -> Self {
+        self.concurrency_limit = concurrency_limit;
+        self
+    }
+
+    /// Limit the concurrent queries by the `query_concurrency_limit`, which may
+    /// be shared by the clients of the other endpoints.
+    pub fn with_query_concurrency_limit(
+        mut self,
+        query_concurrency_limit: Option<Arc<ConcurrencyLimiter>>,
+    ) -> Self {
+        self.query_concurrency_limit = query_concurrency_limit;
+        self
+    }
+
+    /// Reject the queries and writes once the `shutdown_gate` is closed, which
+    /// may be shared by the clients of the other endpoints.
+    pub fn with_shutdown_gate(mut self, shutdown_gate: Arc<ShutdownGate>) -> Self {
+        self.shutdown_gate = shutdown_gate;
+        self
+    }
+
+    /// Record the calls in the `metrics`, which may be shared by the clients
+    /// of the other endpoints.
+    pub fn with_metrics(mut self, metrics: Arc<ClientMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Report the slow calls to the `slow_request_log` if any.
+    pub fn with_slow_request_log(mut self, slow_request_log: Option<Arc<SlowRequestLog>>) -> Self {
+        self.slow_request_log = slow_request_log;
+        self
+    }
+
+    /// Close the connection cached by the factory and the client, and it's
+    /// closed once the calls in flight on it are all finished.
+    pub fn close(&self) {
+        self.factory.evict(&self.endpoint);
+        *self.inner_client.write().unwrap() = Arc::default();
+    }
+
+    /// Wait for the permit of the concurrency `limit` if any in the order of
+    /// the priority of the `ctx`, and the waiting is bounded by the timeout and
+    /// the deadline of the `ctx` if set.
+    async fn acquire(
+        limit: &Option<Arc<ConcurrencyLimiter>>,
+        method: &'static str,
+        ctx: &RpcContext,
+    ) -> Result<Option<ConcurrencyPermit>> {
+        let Some(limit) = limit else {
+            return Ok(None);
+        };
+
+        let acquire = traced!(
+            limit.acquire(ctx.priority.unwrap_or(Priority::Normal)),
+            "horaedb.queue",
+            reason = "concurrency_limit"
+        );
+        let permit = match (ctx.timeout, ctx.deadline) {
+            (None, None) => acquire.await,
+            _ => {
+                let timeout = ctx.remaining_timeout(method, Duration::MAX)?;
+                tokio::time::timeout(timeout, acquire).await.map_err(|_| {
+                    Error::Rpc(tonic::Status::deadline_exceeded(format!(
+                        "too many concurrent calls, method:{method}"
+                    )))
+                })?
+            }
+        };
+        Ok(Some(permit))
+    }
+
+    /// Record the result of the call of the `method` started at `start`.
+    fn record<T>(
+        &self,
+        method: &'static str,
+        start: Instant,
+        result: &Result<T>,
+        subject: RequestSubject<'_>,
+    ) {
+        let elapsed = start.elapsed();
+        self.tracker.record(result);
+        self.metrics
+            .record_call(method, &self.endpoint, elapsed, result.is_ok());
+        if let Some(log) = &self.slow_request_log {
+            log.observe(method, &self.endpoint, elapsed, subject);
         }
     }
 
-    #[inline]
     async fn init(&self) -> Result<Arc<dyn RpcClient>> {
-        self.factory.build(self.endpoint.clone()).await
+        let _guard = self.tracker.connecting();
+        let result = self.factory.build(self.endpoint.clone()).await;
+        self.tracker.record_connect(&result);
+        result
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// The state of the connection to the endpoint.
+    pub fn state(&self) -> ConnectionState {
+        self.tracker.state(
+            &self.endpoint,
+            self.inner_client.read().unwrap().initialized(),
+        )
+    }
+
+    /// The rpc client built once, which is shared by the calls.
+    async fn client_handle(&self) -> Result<Arc<dyn RpcClient>> {
+        let cell = self.inner_client.read().unwrap().clone();
+        cell.get_or_try_init(|| self.init()).await.cloned()
+    }
+
+    /// Establish the connection if not yet.
+    pub async fn connect(&self) -> Result<()> {
+        self.rpc_client().await.map(|_| ())
+    }
+
+    /// Establish the connection if not yet, and fail if it can't be done
+    /// before the `deadline`.
+    pub async fn connect_before(&self, deadline: Instant) -> Result<()> {
+        let connect = self.rpc_client();
+        match tokio::time::timeout_at(deadline, connect).await {
+            Ok(result) => result.map(|_| ()),
+            Err(_) => Err(Error::Connect {
+                addr: self.endpoint.clone(),
+                source: "connect budget is exhausted".into(),
+            }),
+        }
+    }
+
+    /// The underlying rpc client, which is built if not yet, and fails with
+    /// [`Error::Shutdown`] once the client is shut down.
+    pub async fn rpc_client(&self) -> Result<Arc<dyn RpcClient>> {
+        if self.shutdown_gate.is_closed() {
+            return Err(Error::Shutdown);
+        }
+        self.client_handle().await
+    }
+
+    pub async fn health_check_internal(&self, ctx: &RpcContext) -> Result<()> {
+        let _in_flight = self.shutdown_gate.enter()?;
+        let client_handle = self.client_handle().await?;
+        let start = Instant::now();
+        let result = traced!(
+            client_handle.health_check(ctx),
+            "horaedb.rpc",
+            method = "health_check",
+            endpoint = %self.endpoint
+        )
+        .await;
+        self.record("health_check", start, &result, RequestSubject::None);
+        result
     }
 
     pub async fn sql_query_internal(
@@ -57,23 +257,96 @@ impl<F: RpcClientFactory> InnerClient<F> {
         ctx: &RpcContext,
         req: &SqlQueryRequest,
     ) -> Result<SqlQueryResponse> {
+        self.sql_query_pb_internal(ctx, req)
+            .await
+            .and_then(SqlQueryResponse::try_from)
+    }
+
+    /// Feed the rows to `on_row` while decoding them, and return the affected
+    /// rows.
+    pub async fn sql_query_for_each_internal(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+        on_row: &mut (dyn FnMut(Row) + Send),
+    ) -> Result<u32> {
+        let resp_pb = self.sql_query_pb_internal(ctx, req).await?;
+        for_each_row(resp_pb, on_row)
+    }
+
+    /// Query by the streaming rpc, and the responses are decoded one by one.
+    pub async fn sql_query_stream_internal(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+    ) -> Result<BoxStream<'static, Result<SqlQueryResponse>>> {
+        let in_flight = self.shutdown_gate.enter()?;
+        let client_handle = self.client_handle().await?;
+        let req_pb = Self::make_query_request_pb(ctx, req);
+        // The permits and the in-flight guard are held until the stream is
+        // dropped.
+        let query_permit =
+            Self::acquire(&self.query_concurrency_limit, "sql_query_stream", ctx).await?;
+        let permit = Self::acquire(&self.concurrency_limit, "sql_query_stream", ctx).await?;
+        let start = Instant::now();
+        let result = traced!(
+            client_handle.sql_query_stream(ctx, req_pb),
+            "horaedb.rpc",
+            method = "sql_query_stream",
+            endpoint = %self.endpoint
+        )
+        .await;
+        self.record(
+            "sql_query_stream",
+            start,
+            &result,
+            RequestSubject::Query(req),
+        );
+        let stream = result?;
+
+        Ok(stream
+            .map(move |resp_pb| {
+                let _permits = (&query_permit, &permit);
+                let _in_flight = &in_flight;
+                resp_pb.and_then(SqlQueryResponse::try_from)
+            })
+            .boxed())
+    }
+
+    async fn sql_query_pb_internal(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+    ) -> Result<storage::SqlQueryResponse> {
+        let _in_flight = self.shutdown_gate.enter()?;
+        let client_handle = self.client_handle().await?;
+        let req_pb = Self::make_query_request_pb(ctx, req);
+
+        let _query_permit = Self::acquire(&self.query_concurrency_limit, "sql_query", ctx).await?;
+        let _permit = Self::acquire(&self.concurrency_limit, "sql_query", ctx).await?;
+        let start = Instant::now();
+        let result = traced!(
+            client_handle.as_ref().sql_query(ctx, req_pb),
+            "horaedb.rpc",
+            method = "sql_query",
+            endpoint = %self.endpoint
+        )
+        .await;
+        self.record("sql_query", start, &result, RequestSubject::Query(req));
+        result
+    }
+
+    fn make_query_request_pb(ctx: &RpcContext, req: &SqlQueryRequest) -> storage::SqlQueryRequest {
         assert!(ctx.database.is_some());
 
-        let client_handle = self.inner_client.get_or_try_init(|| self.init()).await?;
         let req_ctx = storage::RequestContext {
             database: ctx.database.clone().unwrap(),
         };
-        let req_pb = storage::SqlQueryRequest {
+        storage::SqlQueryRequest {
             context: Some(req_ctx),
             tables: req.tables.clone(),
             sql: req.sql.clone(),
-        };
-
-        client_handle
-            .as_ref()
-            .sql_query(ctx, req_pb)
-            .await
-            .and_then(SqlQueryResponse::try_from)
+        }
     }
 
     pub async fn write_internal(
@@ -83,7 +356,8 @@ impl<F: RpcClientFactory> InnerClient<F> {
     ) -> Result<WriteResponse> {
         assert!(ctx.database.is_some());
 
-        let client_handle = self.inner_client.get_or_try_init(|| self.init()).await?;
+        let _in_flight = self.shutdown_gate.enter()?;
+        let client_handle = self.client_handle().await?;
         let req_ctx = storage::RequestContext {
             database: ctx.database.clone().unwrap(),
         };
@@ -92,10 +366,115 @@ impl<F: RpcClientFactory> InnerClient<F> {
             context: Some(req_ctx),
             table_requests: write_table_request_pbs,
         };
+        let bytes = req_pb.encoded_len() as u64;
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let rows = req.point_groups.values().map(Vec::len).sum::<usize>();
+            traced!(
+                rate_limiter.acquire(rows as u64, bytes),
+                "horaedb.queue",
+                reason = "rate_limit"
+            )
+            .await?;
+        }
+
+        let _permit = Self::acquire(&self.concurrency_limit, "write", ctx).await?;
+        let start = Instant::now();
+        let result = traced!(
+            client_handle.write(ctx, req_pb),
+            "horaedb.rpc",
+            method = "write",
+            endpoint = %self.endpoint
+        )
+        .await;
+        self.record("write", start, &result, RequestSubject::Write(req));
+        let resp: WriteResponse = result?.into();
+        self.metrics.record_write(resp.success as u64, bytes);
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{atomic::Ordering, Arc},
+        time::Duration,
+    };
+
+    use super::InnerClient;
+    use crate::{
+        db_client::{concurrency::ConcurrencyLimiter, shutdown::ShutdownGate},
+        model::sql_query::Request as SqlQueryRequest,
+        rpc_client::{MockRpcClientFactory, RpcContext},
+        Error,
+    };
+
+    #[tokio::test]
+    async fn test_concurrency_limit() {
+        let limit = Some(Arc::new(ConcurrencyLimiter::new(1)));
+
+        let ctx = RpcContext {
+            timeout: Some(Duration::from_millis(10)),
+            ..Default::default()
+        };
+        let acquire = || InnerClient::<MockRpcClientFactory>::acquire(&limit, "write", &ctx);
+        let permit = acquire().await.unwrap();
+        assert!(permit.is_some());
+        let err = acquire().await.unwrap_err();
+        assert!(
+            matches!(err, Error::Rpc(status) if status.code() == tonic::Code::DeadlineExceeded)
+        );
+
+        drop(permit);
+        assert!(acquire().await.unwrap().is_some());
+        let permit = InnerClient::<MockRpcClientFactory>::acquire(&None, "write", &ctx).await;
+        assert!(permit.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let gate = Arc::new(ShutdownGate::default());
+        let client = InnerClient::new(factory, "127.0.0.1:8831".to_string())
+            .with_shutdown_gate(gate.clone());
+
+        let ctx = RpcContext::default().database("public".to_string());
+        let req = SqlQueryRequest {
+            tables: vec!["t".to_string()],
+            sql: "select * from t".to_string(),
+        };
+        let stream = client.sql_query_stream_internal(&ctx, &req).await.unwrap();
+
+        // The stream in flight is drained before the shutdown finishes.
+        let shutdown = tokio::spawn(async move { gate.shutdown(Duration::from_secs(10)).await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!shutdown.is_finished());
+        let err = client.sql_query_internal(&ctx, &req).await.unwrap_err();
+        assert!(matches!(err, Error::Shutdown));
+
+        drop(stream);
+        shutdown.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_close() {
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let gate = Arc::new(ShutdownGate::default());
+        let client = InnerClient::new(factory.clone(), "127.0.0.1:8831".to_string())
+            .with_shutdown_gate(gate.clone());
+        client.connect().await.unwrap();
+        assert!(client.inner_client.read().unwrap().initialized());
 
-        client_handle
-            .write(ctx, req_pb)
+        // The connection is released, and not built again once shut down.
+        let _ = gate.shutdown(Duration::ZERO).await;
+        client.close();
+        assert!(!client.inner_client.read().unwrap().initialized());
+        let err = client.connect().await.unwrap_err();
+        assert!(matches!(err, Error::Shutdown));
+        let err = client
+            .health_check_internal(&RpcContext::default())
             .await
-            .map(|resp_pb| resp_pb.into())
+            .unwrap_err();
+        assert!(matches!(err, Error::Shutdown));
+        assert_eq!(factory.builds.load(Ordering::Relaxed), 1);
     }
 }
@@ -0,0 +1,349 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Built-in metrics of the calls made by the client, which are exposed by
+//! [`DbClient::metrics_snapshot`](crate::DbClient::metrics_snapshot) for the
+//! host application to scrape.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use dashmap::DashMap;
+
+/// The upper bounds of the buckets of the latency histograms, and the last
+/// bucket holds the latencies above all the bounds.
+const LATENCY_BOUNDS_MS: [u64; 13] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// The histogram of the latencies of one operation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    /// The `(upper_bound, count)` of the buckets in ascending order, and the
+    /// bound of the last bucket is [`Duration::MAX`].
+    pub buckets: Vec<(Duration, u64)>,
+    /// The number of the observed calls.
+    pub count: u64,
+    /// The total latency of the observed calls.
+    pub sum: Duration,
+}
+
+impl LatencyHistogram {
+    /// The estimated `q` quantile, i.e. the upper bound of the bucket where
+    /// the quantile falls in, and `None` if nothing is observed.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = (q.clamp(0.0, 1.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        self.buckets.iter().find_map(|(bound, count)| {
+            seen += count;
+            (seen >= rank).then_some(*bound)
+        })
+    }
+
+    fn merge(&mut self, other: &LatencyHistogram) {
+        if self.buckets.is_empty() {
+            self.buckets = other.buckets.clone();
+        } else {
+            for (bucket, (_, count)) in self.buckets.iter_mut().zip(&other.buckets) {
+                bucket.1 += count;
+            }
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+    }
+}
+
+/// The metrics of one operation, e.g. `sql_query` or `write`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OperationMetrics {
+    pub operation: &'static str,
+    pub latency: LatencyHistogram,
+}
+
+/// The counts of the calls to one endpoint.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EndpointMetrics {
+    pub endpoint: String,
+    pub requests: u64,
+    pub errors: u64,
+}
+
+/// The metrics of the routing of the tables in the `Direct` mode.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RouteMetrics {
+    /// The number of the tables found in the route cache.
+    pub hits: u64,
+    /// The number of the tables missing in the route cache or expired, which
+    /// are routed by the route rpcs.
+    pub misses: u64,
+    /// The number of the tables evicted from the route cache, e.g. by the
+    /// failed requests.
+    pub evictions: u64,
+    /// The latency of the route rpcs.
+    pub rpc_latency: LatencyHistogram,
+}
+
+/// The snapshot of the metrics recorded since the client is built.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// The metrics of the operations sorted by the names.
+    pub operations: Vec<OperationMetrics>,
+    /// The metrics of the endpoints sorted by the endpoints.
+    pub endpoints: Vec<EndpointMetrics>,
+    /// The number of the rows written successfully.
+    pub rows_written: u64,
+    /// The encoded size of the write requests written successfully.
+    pub bytes_written: u64,
+    pub routes: RouteMetrics,
+}
+
+impl MetricsSnapshot {
+    /// The metrics of the `operation` if it's ever called.
+    pub fn operation(&self, operation: &str) -> Option<&OperationMetrics> {
+        self.operations.iter().find(|m| m.operation == operation)
+    }
+
+    /// Merge the snapshot of another client, e.g. of the failover one.
+    pub(crate) fn merge(mut self, other: MetricsSnapshot) -> MetricsSnapshot {
+        for metrics in other.operations {
+            match self
+                .operations
+                .iter_mut()
+                .find(|m| m.operation == metrics.operation)
+            {
+                Some(m) => m.latency.merge(&metrics.latency),
+                None => self.operations.push(metrics),
+            }
+        }
+        for metrics in other.endpoints {
+            match self
+                .endpoints
+                .iter_mut()
+                .find(|m| m.endpoint == metrics.endpoint)
+            {
+                Some(m) => {
+                    m.requests += metrics.requests;
+                    m.errors += metrics.errors;
+                }
+                None => self.endpoints.push(metrics),
+            }
+        }
+        self.operations.sort_by_key(|m| m.operation);
+        self.endpoints.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+        self.rows_written += other.rows_written;
+        self.bytes_written += other.bytes_written;
+        self.routes.hits += other.routes.hits;
+        self.routes.misses += other.routes.misses;
+        self.routes.evictions += other.routes.evictions;
+        self.routes.rpc_latency.merge(&other.routes.rpc_latency);
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BOUNDS_MS.len() + 1],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, latency: Duration) {
+        let millis = latency.as_millis();
+        let index = LATENCY_BOUNDS_MS
+            .iter()
+            .position(|bound| millis <= *bound as u128)
+            .unwrap_or(LATENCY_BOUNDS_MS.len());
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LatencyHistogram {
+        let bounds = LATENCY_BOUNDS_MS
+            .iter()
+            .map(|bound| Duration::from_millis(*bound))
+            .chain([Duration::MAX]);
+        LatencyHistogram {
+            buckets: bounds
+                .zip(&self.buckets)
+                .map(|(bound, count)| (bound, count.load(Ordering::Relaxed)))
+                .collect(),
+            count: self.count.load(Ordering::Relaxed),
+            sum: Duration::from_micros(self.sum_micros.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct EndpointCounters {
+    requests: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// The registry of the metrics shared by the clients of all the endpoints.
+#[derive(Debug, Default)]
+pub(crate) struct ClientMetrics {
+    operations: DashMap<&'static str, Histogram>,
+    endpoints: DashMap<String, EndpointCounters>,
+    rows_written: AtomicU64,
+    bytes_written: AtomicU64,
+    route_hits: AtomicU64,
+    route_misses: AtomicU64,
+    route_evictions: AtomicU64,
+    route_rpcs: Histogram,
+}
+
+impl ClientMetrics {
+    /// Record a call of the `operation` to the `endpoint`.
+    pub fn record_call(
+        &self,
+        operation: &'static str,
+        endpoint: &str,
+        latency: Duration,
+        ok: bool,
+    ) {
+        self.operations
+            .entry(operation)
+            .or_default()
+            .observe(latency);
+
+        let counters = match self.endpoints.get(endpoint) {
+            Some(counters) => counters,
+            None => self
+                .endpoints
+                .entry(endpoint.to_string())
+                .or_default()
+                .downgrade(),
+        };
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            counters.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record the successful write of the `rows` encoded in `bytes`.
+    pub fn record_write(&self, rows: u64, bytes: u64) {
+        self.rows_written.fetch_add(rows, Ordering::Relaxed);
+        self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Record the lookups of the route cache.
+    pub fn record_route_lookups(&self, hits: u64, misses: u64) {
+        self.route_hits.fetch_add(hits, Ordering::Relaxed);
+        self.route_misses.fetch_add(misses, Ordering::Relaxed);
+    }
+
+    /// Record the tables evicted from the route cache.
+    pub fn record_route_evictions(&self, evictions: u64) {
+        self.route_evictions.fetch_add(evictions, Ordering::Relaxed);
+    }
+
+    /// Record a route rpc.
+    pub fn record_route_rpc(&self, latency: Duration) {
+        self.route_rpcs.observe(latency);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let mut operations: Vec<_> = self
+            .operations
+            .iter()
+            .map(|entry| OperationMetrics {
+                operation: entry.key(),
+                latency: entry.value().snapshot(),
+            })
+            .collect();
+        operations.sort_by_key(|m| m.operation);
+
+        let mut endpoints: Vec<_> = self
+            .endpoints
+            .iter()
+            .map(|entry| EndpointMetrics {
+                endpoint: entry.key().clone(),
+                requests: entry.requests.load(Ordering::Relaxed),
+                errors: entry.errors.load(Ordering::Relaxed),
+            })
+            .collect();
+        endpoints.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+
+        MetricsSnapshot {
+            operations,
+            endpoints,
+            rows_written: self.rows_written.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            routes: RouteMetrics {
+                hits: self.route_hits.load(Ordering::Relaxed),
+                misses: self.route_misses.load(Ordering::Relaxed),
+                evictions: self.route_evictions.load(Ordering::Relaxed),
+                rpc_latency: self.route_rpcs.snapshot(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::ClientMetrics;
+
+    #[test]
+    fn test_client_metrics() {
+        let metrics = ClientMetrics::default();
+        metrics.record_call("write", "a:1", Duration::from_millis(3), true);
+        metrics.record_call("write", "b:1", Duration::from_millis(30), false);
+        metrics.record_call("sql_query", "a:1", Duration::from_secs(60), true);
+        metrics.record_write(10, 100);
+        metrics.record_route_lookups(3, 1);
+        metrics.record_route_evictions(1);
+        metrics.record_route_rpc(Duration::from_millis(7));
+
+        let snapshot = metrics.snapshot();
+        let ops: Vec<_> = snapshot.operations.iter().map(|m| m.operation).collect();
+        assert_eq!(ops, vec!["sql_query", "write"]);
+        let write = &snapshot.operation("write").unwrap().latency;
+        assert_eq!((write.count, write.sum), (2, Duration::from_millis(33)));
+        assert_eq!(write.quantile(0.5), Some(Duration::from_millis(5)));
+        assert_eq!(write.quantile(0.99), Some(Duration::from_millis(50)));
+        let query = &snapshot.operation("sql_query").unwrap().latency;
+        assert_eq!(query.quantile(0.5), Some(Duration::MAX));
+        assert_eq!(
+            snapshot
+                .endpoints
+                .iter()
+                .map(|m| (m.endpoint.as_str(), m.requests, m.errors))
+                .collect::<Vec<_>>(),
+            vec![("a:1", 2, 0), ("b:1", 1, 1)]
+        );
+        assert_eq!((snapshot.rows_written, snapshot.bytes_written), (10, 100));
+        let routes = &snapshot.routes;
+        assert_eq!((routes.hits, routes.misses, routes.evictions), (3, 1, 1));
+        assert_eq!(routes.rpc_latency.count, 1);
+
+        let merged = snapshot.clone().merge(snapshot);
+        assert_eq!(merged.operation("write").unwrap().latency.count, 4);
+        assert_eq!(merged.endpoints[1].errors, 2);
+        assert_eq!(merged.rows_written, 20);
+        assert_eq!(merged.routes.hits, 6);
+        assert_eq!(merged.routes.rpc_latency.count, 2);
+    }
+}
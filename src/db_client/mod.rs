@@ -18,26 +18,681 @@
 //! This module provides the definition and implementations of the `DbClient`.
 
 mod builder;
+mod cache;
+mod capabilities;
+mod circuit_breaker;
+mod concurrency;
+mod failover;
+mod hedge;
 mod inner;
+mod metrics;
+mod options;
+mod pause;
+mod quota;
+mod rate_limit;
 mod raw;
 mod route_based;
+#[cfg(feature = "tower")]
+mod service;
+mod shutdown;
+mod slow;
+mod state;
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
-pub use builder::{Builder, Mode};
+pub use builder::{Builder, Mode, Transport};
+pub use cache::QueryCacheConfig;
+pub use capabilities::ServerCapabilities;
+pub use circuit_breaker::CircuitBreakerConfig;
+pub use failover::{FailoverClient, FailoverMarker};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+pub use hedge::HedgingConfig;
+pub(crate) use metrics::ClientMetrics;
+pub use metrics::{
+    EndpointMetrics, LatencyHistogram, MetricsSnapshot, OperationMetrics, RouteMetrics,
+};
+pub(crate) use options::REQUEST_ID_METADATA_KEY;
+pub use options::{QueryOptions, WriteOptions};
+pub use pause::PausePolicy;
+pub use quota::ClientQuota;
+pub use rate_limit::{RateLimitPolicy, WriteRateLimit};
+#[cfg(feature = "tower")]
+pub use service::{LayeredClient, QueryService, WriteService};
+pub use slow::{SlowRequest, SlowRequestConfig, SlowRequestHook};
+pub use state::{ConnectionState, ConnectionStatus};
+use tokio::sync::OnceCell;
 
 use crate::{
+    config::RetryConfig,
+    db_client::{
+        cache::{CacheInvalidation, QueryCache},
+        concurrency::ConcurrencyLimiter,
+        options::{call_with_retries, write_with_retries},
+        pause::IngestionGate,
+        rate_limit::WriteRateLimiter,
+        shutdown::ShutdownGate,
+        slow::SlowRequestLog,
+    },
+    errors::RouteBasedWriteError,
     model::{
-        sql_query::{Request as SqlQueryRequest, Response as SqlQueryResponse},
-        write::{Request as WriteRequest, Response as WriteResponse},
+        route::Endpoint,
+        sql_query::{
+            page::{build_page_request, Page},
+            row::Row,
+            series::{build_series_request, TimeRange},
+            statement::Statement,
+            transform::RowTransformer,
+            Request as SqlQueryRequest, Response as SqlQueryResponse,
+        },
+        value::Value,
+        write::{
+            sampling::WriteSampler, stats::WriteStats, Request as WriteRequest,
+            Response as WriteResponse,
+        },
     },
-    rpc_client::RpcContext,
-    Result,
+    router::{LoadBalancePolicy, RouteRefreshConfig},
+    rpc_client::{RpcClient, RpcContext},
+    Error, Result,
 };
 
+/// The stream of the row batches of a streaming query.
+pub type RowBatchStream = BoxStream<'static, Result<Vec<Row>>>;
+
+/// The stream of the rows of a streaming query.
+pub type RowStream = BoxStream<'static, Result<Row>>;
+
 #[async_trait]
 pub trait DbClient: Send + Sync {
     async fn sql_query(&self, ctx: &RpcContext, req: &SqlQueryRequest) -> Result<SqlQueryResponse>;
     async fn write(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse>;
+
+    /// Query by the per-call [`QueryOptions`], which override the ones of the
+    /// `ctx`, and retry the query failed by the transient errors.
+    async fn sql_query_with(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+        opts: &QueryOptions,
+    ) -> Result<SqlQueryResponse> {
+        let ctx = opts.apply(ctx);
+        call_with_retries(ctx, &opts.retry, |ctx| async move {
+            self.sql_query(&ctx, req).await
+        })
+        .await
+    }
+
+    /// Write by the per-call [`WriteOptions`], which override the ones of the
+    /// `ctx`, and retry the write failed by the transient errors.
+    ///
+    /// The write is only checked by [`dry_run_write`](DbClient::dry_run_write)
+    /// without being sent in the [`dry_run`](WriteOptions::dry_run) mode.
+    async fn write_with(
+        &self,
+        ctx: &RpcContext,
+        req: &WriteRequest,
+        opts: &WriteOptions,
+    ) -> Result<WriteResponse> {
+        let ctx = opts.apply(ctx);
+        if opts.dry_run {
+            return self.dry_run_write(&ctx, req).await;
+        }
+
+        write_with_retries(ctx, &opts.retry, |ctx| async move {
+            self.write(&ctx, req).await
+        })
+        .await
+    }
+
+    /// Check the write as it would be sent by the client without sending it
+    /// to the server, i.e. the request is validated by
+    /// [`WriteRequest::validate`], and the database, the write sampling, the
+    /// [`ClientQuota`] and the `max_send_msg_len` of the [`RpcConfig`] are
+    /// applied as the real write.
+    ///
+    /// The points left after the sampling are all reported as written
+    /// successfully if it's valid, and the request is only validated by
+    /// default.
+    ///
+    /// [`RpcConfig`]: crate::RpcConfig
+    async fn dry_run_write(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+        let _ = ctx;
+        req.validate()?;
+        let points = req.point_groups.values().map(Vec::len).sum::<usize>();
+        Ok(WriteResponse::new(points as u32, 0))
+    }
+
+    /// Write and retry only the tables failed by the partial failure, i.e.
+    /// [`Error::RouteBasedWriteError`], up to `max_retries` times, and the
+    /// failed tables are routed again by every retry.
+    ///
+    /// If some tables still fail, the tables written by all the attempts are
+    /// reported in the returned [`Error::RouteBasedWriteError`].
+    async fn write_with_partial_retry(
+        &self,
+        ctx: &RpcContext,
+        req: &WriteRequest,
+        max_retries: u32,
+    ) -> Result<WriteResponse> {
+        let mut req = Cow::Borrowed(req);
+        let mut results = Vec::new();
+        let mut retries = 0;
+        loop {
+            match self.write(ctx, &req).await {
+                Err(Error::RouteBasedWriteError(e)) if retries < max_retries => {
+                    let failed_tables = e
+                        .errors
+                        .into_iter()
+                        .flat_map(|(tables, _)| tables)
+                        .collect::<Vec<_>>();
+                    results.push((e.ok.0, Ok(e.ok.1)));
+                    req = Cow::Owned(req.select_tables(&failed_tables));
+                    retries += 1;
+                }
+                result if results.is_empty() => return result,
+                Err(Error::RouteBasedWriteError(e)) => {
+                    results.push((e.ok.0, Ok(e.ok.1)));
+                    results.extend(e.errors.into_iter().map(|(tables, e)| (tables, Err(e))));
+                    return merge_write_results(results);
+                }
+                result => {
+                    results.push((req.point_groups.keys().cloned().collect(), result));
+                    return merge_write_results(results);
+                }
+            }
+        }
+    }
+
+    /// Check whether the server serving the client is alive, which can be
+    /// used before sending the real traffic.
+    ///
+    /// The server is checked by the [`raw`](DbClient::raw) client by default.
+    async fn health_check(&self, ctx: &RpcContext) -> Result<()> {
+        self.raw(ctx, None).await?.health_check(ctx).await
+    }
+
+    /// Check the server by [`health_check`](DbClient::health_check) and return
+    /// the round-trip latency, e.g. for the readiness probes of the services.
+    ///
+    /// The latency of the first ping includes the connecting to the server.
+    async fn ping(&self, ctx: &RpcContext) -> Result<Duration> {
+        let start = Instant::now();
+        self.health_check(ctx).await?;
+        Ok(start.elapsed())
+    }
+
+    /// Resolve the routes of the `tables` and connect to the servers serving
+    /// them in advance, so that the first requests after the startup don't pay
+    /// the latency of routing and connecting.
+    ///
+    /// The server is checked by [`health_check`](DbClient::health_check) by
+    /// default.
+    async fn warm_up(&self, ctx: &RpcContext, tables: &[String]) -> Result<()> {
+        let _ = tables;
+        self.health_check(ctx).await
+    }
+
+    /// Resolve the routes of the `tables` in advance to warm the route cache,
+    /// so that the service doesn't make a burst of route rpcs when it starts
+    /// handling the traffic.
+    ///
+    /// Unlike [`warm_up`](DbClient::warm_up), the servers are not connected.
+    /// There is nothing to prefetch without routing by default.
+    async fn prefetch(&self, ctx: &RpcContext, tables: &[String]) -> Result<()> {
+        let _ = (ctx, tables);
+        Ok(())
+    }
+
+    /// Clear the cached routes of all the tables, so that they are routed
+    /// again by the following requests, e.g. after a known rebalance of the
+    /// cluster.
+    ///
+    /// There is no route cached by default.
+    fn clear_routes(&self) {}
+
+    /// Pause the writes, which are handled by the [`PausePolicy`] until
+    /// [`resume`](DbClient::resume) is called, while the queries are still
+    /// served.
+    ///
+    /// The writes can't be paused by default.
+    fn pause(&self, policy: PausePolicy) {
+        let _ = policy;
+    }
+
+    /// Resume the paused writes.
+    fn resume(&self) {}
+
+    /// Shut down the client gracefully: the new queries and writes are
+    /// rejected with [`Error::Shutdown`], the ones in flight are waited to
+    /// finish within the `timeout`, and then the connections are closed.
+    ///
+    /// The client has nothing to drain by default.
+    async fn shutdown(&self, _timeout: Duration) -> Result<()> {
+        Ok(())
+    }
+
+    /// Close the client immediately: the new calls are rejected with
+    /// [`Error::Shutdown`], and the connections are closed without waiting for
+    /// the calls in flight, which are released once they finish.
+    ///
+    /// It's the [`shutdown`](DbClient::shutdown) without draining by default.
+    async fn close(&self) {
+        let _ = self.shutdown(Duration::ZERO).await;
+    }
+
+    /// The endpoints of the servers serving the `tables` in order, which are
+    /// resolved from the cached routes or by the server, e.g. to find out
+    /// which server owns a table when debugging.
+    ///
+    /// None means no server serves the table. Routing isn't supported by
+    /// default.
+    async fn route(&self, ctx: &RpcContext, tables: &[String]) -> Result<Vec<Option<Endpoint>>> {
+        let _ = (ctx, tables);
+        Err(Error::Client("routing is not supported".to_string()))
+    }
+
+    /// The underlying [`RpcClient`] to send the hand-built protobuf requests
+    /// by, which is the escape hatch when the typed model lags behind the new
+    /// features of the server.
+    ///
+    /// The client of the server serving the `table` is returned in the
+    /// `Direct` mode, and the one of the configured endpoint otherwise. The
+    /// requests sent by it bypass the features of the [`DbClient`], e.g. the
+    /// default database, the write sampling and the circuit breakers.
+    async fn raw(&self, ctx: &RpcContext, table: Option<&str>) -> Result<Arc<dyn RpcClient>> {
+        let _ = (ctx, table);
+        Err(Error::Client("raw rpc client is not supported".to_string()))
+    }
+
+    /// The capabilities of the server serving the client, so that the callers
+    /// can branch on them instead of failing at runtime.
+    ///
+    /// The server is probed by the [`raw`](DbClient::raw) client every time by
+    /// default.
+    async fn capabilities(&self, ctx: &RpcContext) -> Result<ServerCapabilities> {
+        let client = self.raw(ctx, None).await?;
+        capabilities::probe(client.as_ref(), ctx).await
+    }
+
+    /// The states of the connections to the servers, which help to debug the
+    /// failures of the calls to the specific servers.
+    ///
+    /// No state is reported by default.
+    fn connection_states(&self) -> Vec<ConnectionState> {
+        Vec::new()
+    }
+
+    /// The snapshot of the built-in metrics of the calls to the servers, i.e.
+    /// the latencies of the operations, the counts of the calls and errors of
+    /// the endpoints and the written rows and bytes.
+    ///
+    /// Nothing is recorded by default.
+    fn metrics_snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot::default()
+    }
+
+    /// Feed the rows of the query result to `on_row` one by one, and return
+    /// the affected rows.
+    ///
+    /// The rows are fed while being decoded batch by batch, without holding
+    /// all of them in memory, see [`sql_query_fold`](#method.sql_query_fold)
+    /// for the convenient usage.
+    async fn sql_query_for_each(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+        on_row: &mut (dyn FnMut(Row) + Send),
+    ) -> Result<u32> {
+        let resp = self.sql_query(ctx, req).await?;
+        resp.rows.into_iter().for_each(on_row);
+        Ok(resp.affected_rows)
+    }
+
+    /// Query by the server-streaming rpc, and the rows are decoded batch by
+    /// batch from the returned stream, which keeps the memory bounded for the
+    /// large result sets.
+    ///
+    /// The rows of [`sql_query`](DbClient::sql_query) are returned as the only
+    /// batch by default.
+    async fn sql_query_stream(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+    ) -> Result<RowBatchStream> {
+        let resp = self.sql_query(ctx, req).await?;
+        Ok(stream::once(async { Ok(resp.rows) }).boxed())
+    }
+
+    /// Query by [`sql_query_stream`](DbClient::sql_query_stream), and the rows
+    /// are yielded one by one, so only the batch being yielded is held in
+    /// memory.
+    async fn sql_query_rows(&self, ctx: &RpcContext, req: &SqlQueryRequest) -> Result<RowStream> {
+        let batches = self.sql_query_stream(ctx, req).await?;
+        let rows = batches.flat_map(|batch| match batch {
+            Ok(rows) => stream::iter(rows).map(Ok).left_stream(),
+            Err(e) => stream::once(async { Err(e) }).right_stream(),
+        });
+        Ok(rows.boxed())
+    }
+
+    /// Query by the sql of the [`Statement`] with the parameters bound.
+    async fn sql_query_statement(
+        &self,
+        ctx: &RpcContext,
+        stmt: &Statement,
+    ) -> Result<SqlQueryResponse> {
+        let req = stmt.build()?;
+        self.sql_query(ctx, &req).await
+    }
+
+    /// Query the page of at most `page_size` rows at the `offset` of the
+    /// result, and the [`Page`] tells the offset of the next page if any.
+    ///
+    /// The `LIMIT` and `OFFSET` are appended to the sql of the `req`, which
+    /// should be ordered by the `ORDER BY` so that the pages are stable, and it
+    /// fails if the sql has the `LIMIT` already or ends with a line comment.
+    async fn sql_query_page(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+        page_size: u32,
+        offset: u64,
+    ) -> Result<Page> {
+        if page_size == 0 {
+            return Err(Error::Client("page size should be positive".to_string()));
+        }
+
+        let page_req = build_page_request(req, page_size, offset)?;
+        let resp = self.sql_query(ctx, &page_req).await?;
+        Ok(Page::new(resp.rows, page_size, offset))
+    }
+
+    /// Query the rows of the series identified by the equality of the `tags`
+    /// in the `time_range`, ordered by the timestamp.
+    async fn get_series(
+        &self,
+        ctx: &RpcContext,
+        table: &str,
+        tags: &BTreeMap<String, Value>,
+        time_range: &TimeRange,
+    ) -> Result<Vec<Row>> {
+        let req = build_series_request(table, tags, time_range);
+        self.sql_query(ctx, &req).await.map(|resp| resp.rows)
+    }
+}
+
+impl dyn DbClient {
+    /// Aggregate the rows of the query result by `f` starting from `init`,
+    /// while the rows are decoded without being materialized all together.
+    pub async fn sql_query_fold<B, F>(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+        init: B,
+        mut f: F,
+    ) -> Result<B>
+    where
+        B: Send,
+        F: FnMut(B, Row) -> B + Send,
+    {
+        let mut acc = Some(init);
+        self.sql_query_for_each(ctx, req, &mut |row| {
+            // The accumulator is always put back after every row.
+            acc = acc.take().map(|acc| f(acc, row));
+        })
+        .await?;
+
+        Ok(acc.unwrap())
+    }
+
+    /// Query the pages of `page_size` rows from the start of the result one
+    /// by one by [`sql_query_page`](DbClient::sql_query_page), and the next
+    /// page is queried only when it's polled.
+    pub fn sql_query_pages<'a>(
+        &'a self,
+        ctx: &'a RpcContext,
+        req: &'a SqlQueryRequest,
+        page_size: u32,
+    ) -> BoxStream<'a, Result<Page>> {
+        stream::try_unfold(Some(0), move |offset| async move {
+            let Some(offset) = offset else {
+                return Ok(None);
+            };
+            let page = self.sql_query_page(ctx, req, page_size, offset).await?;
+            let next_offset = page.next_offset;
+            Ok(Some((page, next_offset)))
+        })
+        .boxed()
+    }
+}
+
+/// Options shared by the [`DbClient`] implementations, set by the [`Builder`].
+#[derive(Clone, Default)]
+pub(crate) struct ClientOptions {
+    pub default_database: Option<String>,
+    pub write_sampler: Option<Arc<WriteSampler>>,
+    pub connect_budget: Option<Duration>,
+    pub row_transformers: Vec<Arc<dyn RowTransformer>>,
+    pub max_tables_per_write: Option<usize>,
+    pub write_stats: Option<Arc<WriteStats>>,
+    pub ingestion_gate: Arc<IngestionGate>,
+    pub load_balance_policy: Option<Arc<dyn LoadBalancePolicy>>,
+    pub route_ttl: Option<Duration>,
+    pub route_refresh: Option<RouteRefreshConfig>,
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    pub hedging_config: Option<HedgingConfig>,
+    pub retry: Option<RetryConfig>,
+    pub write_rate_limiter: Option<Arc<WriteRateLimiter>>,
+    /// The client-wide limit of the concurrent queries and writes.
+    pub concurrency_limit: Option<Arc<ConcurrencyLimiter>>,
+    pub quota: ClientQuota,
+    /// The client-wide limit of the concurrent queries by the quota.
+    pub query_concurrency_limit: Option<Arc<ConcurrencyLimiter>>,
+    /// The gate closed by [`DbClient::shutdown`].
+    pub shutdown_gate: Arc<ShutdownGate>,
+    pub metrics: Arc<ClientMetrics>,
+    pub slow_request_log: Option<Arc<SlowRequestLog>>,
+    pub query_cache: Option<Arc<QueryCache>>,
+    /// The capabilities of the server probed at the first time.
+    pub capabilities: Arc<OnceCell<ServerCapabilities>>,
+    /// The `max_send_msg_len` of the [`RpcConfig`](crate::RpcConfig), which is
+    /// unlimited if not set.
+    pub max_send_msg_len: Option<usize>,
+}
+
+impl ClientOptions {
+    /// Split the write request according to the `max_tables_per_write`.
+    pub fn split_write(&self, req: WriteRequest) -> Vec<WriteRequest> {
+        match self.max_tables_per_write {
+            Some(max_tables) => req.split_by_tables(max_tables),
+            None => vec![req],
+        }
+    }
+
+    /// Apply the [`RowTransformer`]s to the rows in the response in order.
+    pub fn transform_rows(&self, mut resp: SqlQueryResponse) -> SqlQueryResponse {
+        if !self.row_transformers.is_empty() {
+            for row in &mut resp.rows {
+                self.transform_row(row);
+            }
+        }
+
+        resp
+    }
+
+    /// Apply the [`RowTransformer`]s to one row in order.
+    pub fn transform_row(&self, row: &mut Row) {
+        for transformer in &self.row_transformers {
+            transformer.transform(row);
+        }
+    }
+
+    /// Apply the [`RowTransformer`]s to the responses of a streaming query, and
+    /// take the rows out of them.
+    pub fn transform_stream(
+        &self,
+        stream: BoxStream<'static, Result<SqlQueryResponse>>,
+    ) -> RowBatchStream {
+        let options = self.clone();
+        stream
+            .map(move |resp| resp.map(|resp| options.transform_rows(resp).rows))
+            .boxed()
+    }
+
+    /// Sample the write request if any sampling policy matches it.
+    pub fn sample_write<'a>(&self, req: &'a WriteRequest) -> Cow<'a, WriteRequest> {
+        match &self.write_sampler {
+            Some(sampler) if sampler.should_sample(req) => Cow::Owned(sampler.sample(req)),
+            _ => Cow::Borrowed(req),
+        }
+    }
+
+    /// Check the write as it would be sent without sending it, see
+    /// [`DbClient::dry_run_write`].
+    pub fn dry_run_write(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+        let ctx = resolve_database(ctx, &self.default_database)?;
+        req.validate()?;
+        let req = match &self.write_sampler {
+            Some(sampler) if sampler.should_sample(req) => sampler.preview(req),
+            _ => req.clone(),
+        };
+        self.quota.check_write(&req)?;
+
+        let points = req.point_groups.values().map(Vec::len).sum::<usize>();
+        let database = ctx.database.as_deref().unwrap_or_default();
+        for req in self.split_write(req) {
+            let len = req.encoded_len(database);
+            if let Some(max_len) = self.max_send_msg_len.filter(|max_len| len > *max_len) {
+                return Err(Error::Client(format!(
+                    "write request is {len} bytes, exceeding the max_send_msg_len:{max_len}"
+                )));
+            }
+        }
+
+        Ok(WriteResponse::new(points as u32, 0))
+    }
+
+    /// Observe the latency of the call to the `endpoint` by the
+    /// [`LoadBalancePolicy`] if set.
+    pub fn observe_latency<T>(&self, endpoint: &Endpoint, start: Instant, result: &Result<T>) {
+        if let Some(policy) = &self.load_balance_policy {
+            policy.observe(endpoint, start.elapsed(), result.is_ok());
+        }
+    }
+
+    /// The capabilities of the server, which are probed by the `client` with
+    /// the default database at the first time.
+    pub async fn capabilities(
+        &self,
+        ctx: &RpcContext,
+        client: impl Future<Output = Result<Arc<dyn RpcClient>>>,
+    ) -> Result<ServerCapabilities> {
+        self.capabilities
+            .get_or_try_init(|| async {
+                let mut ctx = ctx.clone();
+                ctx.database = ctx.database.or_else(|| self.default_database.clone());
+                capabilities::probe(client.await?.as_ref(), &ctx).await
+            })
+            .await
+            .cloned()
+    }
+
+    /// Call `op` with the retries of the [`RetryConfig`] if set.
+    pub async fn with_retries<T, F, Fut>(&self, ctx: &RpcContext, mut op: F) -> Result<T>
+    where
+        F: FnMut(RpcContext) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        match &self.retry {
+            Some(retry) => call_with_retries(ctx.clone(), retry, op).await,
+            None => op(ctx.clone()).await,
+        }
+    }
+
+    /// Call the write `op` with the retries of the [`RetryConfig`] if set.
+    pub async fn with_write_retries<T, F, Fut>(&self, ctx: &RpcContext, mut op: F) -> Result<T>
+    where
+        F: FnMut(RpcContext) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        match &self.retry {
+            Some(retry) => write_with_retries(ctx.clone(), retry, op).await,
+            None => op(ctx.clone()).await,
+        }
+    }
+
+    /// Record the write request into the [`WriteStats`] if set.
+    pub fn record_write(&self, req: &WriteRequest) {
+        if let Some(stats) = &self.write_stats {
+            stats.record(req);
+        }
+    }
+
+    /// Serve the query from the [`QueryCache`] if configured, otherwise just
+    /// run the `query`.
+    pub async fn cached_query<Fut>(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+        query: Fut,
+    ) -> Result<SqlQueryResponse>
+    where
+        Fut: Future<Output = Result<SqlQueryResponse>>,
+    {
+        let Some(cache) = &self.query_cache else {
+            return query.await;
+        };
+        let ctx = RpcContext {
+            database: ctx.database.clone().or(self.default_database.clone()),
+            ..ctx.clone()
+        };
+        cache.query(&ctx, req, query).await
+    }
+
+    /// Invalidate the cached query results of the written tables once the
+    /// returned guard is dropped, if the [`QueryCache`] is configured.
+    pub fn invalidate_cache_on_drop(
+        &self,
+        ctx: &RpcContext,
+        req: &WriteRequest,
+    ) -> Option<CacheInvalidation<'_>> {
+        self.query_cache
+            .as_ref()
+            .map(|cache| cache.invalidate_on_drop(ctx, req.point_groups.keys()))
+    }
+}
+
+/// Merge the results of the write rpcs, and the tables of every failed rpc
+/// are reported in the [`RouteBasedWriteError`].
+pub(crate) fn merge_write_results(
+    tables_result_pairs: Vec<(Vec<String>, Result<WriteResponse>)>,
+) -> Result<WriteResponse> {
+    let route_based_error: RouteBasedWriteError = tables_result_pairs.into();
+    if route_based_error.all_ok() {
+        Ok(route_based_error.ok.1)
+    } else {
+        Err(Error::RouteBasedWriteError(route_based_error))
+    }
+}
+
+/// Reject the query request which can't be handled by the server.
+pub(crate) fn check_sql_query_request(req: &SqlQueryRequest) -> Result<()> {
+    if req.sql.trim().is_empty() {
+        return Err(Error::Client(
+            "sql in query request can't be empty".to_string(),
+        ));
+    }
+
+    Ok(())
 }
 
 pub(crate) fn resolve_database(
@@ -50,6 +705,639 @@ pub(crate) fn resolve_database(
             database: Some(default_database.clone()),
             ..ctx.clone()
         }),
-        (None, None) => Err(crate::Error::NoDatabase),
+        (None, None) => Err(Error::NoDatabase),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{atomic::Ordering, Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use async_trait::async_trait;
+    use futures::StreamExt;
+    use horaedbproto::storage::WriteRequest as WriteRequestPb;
+
+    use super::{
+        raw::RawImpl, route_based::RouteBasedImpl, ClientOptions, ClientQuota, ConnectionStatus,
+        DbClient, HedgingConfig, PausePolicy, WriteOptions,
+    };
+    use crate::{
+        config::{BackoffStrategy, RetryConfig},
+        model::{
+            route::Endpoint,
+            sql_query::{response::test::arrow_response, Request as SqlQueryRequest},
+            value::Value,
+            write::{point::PointBuilder, Request as WriteRequest, Response as WriteResponse},
+        },
+        router::{MockRouter, RoundRobinPolicy},
+        rpc_client::{MockRpcClientFactory, RpcContext},
+        Error, Result,
+    };
+
+    const ENDPOINT: &str = "127.0.0.1:8831";
+
+    fn make_options() -> ClientOptions {
+        ClientOptions {
+            default_database: Some("public".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn make_write_request(tables: &[&str]) -> WriteRequest {
+        let mut req = WriteRequest::default();
+        for table in tables {
+            req.add_point(
+                PointBuilder::new(*table)
+                    .timestamp(0)
+                    .field("field", Value::Int32(0))
+                    .build()
+                    .unwrap(),
+            );
+        }
+        req
+    }
+
+    #[tokio::test]
+    async fn test_empty_write_and_blank_sql() {
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let clients: Vec<Arc<dyn DbClient>> = vec![
+            Arc::new(RawImpl::new(
+                factory.clone(),
+                ENDPOINT.to_string(),
+                make_options(),
+            )),
+            Arc::new(RouteBasedImpl::new(
+                factory.clone(),
+                ENDPOINT.to_string(),
+                make_options(),
+            )),
+        ];
+
+        let ctx = RpcContext::default();
+        for client in clients {
+            let resp = client.write(&ctx, &WriteRequest::default()).await.unwrap();
+            assert_eq!((resp.success, resp.failed), (0, 0));
+
+            let req = SqlQueryRequest {
+                tables: vec!["t".to_string()],
+                sql: " \n".to_string(),
+            };
+            let err = client.sql_query(&ctx, &req).await.unwrap_err();
+            assert!(matches!(err, Error::Client(_)));
+        }
+        assert_eq!(factory.write_calls.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_write() {
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let opts = WriteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let ctx = RpcContext::default();
+        let req = make_write_request(&["t1", "t2"]);
+
+        let client = RawImpl::new(
+            factory.clone(),
+            ENDPOINT.to_string(),
+            ClientOptions::default(),
+        );
+        let err = client.write_with(&ctx, &req, &opts).await.unwrap_err();
+        assert!(matches!(err, Error::NoDatabase));
+
+        let options = ClientOptions {
+            max_send_msg_len: Some(1 << 20),
+            ..make_options()
+        };
+        let client = RawImpl::new(factory.clone(), ENDPOINT.to_string(), options.clone());
+        let resp = client.write_with(&ctx, &req, &opts).await.unwrap();
+        assert_eq!((resp.success, resp.failed), (2, 0));
+
+        // The configured max_send_msg_len and quota are applied.
+        let client = RawImpl::new(
+            factory.clone(),
+            ENDPOINT.to_string(),
+            ClientOptions {
+                max_send_msg_len: Some(16),
+                ..options.clone()
+            },
+        );
+        let err = client.write_with(&ctx, &req, &opts).await.unwrap_err();
+        assert!(err.to_string().contains("max_send_msg_len"));
+        let client = RawImpl::new(
+            factory.clone(),
+            ENDPOINT.to_string(),
+            ClientOptions {
+                quota: ClientQuota {
+                    max_rows_per_write: Some(1),
+                    ..Default::default()
+                },
+                ..options
+            },
+        );
+        let err = client.write_with(&ctx, &req, &opts).await.unwrap_err();
+        assert!(matches!(err, Error::QuotaExceeded(_)));
+        assert_eq!(factory.write_calls.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let clients: Vec<Arc<dyn DbClient>> = vec![
+            Arc::new(RawImpl::new(
+                factory.clone(),
+                ENDPOINT.to_string(),
+                make_options(),
+            )),
+            Arc::new(RouteBasedImpl::new(
+                factory.clone(),
+                ENDPOINT.to_string(),
+                make_options(),
+            )),
+        ];
+        for client in clients {
+            client.health_check(&RpcContext::default()).await.unwrap();
+            let latency = client.ping(&RpcContext::default()).await.unwrap();
+            assert!(latency < Duration::from_secs(1));
+        }
+
+        let client = RouteBasedImpl::new(factory, "invalid".to_string(), make_options());
+        let err = client
+            .health_check(&RpcContext::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Client(_)));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_snapshot() {
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let client = RawImpl::new(factory, ENDPOINT.to_string(), make_options());
+        let ctx = RpcContext::default();
+        client
+            .write(&ctx, &make_write_request(&["t1", "t2"]))
+            .await
+            .unwrap();
+        client.health_check(&ctx).await.unwrap();
+
+        let snapshot = client.metrics_snapshot();
+        let ops: Vec<_> = snapshot.operations.iter().map(|m| m.operation).collect();
+        assert_eq!(ops, vec!["health_check", "write"]);
+        assert_eq!(snapshot.operation("write").unwrap().latency.count, 1);
+        assert_eq!(snapshot.endpoints.len(), 1);
+        assert_eq!(
+            (snapshot.endpoints[0].requests, snapshot.endpoints[0].errors),
+            (2, 0)
+        );
+        assert_eq!(snapshot.rows_written, 2);
+        assert!(snapshot.bytes_written > 0);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_tracing_spans() {
+        use tracing::{span, subscriber, Event, Metadata};
+
+        /// The subscriber recording the names of the new spans.
+        #[derive(Default)]
+        struct SpanRecorder(Mutex<Vec<&'static str>>);
+
+        impl subscriber::Subscriber for SpanRecorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+                let mut names = self.0.lock().unwrap();
+                names.push(attrs.metadata().name());
+                span::Id::from_u64(names.len() as u64)
+            }
+
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+            fn event(&self, _: &Event<'_>) {}
+
+            fn enter(&self, _: &span::Id) {}
+
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        let recorder = Arc::new(SpanRecorder::default());
+        let _guard = subscriber::set_default(recorder.clone());
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let client = RawImpl::new(factory, ENDPOINT.to_string(), make_options());
+        client
+            .write(&RpcContext::default(), &make_write_request(&["t"]))
+            .await
+            .unwrap();
+
+        let names = recorder.0.lock().unwrap().clone();
+        assert_eq!(names.first(), Some(&"horaedb.write"));
+        assert!(names.contains(&"horaedb.rpc"));
+    }
+
+    #[tokio::test]
+    async fn test_sql_query_stream() {
+        let factory = Arc::new(MockRpcClientFactory::default());
+        *factory.query_responses.lock().unwrap() = vec![
+            arrow_response(vec![vec![1, 2]]),
+            arrow_response(vec![vec![3]]),
+        ];
+        let clients: Vec<Arc<dyn DbClient>> = vec![
+            Arc::new(RawImpl::new(
+                factory.clone(),
+                ENDPOINT.to_string(),
+                make_options(),
+            )),
+            Arc::new(RouteBasedImpl::new(
+                factory.clone(),
+                ENDPOINT.to_string(),
+                make_options(),
+            )),
+        ];
+        let req = SqlQueryRequest {
+            tables: vec!["t".to_string()],
+            sql: "select v from t".to_string(),
+        };
+        for client in clients {
+            let batches: Vec<Vec<_>> = client
+                .sql_query_stream(&RpcContext::default(), &req)
+                .await
+                .unwrap()
+                .map(|rows| {
+                    rows.unwrap()
+                        .iter()
+                        .map(|row| row.column("v").unwrap().value().clone())
+                        .collect()
+                })
+                .collect()
+                .await;
+            assert_eq!(
+                batches,
+                vec![
+                    vec![Value::Int32(1), Value::Int32(2)],
+                    vec![Value::Int32(3)]
+                ]
+            );
+
+            let values: Vec<_> = client
+                .sql_query_rows(&RpcContext::default(), &req)
+                .await
+                .unwrap()
+                .map(|row| row.unwrap().column("v").unwrap().value().clone())
+                .collect()
+                .await;
+            assert_eq!(
+                values,
+                vec![Value::Int32(1), Value::Int32(2), Value::Int32(3)]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_route() {
+        let endpoint = Endpoint::new("192.168.0.1".to_string(), 1);
+        let router = MockRouter::new().with_route("t1", endpoint.clone());
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let client = RouteBasedImpl::new(factory.clone(), ENDPOINT.to_string(), make_options())
+            .with_router(Box::new(router));
+        let tables = vec!["t1".to_string(), "t2".to_string()];
+        let endpoints = client.route(&RpcContext::default(), &tables).await.unwrap();
+        assert_eq!(endpoints, vec![Some(endpoint), None]);
+
+        // All the tables are served by the endpoint of the client.
+        let client = RawImpl::new(factory, ENDPOINT.to_string(), make_options());
+        let endpoints = client.route(&RpcContext::default(), &tables).await.unwrap();
+        let endpoint = ENDPOINT.parse().unwrap();
+        assert_eq!(endpoints, vec![Some(endpoint); 2]);
+    }
+
+    #[tokio::test]
+    async fn test_warm_up() {
+        let router = Arc::new(
+            MockRouter::new()
+                .with_route("t1", Endpoint::new("192.168.0.1".to_string(), 1))
+                .with_route("t2", Endpoint::new("192.168.0.2".to_string(), 2))
+                .with_route("t3", Endpoint::new("192.168.0.2".to_string(), 2)),
+        );
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let client = RouteBasedImpl::new(factory.clone(), ENDPOINT.to_string(), make_options())
+            .with_router(Box::new(router.clone()));
+        let tables: Vec<_> = ["t1", "t2", "t3", "t4"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+
+        client
+            .warm_up(&RpcContext::default(), &tables)
+            .await
+            .unwrap();
+        assert_eq!(router.route_calls(), vec![tables.clone()]);
+        // One client is connected for every distinct endpoint.
+        assert_eq!(factory.builds.load(Ordering::Relaxed), 2);
+
+        let client = RouteBasedImpl::new(factory, ENDPOINT.to_string(), ClientOptions::default());
+        let err = client
+            .warm_up(&RpcContext::default(), &tables)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NoDatabase));
+    }
+
+    #[tokio::test]
+    async fn test_load_balance() {
+        let endpoint1 = Endpoint::new("192.168.0.1".to_string(), 1);
+        let endpoint2 = Endpoint::new("192.168.0.2".to_string(), 2);
+        let make_router = || {
+            MockRouter::new()
+                .with_route("t", endpoint1.clone())
+                .with_replicas("t", vec![endpoint1.clone(), endpoint2.clone()])
+        };
+        let ctx = RpcContext::default();
+        let req = make_write_request(&["t"]);
+
+        // The routed endpoint is always taken without the policy.
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let client = RouteBasedImpl::new(factory.clone(), ENDPOINT.to_string(), make_options())
+            .with_router(Box::new(make_router()));
+        for _ in 0..2 {
+            client.write(&ctx, &req).await.unwrap();
+        }
+        assert_eq!(factory.builds.load(Ordering::Relaxed), 1);
+
+        // The writes are balanced among the replicas reported by the router.
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let options = ClientOptions {
+            load_balance_policy: Some(Arc::new(RoundRobinPolicy::default())),
+            ..make_options()
+        };
+        let client = RouteBasedImpl::new(factory.clone(), ENDPOINT.to_string(), options)
+            .with_router(Box::new(make_router()));
+        for _ in 0..2 {
+            client.write(&ctx, &req).await.unwrap();
+        }
+        assert_eq!(factory.builds.load(Ordering::Relaxed), 2);
+        assert_eq!(factory.write_calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_hedged_query() {
+        let primary = Endpoint::new("192.168.0.1".to_string(), 1);
+        let replica = Endpoint::new("192.168.0.2".to_string(), 2);
+        let other = Endpoint::new("192.168.0.3".to_string(), 3);
+        let router = MockRouter::new()
+            .with_route("s", other.clone())
+            .with_replicas("s", vec![other])
+            .with_route("t", primary.clone())
+            .with_replicas("t", vec![primary.clone(), replica.clone()])
+            .with_route("u", primary.clone())
+            .with_replicas("u", vec![primary.clone(), replica]);
+        let factory = Arc::new(MockRpcClientFactory::default());
+        factory
+            .query_delays
+            .insert(primary.to_string(), Duration::from_secs(5));
+        *factory.query_responses.lock().unwrap() = vec![arrow_response(vec![vec![1]])];
+        let options = ClientOptions {
+            hedging_config: Some(HedgingConfig::new(Duration::from_millis(10))),
+            ..make_options()
+        };
+        let client = RouteBasedImpl::new(factory.clone(), ENDPOINT.to_string(), options)
+            .with_router(Box::new(router));
+
+        // The slow primary serving the most of the tables is hedged by the
+        // replica of them.
+        let req = SqlQueryRequest {
+            tables: vec!["s".to_string(), "t".to_string(), "u".to_string()],
+            sql: "SELECT * FROM s, t, u".to_string(),
+        };
+        let start = Instant::now();
+        client
+            .sql_query(&RpcContext::default().hedge(true), &req)
+            .await
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(factory.builds.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_raw_client() {
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let router = MockRouter::new().with_route("t", Endpoint::new("192.168.0.1".to_string(), 1));
+        let clients: Vec<Arc<dyn DbClient>> = vec![
+            Arc::new(RawImpl::new(
+                factory.clone(),
+                ENDPOINT.to_string(),
+                make_options(),
+            )),
+            Arc::new(
+                RouteBasedImpl::new(factory.clone(), ENDPOINT.to_string(), make_options())
+                    .with_router(Box::new(router)),
+            ),
+        ];
+
+        let ctx = RpcContext::default();
+        for client in &clients {
+            for table in [None, Some("t")] {
+                let raw = client.raw(&ctx, table).await.unwrap();
+                let resp = raw.write(&ctx, WriteRequestPb::default()).await.unwrap();
+                assert_eq!(resp.success, 0);
+            }
+        }
+        assert_eq!(factory.write_calls.load(Ordering::Relaxed), 4);
+
+        // The table without any route can't be served in the route based mode.
+        let result = clients[1].raw(&ctx, Some("unknown")).await;
+        assert!(matches!(result, Err(Error::Unknown(_))));
+    }
+
+    #[tokio::test]
+    async fn test_connection_states() {
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let client = RawImpl::new(factory.clone(), ENDPOINT.to_string(), make_options());
+        let states = client.connection_states();
+        assert_eq!(states.len(), 1);
+        assert_eq!(states[0].status, ConnectionStatus::Idle);
+
+        let router = MockRouter::new()
+            .with_route("t1", Endpoint::new("192.168.0.1".to_string(), 1))
+            .with_route("t2", Endpoint::new("192.168.0.2".to_string(), 2));
+        let client = RouteBasedImpl::new(factory, ENDPOINT.to_string(), make_options())
+            .with_router(Box::new(router));
+        assert!(client.connection_states().is_empty());
+
+        client
+            .write(&RpcContext::default(), &make_write_request(&["t1", "t2"]))
+            .await
+            .unwrap();
+        let states = client.connection_states();
+        let endpoints: Vec<_> = states.iter().map(|s| s.endpoint.as_str()).collect();
+        assert_eq!(endpoints, ["192.168.0.1:1", "192.168.0.2:2"]);
+        for state in states {
+            assert_eq!(state.status, ConnectionStatus::Connected);
+            assert!(state.last_error.is_none());
+            assert!(state.last_used.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_capabilities() {
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let client = RawImpl::new(factory.clone(), ENDPOINT.to_string(), make_options());
+        let ctx = RpcContext::default();
+        let capabilities = client.capabilities(&ctx).await.unwrap();
+        assert!(capabilities.streaming_query);
+
+        // The capabilities are probed only once.
+        *factory.query_responses.lock().unwrap() = vec![arrow_response(vec![vec![1]])];
+        assert_eq!(client.capabilities(&ctx).await.unwrap(), capabilities);
+        assert_eq!(factory.builds.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_query_with_retry_config() {
+        let router = Arc::new(
+            MockRouter::new().with_route("t", Endpoint::new("192.168.0.1".to_string(), 1)),
+        );
+        router.push_result(Err(Error::Rpc(tonic::Status::unavailable("down"))));
+        let factory = Arc::new(MockRpcClientFactory::default());
+        *factory.query_responses.lock().unwrap() = vec![arrow_response(vec![vec![1]])];
+        let options = ClientOptions {
+            retry: Some(RetryConfig::new(1, BackoffStrategy::default())),
+            ..make_options()
+        };
+        let client = RouteBasedImpl::new(factory, ENDPOINT.to_string(), options)
+            .with_router(Box::new(router.clone()));
+
+        // The query is routed again by the retry.
+        let req = SqlQueryRequest {
+            tables: vec!["t".to_string()],
+            sql: "SELECT * FROM t".to_string(),
+        };
+        client
+            .sql_query(&RpcContext::default(), &req)
+            .await
+            .unwrap();
+        router.assert_route_call_count(2);
+    }
+
+    #[tokio::test]
+    async fn test_write_without_database() {
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let client = RawImpl::new(factory, ENDPOINT.to_string(), ClientOptions::default());
+        let err = client
+            .write(&RpcContext::default(), &make_write_request(&["t1"]))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NoDatabase));
+        assert!(err.to_string().contains("RpcContext::database"));
+    }
+
+    #[tokio::test]
+    async fn test_route_based_write_with_mock_router() {
+        let router = Arc::new(
+            MockRouter::new()
+                .with_route("t1", Endpoint::new("192.168.0.1".to_string(), 1))
+                .with_route("t2", Endpoint::new("192.168.0.2".to_string(), 2)),
+        );
+        router.push_result(Err(Error::Unknown("route failed".to_string())));
+
+        let factory = Arc::new(MockRpcClientFactory::default());
+        let client = RouteBasedImpl::new(factory.clone(), ENDPOINT.to_string(), make_options())
+            .with_router(Box::new(router.clone()));
+        let ctx = RpcContext::default();
+        let req = make_write_request(&["t1", "t2", "t3"]);
+
+        // The first route fails as scripted.
+        let err = client.write(&ctx, &req).await.unwrap_err();
+        assert!(matches!(err, Error::Unknown(_)));
+
+        // The table without endpoint fails, while the others succeed.
+        match client.write(&ctx, &req).await.unwrap_err() {
+            Error::RouteBasedWriteError(e) => {
+                assert_eq!(e.ok.1.success, 2);
+                assert_eq!(e.errors.len(), 1);
+                assert_eq!(e.errors[0].0, vec!["t3".to_string()]);
+            }
+            e => panic!("unexpected error:{e}"),
+        }
+        assert_eq!(factory.write_calls.load(Ordering::Relaxed), 2);
+        router.assert_route_call_count(2);
+        let mut routed_tables = router.route_calls().pop().unwrap();
+        routed_tables.sort();
+        assert_eq!(routed_tables, vec!["t1", "t2", "t3"]);
+        router.assert_evicted(&[]);
+    }
+
+    /// The client failing the table `t2` by its first write.
+    #[derive(Default)]
+    struct PartialFailureClient {
+        writes: Mutex<Vec<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl DbClient for PartialFailureClient {
+        async fn sql_query(
+            &self,
+            _ctx: &RpcContext,
+            _req: &SqlQueryRequest,
+        ) -> Result<crate::SqlQueryResponse> {
+            unimplemented!()
+        }
+
+        async fn write(&self, _ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+            let mut tables: Vec<_> = req.point_groups.keys().cloned().collect();
+            tables.sort();
+            let mut writes = self.writes.lock().unwrap();
+            writes.push(tables.clone());
+            let (failed, ok): (Vec<_>, Vec<_>) = tables
+                .into_iter()
+                .partition(|table| table == "t2" && writes.len() == 1);
+            let mut results = vec![(ok.clone(), Ok(WriteResponse::new(ok.len() as u32, 0)))];
+            if !failed.is_empty() {
+                results.push((failed, Err(Error::Unknown("stale route".to_string()))));
+            }
+            super::merge_write_results(results)
+        }
+
+        async fn health_check(&self, _ctx: &RpcContext) -> Result<()> {
+            Ok(())
+        }
+
+        fn pause(&self, _policy: PausePolicy) {}
+
+        fn resume(&self) {}
+    }
+
+    #[tokio::test]
+    async fn test_write_with_partial_retry() {
+        let ctx = RpcContext::default();
+        let req = make_write_request(&["t1", "t2", "t3"]);
+
+        let client = PartialFailureClient::default();
+        let resp = client
+            .write_with_partial_retry(&ctx, &req, 1)
+            .await
+            .unwrap();
+        assert_eq!(resp.success, 3);
+        assert_eq!(
+            *client.writes.lock().unwrap(),
+            vec![vec!["t1", "t2", "t3"], vec!["t2"]]
+        );
+
+        let client = PartialFailureClient::default();
+        match client.write_with_partial_retry(&ctx, &req, 0).await {
+            Err(Error::RouteBasedWriteError(e)) => {
+                assert_eq!(e.ok.1.success, 2);
+                assert_eq!(e.errors[0].0, vec!["t2"]);
+            }
+            result => panic!("unexpected result:{result:?}"),
+        }
+        assert_eq!(client.writes.lock().unwrap().len(), 1);
     }
 }
@@ -0,0 +1,414 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Per-call options of the writes and the queries.
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{RetryConfig, WriteRetryMode},
+    rpc_client::{Priority, RpcContext},
+    util::{random_id, traced},
+    Error, Result,
+};
+
+/// The grpc metadata key carrying the request id of the retried writes, see
+/// [`WriteRetryMode::RequestId`].
+pub(crate) const REQUEST_ID_METADATA_KEY: &str = "x-horaedb-request-id";
+
+/// Options of a write, see [`DbClient::write_with`].
+///
+/// The options set here override the corresponding ones of the
+/// [`RpcContext`], and the unset ones are left as they are.
+///
+/// [`DbClient::write_with`]: crate::DbClient::write_with
+#[derive(Clone, Debug, Default)]
+pub struct WriteOptions {
+    /// The timeout of every rpc of the write.
+    pub timeout: Option<Duration>,
+    /// The deadline of the whole write including the retries.
+    pub deadline: Option<Instant>,
+    /// The retries of the write failed by the transient errors.
+    ///
+    /// It is not retried by default.
+    pub retry: RetryConfig,
+    /// Override the compression of the write if set.
+    pub compression: Option<bool>,
+    /// The priority of the write.
+    pub priority: Option<Priority>,
+    /// Check the write by [`DbClient::dry_run_write`] without sending it to
+    /// the server.
+    ///
+    /// [`DbClient::dry_run_write`]: crate::DbClient::dry_run_write
+    pub dry_run: bool,
+}
+
+impl WriteOptions {
+    pub(crate) fn apply(&self, ctx: &RpcContext) -> RpcContext {
+        apply_call_options(
+            ctx,
+            self.timeout,
+            self.deadline,
+            self.compression,
+            self.priority,
+        )
+    }
+}
+
+/// Options of a query, see [`DbClient::sql_query_with`].
+///
+/// The options set here override the corresponding ones of the
+/// [`RpcContext`], and the unset ones are left as they are.
+///
+/// [`DbClient::sql_query_with`]: crate::DbClient::sql_query_with
+#[derive(Clone, Debug, Default)]
+pub struct QueryOptions {
+    /// The timeout of every rpc of the query.
+    pub timeout: Option<Duration>,
+    /// The deadline of the whole query including the retries.
+    pub deadline: Option<Instant>,
+    /// The retries of the query failed by the transient errors.
+    ///
+    /// It is not retried by default.
+    pub retry: RetryConfig,
+    /// Override the compression of the query if set.
+    pub compression: Option<bool>,
+    /// The priority of the query.
+    pub priority: Option<Priority>,
+}
+
+impl QueryOptions {
+    pub(crate) fn apply(&self, ctx: &RpcContext) -> RpcContext {
+        apply_call_options(
+            ctx,
+            self.timeout,
+            self.deadline,
+            self.compression,
+            self.priority,
+        )
+    }
+}
+
+fn apply_call_options(
+    ctx: &RpcContext,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    compression: Option<bool>,
+    priority: Option<Priority>,
+) -> RpcContext {
+    let mut ctx = ctx.clone();
+    ctx.timeout = timeout.or(ctx.timeout);
+    ctx.deadline = deadline.or(ctx.deadline);
+    ctx.compression = compression.or(ctx.compression);
+    ctx.priority = priority.or(ctx.priority);
+
+    ctx
+}
+
+/// Call `op` and retry it at most `max_retries` times of the [`RetryConfig`]
+/// if it fails by the retryable errors.
+///
+/// The call already being a retry of the caller is never retried, and the
+/// error after the retries is wrapped in [`Error::RetryExhausted`].
+pub(crate) async fn call_with_retries<T, F, Fut>(
+    ctx: RpcContext,
+    retry: &RetryConfig,
+    op: F,
+) -> Result<T>
+where
+    F: FnMut(RpcContext) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    retry_while(ctx, retry, |e| retry.is_retryable(e), op).await
+}
+
+/// Call the write `op` with the retries following the [`WriteRetryMode`] of
+/// the [`RetryConfig`].
+pub(crate) async fn write_with_retries<T, F, Fut>(
+    ctx: RpcContext,
+    retry: &RetryConfig,
+    op: F,
+) -> Result<T>
+where
+    F: FnMut(RpcContext) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    match retry.write_mode {
+        WriteRetryMode::Always => call_with_retries(ctx, retry, op).await,
+        WriteRetryMode::Unsent => {
+            retry_while(ctx, retry, |e| retry.is_retryable(e) && e.is_unsent(), op).await
+        }
+        WriteRetryMode::RequestId => {
+            // The id of the caller is kept, e.g. of the per-call retries.
+            let mut ctx = ctx;
+            ctx.headers
+                .entry(REQUEST_ID_METADATA_KEY.to_string())
+                .or_insert_with(random_id);
+            call_with_retries(ctx, retry, op).await
+        }
+    }
+}
+
+async fn retry_while<T, F, Fut>(
+    ctx: RpcContext,
+    retry: &RetryConfig,
+    should_retry: impl Fn(&Error) -> bool,
+    mut op: F,
+) -> Result<T>
+where
+    F: FnMut(RpcContext) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let max_retries = if ctx.is_retry() { 0 } else { retry.max_retries };
+    let mut ctx = ctx;
+    let mut retries = 0;
+    let mut backoff = Duration::ZERO;
+    // The throttled attempts are bounded by the budget rather than the retries.
+    let started = Instant::now();
+    let mut throttled = 0;
+    let mut throttle_backoff = Duration::ZERO;
+    loop {
+        let attempts = retries + throttled + 1;
+        let attempt = traced!(op(ctx.clone()), "horaedb.attempt", attempt = attempts);
+        let err = match attempt.await {
+            Err(e) if should_retry(&e) || (retry.throttle.is_some() && e.is_throttled()) => e,
+            result => return result,
+        };
+        if let Some(throttle) = retry.throttle.as_ref().filter(|_| err.is_throttled()) {
+            throttle_backoff = throttle.backoff(&err, throttled, throttle_backoff);
+            let resend_at = Instant::now() + throttle_backoff;
+            let exhausted = resend_at > started + throttle.budget
+                || ctx.deadline.is_some_and(|deadline| resend_at >= deadline);
+            if exhausted {
+                return Err(Error::Throttled {
+                    attempts,
+                    source: Box::new(err),
+                });
+            }
+
+            throttled += 1;
+            ctx = ctx.next_attempt();
+            tokio::time::sleep(throttle_backoff).await;
+            continue;
+        }
+        if retries == max_retries {
+            return Err(if attempts == 1 {
+                err
+            } else {
+                Error::RetryExhausted {
+                    attempts,
+                    source: Box::new(err),
+                }
+            });
+        }
+
+        backoff = retry.backoff.backoff(retries, backoff);
+        retries += 1;
+        ctx = ctx.next_attempt();
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use super::{call_with_retries, write_with_retries, WriteOptions, REQUEST_ID_METADATA_KEY};
+    use crate::{
+        config::{BackoffStrategy, RetryConfig, ThrottlePolicy, WriteRetryMode},
+        rpc_client::{Priority, RpcContext},
+        Error, Result,
+    };
+
+    fn retries(max_retries: u32) -> RetryConfig {
+        RetryConfig::new(max_retries, BackoffStrategy::default())
+    }
+
+    fn unavailable() -> Error {
+        Error::Rpc(tonic::Status::unavailable("down"))
+    }
+
+    #[test]
+    fn test_apply_options() {
+        let ctx = RpcContext::default()
+            .timeout(Duration::from_secs(1))
+            .compression(true);
+        let opts = WriteOptions {
+            timeout: Some(Duration::from_secs(3)),
+            priority: Some(Priority::High),
+            ..Default::default()
+        };
+
+        let ctx = opts.apply(&ctx);
+        assert_eq!(ctx.timeout, Some(Duration::from_secs(3)));
+        assert_eq!(ctx.compression, Some(true));
+        assert_eq!(ctx.priority, Some(Priority::High));
+    }
+
+    #[tokio::test]
+    async fn test_call_with_retries() {
+        // Succeed after the retries.
+        let calls = AtomicU32::new(0);
+        let result = call_with_retries(RpcContext::default(), &retries(2), |ctx| {
+            let call = calls.fetch_add(1, Ordering::Relaxed);
+            async move {
+                assert_eq!(ctx.attempt, call);
+                if call < 2 {
+                    Err(unavailable())
+                } else {
+                    Ok(call)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 2);
+
+        // Fail after the retries are exhausted.
+        let err = call_with_retries(RpcContext::default(), &retries(1), |_| async {
+            Result::<()>::Err(unavailable())
+        })
+        .await
+        .unwrap_err();
+        assert!(matches!(err, Error::RetryExhausted { attempts: 2, .. }));
+        assert!(err.is_unavailable());
+
+        // The errors not caused by the unavailable servers are not retried.
+        let calls = AtomicU32::new(0);
+        let err = call_with_retries(RpcContext::default(), &retries(3), |_| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            async { Result::<()>::Err(Error::Client("bad request".to_string())) }
+        })
+        .await
+        .unwrap_err();
+        assert!(matches!(err, Error::Client(_)));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // The retries of the caller are not retried again.
+        let err = call_with_retries(
+            RpcContext::default().next_attempt(),
+            &retries(3),
+            |_| async { Result::<()>::Err(unavailable()) },
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.attempts(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_write_with_retries() {
+        // Only the writes failed before being sent are retried by default.
+        let retry = retries(1);
+        assert_eq!(retry.write_mode, WriteRetryMode::Unsent);
+        let err = write_with_retries(RpcContext::default(), &retry, |_| async {
+            Result::<()>::Err(unavailable())
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.attempts(), 1);
+        let err = write_with_retries(RpcContext::default(), &retry, |_| async {
+            Result::<()>::Err(Error::Connect {
+                addr: "127.0.0.1:8831".to_string(),
+                source: "refused".into(),
+            })
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.attempts(), 2);
+
+        // Any of the retryable errors are retried if opted in.
+        let retry = retries(1).write_mode(WriteRetryMode::Always);
+        let err = write_with_retries(RpcContext::default(), &retry, |_| async {
+            Result::<()>::Err(unavailable())
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.attempts(), 2);
+
+        // The request id is kept across the retries.
+        let retry = retries(2).write_mode(WriteRetryMode::RequestId);
+        let request_ids = Mutex::new(Vec::new());
+        let _ = write_with_retries(RpcContext::default(), &retry, |ctx| {
+            request_ids
+                .lock()
+                .unwrap()
+                .push(ctx.headers[REQUEST_ID_METADATA_KEY].clone());
+            async { Result::<()>::Err(unavailable()) }
+        })
+        .await;
+        let request_ids = request_ids.into_inner().unwrap();
+        assert_eq!(request_ids.len(), 3);
+        assert!(request_ids.iter().all(|id| *id == request_ids[0]));
+    }
+
+    #[tokio::test]
+    async fn test_throttled_calls() {
+        let throttled = || {
+            let mut status = tonic::Status::resource_exhausted("busy");
+            status
+                .metadata_mut()
+                .insert("retry-after", "0.01".parse().unwrap());
+            Error::Rpc(status)
+        };
+        let throttle = ThrottlePolicy {
+            budget: Duration::from_millis(100),
+            backoff: BackoffStrategy::Fixed(Duration::from_millis(10)),
+        };
+
+        // The throttled calls are not retried without the throttle policy.
+        let err = call_with_retries(RpcContext::default(), &retries(0), |_| async {
+            Result::<()>::Err(throttled())
+        })
+        .await
+        .unwrap_err();
+        assert!(matches!(err, Error::Rpc(_)));
+
+        // Resent until accepted by the server, independent of the retries.
+        let retry = retries(0).throttle(throttle.clone());
+        let calls = AtomicU32::new(0);
+        let result = write_with_retries(RpcContext::default(), &retry, |_| {
+            let call = calls.fetch_add(1, Ordering::Relaxed);
+            async move {
+                if call < 3 {
+                    Err(throttled())
+                } else {
+                    Ok(call)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+
+        // Fail once the budget is exhausted.
+        let err = call_with_retries(RpcContext::default(), &retry, |_| async {
+            Result::<()>::Err(throttled())
+        })
+        .await
+        .unwrap_err();
+        assert!(matches!(err, Error::Throttled { .. }));
+        assert!(err.attempts() > 1);
+        assert_eq!(throttled().retry_after(), Some(Duration::from_millis(10)));
+    }
+}
@@ -0,0 +1,100 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Pause of the writes during the maintenance of the cluster.
+
+use tokio::sync::watch;
+
+use crate::{Error, Result};
+
+/// How the writes are handled while the ingestion is paused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PausePolicy {
+    /// The writes wait until the ingestion is resumed.
+    Queue,
+    /// The writes fail with [`Error::Paused`] immediately.
+    Reject,
+}
+
+/// The gate of the writes, which is opened unless the ingestion is paused.
+#[derive(Debug)]
+pub(crate) struct IngestionGate {
+    state: watch::Sender<Option<PausePolicy>>,
+}
+
+impl Default for IngestionGate {
+    fn default() -> Self {
+        Self {
+            state: watch::channel(None).0,
+        }
+    }
+}
+
+impl IngestionGate {
+    pub fn pause(&self, policy: PausePolicy) {
+        self.state.send_replace(Some(policy));
+    }
+
+    pub fn resume(&self) {
+        self.state.send_replace(None);
+    }
+
+    /// Wait until the write is allowed, or fail if it is rejected.
+    pub async fn pass(&self) -> Result<()> {
+        let mut state = self.state.subscribe();
+        loop {
+            let policy = *state.borrow_and_update();
+            match policy {
+                None => return Ok(()),
+                Some(PausePolicy::Reject) => return Err(Error::Paused),
+                Some(PausePolicy::Queue) => {
+                    // The sender is held by self, so it never fails.
+                    let _ = state.changed().await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use super::{IngestionGate, PausePolicy};
+    use crate::Error;
+
+    #[tokio::test]
+    async fn test_ingestion_gate() {
+        let gate = Arc::new(IngestionGate::default());
+        gate.pass().await.unwrap();
+
+        gate.pause(PausePolicy::Reject);
+        assert!(matches!(gate.pass().await, Err(Error::Paused)));
+
+        gate.pause(PausePolicy::Queue);
+        let queued = tokio::spawn({
+            let gate = gate.clone();
+            async move { gate.pass().await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!queued.is_finished());
+
+        gate.resume();
+        queued.await.unwrap().unwrap();
+        gate.pass().await.unwrap();
+    }
+}
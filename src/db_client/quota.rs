@@ -0,0 +1,95 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Client-side quotas, which bound the load of one process on the shared
+//! cluster.
+
+use crate::{model::write::Request as WriteRequest, Error, Result};
+
+/// The quotas of the client enforced before the calls are sent, see
+/// [`Builder::quota`](crate::Builder::quota).
+///
+/// They are shared by the calls to all the servers, and unlike the server-side
+/// limits, they can't be bypassed by the per-call options.
+#[derive(Debug, Clone, Default)]
+pub struct ClientQuota {
+    /// The max rows of one write, and the larger writes fail with
+    /// [`Error::QuotaExceeded`] without being sent.
+    ///
+    /// Default value is None, i.e. unlimited.
+    pub max_rows_per_write: Option<usize>,
+    /// The max concurrent queries, and the extra ones wait for the permits in
+    /// the order of their priorities within their timeouts and deadlines.
+    ///
+    /// Default value is None, i.e. unlimited.
+    pub max_concurrent_queries: Option<usize>,
+}
+
+impl ClientQuota {
+    /// Check the write `req` against the quota.
+    pub(crate) fn check_write(&self, req: &WriteRequest) -> Result<()> {
+        let Some(max_rows) = self.max_rows_per_write else {
+            return Ok(());
+        };
+        let rows = req.point_groups.values().map(Vec::len).sum::<usize>();
+        if rows > max_rows {
+            return Err(Error::QuotaExceeded(format!(
+                "too many rows in one write, rows:{rows}, max_rows_per_write:{max_rows}"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ClientQuota;
+    use crate::{
+        model::{
+            value::Value,
+            write::{point::PointBuilder, Request as WriteRequest},
+        },
+        Error,
+    };
+
+    #[test]
+    fn test_check_write() {
+        let mut req = WriteRequest::default();
+        for ts in 0..3 {
+            let point = PointBuilder::new("t")
+                .timestamp(ts)
+                .field("v", Value::Int32(1))
+                .build()
+                .unwrap();
+            req.add_point(point);
+        }
+
+        assert!(ClientQuota::default().check_write(&req).is_ok());
+        let quota = ClientQuota {
+            max_rows_per_write: Some(3),
+            ..Default::default()
+        };
+        assert!(quota.check_write(&req).is_ok());
+        let quota = ClientQuota {
+            max_rows_per_write: Some(2),
+            ..Default::default()
+        };
+        let err = quota.check_write(&req).unwrap_err();
+        assert!(matches!(err, Error::QuotaExceeded(_)));
+    }
+}
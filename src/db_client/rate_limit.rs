@@ -0,0 +1,176 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Client-side rate limit of the writes.
+
+use std::{sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::{Error, Result};
+
+/// How the writes exceeding the [`WriteRateLimit`] are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RateLimitPolicy {
+    /// The writes wait until they are allowed by the rate limit.
+    #[default]
+    Wait,
+    /// The writes fail with [`Error::RateLimited`] immediately.
+    Reject,
+}
+
+/// The rate limit of the writes of the client, shared by the writes to all
+/// the servers.
+///
+/// The rows and the bytes are limited by the token buckets holding the tokens
+/// of one second at most, so the bursts up to the rates are allowed. The write
+/// larger than the bucket is allowed once the bucket is full, and the following
+/// writes wait for the tokens it has overdrawn.
+#[derive(Debug, Clone, Default)]
+pub struct WriteRateLimit {
+    /// The max rows written per second.
+    ///
+    /// Default value is None, i.e. unlimited.
+    pub rows_per_sec: Option<u64>,
+    /// The max bytes of the encoded write requests per second.
+    ///
+    /// Default value is None, i.e. unlimited.
+    pub bytes_per_sec: Option<u64>,
+    /// Default value is [`RateLimitPolicy::Wait`].
+    pub policy: RateLimitPolicy,
+}
+
+/// Token bucket tracked by the time when it's full again, i.e. the theoretical
+/// arrival time of the generic cell rate algorithm.
+#[derive(Debug)]
+struct Bucket {
+    rate: u64,
+    full_at: Option<Instant>,
+}
+
+impl Bucket {
+    /// The capacity of the bucket in time.
+    const BURST: Duration = Duration::from_secs(1);
+
+    fn new(rate: u64) -> Self {
+        assert!(rate > 0, "rate limit should be positive");
+        Self {
+            rate,
+            full_at: None,
+        }
+    }
+
+    /// The time when the bucket is full again after taking the `tokens`, and
+    /// the tokens are available once it's within the burst.
+    fn full_at_after(&self, tokens: u64, now: Instant) -> Instant {
+        let cost = Duration::from_secs_f64(tokens as f64 / self.rate as f64);
+        self.full_at.map_or(now, |full_at| full_at.max(now)) + cost
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct WriteRateLimiter {
+    policy: RateLimitPolicy,
+    /// The buckets of the rows and the bytes.
+    buckets: Mutex<[Option<Bucket>; 2]>,
+}
+
+impl WriteRateLimiter {
+    pub fn new(limit: &WriteRateLimit) -> Self {
+        Self {
+            policy: limit.policy,
+            buckets: Mutex::new([
+                limit.rows_per_sec.map(Bucket::new),
+                limit.bytes_per_sec.map(Bucket::new),
+            ]),
+        }
+    }
+
+    /// Take the tokens of the `rows` and the `bytes` of the write, and wait
+    /// until they are available or fail following the policy.
+    pub async fn acquire(&self, rows: u64, bytes: u64) -> Result<()> {
+        let ready_at = {
+            let now = Instant::now();
+            let mut buckets = self.buckets.lock().unwrap();
+            let full_at = buckets
+                .iter()
+                .zip([rows, bytes])
+                .map(|(bucket, tokens)| bucket.as_ref().map(|b| b.full_at_after(tokens, now)))
+                .collect::<Vec<_>>();
+            let ready_at = full_at
+                .iter()
+                .flatten()
+                .map(|full_at| full_at.checked_sub(Bucket::BURST).unwrap_or(now))
+                .max()
+                .unwrap_or(now);
+            if ready_at > now && self.policy == RateLimitPolicy::Reject {
+                return Err(Error::RateLimited);
+            }
+            for (bucket, full_at) in buckets.iter_mut().zip(full_at) {
+                if let Some(bucket) = bucket {
+                    bucket.full_at = full_at;
+                }
+            }
+            ready_at
+        };
+
+        tokio::time::sleep_until(ready_at).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{RateLimitPolicy, WriteRateLimit, WriteRateLimiter};
+    use crate::Error;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_write_rate_limiter() {
+        let limiter = WriteRateLimiter::new(&WriteRateLimit {
+            rows_per_sec: Some(100),
+            bytes_per_sec: Some(1000),
+            policy: RateLimitPolicy::Wait,
+        });
+        let start = Instant::now();
+        // The burst of one second is allowed.
+        limiter.acquire(100, 10).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        // Wait for the rows.
+        limiter.acquire(50, 10).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+        // Wait for the bytes.
+        limiter.acquire(0, 2500).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+
+        let limiter = WriteRateLimiter::new(&WriteRateLimit {
+            rows_per_sec: Some(100),
+            bytes_per_sec: None,
+            policy: RateLimitPolicy::Reject,
+        });
+        limiter.acquire(100, 10).await.unwrap();
+        assert!(matches!(
+            limiter.acquire(1, 0).await,
+            Err(Error::RateLimited)
+        ));
+        tokio::time::advance(Duration::from_millis(10)).await;
+        limiter.acquire(1, 0).await.unwrap();
+    }
+}
@@ -15,18 +15,23 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use async_trait::async_trait;
+use futures::future::join_all;
 
 use crate::{
-    db_client::{inner::InnerClient, DbClient},
+    db_client::{
+        inner::InnerClient, ClientOptions, ConnectionState, DbClient, MetricsSnapshot, PausePolicy,
+        RowBatchStream, ServerCapabilities,
+    },
     model::{
-        sql_query::{Request as SqlQueryRequest, Response as SqlQueryResponse},
+        route::Endpoint,
+        sql_query::{row::Row, Request as SqlQueryRequest, Response as SqlQueryResponse},
         write::{Request as WriteRequest, Response as WriteResponse},
     },
-    rpc_client::{RpcClientFactory, RpcContext},
-    Result,
+    rpc_client::{RpcClient, RpcClientFactory, RpcContext},
+    Error, Result,
 };
 
 /// Client for horaedb of standalone mode.
@@ -34,27 +39,171 @@ use crate::{
 /// Now, [`RawImpl`] just wraps [`InnerClient`] simply.
 pub struct RawImpl<F: RpcClientFactory> {
     inner_client: InnerClient<F>,
-    default_database: Option<String>,
+    options: ClientOptions,
 }
 
 impl<F: RpcClientFactory> RawImpl<F> {
-    pub fn new(factory: Arc<F>, endpoint: String, default_database: Option<String>) -> Self {
+    pub fn new(factory: Arc<F>, endpoint: String, options: ClientOptions) -> Self {
         Self {
-            inner_client: InnerClient::new(factory, endpoint),
-            default_database,
+            inner_client: InnerClient::new(factory, endpoint)
+                .with_rate_limiter(options.write_rate_limiter.clone())
+                .with_concurrency_limit(options.concurrency_limit.clone())
+                .with_query_concurrency_limit(options.query_concurrency_limit.clone())
+                .with_shutdown_gate(options.shutdown_gate.clone())
+                .with_metrics(options.metrics.clone())
+                .with_slow_request_log(options.slow_request_log.clone()),
+            options,
         }
     }
+
+    /// Connect to the endpoint eagerly.
+    pub async fn connect(&self) -> Result<()> {
+        self.inner_client.connect().await
+    }
 }
 
 #[async_trait]
 impl<F: RpcClientFactory> DbClient for RawImpl<F> {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "horaedb.sql_query", skip_all, fields(tables = req.tables.len()))
+    )]
     async fn sql_query(&self, ctx: &RpcContext, req: &SqlQueryRequest) -> Result<SqlQueryResponse> {
-        let ctx = crate::db_client::resolve_database(ctx, &self.default_database)?;
-        self.inner_client.sql_query_internal(&ctx, req).await
+        crate::db_client::check_sql_query_request(req)?;
+        let ctx = crate::db_client::resolve_database(ctx, &self.options.default_database)?;
+        let query = self.options.with_retries(&ctx, |ctx| async move {
+            self.inner_client.sql_query_internal(&ctx, req).await
+        });
+        self.options
+            .cached_query(&ctx, req, query)
+            .await
+            .map(|resp| self.options.transform_rows(resp))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "horaedb.sql_query_for_each", skip_all, fields(tables = req.tables.len()))
+    )]
+    async fn sql_query_for_each(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+        on_row: &mut (dyn FnMut(Row) + Send),
+    ) -> Result<u32> {
+        crate::db_client::check_sql_query_request(req)?;
+        let ctx = crate::db_client::resolve_database(ctx, &self.options.default_database)?;
+        self.inner_client
+            .sql_query_for_each_internal(&ctx, req, &mut |mut row| {
+                self.options.transform_row(&mut row);
+                on_row(row)
+            })
+            .await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "horaedb.sql_query_stream", skip_all, fields(tables = req.tables.len()))
+    )]
+    async fn sql_query_stream(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+    ) -> Result<RowBatchStream> {
+        crate::db_client::check_sql_query_request(req)?;
+        let ctx = crate::db_client::resolve_database(ctx, &self.options.default_database)?;
+        let stream = self
+            .inner_client
+            .sql_query_stream_internal(&ctx, req)
+            .await?;
+        Ok(self.options.transform_stream(stream))
+    }
+
+    async fn health_check(&self, ctx: &RpcContext) -> Result<()> {
+        self.inner_client.health_check_internal(ctx).await
+    }
+
+    /// Connect to the server, and the tables are all served by it.
+    async fn warm_up(&self, _ctx: &RpcContext, _tables: &[String]) -> Result<()> {
+        self.inner_client.connect().await
+    }
+
+    /// All the tables are served by the server of the endpoint.
+    async fn route(&self, _ctx: &RpcContext, tables: &[String]) -> Result<Vec<Option<Endpoint>>> {
+        let endpoint = self.inner_client.endpoint().parse().map_err(|e| {
+            Error::Client(format!(
+                "failed to parse endpoint:{}, err:{e}",
+                self.inner_client.endpoint()
+            ))
+        })?;
+        Ok(vec![Some(endpoint); tables.len()])
+    }
+
+    async fn raw(&self, _ctx: &RpcContext, _table: Option<&str>) -> Result<Arc<dyn RpcClient>> {
+        self.inner_client.rpc_client().await
+    }
+
+    async fn capabilities(&self, ctx: &RpcContext) -> Result<ServerCapabilities> {
+        let client = self.inner_client.rpc_client();
+        self.options.capabilities(ctx, client).await
     }
 
+    fn connection_states(&self) -> Vec<ConnectionState> {
+        vec![self.inner_client.state()]
+    }
+
+    fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.options.metrics.snapshot()
+    }
+
+    fn pause(&self, policy: PausePolicy) {
+        self.options.ingestion_gate.pause(policy);
+    }
+
+    fn resume(&self) {
+        self.options.ingestion_gate.resume();
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> Result<()> {
+        let drained = self.options.shutdown_gate.shutdown(timeout).await;
+        self.inner_client.close();
+        drained
+    }
+
+    async fn dry_run_write(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+        self.options.dry_run_write(ctx, req)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "horaedb.write", skip_all, fields(tables = req.point_groups.len()))
+    )]
     async fn write(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
-        let ctx = crate::db_client::resolve_database(ctx, &self.default_database)?;
-        self.inner_client.write_internal(&ctx, req).await
+        let ctx = crate::db_client::resolve_database(ctx, &self.options.default_database)?;
+        self.options.ingestion_gate.pass().await?;
+        let req = self.options.sample_write(req);
+        if req.is_empty() {
+            return Ok(WriteResponse::new(0, 0));
+        }
+        self.options.quota.check_write(&req)?;
+        self.options.record_write(&req);
+        let _invalidation = self.options.invalidate_cache_on_drop(&ctx, &req);
+
+        let reqs = self.options.split_write(req.into_owned());
+        let write = |req| {
+            self.options
+                .with_write_retries(&ctx, move |ctx| async move {
+                    self.inner_client.write_internal(&ctx, req).await
+                })
+        };
+        if reqs.len() == 1 {
+            return write(&reqs[0]).await;
+        }
+
+        let write_tables: Vec<Vec<_>> = reqs
+            .iter()
+            .map(|req| req.point_groups.keys().cloned().collect())
+            .collect();
+        let results = join_all(reqs.iter().map(write)).await;
+        crate::db_client::merge_write_results(write_tables.into_iter().zip(results).collect())
     }
 }
@@ -15,23 +15,38 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use dashmap::DashMap;
-use futures::future::join_all;
-use tokio::sync::OnceCell;
+use futures::future::{join_all, try_join_all};
+use tokio::{sync::OnceCell, time::Instant};
 
 use crate::{
-    db_client::{inner::InnerClient, DbClient},
-    errors::RouteBasedWriteError,
+    db_client::{
+        circuit_breaker::{CircuitBreaker, CircuitBreakerConfig},
+        concurrency::ConcurrencyLimiter,
+        hedge,
+        inner::InnerClient,
+        metrics::ClientMetrics,
+        rate_limit::WriteRateLimiter,
+        shutdown::ShutdownGate,
+        slow::SlowRequestLog,
+        ClientOptions, ConnectionState, DbClient, MetricsSnapshot, PausePolicy, RowBatchStream,
+        ServerCapabilities,
+    },
     model::{
         route::Endpoint,
-        sql_query::{Request as SqlQueryRequest, Response as SqlQueryResponse},
+        sql_query::{row::Row, Request as SqlQueryRequest, Response as SqlQueryResponse},
         write::{Request as WriteRequest, Response as WriteResponse},
     },
     router::{Router, RouterImpl},
-    rpc_client::{RpcClientFactory, RpcContext},
+    rpc_client::{RpcClient, RpcClientFactory, RpcContext},
     util::should_refresh,
     Error, Result,
 };
@@ -42,74 +57,376 @@ pub struct RouteBasedImpl<F: RpcClientFactory> {
     router_endpoint: String,
     router: OnceCell<Box<dyn Router>>,
     standalone_pool: DirectClientPool<F>,
-    default_database: Option<String>,
+    options: ClientOptions,
 }
 
 impl<F: RpcClientFactory> RouteBasedImpl<F> {
-    pub fn new(factory: Arc<F>, router_endpoint: String, default_database: Option<String>) -> Self {
+    pub fn new(factory: Arc<F>, router_endpoint: String, options: ClientOptions) -> Self {
         Self {
             factory: factory.clone(),
             router_endpoint,
             router: OnceCell::new(),
-            standalone_pool: DirectClientPool::new(factory),
-            default_database,
+            standalone_pool: DirectClientPool::new(factory, &options),
+            options,
         }
     }
 
+    /// Route the tables by the `router` instead of the [`RouterImpl`] built on
+    /// the router endpoint.
+    pub fn with_router(mut self, router: Box<dyn Router>) -> Self {
+        self.router = OnceCell::new_with(Some(router));
+        self
+    }
+
+    /// Connect to the router endpoint eagerly.
+    pub async fn connect(&self) -> Result<()> {
+        self.router
+            .get_or_try_init(|| self.init_router())
+            .await
+            .map(|_| ())
+    }
+
     async fn init_router(&self) -> Result<Box<dyn Router>> {
         let router_client = self.factory.build(self.router_endpoint.clone()).await?;
-        let default_endpoint: Endpoint = self.router_endpoint.parse().map_err(|e| {
+        let default_endpoint = self.parse_router_endpoint()?;
+        let mut router = RouterImpl::new(default_endpoint, router_client)
+            .with_metrics(self.options.metrics.clone());
+        if let Some(ttl) = self.options.route_ttl {
+            router = router.with_route_ttl(ttl);
+        }
+        let Some(config) = &self.options.route_refresh else {
+            return Ok(Box::new(router));
+        };
+        let router = Arc::new(router);
+        router.spawn_refresh(config.clone());
+        Ok(Box::new(router))
+    }
+
+    fn parse_router_endpoint(&self) -> Result<Endpoint> {
+        self.router_endpoint.parse().map_err(|e| {
             Error::Client(format!(
                 "Failed to parse default endpoint:{}, err:{}",
                 self.router_endpoint, e
             ))
-        })?;
-        Ok(Box::new(RouterImpl::new(default_endpoint, router_client)))
+        })
     }
-}
 
-#[async_trait]
-impl<F: RpcClientFactory> DbClient for RouteBasedImpl<F> {
-    async fn sql_query(&self, ctx: &RpcContext, req: &SqlQueryRequest) -> Result<SqlQueryResponse> {
+    /// Route the `tables` by the `router`, and choose among the replicas
+    /// reported by the router by the [`LoadBalancePolicy`] if set.
+    ///
+    /// [`LoadBalancePolicy`]: crate::LoadBalancePolicy
+    async fn route_tables(
+        &self,
+        router: &dyn Router,
+        tables: &[String],
+        ctx: &RpcContext,
+    ) -> Result<Vec<Option<Endpoint>>> {
+        let mut endpoints = router.route(tables, ctx).await?;
+        let Some(policy) = &self.options.load_balance_policy else {
+            return Ok(endpoints);
+        };
+
+        for (table, endpoint) in tables.iter().zip(&mut endpoints) {
+            let replicas = router.replicas(table);
+            if endpoint.is_none() || replicas.len() < 2 {
+                continue;
+            }
+            if let Some(replica) = replicas.get(policy.select(table, &replicas)) {
+                *endpoint = Some(replica.clone());
+            }
+        }
+        Ok(endpoints)
+    }
+
+    async fn query_endpoint(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+        endpoint: &Endpoint,
+        client: Arc<InnerClient<F>>,
+    ) -> Result<SqlQueryResponse> {
+        let start = std::time::Instant::now();
+        let result = self
+            .standalone_pool
+            .call(endpoint, client.sql_query_internal(ctx, req))
+            .await;
+        self.options.observe_latency(endpoint, start, &result);
+        result
+    }
+
+    /// Another replica to hedge the query to, if the query can be hedged.
+    fn hedge_endpoint(
+        &self,
+        ctx: &RpcContext,
+        router: &dyn Router,
+        req: &SqlQueryRequest,
+        primary: &Endpoint,
+    ) -> Option<Endpoint> {
+        if self.options.hedging_config.is_none() || !ctx.hedge || !hedge::is_idempotent(&req.sql) {
+            return None;
+        }
+        // The hedge goes to another replica of the tables served by the primary,
+        // which are only reported by the custom routers.
+        req.tables.iter().find_map(|table| {
+            let replicas = router.replicas(table);
+            if !replicas.contains(primary) {
+                return None;
+            }
+            replicas.into_iter().find(|endpoint| endpoint != primary)
+        })
+    }
+
+    /// Query the server serving the tables, and hedge it to another replica
+    /// if configured.
+    async fn sql_query_once(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+    ) -> Result<SqlQueryResponse> {
+        let (ctx, router_handle, endpoint, client) = self.route_query(ctx, req).await?;
+        let primary = self.query_endpoint(&ctx, req, &endpoint, client);
+        let hedge_endpoint = self.hedge_endpoint(&ctx, router_handle, req, &endpoint);
+        let result = match (&self.options.hedging_config, hedge_endpoint) {
+            (Some(policy), Some(hedge_endpoint)) => {
+                let hedge_client = self.standalone_pool.get_or_create(&hedge_endpoint);
+                let hedge = self.query_endpoint(&ctx, req, &hedge_endpoint, hedge_client);
+                hedge::hedge(policy.delay, primary, hedge).await
+            }
+            _ => primary.await,
+        };
+        result
+            .map(|resp| self.options.transform_rows(resp))
+            .inspect_err(|_| router_handle.evict(&req.tables))
+    }
+
+    /// Route the query to the client of the endpoint serving the tables.
+    ///
+    /// The sql can't be split by the tables, so the query touching the tables
+    /// on different endpoints is sent to the one serving the most of them (see
+    /// [`query_endpoint_of`]), which has to read the others remotely.
+    async fn route_query(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+    ) -> Result<(RpcContext, &dyn Router, Endpoint, Arc<InnerClient<F>>)> {
+        crate::db_client::check_sql_query_request(req)?;
         if req.tables.is_empty() {
             return Err(Error::Unknown(
                 "tables in query request can't be empty in route based mode".to_string(),
             ));
         }
-        let ctx = crate::db_client::resolve_database(ctx, &self.default_database)?;
+        let ctx = crate::db_client::resolve_database(ctx, &self.options.default_database)?;
 
         let router_handle = self.router.get_or_try_init(|| self.init_router()).await?;
 
-        let endpoint = match router_handle.route(&req.tables, &ctx).await {
-            Ok(mut eps) => {
-                if let Some(ep) = eps[0].take() {
-                    ep
-                } else {
-                    return Err(Error::Unknown(
-                        "table doesn't have corresponding endpoint".to_string(),
-                    ));
-                }
-            }
-            Err(e) => {
-                return Err(e);
-            }
+        let eps = self
+            .route_tables(router_handle.as_ref(), &req.tables, &ctx)
+            .await?;
+        let Some(endpoint) = query_endpoint_of(eps) else {
+            return Err(Error::Unknown(
+                "table doesn't have corresponding endpoint".to_string(),
+            ));
         };
 
-        let client = self.standalone_pool.get_or_create(&endpoint).clone();
+        let client = self.standalone_pool.get_or_create(&endpoint);
 
-        client.sql_query_internal(&ctx, req).await.map_err(|e| {
-            router_handle.evict(&req.tables);
-            e
-        })
+        Ok((ctx, router_handle.as_ref(), endpoint, client))
     }
+}
 
+#[async_trait]
+impl<F: RpcClientFactory> DbClient for RouteBasedImpl<F> {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "horaedb.sql_query", skip_all, fields(tables = req.tables.len()))
+    )]
+    async fn sql_query(&self, ctx: &RpcContext, req: &SqlQueryRequest) -> Result<SqlQueryResponse> {
+        // The query is routed again by the retries.
+        let query =
+            self.options.with_retries(
+                ctx,
+                |ctx| async move { self.sql_query_once(&ctx, req).await },
+            );
+        self.options.cached_query(ctx, req, query).await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "horaedb.sql_query_for_each", skip_all, fields(tables = req.tables.len()))
+    )]
+    async fn sql_query_for_each(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+        on_row: &mut (dyn FnMut(Row) + Send),
+    ) -> Result<u32> {
+        let (ctx, router_handle, endpoint, client) = self.route_query(ctx, req).await?;
+        let start = std::time::Instant::now();
+        let mut on_row = |mut row| {
+            self.options.transform_row(&mut row);
+            on_row(row)
+        };
+        let query = client.sql_query_for_each_internal(&ctx, req, &mut on_row);
+        let result = self.standalone_pool.call(&endpoint, query).await;
+        self.options.observe_latency(&endpoint, start, &result);
+        result.inspect_err(|_| router_handle.evict(&req.tables))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "horaedb.sql_query_stream", skip_all, fields(tables = req.tables.len()))
+    )]
+    async fn sql_query_stream(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+    ) -> Result<RowBatchStream> {
+        let (ctx, router_handle, endpoint, client) = self.route_query(ctx, req).await?;
+        let stream = self
+            .standalone_pool
+            .call(&endpoint, client.sql_query_stream_internal(&ctx, req))
+            .await
+            .inspect_err(|_| router_handle.evict(&req.tables))?;
+        Ok(self.options.transform_stream(stream))
+    }
+
+    /// Check the server of the router endpoint.
+    async fn health_check(&self, ctx: &RpcContext) -> Result<()> {
+        let endpoint = self.parse_router_endpoint()?;
+        self.standalone_pool
+            .get_or_create(&endpoint)
+            .health_check_internal(ctx)
+            .await
+    }
+
+    async fn warm_up(&self, ctx: &RpcContext, tables: &[String]) -> Result<()> {
+        let ctx = crate::db_client::resolve_database(ctx, &self.options.default_database)?;
+        let router_handle = self.router.get_or_try_init(|| self.init_router()).await?;
+        let endpoints: HashSet<_> = router_handle
+            .route(tables, &ctx)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let clients: Vec<_> = endpoints
+            .iter()
+            .map(|ep| self.standalone_pool.get_or_create(ep))
+            .collect();
+        try_join_all(clients.iter().map(|client| client.connect()))
+            .await
+            .map(|_| ())
+    }
+
+    fn clear_routes(&self) {
+        if let Some(router) = self.router.get() {
+            router.clear();
+        }
+    }
+
+    async fn prefetch(&self, ctx: &RpcContext, tables: &[String]) -> Result<()> {
+        let ctx = crate::db_client::resolve_database(ctx, &self.options.default_database)?;
+        let router_handle = self.router.get_or_try_init(|| self.init_router()).await?;
+        router_handle.prefetch(tables, &ctx).await
+    }
+
+    async fn route(&self, ctx: &RpcContext, tables: &[String]) -> Result<Vec<Option<Endpoint>>> {
+        let ctx = crate::db_client::resolve_database(ctx, &self.options.default_database)?;
+        let router_handle = self.router.get_or_try_init(|| self.init_router()).await?;
+        router_handle.route(tables, &ctx).await
+    }
+
+    async fn raw(&self, ctx: &RpcContext, table: Option<&str>) -> Result<Arc<dyn RpcClient>> {
+        let Some(table) = table else {
+            let endpoint = self.parse_router_endpoint()?;
+            return self
+                .standalone_pool
+                .get_or_create(&endpoint)
+                .rpc_client()
+                .await;
+        };
+
+        let tables = [table.to_string()];
+        let ctx = crate::db_client::resolve_database(ctx, &self.options.default_database)?;
+        let router_handle = self.router.get_or_try_init(|| self.init_router()).await?;
+        let endpoint = router_handle.route(&tables, &ctx).await?.remove(0);
+        let Some(endpoint) = endpoint else {
+            return Err(Error::Unknown(
+                "table doesn't have corresponding endpoint".to_string(),
+            ));
+        };
+        self.standalone_pool
+            .get_or_create(&endpoint)
+            .rpc_client()
+            .await
+    }
+
+    /// The capabilities of the router server, which are assumed to be the same
+    /// for all the servers of the cluster.
+    async fn capabilities(&self, ctx: &RpcContext) -> Result<ServerCapabilities> {
+        let client = self.raw(ctx, None);
+        self.options.capabilities(ctx, client).await
+    }
+
+    /// The states of the connections to the servers having been called, sorted
+    /// by the endpoints.
+    fn connection_states(&self) -> Vec<ConnectionState> {
+        let mut states: Vec<_> = self
+            .standalone_pool
+            .pool
+            .iter()
+            .map(|client| client.value().state())
+            .collect();
+        states.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+        states
+    }
+
+    fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.options.metrics.snapshot()
+    }
+
+    fn pause(&self, policy: PausePolicy) {
+        self.options.ingestion_gate.pause(policy);
+    }
+
+    fn resume(&self) {
+        self.options.ingestion_gate.resume();
+    }
+
+    /// The clients to the data nodes are dropped after the draining, and the
+    /// connections to the data nodes and the router are closed.
+    async fn shutdown(&self, timeout: Duration) -> Result<()> {
+        let drained = self.options.shutdown_gate.shutdown(timeout).await;
+        self.standalone_pool.close();
+        self.factory.evict(&self.router_endpoint);
+        drained
+    }
+
+    async fn dry_run_write(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+        self.options.dry_run_write(ctx, req)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "horaedb.write", skip_all, fields(tables = req.point_groups.len()))
+    )]
     async fn write(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
-        let ctx = crate::db_client::resolve_database(ctx, &self.default_database)?;
+        let ctx = crate::db_client::resolve_database(ctx, &self.options.default_database)?;
+        self.options.ingestion_gate.pass().await?;
+        let req = self.options.sample_write(req);
+        if req.is_empty() {
+            return Ok(WriteResponse::new(0, 0));
+        }
+        self.options.quota.check_write(&req)?;
+        self.options.record_write(&req);
+        let _invalidation = self.options.invalidate_cache_on_drop(&ctx, &req);
 
         // Get tables' related endpoints(some may not exist).
         let should_routes: Vec<_> = req.point_groups.keys().cloned().collect();
         let router_handle = self.router.get_or_try_init(|| self.init_router()).await?;
-        let endpoints = router_handle.route(&should_routes, &ctx).await?;
+        let endpoints = self
+            .route_tables(router_handle.as_ref(), &should_routes, &ctx)
+            .await?;
 
         // Partition write entries in request according to related endpoints.
         let mut no_corresponding_endpoints = Vec::new();
@@ -119,9 +436,13 @@ impl<F: RpcClientFactory> DbClient for RouteBasedImpl<F> {
             .zip(should_routes)
             .for_each(|(ep, m)| match ep {
                 Some(ep) => {
-                    let write_req = partition_by_endpoint
-                        .entry(ep)
-                        .or_insert_with(WriteRequest::default);
+                    let write_req =
+                        partition_by_endpoint
+                            .entry(ep)
+                            .or_insert_with(|| WriteRequest {
+                                order: req.order,
+                                ..Default::default()
+                            });
                     write_req.point_groups.insert(
                         m.clone(),
                         req.point_groups.get(m.as_str()).cloned().unwrap(),
@@ -132,21 +453,67 @@ impl<F: RpcClientFactory> DbClient for RouteBasedImpl<F> {
                 }
             });
 
-        // Get client and send.
-        let mut write_tables = vec![Vec::new(); partition_by_endpoint.len()];
-        let client_req_paris: Vec<_> = partition_by_endpoint
-            .into_iter()
-            .enumerate()
-            .map(|(idx, (ep, req))| {
-                assert!(idx < write_tables.len());
-                write_tables[idx].extend(req.point_groups.keys().cloned());
-                (self.standalone_pool.get_or_create(&ep), req)
-            })
-            .collect();
+        // Get client and send, the request of one endpoint may be split into
+        // multiple ones.
+        let mut write_tables = Vec::with_capacity(partition_by_endpoint.len());
+        let mut client_req_paris = Vec::with_capacity(partition_by_endpoint.len());
+        for (ep, req) in partition_by_endpoint {
+            let client = self.standalone_pool.get_or_create(&ep);
+            for req in self.options.split_write(req) {
+                write_tables.push(req.point_groups.keys().cloned().collect::<Vec<_>>());
+                client_req_paris.push((ep.clone(), client.clone(), req));
+            }
+        }
+
+        // Connect to the endpoints in parallel within the budget, and the tables
+        // whose endpoints fail to connect in time are reported as failed.
+        let mut connect_failures = Vec::new();
+        if let Some(budget) = self.options.connect_budget {
+            let deadline = Instant::now() + budget;
+            let connect_results = join_all(
+                client_req_paris
+                    .iter()
+                    .map(|(_, client, _)| client.connect_before(deadline)),
+            )
+            .await;
+
+            let mut connected_pairs = Vec::with_capacity(client_req_paris.len());
+            let mut connected_tables = Vec::with_capacity(client_req_paris.len());
+            for ((pair, tables), result) in client_req_paris
+                .into_iter()
+                .zip(write_tables)
+                .zip(connect_results)
+            {
+                match result {
+                    Ok(()) => {
+                        connected_pairs.push(pair);
+                        connected_tables.push(tables);
+                    }
+                    Err(e) => connect_failures.push((tables, Err(e))),
+                }
+            }
+            client_req_paris = connected_pairs;
+            write_tables = connected_tables;
+        }
+
         let mut futures = Vec::with_capacity(client_req_paris.len());
-        for (client, req) in client_req_paris {
+        for (ep, client, req) in client_req_paris {
             let ctx_clone = ctx.clone();
-            futures.push(async move { client.write_internal(&ctx_clone, &req).await })
+            futures.push(async move {
+                let start = std::time::Instant::now();
+                let result = self
+                    .options
+                    .with_write_retries(&ctx_clone, |ctx| {
+                        let (ep, client, req) = (&ep, &client, &req);
+                        async move {
+                            let write = client.write_internal(&ctx, req);
+                            self.standalone_pool.call(ep, write).await
+                        }
+                    })
+                    .await;
+                self.options.observe_latency(&ep, start, &result);
+                result
+            })
         }
 
         // Await rpc results and collect results.
@@ -156,6 +523,7 @@ impl<F: RpcClientFactory> DbClient for RouteBasedImpl<F> {
             .zip(write_tables)
             .map(|(results, tables)| (tables, results))
             .collect();
+        tables_result_pairs.extend(connect_failures);
 
         if !no_corresponding_endpoints.is_empty() {
             tables_result_pairs.push((
@@ -186,27 +554,92 @@ impl<F: RpcClientFactory> DbClient for RouteBasedImpl<F> {
             .collect();
         router_handle.evict(&evicts);
 
-        let route_based_error: RouteBasedWriteError = tables_result_pairs.into();
-        if route_based_error.all_ok() {
-            Ok(route_based_error.ok.1)
-        } else {
-            Err(Error::RouteBasedWriteError(route_based_error))
+        crate::db_client::merge_write_results(tables_result_pairs)
+    }
+}
+
+/// The endpoint serving the most of the queried tables by the routed `eps`,
+/// and the first routed one among the ties.
+///
+/// The tables without the endpoint are ignored, and `None` is returned if none
+/// of them is routed.
+fn query_endpoint_of(eps: Vec<Option<Endpoint>>) -> Option<Endpoint> {
+    let mut counts: Vec<(Endpoint, usize)> = Vec::new();
+    for ep in eps.into_iter().flatten() {
+        match counts.iter_mut().find(|(counted, _)| *counted == ep) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((ep, 1)),
         }
     }
+
+    // `max_by_key` returns the last max, so reverse to prefer the first routed.
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(ep, _)| ep)
 }
 
 /// DirectClientPool is the pool actually holding connections to data nodes.
 struct DirectClientPool<F: RpcClientFactory> {
     pool: DashMap<Endpoint, Arc<InnerClient<F>>>,
     factory: Arc<F>,
+    circuit_breaker: Option<CircuitBreakerConfig>,
+    breakers: DashMap<Endpoint, Arc<CircuitBreaker>>,
+    /// The rate limiter shared by the writes to all the data nodes.
+    write_rate_limiter: Option<Arc<WriteRateLimiter>>,
+    /// The concurrency limit shared by the calls to all the data nodes.
+    concurrency_limit: Option<Arc<ConcurrencyLimiter>>,
+    query_concurrency_limit: Option<Arc<ConcurrencyLimiter>>,
+    shutdown_gate: Arc<ShutdownGate>,
+    metrics: Arc<ClientMetrics>,
+    slow_request_log: Option<Arc<SlowRequestLog>>,
 }
 
 impl<F: RpcClientFactory> DirectClientPool<F> {
-    fn new(factory: Arc<F>) -> Self {
+    fn new(factory: Arc<F>, options: &ClientOptions) -> Self {
         Self {
             pool: DashMap::new(),
             factory,
+            circuit_breaker: options.circuit_breaker.clone(),
+            breakers: DashMap::new(),
+            write_rate_limiter: options.write_rate_limiter.clone(),
+            concurrency_limit: options.concurrency_limit.clone(),
+            query_concurrency_limit: options.query_concurrency_limit.clone(),
+            shutdown_gate: options.shutdown_gate.clone(),
+            metrics: options.metrics.clone(),
+            slow_request_log: options.slow_request_log.clone(),
+        }
+    }
+
+    /// Make the `call` to the `endpoint` through its circuit breaker if
+    /// configured.
+    async fn call<T>(
+        &self,
+        endpoint: &Endpoint,
+        call: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let Some(config) = &self.circuit_breaker else {
+            return call.await;
+        };
+
+        let breaker = self
+            .breakers
+            .entry(endpoint.clone())
+            .or_insert_with(|| Arc::new(CircuitBreaker::new(config.clone())))
+            .clone();
+        breaker.acquire(&endpoint.to_string())?;
+        let result = call.await;
+        breaker.record(&result);
+        result
+    }
+
+    /// Close and drop all the clients in the pool.
+    fn close(&self) {
+        for client in self.pool.iter() {
+            client.value().close();
         }
+        self.pool.clear();
     }
 
     fn get_or_create(&self, endpoint: &Endpoint) -> Arc<InnerClient<F>> {
@@ -217,11 +650,37 @@ impl<F: RpcClientFactory> DirectClientPool<F> {
             // If not exist, build --> insert --> return.
             self.pool
                 .entry(endpoint.clone())
-                .or_insert(Arc::new(InnerClient::new(
-                    self.factory.clone(),
-                    endpoint.to_string(),
-                )))
+                .or_insert(Arc::new(
+                    InnerClient::new(self.factory.clone(), endpoint.to_string())
+                        .with_rate_limiter(self.write_rate_limiter.clone())
+                        .with_concurrency_limit(self.concurrency_limit.clone())
+                        .with_query_concurrency_limit(self.query_concurrency_limit.clone())
+                        .with_shutdown_gate(self.shutdown_gate.clone())
+                        .with_metrics(self.metrics.clone())
+                        .with_slow_request_log(self.slow_request_log.clone()),
+                ))
                 .clone()
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::query_endpoint_of;
+    use crate::model::route::Endpoint;
+
+    #[test]
+    fn test_query_endpoint_of() {
+        let a = Endpoint::new("a".to_string(), 8831);
+        let b = Endpoint::new("b".to_string(), 8831);
+
+        let eps = vec![Some(a.clone()), Some(b.clone()), None, Some(b.clone())];
+        assert_eq!(query_endpoint_of(eps), Some(b.clone()));
+
+        // The first routed endpoint wins the ties.
+        let eps = vec![None, Some(b.clone()), Some(a.clone())];
+        assert_eq!(query_endpoint_of(eps), Some(b));
+
+        assert_eq!(query_endpoint_of(vec![None, None]), None);
+    }
+}
@@ -0,0 +1,300 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The [`DbClient`] operations as the tower [`Service`]s, so that the standard
+//! middleware, e.g. the timeouts, the rate limits and the metrics, can be
+//! composed around them.
+
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::future::{poll_fn, BoxFuture};
+use tower::{BoxError, Layer, Service};
+
+use crate::{
+    db_client::{
+        ConnectionState, DbClient, MetricsSnapshot, PausePolicy, RowBatchStream, ServerCapabilities,
+    },
+    model::{
+        route::Endpoint,
+        sql_query::{Request as SqlQueryRequest, Response as SqlQueryResponse},
+        write::{Request as WriteRequest, Response as WriteResponse},
+    },
+    rpc_client::{RpcClient, RpcContext},
+    Error, Result,
+};
+
+/// The [`Service`] of [`DbClient::sql_query`], which is always ready.
+#[derive(Clone)]
+pub struct QueryService {
+    client: Arc<dyn DbClient>,
+}
+
+impl QueryService {
+    pub fn new(client: Arc<dyn DbClient>) -> Self {
+        Self { client }
+    }
+}
+
+impl Service<(RpcContext, SqlQueryRequest)> for QueryService {
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<SqlQueryResponse>>;
+    type Response = SqlQueryResponse;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, (ctx, req): (RpcContext, SqlQueryRequest)) -> Self::Future {
+        let client = self.client.clone();
+        Box::pin(async move { client.sql_query(&ctx, &req).await })
+    }
+}
+
+/// The [`Service`] of [`DbClient::write`], which is always ready.
+#[derive(Clone)]
+pub struct WriteService {
+    client: Arc<dyn DbClient>,
+}
+
+impl WriteService {
+    pub fn new(client: Arc<dyn DbClient>) -> Self {
+        Self { client }
+    }
+}
+
+impl Service<(RpcContext, WriteRequest)> for WriteService {
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<WriteResponse>>;
+    type Response = WriteResponse;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, (ctx, req): (RpcContext, WriteRequest)) -> Self::Future {
+        let client = self.client.clone();
+        Box::pin(async move { client.write(&ctx, &req).await })
+    }
+}
+
+/// Convert the error of the middleware back, and the ones not from the client
+/// are wrapped as [`Error::Other`].
+fn into_error(err: impl Into<BoxError>) -> Error {
+    match err.into().downcast::<Error>() {
+        Ok(err) => *err,
+        Err(err) => Error::Other {
+            source: anyhow::anyhow!(err),
+        },
+    }
+}
+
+/// Call a clone of the `service` once it's ready.
+async fn call_service<S, Req>(service: &S, req: Req) -> Result<S::Response>
+where
+    S: Service<Req> + Clone,
+    S::Error: Into<BoxError>,
+{
+    let mut service = service.clone();
+    poll_fn(|cx| service.poll_ready(cx))
+        .await
+        .map_err(into_error)?;
+    service.call(req).await.map_err(into_error)
+}
+
+/// A [`DbClient`] sending the queries and the writes by the services layered
+/// around the [`QueryService`] and [`WriteService`] of the wrapped client.
+///
+/// The other operations, including the streaming queries, are sent by the
+/// wrapped client directly.
+pub struct LayeredClient<Q, W> {
+    client: Arc<dyn DbClient>,
+    query: Q,
+    write: W,
+}
+
+impl<Q, W> LayeredClient<Q, W> {
+    /// Wrap the services of the `client` by the `query_layer` and the
+    /// `write_layer` respectively, e.g. the ones built by the
+    /// [`ServiceBuilder`](tower::ServiceBuilder).
+    pub fn new<LQ, LW>(client: Arc<dyn DbClient>, query_layer: LQ, write_layer: LW) -> Self
+    where
+        LQ: Layer<QueryService, Service = Q>,
+        LW: Layer<WriteService, Service = W>,
+    {
+        Self {
+            query: query_layer.layer(QueryService::new(client.clone())),
+            write: write_layer.layer(WriteService::new(client.clone())),
+            client,
+        }
+    }
+}
+
+#[async_trait]
+impl<Q, W> DbClient for LayeredClient<Q, W>
+where
+    Q: Service<(RpcContext, SqlQueryRequest), Response = SqlQueryResponse>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    Q::Error: Into<BoxError>,
+    Q::Future: Send,
+    W: Service<(RpcContext, WriteRequest), Response = WriteResponse>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    W::Error: Into<BoxError>,
+    W::Future: Send,
+{
+    async fn sql_query(&self, ctx: &RpcContext, req: &SqlQueryRequest) -> Result<SqlQueryResponse> {
+        call_service(&self.query, (ctx.clone(), req.clone())).await
+    }
+
+    async fn write(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+        call_service(&self.write, (ctx.clone(), req.clone())).await
+    }
+
+    async fn dry_run_write(&self, ctx: &RpcContext, req: &WriteRequest) -> Result<WriteResponse> {
+        self.client.dry_run_write(ctx, req).await
+    }
+
+    async fn sql_query_stream(
+        &self,
+        ctx: &RpcContext,
+        req: &SqlQueryRequest,
+    ) -> Result<RowBatchStream> {
+        self.client.sql_query_stream(ctx, req).await
+    }
+
+    async fn health_check(&self, ctx: &RpcContext) -> Result<()> {
+        self.client.health_check(ctx).await
+    }
+
+    async fn warm_up(&self, ctx: &RpcContext, tables: &[String]) -> Result<()> {
+        self.client.warm_up(ctx, tables).await
+    }
+
+    fn pause(&self, policy: PausePolicy) {
+        self.client.pause(policy)
+    }
+
+    fn resume(&self) {
+        self.client.resume()
+    }
+
+    async fn shutdown(&self, timeout: Duration) -> Result<()> {
+        self.client.shutdown(timeout).await
+    }
+
+    async fn prefetch(&self, ctx: &RpcContext, tables: &[String]) -> Result<()> {
+        self.client.prefetch(ctx, tables).await
+    }
+
+    fn clear_routes(&self) {
+        self.client.clear_routes()
+    }
+
+    async fn route(&self, ctx: &RpcContext, tables: &[String]) -> Result<Vec<Option<Endpoint>>> {
+        self.client.route(ctx, tables).await
+    }
+
+    async fn raw(&self, ctx: &RpcContext, table: Option<&str>) -> Result<Arc<dyn RpcClient>> {
+        self.client.raw(ctx, table).await
+    }
+
+    async fn capabilities(&self, ctx: &RpcContext) -> Result<ServerCapabilities> {
+        self.client.capabilities(ctx).await
+    }
+
+    fn connection_states(&self) -> Vec<ConnectionState> {
+        self.client.connection_states()
+    }
+
+    fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.client.metrics_snapshot()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use async_trait::async_trait;
+    use tower::{layer::util::Identity, ServiceBuilder};
+
+    use super::LayeredClient;
+    use crate::{
+        db_client::{DbClient, PausePolicy},
+        model::{
+            sql_query::{Request as SqlQueryRequest, Response as SqlQueryResponse},
+            write::{Request as WriteRequest, Response as WriteResponse},
+        },
+        rpc_client::RpcContext,
+        Error, Result,
+    };
+
+    /// Client failing the queries, and writing slowly.
+    struct SlowClient;
+
+    #[async_trait]
+    impl DbClient for SlowClient {
+        async fn sql_query(&self, _: &RpcContext, _: &SqlQueryRequest) -> Result<SqlQueryResponse> {
+            Err(Error::Client("query failed".to_string()))
+        }
+
+        async fn write(&self, _: &RpcContext, _: &WriteRequest) -> Result<WriteResponse> {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok(WriteResponse::new(1, 0))
+        }
+
+        async fn health_check(&self, _: &RpcContext) -> Result<()> {
+            Ok(())
+        }
+
+        fn pause(&self, _: PausePolicy) {}
+
+        fn resume(&self) {}
+    }
+
+    #[tokio::test]
+    async fn test_layered_client() {
+        let ctx = RpcContext::default();
+        let req = SqlQueryRequest {
+            tables: vec!["t".to_string()],
+            sql: "select * from t".to_string(),
+        };
+        let write_layer = ServiceBuilder::new().timeout(Duration::from_millis(10));
+        let client = LayeredClient::new(Arc::new(SlowClient), Identity::new(), write_layer);
+
+        // The errors of the client are kept.
+        let err = client.sql_query(&ctx, &req).await.unwrap_err();
+        assert!(matches!(err, Error::Client(msg) if msg == "query failed"));
+
+        let err = client
+            .write(&ctx, &WriteRequest::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Other { .. }), "{err}");
+        assert!(client.health_check(&ctx).await.is_ok());
+    }
+}
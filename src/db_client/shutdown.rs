@@ -0,0 +1,123 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Graceful shutdown of the client.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::sync::watch;
+
+use crate::{Error, Result};
+
+/// The gate of the queries and writes, which is closed by the shutdown and
+/// tracks the calls in flight to be drained.
+#[derive(Debug)]
+pub(crate) struct ShutdownGate {
+    closed: AtomicBool,
+    in_flight: watch::Sender<usize>,
+}
+
+impl Default for ShutdownGate {
+    fn default() -> Self {
+        Self {
+            closed: AtomicBool::new(false),
+            in_flight: watch::channel(0).0,
+        }
+    }
+}
+
+/// The call in flight, which is finished when dropped.
+#[derive(Debug)]
+pub(crate) struct InFlightGuard {
+    gate: Arc<ShutdownGate>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.gate.in_flight.send_modify(|n| *n -= 1);
+    }
+}
+
+impl ShutdownGate {
+    /// Start a call, or fail with [`Error::Shutdown`] if the gate is closed.
+    pub fn enter(self: &Arc<Self>) -> Result<InFlightGuard> {
+        self.in_flight.send_modify(|n| *n += 1);
+        let guard = InFlightGuard { gate: self.clone() };
+        // Checked after the counting, so the shutdown never misses the call.
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(Error::Shutdown);
+        }
+        Ok(guard)
+    }
+
+    /// Whether the gate is closed by the shutdown.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Close the gate, and wait for the calls in flight to finish within the
+    /// `timeout`.
+    pub async fn shutdown(&self, timeout: Duration) -> Result<()> {
+        self.closed.store(true, Ordering::SeqCst);
+        let mut in_flight = self.in_flight.subscribe();
+        // The sender is held by self, so the waiting never fails.
+        let drained = tokio::time::timeout(timeout, in_flight.wait_for(|n| *n == 0))
+            .await
+            .is_ok();
+        if drained {
+            return Ok(());
+        }
+        Err(Error::Client(format!(
+            "shutdown timed out with {} calls in flight",
+            *self.in_flight.borrow()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use super::ShutdownGate;
+    use crate::Error;
+
+    #[tokio::test]
+    async fn test_shutdown_gate() {
+        let gate = Arc::new(ShutdownGate::default());
+        let guard = gate.enter().unwrap();
+
+        let err = gate.shutdown(Duration::from_millis(10)).await.unwrap_err();
+        assert!(matches!(err, Error::Client(_)));
+        assert!(matches!(gate.enter(), Err(Error::Shutdown)));
+
+        let shutdown = tokio::spawn({
+            let gate = gate.clone();
+            async move { gate.shutdown(Duration::from_secs(10)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!shutdown.is_finished());
+
+        drop(guard);
+        shutdown.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Reporting of the slow requests, which helps to hunt the tail latency.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use crate::model::{sql_query::Request as SqlQueryRequest, write::Request as WriteRequest};
+
+/// Config of the thresholds of the slow requests, see
+/// [`Builder::slow_request_config`](crate::Builder::slow_request_config).
+#[derive(Debug, Clone)]
+pub struct SlowRequestConfig {
+    /// The threshold of the operations without their own ones.
+    ///
+    /// Default value is 1s.
+    pub threshold: Duration,
+    /// The thresholds keyed by the operations, i.e. `sql_query`,
+    /// `sql_query_stream`, `write` or `health_check`.
+    pub operation_thresholds: HashMap<String, Duration>,
+}
+
+impl Default for SlowRequestConfig {
+    fn default() -> Self {
+        Self {
+            threshold: Duration::from_secs(1),
+            operation_thresholds: HashMap::new(),
+        }
+    }
+}
+
+impl SlowRequestConfig {
+    /// Set the threshold of the `operation`.
+    pub fn operation_threshold(
+        mut self,
+        operation: impl Into<String>,
+        threshold: Duration,
+    ) -> Self {
+        self.operation_thresholds
+            .insert(operation.into(), threshold);
+        self
+    }
+
+    fn threshold_of(&self, operation: &str) -> Duration {
+        self.operation_thresholds
+            .get(operation)
+            .copied()
+            .unwrap_or(self.threshold)
+    }
+}
+
+/// The request exceeding the threshold of its operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowRequest {
+    pub operation: &'static str,
+    pub endpoint: String,
+    /// The queried or written tables.
+    pub tables: Vec<String>,
+    /// The sql of the queries.
+    pub sql: Option<String>,
+    /// The elapsed time of the rpc, and only the opening of the stream is
+    /// counted for the `sql_query_stream`.
+    pub elapsed: Duration,
+}
+
+/// Hook called with every [`SlowRequest`], e.g. to log it.
+pub trait SlowRequestHook: Send + Sync {
+    fn on_slow_request(&self, req: &SlowRequest);
+}
+
+impl<F> SlowRequestHook for F
+where
+    F: Fn(&SlowRequest) + Send + Sync,
+{
+    fn on_slow_request(&self, req: &SlowRequest) {
+        self(req)
+    }
+}
+
+/// The request checked by the [`SlowRequestLog`].
+#[derive(Clone, Copy)]
+pub(crate) enum RequestSubject<'a> {
+    Query(&'a SqlQueryRequest),
+    Write(&'a WriteRequest),
+    None,
+}
+
+/// The log reporting the slow requests to the hook, or as the `tracing`
+/// events if no hook is set.
+#[derive(Clone)]
+pub(crate) struct SlowRequestLog {
+    config: SlowRequestConfig,
+    hook: Option<Arc<dyn SlowRequestHook>>,
+}
+
+impl SlowRequestLog {
+    pub fn new(config: SlowRequestConfig, hook: Option<Arc<dyn SlowRequestHook>>) -> Self {
+        Self { config, hook }
+    }
+
+    pub fn observe(
+        &self,
+        operation: &'static str,
+        endpoint: &str,
+        elapsed: Duration,
+        subject: RequestSubject<'_>,
+    ) {
+        if elapsed < self.config.threshold_of(operation) {
+            return;
+        }
+
+        let (tables, sql) = match subject {
+            RequestSubject::Query(req) => (req.tables.clone(), Some(req.sql.clone())),
+            RequestSubject::Write(req) => {
+                let mut tables: Vec<_> = req.point_groups.keys().cloned().collect();
+                tables.sort();
+                (tables, None)
+            }
+            RequestSubject::None => (Vec::new(), None),
+        };
+        let req = SlowRequest {
+            operation,
+            endpoint: endpoint.to_string(),
+            tables,
+            sql,
+            elapsed,
+        };
+        let Some(hook) = &self.hook else {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                operation = req.operation,
+                endpoint = %req.endpoint,
+                tables = ?req.tables,
+                sql = req.sql.as_deref(),
+                elapsed = ?req.elapsed,
+                "slow request"
+            );
+            return;
+        };
+        hook.on_slow_request(&req);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::{RequestSubject, SlowRequest, SlowRequestConfig, SlowRequestLog};
+    use crate::model::sql_query::Request as SqlQueryRequest;
+
+    #[test]
+    fn test_slow_request_log() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let config = SlowRequestConfig {
+            threshold: Duration::from_millis(100),
+            ..Default::default()
+        }
+        .operation_threshold("write", Duration::from_secs(1));
+        let log = SlowRequestLog::new(
+            config,
+            Some(Arc::new({
+                let reported = reported.clone();
+                move |req: &SlowRequest| reported.lock().unwrap().push(req.clone())
+            })),
+        );
+
+        let req = SqlQueryRequest {
+            tables: vec!["t".to_string()],
+            sql: "select * from t".to_string(),
+        };
+        log.observe(
+            "sql_query",
+            "a:1",
+            Duration::from_millis(10),
+            RequestSubject::Query(&req),
+        );
+        log.observe(
+            "sql_query",
+            "a:1",
+            Duration::from_millis(200),
+            RequestSubject::Query(&req),
+        );
+        log.observe(
+            "write",
+            "a:1",
+            Duration::from_millis(200),
+            RequestSubject::None,
+        );
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(
+            *reported,
+            vec![SlowRequest {
+                operation: "sql_query",
+                endpoint: "a:1".to_string(),
+                tables: vec!["t".to_string()],
+                sql: Some("select * from t".to_string()),
+                elapsed: Duration::from_millis(200),
+            }]
+        );
+    }
+}
@@ -0,0 +1,148 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Inspection of the states of the connections to the servers.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::SystemTime,
+};
+
+use crate::Result;
+
+/// The status of the connection to one endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// No connection has been established yet, or the last attempt failed.
+    Idle,
+    Connecting,
+    Connected,
+}
+
+/// The state of the connection to one endpoint, see
+/// [`DbClient::connection_states`].
+///
+/// [`DbClient::connection_states`]: crate::DbClient::connection_states
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionState {
+    pub endpoint: String,
+    pub status: ConnectionStatus,
+    /// The last error of the connecting or the calls, which is kept after the
+    /// following successful calls.
+    pub last_error: Option<String>,
+    /// The time of the last call made through the connection.
+    pub last_used: Option<SystemTime>,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    last_error: Option<String>,
+    last_used: Option<SystemTime>,
+}
+
+/// Tracker of the usage of the connection to one endpoint.
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionTracker {
+    connecting: AtomicUsize,
+    usage: Mutex<Usage>,
+}
+
+impl ConnectionTracker {
+    /// Mark the connection as connecting until the returned guard is dropped.
+    pub fn connecting(&self) -> ConnectingGuard<'_> {
+        self.connecting.fetch_add(1, Ordering::Relaxed);
+        ConnectingGuard(&self.connecting)
+    }
+
+    /// Record the result of a call through the connection.
+    pub fn record<T>(&self, result: &Result<T>) {
+        let mut usage = self.usage.lock().unwrap();
+        usage.last_used = Some(SystemTime::now());
+        if let Err(e) = result {
+            usage.last_error = Some(e.to_string());
+        }
+    }
+
+    /// Record the failure of connecting, which doesn't count as a use.
+    pub fn record_connect<T>(&self, result: &Result<T>) {
+        if let Err(e) = result {
+            self.usage.lock().unwrap().last_error = Some(e.to_string());
+        }
+    }
+
+    pub fn state(&self, endpoint: &str, connected: bool) -> ConnectionState {
+        let status = if connected {
+            ConnectionStatus::Connected
+        } else if self.connecting.load(Ordering::Relaxed) > 0 {
+            ConnectionStatus::Connecting
+        } else {
+            ConnectionStatus::Idle
+        };
+        let usage = self.usage.lock().unwrap();
+        ConnectionState {
+            endpoint: endpoint.to_string(),
+            status,
+            last_error: usage.last_error.clone(),
+            last_used: usage.last_used,
+        }
+    }
+}
+
+/// Guard of an ongoing connecting, which may be cancelled at any await point.
+pub(crate) struct ConnectingGuard<'a>(&'a AtomicUsize);
+
+impl Drop for ConnectingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConnectionStatus, ConnectionTracker};
+    use crate::{Error, Result};
+
+    #[test]
+    fn test_connection_tracker() {
+        let tracker = ConnectionTracker::default();
+        let state = tracker.state("127.0.0.1:8831", false);
+        assert_eq!(state.status, ConnectionStatus::Idle);
+        assert_eq!((state.last_error, state.last_used), (None, None));
+
+        let guard = tracker.connecting();
+        assert_eq!(
+            tracker.state("127.0.0.1:8831", false).status,
+            ConnectionStatus::Connecting
+        );
+        drop(guard);
+        assert_eq!(
+            tracker.state("127.0.0.1:8831", false).status,
+            ConnectionStatus::Idle
+        );
+
+        let result: Result<()> = Err(Error::Unknown("broken".to_string()));
+        tracker.record(&result);
+        tracker.record(&Ok(()));
+        let state = tracker.state("127.0.0.1:8831", true);
+        assert_eq!(state.status, ConnectionStatus::Connected);
+        assert!(state.last_error.unwrap().contains("broken"));
+        assert!(state.last_used.is_some());
+    }
+}
@@ -15,11 +15,14 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::fmt::Display;
+use std::{fmt::Display, time::Duration};
 
 use thiserror::Error as ThisError;
 
-use crate::model::write::Response;
+use crate::{config::RetryableError, model::write::Response};
+
+/// The metadata key of the backoff hinted by the server throttling the call.
+const RETRY_AFTER_METADATA_KEY: &str = "retry-after";
 
 /// An error generated by the client.
 #[derive(Debug, ThisError)]
@@ -52,8 +55,8 @@ pub enum Error {
     #[error("failed to check auth, err:{0}")]
     AuthFail(AuthFailStatus),
 
-    /// Error from write in route based mode, some of rows may be written
-    /// successfully, and others may fail.
+    /// Error from write in route based mode or split into multiple rpcs, some
+    /// of rows may be written successfully, and others may fail.
     #[error("failed to write with route based client, err:{0}")]
     RouteBasedWriteError(RouteBasedWriteError),
 
@@ -64,12 +67,63 @@ pub enum Error {
     #[error("failed to decode, msg:{0}")]
     BuildRows(String),
 
+    /// The record batches in one response have inconsistent schemas.
+    #[error("failed to decode, err:{0}")]
+    SchemaMismatch(SchemaMismatchError),
+
     #[error("failed to decode arrow payload, msg:{0}")]
     DecodeArrowPayload(Box<dyn std::error::Error + Send + Sync>),
 
-    #[error("failed to find a database")]
+    /// Neither the default database nor the database in the [`RpcContext`] is
+    /// set.
+    ///
+    /// [`RpcContext`]: crate::RpcContext
+    #[error(
+        "failed to find a database, set the default database by \
+         `Builder::default_database` or the database of the request by \
+         `RpcContext::database`"
+    )]
     NoDatabase,
 
+    /// The write is rejected as the ingestion is paused by
+    /// [`DbClient::pause`](crate::DbClient::pause).
+    #[error("write is rejected as the ingestion is paused")]
+    Paused,
+
+    /// The call is rejected as the client is shut down by
+    /// [`DbClient::shutdown`](crate::DbClient::shutdown).
+    #[error("client is shut down")]
+    Shutdown,
+
+    /// The write is rejected by the client-side rate limit, see
+    /// [`WriteRateLimit`](crate::WriteRateLimit).
+    #[error("write is rejected by the rate limit")]
+    RateLimited,
+
+    /// The call is rejected as it exceeds the
+    /// [`ClientQuota`](crate::ClientQuota).
+    #[error("call is rejected by the quota, msg:{0}")]
+    QuotaExceeded(String),
+
+    /// The call is rejected as the circuit breaker of the endpoint is open,
+    /// see [`CircuitBreakerConfig`](crate::CircuitBreakerConfig).
+    #[error("circuit breaker is open, endpoint:{0}")]
+    CircuitOpen(String),
+
+    /// The column added by [`admin::add_columns`](crate::admin::add_columns)
+    /// exists already.
+    #[error("column exists already, table:{table}, column:{column}")]
+    ColumnExists { table: String, column: String },
+
+    /// Error of the last attempt after the request is retried.
+    #[error("failed after {attempts} attempts, err:{source}")]
+    RetryExhausted { attempts: u32, source: Box<Error> },
+
+    /// The call is still throttled by the server after the budget of the
+    /// [`ThrottlePolicy`](crate::ThrottlePolicy) is exhausted.
+    #[error("throttled by server after {attempts} attempts, err:{source}")]
+    Throttled { attempts: u32, source: Box<Error> },
+
     #[error(transparent)]
     Other {
         #[from]
@@ -77,6 +131,59 @@ pub enum Error {
     },
 }
 
+impl Error {
+    /// The number of the attempts made before returning this error.
+    pub fn attempts(&self) -> u32 {
+        match self {
+            Error::RetryExhausted { attempts, .. } | Error::Throttled { attempts, .. } => *attempts,
+            _ => 1,
+        }
+    }
+
+    /// Whether the call failed before the request was sent to the server.
+    pub(crate) fn is_unsent(&self) -> bool {
+        match self {
+            Error::Connect { .. } | Error::CircuitOpen(_) => true,
+            Error::RetryExhausted { source, .. } => source.is_unsent(),
+            _ => false,
+        }
+    }
+
+    /// Whether the call is throttled by the overloaded server, which is worth
+    /// resending after the backoff.
+    pub(crate) fn is_throttled(&self) -> bool {
+        RetryableError::Throttled.matches(self)
+    }
+
+    /// The backoff hinted by the server throttling the call, in the
+    /// `retry-after` metadata in seconds.
+    pub(crate) fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::Rpc(status) => {
+                let secs = status.metadata().get(RETRY_AFTER_METADATA_KEY)?;
+                let secs = secs.to_str().ok()?.trim().parse::<f64>().ok()?;
+                Duration::try_from_secs_f64(secs).ok()
+            }
+            Error::RetryExhausted { source, .. } => source.retry_after(),
+            _ => None,
+        }
+    }
+
+    /// Whether the error is caused by the unavailable servers rather than the
+    /// request itself.
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            Error::Connect { .. } | Error::CircuitOpen(_) => true,
+            Error::Rpc(status) => matches!(
+                status.code(),
+                tonic::Code::Unavailable | tonic::Code::DeadlineExceeded
+            ),
+            Error::RetryExhausted { source, .. } => source.is_unavailable(),
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RouteBasedWriteError {
     pub ok: (Vec<String>, Response),       // (tables, write_response)
@@ -124,6 +231,26 @@ impl Display for RouteBasedWriteError {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct SchemaMismatchError {
+    /// The index of the mismatched record batch in the response.
+    pub batch_index: usize,
+    /// The `(name, type)` of the fields of the first record batch.
+    pub expected: Vec<(String, String)>,
+    /// The `(name, type)` of the fields of the mismatched record batch.
+    pub actual: Vec<(String, String)>,
+}
+
+impl Display for SchemaMismatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "schema of record batch:{} mismatches the first one, expected:{:?}, actual:{:?}",
+            self.batch_index, self.expected, self.actual
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ServerError {
     pub code: u32,
@@ -76,6 +76,14 @@
 //! # }
 //! ```
 
+// `tonic::Status` makes `Error` large, but boxing it would break the public
+// error type.
+#![allow(clippy::result_large_err)]
+
+pub mod admin;
+mod assertions;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 mod config;
 #[doc(hidden)]
 pub mod db_client;
@@ -86,14 +94,40 @@ mod router;
 mod rpc_client;
 mod util;
 
+/// The protobuf definitions of the protocol, which are sent and received by
+/// the [`RpcClient`] returned by [`DbClient::raw`].
+pub use horaedbproto;
+
+#[cfg(feature = "tower")]
+#[doc(inline)]
+pub use crate::db_client::{LayeredClient, QueryService, WriteService};
+#[cfg(feature = "test-util")]
+#[doc(inline)]
+pub use crate::router::MockRouter;
 #[doc(inline)]
 pub use crate::{
-    config::{Authorization, RpcConfig},
-    db_client::{Builder, DbClient, Mode},
+    config::{
+        Authorization, BackoffStrategy, Compression, EndpointConfig, ReconnectPolicy, RetryConfig,
+        RetryableError, RpcConfig, ThrottlePolicy, TlsConfig, WriteRetryMode,
+    },
+    db_client::{
+        Builder, CircuitBreakerConfig, ClientQuota, ConnectionState, ConnectionStatus, DbClient,
+        EndpointMetrics, FailoverClient, FailoverMarker, HedgingConfig, LatencyHistogram,
+        MetricsSnapshot, Mode, OperationMetrics, PausePolicy, QueryCacheConfig, QueryOptions,
+        RateLimitPolicy, RouteMetrics, RowBatchStream, RowStream, ServerCapabilities, SlowRequest,
+        SlowRequestConfig, SlowRequestHook, Transport, WriteOptions, WriteRateLimit,
+    },
     errors::{Error, Result},
     model::{
         sql_query::{Request as SqlQueryRequest, Response as SqlQueryResponse},
         write::{Request as WriteRequest, Response as WriteResponse},
     },
-    rpc_client::RpcContext,
+    router::{
+        LatencyAwarePolicy, LoadBalancePolicy, RandomPolicy, RoundRobinPolicy, RouteRefreshConfig,
+        Router,
+    },
+    rpc_client::{
+        AuthProvider, BearerToken, ChannelProvider, Priority, RequestInterceptor, RequestObserver,
+        RpcClient, RpcContext, SessionSettings, SqlQueryStream, TokenProvider,
+    },
 };
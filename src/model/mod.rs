@@ -16,6 +16,8 @@
 // under the License.
 
 pub mod route;
+pub mod schema;
 pub mod sql_query;
+pub mod system;
 pub mod value;
 pub mod write;
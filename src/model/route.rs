@@ -19,6 +19,12 @@ use std::{fmt::Display, str::FromStr};
 
 use horaedbproto::storage::Endpoint as EndPointPb;
 
+/// The endpoint of a server serving the routed tables.
+///
+/// The routing is at the granularity of the tables, as the route response of
+/// the protocol only carries the endpoints of the tables without any placement
+/// of their shards or partitions, and the writes of the partitioned tables are
+/// partitioned by the server.
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct Endpoint {
     pub addr: String,
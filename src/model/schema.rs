@@ -0,0 +1,376 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Schema of the tables inferred from the points, which is used to create the
+//! tables before writing.
+
+use std::collections::{btree_map::Entry, BTreeMap, HashSet};
+
+use crate::{
+    model::{value::DataType, write::point::Point},
+    util::{quote_ident, quote_literal},
+    Error, Result,
+};
+
+/// The default name of the timestamp key column.
+pub const DEFAULT_TIMESTAMP_COLUMN: &str = "timestamp";
+
+/// The schema of one tag or field column.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnSchema {
+    pub name: String,
+    pub data_type: DataType,
+    /// Whether the column is a tag, which identifies the series together with
+    /// the other tags.
+    pub is_tag: bool,
+    pub not_null: bool,
+}
+
+impl ColumnSchema {
+    pub fn tag(name: impl Into<String>, data_type: DataType) -> Self {
+        Self {
+            name: name.into(),
+            data_type,
+            is_tag: true,
+            not_null: false,
+        }
+    }
+
+    pub fn field(name: impl Into<String>, data_type: DataType) -> Self {
+        Self {
+            name: name.into(),
+            data_type,
+            is_tag: false,
+            not_null: false,
+        }
+    }
+
+    pub fn not_null(mut self) -> Self {
+        self.not_null = true;
+        self
+    }
+
+    /// The definition of the column in the sql.
+    pub(crate) fn def(&self) -> String {
+        let mut def = format!("{} {}", quote_ident(&self.name), type_name(self.data_type));
+        if self.is_tag {
+            def.push_str(" TAG");
+        }
+        if self.not_null {
+            def.push_str(" NOT NULL");
+        }
+        def
+    }
+}
+
+/// The options to build the `CREATE TABLE` sql.
+#[derive(Clone, Debug)]
+pub struct CreateTableOptions {
+    /// The name of the timestamp key column.
+    ///
+    /// Default value is [`DEFAULT_TIMESTAMP_COLUMN`].
+    pub timestamp_column: String,
+    /// Add `IF NOT EXISTS` to the sql, and it is enabled by default.
+    pub if_not_exists: bool,
+    /// The table engine, and default value is `Analytic`.
+    pub engine: String,
+    /// The table options in the `WITH` clause, e.g. `enable_ttl`.
+    pub table_options: BTreeMap<String, String>,
+}
+
+impl Default for CreateTableOptions {
+    fn default() -> Self {
+        Self {
+            timestamp_column: DEFAULT_TIMESTAMP_COLUMN.to_string(),
+            if_not_exists: true,
+            engine: "Analytic".to_string(),
+            table_options: BTreeMap::new(),
+        }
+    }
+}
+
+/// The schema of a table, except the timestamp key column which is decided by
+/// the [`CreateTableOptions`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TableSchema {
+    /// The tag and field columns, and the inferred ones are the tag columns
+    /// followed by the field columns, both ordered by the name.
+    pub columns: Vec<ColumnSchema>,
+    /// The primary key of the table, which is decided by the server from the
+    /// tags and the timestamp key if empty.
+    pub primary_key: Vec<String>,
+}
+
+impl TableSchema {
+    /// Infer the schema from the tags and fields of the points.
+    ///
+    /// The null values are skipped, and it fails if the type of a column
+    /// can't be decided, or a column is used as both tag and field, or the
+    /// values of a column have different types.
+    pub fn infer_from_points(points: &[Point]) -> Result<Self> {
+        // The column name -> (data type, is tag).
+        let mut columns: BTreeMap<&str, (Option<DataType>, bool)> = BTreeMap::new();
+        let tag_columns = points
+            .iter()
+            .flat_map(|point| point.tags.iter().map(|(name, value)| (name, value, true)));
+        let field_columns = points.iter().flat_map(|point| {
+            point
+                .fields
+                .iter()
+                .map(|(name, value)| (name, value, false))
+        });
+        for (name, value, is_tag) in tag_columns.chain(field_columns) {
+            let data_type = match value.data_type() {
+                DataType::Null => None,
+                data_type => Some(data_type),
+            };
+            match columns.entry(name) {
+                Entry::Vacant(entry) => {
+                    entry.insert((data_type, is_tag));
+                }
+                Entry::Occupied(mut entry) => {
+                    let (existing_type, existing_is_tag) = entry.get_mut();
+                    if *existing_is_tag != is_tag {
+                        return Err(Error::Client(format!(
+                            "column:{name} is used as both tag and field"
+                        )));
+                    }
+                    match (*existing_type, data_type) {
+                        (Some(existing), Some(new)) if existing != new => {
+                            return Err(Error::Client(format!(
+                                "column:{name} has different types, {existing:?} and {new:?}"
+                            )));
+                        }
+                        (None, new) => *existing_type = new,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let mut columns = columns
+            .into_iter()
+            .map(|(name, (data_type, is_tag))| {
+                let data_type = data_type.ok_or_else(|| {
+                    Error::Client(format!(
+                        "type of column:{name} can't be inferred from nulls"
+                    ))
+                })?;
+                Ok(ColumnSchema {
+                    name: name.to_string(),
+                    data_type,
+                    is_tag,
+                    not_null: false,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // The sort is stable, so the columns are still ordered by the name.
+        columns.sort_by_key(|column| !column.is_tag);
+
+        Ok(Self {
+            columns,
+            primary_key: Vec::new(),
+        })
+    }
+
+    /// Build the `CREATE TABLE` sql of the `table`, and fail if the schema or
+    /// the options are invalid.
+    pub fn to_create_sql(&self, table: &str, options: &CreateTableOptions) -> Result<String> {
+        if let Err(msg) = self.validate(options) {
+            return Err(Error::Client(format!(
+                "invalid schema of table:{table}, {msg}"
+            )));
+        }
+
+        let mut column_defs = Vec::with_capacity(self.columns.len() + 3);
+        column_defs.extend(self.columns.iter().map(ColumnSchema::def));
+        let ts_col = quote_ident(&options.timestamp_column);
+        column_defs.push(format!("{ts_col} timestamp NOT NULL"));
+        column_defs.push(format!("TIMESTAMP KEY({ts_col})"));
+        if !self.primary_key.is_empty() {
+            let columns: Vec<_> = self.primary_key.iter().map(|c| quote_ident(c)).collect();
+            column_defs.push(format!("PRIMARY KEY({})", columns.join(", ")));
+        }
+
+        let mut sql = format!(
+            "CREATE TABLE {}{} ({}) ENGINE={}",
+            if options.if_not_exists {
+                "IF NOT EXISTS "
+            } else {
+                ""
+            },
+            quote_ident(table),
+            column_defs.join(", "),
+            options.engine
+        );
+        if !options.table_options.is_empty() {
+            let table_options = options
+                .table_options
+                .iter()
+                .map(|(k, v)| format!("{k}={}", quote_literal(v)))
+                .collect::<Vec<_>>();
+            sql.push_str(&format!(" WITH ({})", table_options.join(", ")));
+        }
+
+        Ok(sql)
+    }
+
+    fn validate(&self, options: &CreateTableOptions) -> std::result::Result<(), String> {
+        let mut names = HashSet::from([options.timestamp_column.as_str()]);
+        validate_columns(&mut names, &self.columns)?;
+        if let Some(column) = self
+            .primary_key
+            .iter()
+            .find(|c| !names.contains(c.as_str()))
+        {
+            return Err(format!("primary key column:{column} doesn't exist"));
+        }
+        let is_option_key = |key: &String| {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        };
+        if let Some(key) = options.table_options.keys().find(|key| !is_option_key(key)) {
+            return Err(format!("invalid option key:{key}"));
+        }
+        if !is_option_key(&options.engine) {
+            return Err(format!("invalid engine:{}", options.engine));
+        }
+
+        Ok(())
+    }
+}
+
+/// Check the `columns` have the types and the names different from the
+/// `names`, which are extended by the names of the `columns`.
+pub(crate) fn validate_columns<'a>(
+    names: &mut HashSet<&'a str>,
+    columns: &'a [ColumnSchema],
+) -> std::result::Result<(), String> {
+    for column in columns {
+        if !names.insert(&column.name) {
+            return Err(format!("duplicate column:{}", column.name));
+        }
+        if column.data_type == DataType::Null {
+            return Err(format!("column:{} has no type", column.name));
+        }
+    }
+
+    Ok(())
+}
+
+/// The sql type of the `data_type` understood by HoraeDB.
+fn type_name(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::Null => unreachable!("null type is rejected by the validation"),
+        DataType::Timestamp => "timestamp",
+        DataType::Double => "double",
+        DataType::Float => "float",
+        DataType::Varbinary => "varbinary",
+        DataType::String => "string",
+        DataType::UInt64 => "uint64",
+        DataType::UInt32 => "uint32",
+        DataType::UInt16 => "uint16",
+        DataType::UInt8 => "uint8",
+        DataType::Int64 => "int64",
+        DataType::Int32 => "int32",
+        DataType::Int16 => "int16",
+        DataType::Int8 => "int8",
+        DataType::Boolean => "boolean",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{ColumnSchema, CreateTableOptions, TableSchema};
+    use crate::model::{
+        value::{DataType, Value},
+        write::point::{Point, PointBuilder},
+    };
+
+    fn make_point(host: Value, cpu: Value) -> Point {
+        PointBuilder::new("cpu")
+            .timestamp(0)
+            .tag("host", host)
+            .field("cpu", cpu)
+            .field("note", Value::String("n".to_string()))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_infer_from_points() {
+        let points = vec![
+            make_point(Value::String("a".to_string()), Value::Null),
+            make_point(Value::String("b".to_string()), Value::Double(0.5)),
+        ];
+        let schema = TableSchema::infer_from_points(&points).unwrap();
+        let options = CreateTableOptions {
+            table_options: BTreeMap::from([("enable_ttl".to_string(), "false".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(
+            schema.to_create_sql("cpu", &options).unwrap(),
+            "CREATE TABLE IF NOT EXISTS `cpu` (`host` string TAG, `cpu` double, `note` string, \
+             `timestamp` timestamp NOT NULL, TIMESTAMP KEY(`timestamp`)) ENGINE=Analytic WITH \
+             (enable_ttl='false')"
+        );
+    }
+
+    #[test]
+    fn test_invalid_create_sql() {
+        let schema = TableSchema {
+            columns: vec![ColumnSchema::tag("host", DataType::String).not_null()],
+            primary_key: vec!["host".to_string(), "timestamp".to_string()],
+        };
+        assert_eq!(
+            schema
+                .to_create_sql("cpu", &CreateTableOptions::default())
+                .unwrap(),
+            "CREATE TABLE IF NOT EXISTS `cpu` (`host` string TAG NOT NULL, `timestamp` timestamp \
+             NOT NULL, TIMESTAMP KEY(`timestamp`), PRIMARY KEY(`host`, `timestamp`)) \
+             ENGINE=Analytic"
+        );
+
+        let options = CreateTableOptions {
+            engine: "Analytic) --".to_string(),
+            ..Default::default()
+        };
+        assert!(schema.to_create_sql("cpu", &options).is_err());
+        let options = CreateTableOptions {
+            timestamp_column: "host".to_string(),
+            ..Default::default()
+        };
+        assert!(schema.to_create_sql("cpu", &options).is_err());
+    }
+
+    #[test]
+    fn test_infer_conflicts() {
+        let points = vec![
+            make_point(Value::String("a".to_string()), Value::Double(0.5)),
+            make_point(Value::String("b".to_string()), Value::Int64(1)),
+        ];
+        assert!(TableSchema::infer_from_points(&points).is_err());
+
+        let points = vec![make_point(Value::String("a".to_string()), Value::Null)];
+        assert!(TableSchema::infer_from_points(&points).is_err());
+
+        let mut point = make_point(Value::String("a".to_string()), Value::Double(0.5));
+        point.fields.insert("host".to_string(), Value::Int64(1));
+        assert!(TableSchema::infer_from_points(&[point]).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Column-major view of the rows returned by the queries.
+
+use std::collections::HashMap;
+
+use crate::model::{sql_query::row::Row, value::Value};
+
+/// The rows stored column by column, built by
+/// [`Response::to_columnar`](crate::model::sql_query::Response::to_columnar).
+///
+/// The columns are in the order of their first appearance in the rows, and the
+/// missing values of a row are filled with [`Value::Null`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColumnarRows {
+    names: Vec<String>,
+    columns: Vec<Vec<Value>>,
+    num_rows: usize,
+}
+
+impl ColumnarRows {
+    pub fn from_rows(rows: &[Row]) -> Self {
+        let mut col_idx_by_name: HashMap<&str, usize> = HashMap::new();
+        let mut names = Vec::new();
+        let mut columns: Vec<Vec<Value>> = Vec::new();
+        for (row_idx, row) in rows.iter().enumerate() {
+            for column in row.columns() {
+                let col_idx = *col_idx_by_name.entry(column.name()).or_insert_with(|| {
+                    names.push(column.name().to_string());
+                    // The previous rows don't have this column.
+                    columns.push(vec![Value::Null; row_idx]);
+                    columns.len() - 1
+                });
+                columns[col_idx].push(column.value().clone());
+            }
+
+            // Fill the columns missing in this row.
+            for values in &mut columns {
+                values.resize(row_idx + 1, Value::Null);
+            }
+        }
+
+        Self {
+            names,
+            columns,
+            num_rows: rows.len(),
+        }
+    }
+
+    /// The names of all the columns.
+    pub fn column_names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// Get the values of the column.
+    pub fn column_values(&self, name: &str) -> Option<&[Value]> {
+        self.names
+            .iter()
+            .position(|col_name| col_name == name)
+            .map(|col_idx| self.columns[col_idx].as_slice())
+    }
+
+    /// Get the values of the column as `f64`, and `None` is returned if any of
+    /// the values can't be converted.
+    pub fn column_f64(&self, name: &str) -> Option<Vec<f64>> {
+        self.column_as(name, Value::as_f64)
+    }
+
+    /// Get the values of the column as `i64`, and `None` is returned if any of
+    /// the values can't be converted.
+    pub fn column_i64(&self, name: &str) -> Option<Vec<i64>> {
+        self.column_as(name, |value| match value {
+            Value::Timestamp(v) => Some(*v),
+            _ => value.as_i64(),
+        })
+    }
+
+    /// Get the values of the column as strings, and `None` is returned if any
+    /// of the values is not string.
+    pub fn column_str(&self, name: &str) -> Option<Vec<&str>> {
+        self.column_values(name)?
+            .iter()
+            .map(|value| match value {
+                Value::String(v) => Some(v.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn column_as<T>(&self, name: &str, convert: impl Fn(&Value) -> Option<T>) -> Option<Vec<T>> {
+        self.column_values(name)?.iter().map(convert).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ColumnarRows;
+    use crate::model::{
+        sql_query::row::{Column, Row},
+        value::Value,
+    };
+
+    #[test]
+    fn test_columnar_rows() {
+        let rows = vec![
+            Row::new(vec![
+                Column::new("host".to_string(), Value::String("a".to_string())),
+                Column::new("cpu".to_string(), Value::Double(0.5)),
+            ]),
+            Row::new(vec![
+                Column::new("host".to_string(), Value::String("b".to_string())),
+                Column::new("cpu".to_string(), Value::Float(1.0)),
+                Column::new("mem".to_string(), Value::Int64(3)),
+            ]),
+        ];
+
+        let columnar = ColumnarRows::from_rows(&rows);
+        assert_eq!(columnar.num_rows(), 2);
+        assert_eq!(columnar.column_names(), &["host", "cpu", "mem"]);
+        assert_eq!(columnar.column_f64("cpu"), Some(vec![0.5, 1.0]));
+        assert_eq!(columnar.column_str("host"), Some(vec!["a", "b"]));
+        assert_eq!(
+            columnar.column_values("mem"),
+            Some([Value::Null, Value::Int64(3)].as_slice())
+        );
+        assert_eq!(columnar.column_i64("mem"), None);
+        assert_eq!(columnar.column_values("disk"), None);
+    }
+}
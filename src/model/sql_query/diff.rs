@@ -0,0 +1,224 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Diff of the rows returned by two queries, which is useful to write
+//! data-regression tests, e.g. around server upgrades.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::model::{sql_query::row::Row, value::Value};
+
+/// Options controlling how two values are considered equal.
+#[derive(Clone, Debug, Default)]
+pub struct DiffOptions {
+    /// Max absolute difference between two float values to consider them
+    /// equal.
+    pub float_tolerance: f64,
+    /// Max difference in milliseconds between two timestamps to consider them
+    /// equal.
+    pub timestamp_tolerance_ms: i64,
+}
+
+impl DiffOptions {
+    fn value_eq(&self, left: &Value, right: &Value) -> bool {
+        match (left, right) {
+            (Value::Timestamp(l), Value::Timestamp(r)) => {
+                l.abs_diff(*r) <= self.timestamp_tolerance_ms.unsigned_abs()
+            }
+            (Value::Double(_) | Value::Float(_), Value::Double(_) | Value::Float(_)) => {
+                let (l, r) = (left.as_f64().unwrap(), right.as_f64().unwrap());
+                l == r || (l - r).abs() <= self.float_tolerance
+            }
+            _ => left == right,
+        }
+    }
+}
+
+/// A row found in both row sets with the same key but different values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangedRow {
+    /// The values of the key columns.
+    pub key: Vec<Value>,
+    /// The names of the columns whose values differ.
+    pub columns: Vec<String>,
+    pub old: Row,
+    pub new: Row,
+}
+
+/// The difference between two row sets keyed by the chosen columns.
+///
+/// If multiple rows share the same key in one row set, only the first one is
+/// compared.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RowSetDiff {
+    /// Rows only in the new row set.
+    pub added: Vec<Row>,
+    /// Rows only in the old row set.
+    pub removed: Vec<Row>,
+    /// Rows in both row sets but with different values.
+    pub changed: Vec<ChangedRow>,
+}
+
+impl RowSetDiff {
+    /// Compare the `old` and `new` rows keyed by the `key_columns`.
+    ///
+    /// A missing key column is treated as [`Value::Null`].
+    pub fn compute(old: &[Row], new: &[Row], key_columns: &[&str], opts: &DiffOptions) -> Self {
+        let make_key = |row: &Row| -> Vec<Value> {
+            key_columns
+                .iter()
+                .map(|name| {
+                    row.column(name)
+                        .map(|col| col.value().clone())
+                        .unwrap_or_default()
+                })
+                .collect()
+        };
+
+        let mut old_rows = HashMap::with_capacity(old.len());
+        for row in old {
+            old_rows
+                .entry(encode_key(&make_key(row)))
+                .or_insert((row, false));
+        }
+
+        let mut diff = RowSetDiff::default();
+        let mut seen_new_keys = BTreeSet::new();
+        for row in new {
+            let key = make_key(row);
+            let encoded_key = encode_key(&key);
+            if !seen_new_keys.insert(encoded_key.clone()) {
+                continue;
+            }
+
+            match old_rows.get_mut(&encoded_key) {
+                Some((old_row, matched)) => {
+                    *matched = true;
+                    let columns = changed_columns(old_row, row, opts);
+                    if !columns.is_empty() {
+                        diff.changed.push(ChangedRow {
+                            key,
+                            columns,
+                            old: (*old_row).clone(),
+                            new: row.clone(),
+                        });
+                    }
+                }
+                None => diff.added.push(row.clone()),
+            }
+        }
+
+        // Keep the removed rows in the order of the old row set.
+        for row in old {
+            if let Some((old_row, matched)) = old_rows.remove(&encode_key(&make_key(row))) {
+                if !matched {
+                    diff.removed.push(old_row.clone());
+                }
+            }
+        }
+
+        diff
+    }
+
+    /// Whether the two row sets are equal.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn changed_columns(old: &Row, new: &Row, opts: &DiffOptions) -> Vec<String> {
+    let mut columns = Vec::new();
+    for old_col in old.columns() {
+        match new.column(old_col.name()) {
+            Some(new_col) if opts.value_eq(old_col.value(), new_col.value()) => {}
+            _ => columns.push(old_col.name().to_string()),
+        }
+    }
+    for new_col in new.columns() {
+        if old.column(new_col.name()).is_none() {
+            columns.push(new_col.name().to_string());
+        }
+    }
+
+    columns
+}
+
+/// Encode the key values with type and length prefixes to avoid collisions.
+fn encode_key(values: &[Value]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for value in values {
+        let bytes = value.to_bytes();
+        buf.push(value.data_type() as u8);
+        buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&bytes);
+    }
+
+    buf
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DiffOptions, RowSetDiff};
+    use crate::model::{
+        sql_query::row::{Column, Row},
+        value::Value,
+    };
+
+    fn make_row(host: &str, ts: i64, cpu: f64) -> Row {
+        Row::new(vec![
+            Column::new("host".to_string(), Value::String(host.to_string())),
+            Column::new("ts".to_string(), Value::Timestamp(ts)),
+            Column::new("cpu".to_string(), Value::Double(cpu)),
+        ])
+    }
+
+    #[test]
+    fn test_row_set_diff() {
+        let old = vec![
+            make_row("a", 1000, 0.5),
+            make_row("b", 1000, 0.5),
+            make_row("c", 1000, 0.5),
+        ];
+        let new = vec![
+            make_row("a", 1001, 0.5000001),
+            make_row("c", 1000, 0.7),
+            make_row("d", 1000, 0.5),
+        ];
+
+        let strict = RowSetDiff::compute(&old, &new, &["host"], &DiffOptions::default());
+        assert_eq!(strict.added, vec![make_row("d", 1000, 0.5)]);
+        assert_eq!(strict.removed, vec![make_row("b", 1000, 0.5)]);
+        assert_eq!(strict.changed.len(), 2);
+        assert_eq!(strict.changed[0].key, vec![Value::String("a".to_string())]);
+        assert_eq!(strict.changed[0].columns, vec!["ts", "cpu"]);
+        assert_eq!(strict.changed[1].columns, vec!["cpu"]);
+
+        let opts = DiffOptions {
+            float_tolerance: 1e-3,
+            timestamp_tolerance_ms: 1,
+        };
+        let tolerant = RowSetDiff::compute(&old, &new, &["host"], &opts);
+        assert_eq!(tolerant.changed.len(), 1);
+        assert_eq!(
+            tolerant.changed[0].key,
+            vec![Value::String("c".to_string())]
+        );
+
+        let same = RowSetDiff::compute(&old, &old, &["host", "ts"], &opts);
+        assert!(same.is_empty());
+    }
+}
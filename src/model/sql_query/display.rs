@@ -18,6 +18,8 @@
 use std::fmt::Display;
 
 use crate::model::sql_query::response::Response;
+#[cfg(feature = "cli-format")]
+use crate::model::value::Value;
 
 /// Display [`SqlQueryResponse`](Response) in csv format.
 pub struct CsvFormatter {
@@ -52,3 +54,143 @@ impl Display for CsvFormatter {
         Ok(())
     }
 }
+
+/// Display [`SqlQueryResponse`](Response) as an aligned and bordered table,
+/// which is easier to read in the console than the csv format.
+#[cfg(feature = "cli-format")]
+pub struct TableFormatter {
+    pub resp: Response,
+}
+
+#[cfg(feature = "cli-format")]
+impl TableFormatter {
+    fn format_value(value: &Value) -> String {
+        match value {
+            Value::Null => "NULL".to_string(),
+            Value::Timestamp(v) => v.to_string(),
+            Value::Double(v) => v.to_string(),
+            Value::Float(v) => v.to_string(),
+            Value::Varbinary(v) => {
+                let hex: String = v.iter().map(|b| format!("{b:02x}")).collect();
+                format!("0x{hex}")
+            }
+            Value::String(v) => v.clone(),
+            Value::UInt64(v) => v.to_string(),
+            Value::UInt32(v) => v.to_string(),
+            Value::UInt16(v) => v.to_string(),
+            Value::UInt8(v) => v.to_string(),
+            Value::Int64(v) => v.to_string(),
+            Value::Int32(v) => v.to_string(),
+            Value::Int16(v) => v.to_string(),
+            Value::Int8(v) => v.to_string(),
+            Value::Boolean(v) => v.to_string(),
+        }
+    }
+
+    fn write_border(f: &mut std::fmt::Formatter<'_>, widths: &[usize]) -> std::fmt::Result {
+        for width in widths {
+            f.write_fmt(format_args!("+{}", "-".repeat(width + 2)))?;
+        }
+        f.write_str("+\n")
+    }
+
+    fn write_cells(
+        f: &mut std::fmt::Formatter<'_>,
+        widths: &[usize],
+        cells: &[String],
+    ) -> std::fmt::Result {
+        for (cell, width) in cells.iter().zip(widths) {
+            let padding = width - cell.chars().count();
+            f.write_fmt(format_args!("| {cell}{} ", " ".repeat(padding)))?;
+        }
+        f.write_str("|\n")
+    }
+}
+
+#[cfg(feature = "cli-format")]
+impl Display for TableFormatter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Just print while returned `rows` in not empty.
+        let first_row = match self.resp.rows.first() {
+            Some(row) => row,
+            None => return Ok(()),
+        };
+
+        let col_names = first_row
+            .columns()
+            .iter()
+            .map(|col| col.name().to_string())
+            .collect::<Vec<_>>();
+        let rows = self
+            .resp
+            .rows
+            .iter()
+            .map(|row| {
+                row.columns()
+                    .iter()
+                    .map(|col| Self::format_value(col.value()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut widths = col_names
+            .iter()
+            .map(|name| name.chars().count())
+            .collect::<Vec<_>>();
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        Self::write_border(f, &widths)?;
+        Self::write_cells(f, &widths, &col_names)?;
+        Self::write_border(f, &widths)?;
+        for row in &rows {
+            Self::write_cells(f, &widths, row)?;
+        }
+        Self::write_border(f, &widths)
+    }
+}
+
+#[cfg(all(test, feature = "cli-format"))]
+mod test {
+    use super::TableFormatter;
+    use crate::model::{
+        sql_query::{
+            row::{Column, Row},
+            Response,
+        },
+        value::Value,
+    };
+
+    #[test]
+    fn test_table_formatter() {
+        let rows = vec![
+            Row::new(vec![
+                Column::new("host".to_string(), Value::String("a".to_string())),
+                Column::new("cpu".to_string(), Value::Double(0.25)),
+            ]),
+            Row::new(vec![
+                Column::new("host".to_string(), Value::String("host_b".to_string())),
+                Column::new("cpu".to_string(), Value::Null),
+            ]),
+        ];
+        let formatter = TableFormatter {
+            resp: Response {
+                affected_rows: 0,
+                rows,
+            },
+        };
+
+        let expected = "\
++--------+------+
+| host   | cpu  |
++--------+------+
+| a      | 0.25 |
+| host_b | NULL |
++--------+------+
+";
+        assert_eq!(formatter.to_string(), expected);
+    }
+}
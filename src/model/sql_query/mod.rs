@@ -15,10 +15,16 @@
 // specific language governing permissions and limitations
 // under the License.
 
+pub mod columnar;
+pub mod diff;
 pub mod display;
+pub mod page;
 pub(crate) mod request;
 pub(crate) mod response;
 pub mod row;
+pub mod series;
+pub mod statement;
+pub mod transform;
 
 pub use request::Request;
 pub use response::Response;
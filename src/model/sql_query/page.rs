@@ -0,0 +1,175 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Query the large results page by page.
+
+use crate::{
+    model::sql_query::{row::Row, Request},
+    Error, Result,
+};
+
+/// One page of the query result, see [`DbClient::sql_query_page`].
+///
+/// [`DbClient::sql_query_page`]: crate::DbClient::sql_query_page
+#[derive(Clone, Debug)]
+pub struct Page {
+    pub rows: Vec<Row>,
+    /// The offset of the next page, which is None if this is the last page.
+    pub next_offset: Option<u64>,
+}
+
+impl Page {
+    /// Build the page from the `rows` queried by the request built by
+    /// [`build_page_request`], which has one more row than the page if there
+    /// is a next page.
+    pub(crate) fn new(mut rows: Vec<Row>, page_size: u32, offset: u64) -> Self {
+        let page_size = page_size as usize;
+        let next_offset = match rows.len() > page_size {
+            true => {
+                rows.truncate(page_size);
+                Some(offset + page_size as u64)
+            }
+            false => None,
+        };
+        Self { rows, next_offset }
+    }
+}
+
+/// Build the request to query one more row than the page at the `offset`, so
+/// that whether there is a next page is known.
+///
+/// It fails if the appended `LIMIT` can't take effect, i.e. the sql has the
+/// `LIMIT` already or ends with a line comment.
+pub(crate) fn build_page_request(req: &Request, page_size: u32, offset: u64) -> Result<Request> {
+    let sql = req.sql.trim().trim_end_matches(';').trim_end();
+    if let Some(reason) = unpageable_reason(sql) {
+        return Err(Error::Client(format!(
+            "failed to page the sql as it {reason}, sql:{sql}"
+        )));
+    }
+
+    Ok(Request {
+        tables: req.tables.clone(),
+        sql: format!("{sql} LIMIT {} OFFSET {offset}", page_size as u64 + 1),
+    })
+}
+
+/// Scan the `sql` outside the quotes and the comments, and tell why the `LIMIT`
+/// can't be appended to it if any.
+fn unpageable_reason(sql: &str) -> Option<&'static str> {
+    let bytes = sql.as_bytes();
+    let mut depth = 0i32;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'\'' | b'"' | b'`') => match sql[i + 1..].find(quote as char) {
+                Some(len) => i += len + 2,
+                None => return Some("has an unterminated quote"),
+            },
+            b'-' if bytes.get(i + 1) == Some(&b'-') => match sql[i..].find('\n') {
+                Some(len) => i += len + 1,
+                None => return Some("ends with a line comment"),
+            },
+            b'/' if bytes.get(i + 1) == Some(&b'*') => match sql[i + 2..].find("*/") {
+                Some(len) => i += len + 4,
+                None => return Some("has an unterminated comment"),
+            },
+            b'(' => {
+                depth += 1;
+                i += 1;
+            }
+            b')' => {
+                depth -= 1;
+                i += 1;
+            }
+            b if b.is_ascii_alphanumeric() || b == b'_' => {
+                let len = sql[i..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(sql.len() - i);
+                if depth == 0 && sql[i..i + len].eq_ignore_ascii_case("limit") {
+                    return Some("has the limit already");
+                }
+                i += len;
+            }
+            _ => i += 1,
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::{build_page_request, Page};
+    use crate::{
+        model::{
+            sql_query::{
+                row::{Column, Row},
+                Request,
+            },
+            value::Value,
+        },
+        Error,
+    };
+
+    #[test]
+    fn test_page() {
+        let req = Request {
+            tables: vec!["t".to_string()],
+            sql: "SELECT * FROM t ORDER BY ts; ".to_string(),
+        };
+        let req = build_page_request(&req, 2, 4).unwrap();
+        assert_eq!(req.sql, "SELECT * FROM t ORDER BY ts LIMIT 3 OFFSET 4");
+
+        let rows = vec![Row::new(vec![Column::new("v".to_string(), Value::Int32(1))]); 3];
+        let page = Page::new(rows.clone(), 2, 4);
+        assert_eq!(page.rows.len(), 2);
+        assert_eq!(page.next_offset, Some(6));
+        let page = Page::new(rows[..2].to_vec(), 2, 4);
+        assert_eq!(page.rows.len(), 2);
+        assert_eq!(page.next_offset, None);
+    }
+
+    #[test]
+    fn test_unpageable_sql() {
+        let page_request = |sql: &str| {
+            let req = Request {
+                tables: vec!["t".to_string()],
+                sql: sql.to_string(),
+            };
+            build_page_request(&req, 2, 4)
+        };
+
+        for sql in [
+            "SELECT * FROM t ORDER BY ts LIMIT 10",
+            "select * from t limit 10 offset 2;",
+            "SELECT * FROM t ORDER BY ts -- latest first",
+            "SELECT * FROM t WHERE name = 'a",
+            "SELECT * FROM t /* ordered",
+        ] {
+            assert!(matches!(page_request(sql), Err(Error::Client(_))), "{sql}");
+        }
+
+        // The limits of the subqueries, the quotes and the comments are ignored.
+        let req = page_request(
+            "SELECT * FROM (SELECT * FROM t LIMIT 10) WHERE name = 'limit' -- limit\n\
+             /* limit */ ORDER BY `limit`",
+        )
+        .unwrap();
+        assert!(req.sql.ends_with("ORDER BY `limit` LIMIT 3 OFFSET 4"));
+    }
+}
@@ -17,19 +17,22 @@
 
 use std::io::Cursor;
 
-use arrow::{ipc::reader::StreamReader, record_batch::RecordBatch};
+use arrow::{datatypes::SchemaRef, ipc::reader::StreamReader, record_batch::RecordBatch};
 use horaedbproto::storage::{
     arrow_payload::Compression, sql_query_response::Output as OutputPb, ArrowPayload,
     SqlQueryResponse,
 };
 
 use crate::{
-    errors::{Error, Result},
-    model::sql_query::row::{Row, RowBuilder},
+    errors::{Error, Result, SchemaMismatchError},
+    model::sql_query::{
+        columnar::ColumnarRows,
+        row::{Row, RowBuilder},
+    },
 };
 
 /// The response for [`SqlQueryRequest`](crate::model::sql_query::Request).
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct Response {
     /// The affected rows by the query sql.
     pub affected_rows: u32,
@@ -37,6 +40,13 @@ pub struct Response {
     pub rows: Vec<Row>,
 }
 
+impl Response {
+    /// Convert the rows to the column-major [`ColumnarRows`].
+    pub fn to_columnar(&self) -> ColumnarRows {
+        ColumnarRows::from_rows(&self.rows)
+    }
+}
+
 #[derive(Debug)]
 enum Output {
     AffectedRows(u32),
@@ -75,6 +85,10 @@ impl TryFrom<OutputPb> for Output {
             OutputPb::AffectedRows(affected) => Output::AffectedRows(affected),
             OutputPb::Arrow(arrow_payload) => {
                 let arrow_record_batches = decode_arrow_payload(arrow_payload)?;
+                let mut schema_checker = SchemaChecker::default();
+                for record_batch in &arrow_record_batches {
+                    schema_checker.check(record_batch)?;
+                }
                 let rows_group = arrow_record_batches
                     .into_iter()
                     .map(|record_batch| {
@@ -95,6 +109,80 @@ impl TryFrom<OutputPb> for Output {
     }
 }
 
+/// Feed the rows in the response to `on_row` while decoding them one record
+/// batch at a time, and return the affected rows.
+pub(crate) fn for_each_row(
+    sql_resp_pb: SqlQueryResponse,
+    on_row: &mut (dyn FnMut(Row) + Send),
+) -> Result<u32> {
+    let output_pb = sql_resp_pb
+        .output
+        .ok_or_else(|| Error::Unknown("output is empty in sql query response".to_string()))?;
+    let arrow_payload = match output_pb {
+        OutputPb::AffectedRows(affected) => return Ok(affected),
+        OutputPb::Arrow(arrow_payload) => arrow_payload,
+    };
+
+    let compression = arrow_payload.compression();
+    let mut schema_checker = SchemaChecker::default();
+    for byte_batch in arrow_payload.record_batches {
+        let byte_batch = unzip_byte_batch(compression, byte_batch)?;
+        let stream_reader = StreamReader::try_new(Cursor::new(byte_batch), None)
+            .map_err(|e| Error::DecodeArrowPayload(Box::new(e)))?;
+        for record_batch in stream_reader {
+            let record_batch = record_batch.map_err(|e| Error::DecodeArrowPayload(Box::new(e)))?;
+            schema_checker.check(&record_batch)?;
+            RowBuilder::with_arrow_record_batch(record_batch)?
+                .build()
+                .into_iter()
+                .for_each(&mut *on_row);
+        }
+    }
+
+    Ok(0)
+}
+
+/// Checker of the schemas of the record batches in one response, which should
+/// be all the same as the first one.
+#[derive(Default)]
+struct SchemaChecker {
+    first_schema: Option<SchemaRef>,
+    batch_index: usize,
+}
+
+impl SchemaChecker {
+    fn check(&mut self, record_batch: &RecordBatch) -> Result<()> {
+        let schema = record_batch.schema();
+        let batch_index = self.batch_index;
+        self.batch_index += 1;
+        let first_schema = self.first_schema.get_or_insert_with(|| schema.clone());
+        if first_schema.fields() == schema.fields() {
+            return Ok(());
+        }
+
+        let describe = |schema: &SchemaRef| {
+            schema
+                .fields()
+                .iter()
+                .map(|field| (field.name().clone(), field.data_type().to_string()))
+                .collect()
+        };
+        Err(Error::SchemaMismatch(SchemaMismatchError {
+            batch_index,
+            expected: describe(first_schema),
+            actual: describe(&schema),
+        }))
+    }
+}
+
+fn unzip_byte_batch(compression: Compression, byte_batch: Vec<u8>) -> Result<Vec<u8>> {
+    match compression {
+        Compression::None => Ok(byte_batch),
+        Compression::Zstd => zstd::stream::decode_all(Cursor::new(byte_batch))
+            .map_err(|e| Error::DecodeArrowPayload(Box::new(e))),
+    }
+}
+
 pub fn decode_arrow_payload(arrow_payload: ArrowPayload) -> Result<Vec<RecordBatch>> {
     let compression = arrow_payload.compression();
     let byte_batches = arrow_payload.record_batches;
@@ -102,11 +190,7 @@ pub fn decode_arrow_payload(arrow_payload: ArrowPayload) -> Result<Vec<RecordBat
     // Maybe unzip payload bytes firstly.
     let unzip_byte_batches = byte_batches
         .into_iter()
-        .map(|bytes_batch| match compression {
-            Compression::None => Ok(bytes_batch),
-            Compression::Zstd => zstd::stream::decode_all(Cursor::new(bytes_batch))
-                .map_err(|e| Error::DecodeArrowPayload(Box::new(e))),
-        })
+        .map(|bytes_batch| unzip_byte_batch(compression, bytes_batch))
         .collect::<Result<Vec<Vec<u8>>>>()?;
 
     // Decode the byte batches to record batches, multiple record batches may be
@@ -138,3 +222,101 @@ pub fn decode_arrow_payload(arrow_payload: ArrowPayload) -> Result<Vec<RecordBat
 
     Ok(record_batches)
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::Int32Array,
+        datatypes::{DataType, Field, Schema},
+        ipc::writer::StreamWriter,
+        record_batch::RecordBatch,
+    };
+    use horaedbproto::storage::{
+        arrow_payload::Compression, sql_query_response::Output as OutputPb, ArrowPayload,
+        SqlQueryResponse,
+    };
+
+    use super::{for_each_row, Response};
+    use crate::{model::value::Value, Error};
+
+    fn encode_batch(values: Vec<i32>) -> Vec<u8> {
+        encode_batch_with_name("v", values)
+    }
+
+    fn encode_batch_with_name(name: &str, values: Vec<i32>) -> Vec<u8> {
+        let schema = Arc::new(Schema::new(vec![Field::new(name, DataType::Int32, false)]));
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap();
+        let mut writer = StreamWriter::try_new(Vec::new(), &schema).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+        let bytes = writer.into_inner().unwrap();
+
+        zstd::stream::encode_all(bytes.as_slice(), 0).unwrap()
+    }
+
+    /// Build the response of the zstd compressed batches of the column `v`.
+    pub(crate) fn arrow_response(batches: Vec<Vec<i32>>) -> SqlQueryResponse {
+        let mut payload = ArrowPayload {
+            record_batches: batches.into_iter().map(encode_batch).collect(),
+            ..Default::default()
+        };
+        payload.set_compression(Compression::Zstd);
+        SqlQueryResponse {
+            output: Some(OutputPb::Arrow(payload)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_for_each_row() {
+        let resp_pb = arrow_response(vec![vec![1, 2], vec![3]]);
+
+        let mut values = Vec::new();
+        let affected = for_each_row(resp_pb, &mut |row| {
+            values.push(row.column("v").unwrap().value().clone());
+        })
+        .unwrap();
+        assert_eq!(affected, 0);
+        assert_eq!(
+            values,
+            vec![Value::Int32(1), Value::Int32(2), Value::Int32(3)]
+        );
+
+        let resp_pb = SqlQueryResponse {
+            output: Some(OutputPb::AffectedRows(5)),
+            ..Default::default()
+        };
+        assert_eq!(for_each_row(resp_pb, &mut |_| unreachable!()).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_schema_mismatch() {
+        let mut payload = ArrowPayload {
+            record_batches: vec![
+                encode_batch(vec![1]),
+                encode_batch(vec![2]),
+                encode_batch_with_name("w", vec![3]),
+            ],
+            ..Default::default()
+        };
+        payload.set_compression(Compression::Zstd);
+        let resp_pb = SqlQueryResponse {
+            output: Some(OutputPb::Arrow(payload)),
+            ..Default::default()
+        };
+
+        let check_err = |err: Error| match err {
+            Error::SchemaMismatch(e) => {
+                assert_eq!(e.batch_index, 2);
+                assert_eq!(e.expected, vec![("v".to_string(), "Int32".to_string())]);
+                assert_eq!(e.actual, vec![("w".to_string(), "Int32".to_string())]);
+            }
+            _ => panic!("unexpected error:{err}"),
+        };
+        check_err(Response::try_from(resp_pb.clone()).unwrap_err());
+        check_err(for_each_row(resp_pb, &mut |_| {}).unwrap_err());
+    }
+}
@@ -37,6 +37,10 @@ pub struct Row {
 }
 
 impl Row {
+    pub(crate) fn new(columns: Vec<Column>) -> Self {
+        Self { columns }
+    }
+
     /// Find the [`Column`] by the column name.
     pub fn column(&self, name: &str) -> Option<&Column> {
         self.columns.iter().find(|column| column.name == name)
@@ -46,6 +50,11 @@ impl Row {
     pub fn columns(&self) -> &[Column] {
         &self.columns
     }
+
+    /// Get the mutable columns, e.g. to add, remove or modify columns.
+    pub fn columns_mut(&mut self) -> &mut Vec<Column> {
+        &mut self.columns
+    }
 }
 
 /// A column in the [`Row`].
@@ -56,7 +65,7 @@ pub struct Column {
 }
 
 impl Column {
-    pub(crate) fn new(name: String, value: Value) -> Self {
+    pub fn new(name: String, value: Value) -> Self {
         Self { name, value }
     }
 
@@ -69,6 +78,16 @@ impl Column {
     pub fn value(&self) -> &Value {
         &self.value
     }
+
+    /// Rename the column.
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    /// Replace the [`Value`] of the column.
+    pub fn set_value(&mut self, value: Value) {
+        self.value = value;
+    }
 }
 
 macro_rules! fill_column {
@@ -110,7 +129,7 @@ impl RowBuilder {
                     })
                     .collect::<Vec<Column>>();
 
-                Row { columns }
+                Row::new(columns)
             })
             .collect::<Vec<_>>()
     }
@@ -0,0 +1,125 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Query the rows of one series without formatting the sql by hand.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    model::{
+        sql_query::Request,
+        value::{TimestampMs, Value},
+    },
+    util::{quote_ident, quote_literal},
+};
+
+/// The time range to query by [`DbClient::get_series`].
+///
+/// [`DbClient::get_series`]: crate::DbClient::get_series
+#[derive(Clone, Debug)]
+pub struct TimeRange {
+    /// The name of the timestamp column of the table.
+    pub timestamp_column: String,
+    /// The inclusive start of the range.
+    pub start: TimestampMs,
+    /// The exclusive end of the range.
+    pub end: TimestampMs,
+}
+
+/// Build the request to query the rows of the series identified by the
+/// `tags` in the `time_range`, ordered by the timestamp.
+pub(crate) fn build_series_request(
+    table: &str,
+    tags: &BTreeMap<String, Value>,
+    time_range: &TimeRange,
+) -> Request {
+    let ts_col = quote_ident(&time_range.timestamp_column);
+    let mut conditions = Vec::with_capacity(tags.len() + 2);
+    for (name, value) in tags {
+        let condition = match value {
+            Value::Null => format!("{} IS NULL", quote_ident(name)),
+            _ => format!("{} = {}", quote_ident(name), value_literal(value)),
+        };
+        conditions.push(condition);
+    }
+    conditions.push(format!("{ts_col} >= {}", time_range.start));
+    conditions.push(format!("{ts_col} < {}", time_range.end));
+
+    Request {
+        tables: vec![table.to_string()],
+        sql: format!(
+            "SELECT * FROM {} WHERE {} ORDER BY {ts_col}",
+            quote_ident(table),
+            conditions.join(" AND ")
+        ),
+    }
+}
+
+/// Format the `value` as the sql literal.
+pub(crate) fn value_literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Timestamp(v) => v.to_string(),
+        Value::Double(v) => v.to_string(),
+        Value::Float(v) => v.to_string(),
+        Value::Varbinary(v) => {
+            let hex: String = v.iter().map(|b| format!("{b:02x}")).collect();
+            format!("X'{hex}'")
+        }
+        Value::String(v) => quote_literal(v),
+        Value::UInt64(v) => v.to_string(),
+        Value::UInt32(v) => v.to_string(),
+        Value::UInt16(v) => v.to_string(),
+        Value::UInt8(v) => v.to_string(),
+        Value::Int64(v) => v.to_string(),
+        Value::Int32(v) => v.to_string(),
+        Value::Int16(v) => v.to_string(),
+        Value::Int8(v) => v.to_string(),
+        Value::Boolean(v) => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{build_series_request, TimeRange};
+    use crate::model::value::Value;
+
+    #[test]
+    fn test_build_series_request() {
+        let tags = BTreeMap::from([
+            ("host".to_string(), Value::String("a'b".to_string())),
+            ("id`x".to_string(), Value::Int32(1)),
+            ("key".to_string(), Value::Varbinary(vec![0x0a, 0xff])),
+            ("zone".to_string(), Value::Null),
+        ]);
+        let time_range = TimeRange {
+            timestamp_column: "t".to_string(),
+            start: 1000,
+            end: 2000,
+        };
+
+        let req = build_series_request("cpu", &tags, &time_range);
+        assert_eq!(req.tables, vec!["cpu".to_string()]);
+        assert_eq!(
+            req.sql,
+            "SELECT * FROM `cpu` WHERE `host` = 'a''b' AND `id``x` = 1 AND `key` = X'0aff' \
+             AND `zone` IS NULL AND `t` >= 1000 AND `t` < 2000 ORDER BY `t`"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Bind the parameters to the sql instead of formatting it by hand.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    model::{
+        sql_query::{series::value_literal, Request},
+        value::Value,
+    },
+    Error, Result,
+};
+
+/// The sql with the placeholders bound to the typed parameters, which are
+/// quoted as the literals when the [`Request`] is built, so that the values
+/// can't break out of the literals.
+///
+/// The positional placeholders `?` are bound by [`bind`](Statement::bind) in
+/// order, and the named ones `:name` by [`bind_named`](Statement::bind_named).
+/// The placeholders in the literals, the quoted identifiers and the comments
+/// are kept as is, and so are the casts like `::`.
+///
+/// ```
+/// use horaedb_client::model::{sql_query::statement::Statement, value::Value};
+///
+/// let req = Statement::new("SELECT * FROM cpu WHERE host = ? AND t >= :start")
+///     .table("cpu")
+///     .bind(Value::String("a'b".to_string()))
+///     .bind_named("start", Value::Timestamp(1000))
+///     .build()
+///     .unwrap();
+/// assert_eq!(
+///     req.sql,
+///     "SELECT * FROM cpu WHERE host = 'a''b' AND t >= 1000"
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Statement {
+    sql: String,
+    tables: Vec<String>,
+    params: Vec<Value>,
+    named_params: HashMap<String, Value>,
+}
+
+impl Statement {
+    pub fn new(sql: impl Into<String>) -> Self {
+        Self {
+            sql: sql.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Add the table involved in the sql, see [`Request::tables`].
+    pub fn table(mut self, table: impl Into<String>) -> Self {
+        self.tables.push(table.into());
+        self
+    }
+
+    /// Bind the `value` to the next positional placeholder `?`.
+    pub fn bind(mut self, value: Value) -> Self {
+        self.params.push(value);
+        self
+    }
+
+    /// Bind the `value` to the named placeholders `:name`.
+    pub fn bind_named(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.named_params.insert(name.into(), value);
+        self
+    }
+
+    /// Build the request with the placeholders replaced by the literals of the
+    /// parameters.
+    ///
+    /// It fails if any placeholder isn't bound, or any parameter isn't used.
+    pub fn build(&self) -> Result<Request> {
+        let mut sql = String::with_capacity(self.sql.len());
+        let mut params = self.params.iter();
+        let mut used_positions = 0;
+        let mut used_names = HashSet::new();
+        let mut rest = self.sql.as_str();
+        while let Some(c) = rest.chars().next() {
+            let len = match c {
+                // The escaped quotes, which are doubled, are skipped as the
+                // adjacent quoted parts.
+                '\'' | '"' | '`' => rest[1..].find(c).map_or(rest.len(), |i| i + 2),
+                '-' if rest.starts_with("--") => rest.find('\n').map_or(rest.len(), |i| i + 1),
+                '/' if rest.starts_with("/*") => rest[2..].find("*/").map_or(rest.len(), |i| i + 4),
+                ':' if rest.starts_with("::") => 2,
+                ':' if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => {
+                    let name_len = rest[1..]
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                        .unwrap_or(rest.len() - 1);
+                    let name = &rest[1..1 + name_len];
+                    let value = self.named_params.get(name).ok_or_else(|| {
+                        Error::Client(format!("named parameter :{name} is not bound"))
+                    })?;
+                    sql.push_str(&literal(value)?);
+                    used_names.insert(name);
+                    rest = &rest[1 + name_len..];
+                    continue;
+                }
+                '?' => {
+                    used_positions += 1;
+                    let value = params.next().ok_or_else(|| {
+                        Error::Client(format!(
+                            "positional parameter {used_positions} is not bound"
+                        ))
+                    })?;
+                    sql.push_str(&literal(value)?);
+                    rest = &rest[1..];
+                    continue;
+                }
+                _ => c.len_utf8(),
+            };
+            sql.push_str(&rest[..len]);
+            rest = &rest[len..];
+        }
+
+        if used_positions < self.params.len() {
+            return Err(Error::Client(format!(
+                "{} positional parameters are bound, but only {used_positions} are used",
+                self.params.len()
+            )));
+        }
+        if let Some(name) = self
+            .named_params
+            .keys()
+            .find(|name| !used_names.contains(name.as_str()))
+        {
+            return Err(Error::Client(format!(
+                "named parameter :{name} is bound but not used"
+            )));
+        }
+
+        Ok(Request {
+            tables: self.tables.clone(),
+            sql,
+        })
+    }
+}
+
+/// The literal of the parameter, and the non-finite floats are rejected as
+/// they have no literals.
+fn literal(value: &Value) -> Result<String> {
+    let finite = match value {
+        Value::Double(v) => v.is_finite(),
+        Value::Float(v) => v.is_finite(),
+        _ => true,
+    };
+    match finite {
+        true => Ok(value_literal(value)),
+        false => Err(Error::Client(format!(
+            "non-finite parameter {value:?} can't be bound"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Statement;
+    use crate::{model::value::Value, Error};
+
+    #[test]
+    fn test_build_statement() {
+        let sql = "SELECT '?', `:a`, \"it''s ?\" -- :b ?\n\
+                   FROM t /* ? */ WHERE a = ? AND b = :b AND c::string = :c_1 AND d = :b";
+        let req = Statement::new(sql)
+            .table("t")
+            .bind(Value::String("x'; DROP TABLE t; --".to_string()))
+            .bind_named("b", Value::Int32(1))
+            .bind_named("c_1", Value::Null)
+            .build()
+            .unwrap();
+        assert_eq!(req.tables, vec!["t".to_string()]);
+        assert_eq!(
+            req.sql,
+            "SELECT '?', `:a`, \"it''s ?\" -- :b ?\n\
+             FROM t /* ? */ WHERE a = 'x''; DROP TABLE t; --' AND b = 1 AND c::string = NULL \
+             AND d = 1"
+        );
+
+        let err = Statement::new("SELECT ?, ?")
+            .bind(Value::Int32(1))
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::Client(msg) if msg.contains("parameter 2 is not bound")));
+        let err = Statement::new("SELECT ?")
+            .bind(Value::Int32(1))
+            .bind(Value::Int32(2))
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::Client(_)));
+        let err = Statement::new("SELECT :a")
+            .bind_named("a", Value::Int32(1))
+            .bind_named("b", Value::Int32(2))
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::Client(msg) if msg.contains(":b")));
+        let err = Statement::new("SELECT ?")
+            .bind(Value::Double(f64::NAN))
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::Client(_)));
+    }
+}
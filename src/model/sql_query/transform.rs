@@ -0,0 +1,67 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Post-processing of the rows returned by the queries.
+
+use crate::model::sql_query::row::Row;
+
+/// Transformer applied to every row of the query responses before they are
+/// returned to the caller, e.g. to rename columns, convert units or redact
+/// values.
+pub trait RowTransformer: Send + Sync {
+    fn transform(&self, row: &mut Row);
+}
+
+impl<F> RowTransformer for F
+where
+    F: Fn(&mut Row) + Send + Sync,
+{
+    fn transform(&self, row: &mut Row) {
+        self(row)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RowTransformer;
+    use crate::model::{
+        sql_query::row::{Column, Row},
+        value::Value,
+    };
+
+    #[test]
+    fn test_closure_transformer() {
+        let redact = |row: &mut Row| {
+            for column in row.columns_mut() {
+                if column.name() == "password" {
+                    column.set_value(Value::Null);
+                }
+            }
+        };
+
+        let mut row = Row::new(vec![
+            Column::new("user".to_string(), Value::String("u".to_string())),
+            Column::new("password".to_string(), Value::String("p".to_string())),
+        ]);
+        redact.transform(&mut row);
+        assert_eq!(row.column("password").unwrap().value(), &Value::Null);
+        assert_eq!(
+            row.column("user").unwrap().value(),
+            &Value::String("u".to_string())
+        );
+    }
+}
@@ -0,0 +1,154 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Typed models of the HoraeDB system tables and informational statements,
+//! decoded from the rows returned by the sql query.
+
+use crate::{
+    model::sql_query::{row::Row, Request, Response},
+    util::quote_ident,
+    Error, Result,
+};
+
+/// The system table holding the information of all the tables.
+pub const TABLES_TABLE: &str = "system.public.tables";
+
+/// One row of the [`TABLES_TABLE`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableInfo {
+    pub catalog: String,
+    pub schema: String,
+    pub table_name: String,
+    pub table_id: u64,
+    pub engine: String,
+}
+
+impl TableInfo {
+    /// Build the request querying all the tables.
+    pub fn query_request() -> Request {
+        Request {
+            tables: vec![TABLES_TABLE.to_string()],
+            sql: format!("SELECT * FROM {TABLES_TABLE}"),
+        }
+    }
+
+    /// Decode the rows in the response of [`TableInfo::query_request`].
+    pub fn decode(resp: &Response) -> Result<Vec<Self>> {
+        resp.rows.iter().map(Self::try_from).collect()
+    }
+}
+
+impl TryFrom<&Row> for TableInfo {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self> {
+        Ok(Self {
+            catalog: string_column(row, "catalog")?,
+            schema: string_column(row, "schema")?,
+            table_name: string_column(row, "table_name")?,
+            table_id: row
+                .column("table_id")
+                .and_then(|col| col.value().as_u64())
+                .ok_or_else(|| missing_column("table_id"))?,
+            engine: string_column(row, "engine")?,
+        })
+    }
+}
+
+/// The result of `SHOW CREATE TABLE`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CreateTableInfo {
+    pub table: String,
+    pub create_sql: String,
+}
+
+impl CreateTableInfo {
+    /// Build the request showing the sql creating the `table`.
+    pub fn query_request(table: &str) -> Request {
+        Request {
+            tables: vec![table.to_string()],
+            sql: format!("SHOW CREATE TABLE {}", quote_ident(table)),
+        }
+    }
+
+    /// Decode the response of [`CreateTableInfo::query_request`].
+    pub fn decode(resp: &Response) -> Result<Self> {
+        let row = resp
+            .rows
+            .first()
+            .ok_or_else(|| Error::BuildRows("no rows in show create table result".to_string()))?;
+        Self::try_from(row)
+    }
+}
+
+impl TryFrom<&Row> for CreateTableInfo {
+    type Error = Error;
+
+    fn try_from(row: &Row) -> Result<Self> {
+        Ok(Self {
+            table: string_column(row, "Table")?,
+            create_sql: string_column(row, "Create Table")?,
+        })
+    }
+}
+
+fn string_column(row: &Row, name: &str) -> Result<String> {
+    row.column(name)
+        .and_then(|col| col.value().as_str())
+        .ok_or_else(|| missing_column(name))
+}
+
+fn missing_column(name: &str) -> Error {
+    Error::BuildRows(format!("column:{name} is missing or has unexpected type"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{sql_query::row::Column, value::Value};
+
+    #[test]
+    fn test_decode_table_info() {
+        let row = Row::new(vec![
+            Column::new("timestamp".to_string(), Value::Timestamp(0)),
+            Column::new("catalog".to_string(), Value::String("horaedb".to_string())),
+            Column::new("schema".to_string(), Value::String("public".to_string())),
+            Column::new("table_name".to_string(), Value::String("demo".to_string())),
+            Column::new("table_id".to_string(), Value::UInt64(42)),
+            Column::new("engine".to_string(), Value::String("Analytic".to_string())),
+        ]);
+        let resp = Response {
+            affected_rows: 0,
+            rows: vec![row],
+        };
+
+        let tables = TableInfo::decode(&resp).unwrap();
+        assert_eq!(
+            tables,
+            vec![TableInfo {
+                catalog: "horaedb".to_string(),
+                schema: "public".to_string(),
+                table_name: "demo".to_string(),
+                table_id: 42,
+                engine: "Analytic".to_string(),
+            }]
+        );
+
+        let bad_row = Row::new(vec![Column::new("table_name".to_string(), Value::Int32(1))]);
+        assert!(TableInfo::try_from(&bad_row).is_err());
+    }
+}
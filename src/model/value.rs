@@ -22,8 +22,9 @@ use horaedbproto::storage::{value, Value as ValuePb};
 pub type TimestampMs = i64;
 
 /// The value enum to express the data in HoraeDB.
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd)]
 pub enum Value {
+    #[default]
     Null,
     Timestamp(TimestampMs),
     Double(f64),
@@ -238,12 +239,6 @@ impl Value {
     }
 }
 
-impl Default for Value {
-    fn default() -> Self {
-        Self::Null
-    }
-}
-
 impl From<Value> for ValuePb {
     fn from(val: Value) -> Self {
         let value = match val {
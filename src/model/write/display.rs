@@ -0,0 +1,148 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+};
+
+use crate::model::write::{series_key::SeriesKey, Request};
+
+/// Display a summary of the [`WriteRequest`](Request), including the tables,
+/// the numbers of points and series and the timestamp ranges, which is safe to
+/// be logged.
+///
+/// The tags and fields of every point are displayed only if `verbose` is set.
+pub struct WriteSummaryFormatter<'a> {
+    pub req: &'a Request,
+    pub verbose: bool,
+}
+
+struct TableSummary {
+    num_points: usize,
+    num_series: usize,
+    min_timestamp: i64,
+    max_timestamp: i64,
+}
+
+impl WriteSummaryFormatter<'_> {
+    fn summarize(&self) -> BTreeMap<&str, TableSummary> {
+        self.req
+            .point_groups
+            .iter()
+            .filter(|(_, points)| !points.is_empty())
+            .map(|(table, points)| {
+                let series = points
+                    .iter()
+                    .map(|point| SeriesKey::encode(&point.tags))
+                    .collect::<HashSet<_>>();
+                let summary = TableSummary {
+                    num_points: points.len(),
+                    num_series: series.len(),
+                    min_timestamp: points.iter().map(|p| p.timestamp).min().unwrap(),
+                    max_timestamp: points.iter().map(|p| p.timestamp).max().unwrap(),
+                };
+                (table.as_str(), summary)
+            })
+            .collect()
+    }
+}
+
+impl Display for WriteSummaryFormatter<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let summaries = self.summarize();
+        let num_points: usize = summaries.values().map(|s| s.num_points).sum();
+        let num_series: usize = summaries.values().map(|s| s.num_series).sum();
+        f.write_fmt(format_args!(
+            "tables:{}, points:{num_points}, series:{num_series}",
+            summaries.len()
+        ))?;
+
+        for (table, summary) in &summaries {
+            f.write_fmt(format_args!(
+                "\n  {table}: points:{}, series:{}, timestamp:[{}, {}]",
+                summary.num_points,
+                summary.num_series,
+                summary.min_timestamp,
+                summary.max_timestamp
+            ))?;
+
+            if self.verbose {
+                for point in &self.req.point_groups[*table] {
+                    f.write_fmt(format_args!(
+                        "\n    timestamp:{}, tags:{:?}, fields:{:?}",
+                        point.timestamp, point.tags, point.fields
+                    ))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::WriteSummaryFormatter;
+    use crate::model::{
+        value::Value,
+        write::{point::PointBuilder, Request},
+    };
+
+    fn make_request() -> Request {
+        let mut req = Request::default();
+        for (table, host, ts) in [("cpu", "a", 3000), ("cpu", "a", 1000), ("cpu", "b", 2000)] {
+            req.add_point(
+                PointBuilder::new(table)
+                    .timestamp(ts)
+                    .tag("host", Value::String(host.to_string()))
+                    .field("value", Value::Double(0.5))
+                    .build()
+                    .unwrap(),
+            );
+        }
+        req.add_point(
+            PointBuilder::new("mem")
+                .timestamp(1000)
+                .field("value", Value::Int64(1))
+                .build()
+                .unwrap(),
+        );
+        req
+    }
+
+    #[test]
+    fn test_write_summary() {
+        let req = make_request();
+        let summary = WriteSummaryFormatter {
+            req: &req,
+            verbose: false,
+        };
+        let expected = "\
+tables:2, points:4, series:3
+  cpu: points:3, series:2, timestamp:[1000, 3000]
+  mem: points:1, series:1, timestamp:[1000, 1000]";
+        assert_eq!(summary.to_string(), expected);
+
+        let verbose = WriteSummaryFormatter {
+            req: &req,
+            verbose: true,
+        }
+        .to_string();
+        assert!(verbose.contains("\n    timestamp:1000, tags:{}, fields:{\"value\": Int64(1)}"));
+    }
+}
@@ -15,9 +15,13 @@
 // specific language governing permissions and limitations
 // under the License.
 
+pub mod display;
 pub mod point;
 mod request;
 mod response;
+pub mod sampling;
+pub mod series_key;
+pub mod stats;
 
-pub use request::{pb_builder::WriteTableRequestPbsBuilder, Request};
+pub use request::{pb_builder::WriteTableRequestPbsBuilder, Request, WriteOrder};
 pub use response::Response;
@@ -17,16 +17,190 @@
 
 use std::collections::HashMap;
 
-use crate::model::write::point::Point;
+use horaedbproto::storage::{RequestContext as RequestContextPb, WriteRequest as WriteRequestPb};
+use prost::Message;
+
+use crate::{
+    model::{
+        value::DataType,
+        write::{
+            display::WriteSummaryFormatter,
+            point::{is_reserved_column_name, Point},
+            WriteTableRequestPbsBuilder,
+        },
+    },
+    Error, Result,
+};
+
+/// The order of the points of one series in the request sent to the server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WriteOrder {
+    /// Points are sorted by timestamp, and for the points with the same series
+    /// and timestamp, only the last added one is kept.
+    #[default]
+    Timestamp,
+    /// Points are kept in the order they are added to the request, which
+    /// matters when the last-write-wins semantics depend on arrival order.
+    Insertion,
+}
 
 /// Write request.
 #[derive(Clone, Debug, Default)]
 pub struct Request {
     /// The points of different tables.
     pub point_groups: HashMap<String, Vec<Point>>,
+    /// The order of the points of one series when building the request, which
+    /// is set by [`Request::set_order`].
+    pub(crate) order: WriteOrder,
 }
 
 impl Request {
+    /// Build the request of the points of different tables.
+    pub fn new(point_groups: HashMap<String, Vec<Point>>) -> Self {
+        Self {
+            point_groups,
+            order: WriteOrder::default(),
+        }
+    }
+
+    /// Set the [`WriteOrder`] of the points in the request.
+    pub fn set_order(&mut self, order: WriteOrder) -> &mut Self {
+        self.order = order;
+
+        self
+    }
+
+    /// The [`WriteOrder`] of the points in the request.
+    pub fn order(&self) -> WriteOrder {
+        self.order
+    }
+
+    /// Whether there is no point in the request.
+    pub fn is_empty(&self) -> bool {
+        self.point_groups.values().all(Vec::is_empty)
+    }
+
+    /// Split the request into multiple requests, each of which contains
+    /// `max_tables` tables at most.
+    pub fn split_by_tables(self, max_tables: usize) -> Vec<Request> {
+        let max_tables = max_tables.max(1);
+        if self.point_groups.len() <= max_tables {
+            return vec![self];
+        }
+
+        let mut reqs: Vec<Request> = Vec::new();
+        for (table, points) in self.point_groups {
+            match reqs.last_mut() {
+                Some(req) if req.point_groups.len() < max_tables => {
+                    req.point_groups.insert(table, points);
+                }
+                _ => {
+                    let mut req = Request {
+                        order: self.order,
+                        ..Default::default()
+                    };
+                    req.point_groups.insert(table, points);
+                    reqs.push(req);
+                }
+            }
+        }
+
+        reqs
+    }
+
+    /// The request of the points of the `tables` only.
+    pub fn select_tables(&self, tables: &[String]) -> Request {
+        let point_groups = tables
+            .iter()
+            .filter_map(|table| {
+                let points = self.point_groups.get(table)?;
+                Some((table.clone(), points.clone()))
+            })
+            .collect();
+        Request {
+            point_groups,
+            order: self.order,
+        }
+    }
+
+    /// Check the request can be written without sending it, i.e. the points
+    /// have the fields and no reserved column names, and the columns of the
+    /// same name in one table have the same kind (tag or field) and type.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |table: &str, msg: String| {
+            Err(Error::Client(format!(
+                "invalid write request of table:{table}, {msg}"
+            )))
+        };
+
+        for (table, points) in &self.point_groups {
+            if table.is_empty() {
+                return invalid(table, "table name is empty".to_string());
+            }
+            // The kind (whether it's a tag) and the type of the columns.
+            let mut columns: HashMap<&str, (bool, DataType)> = HashMap::new();
+            for point in points {
+                if &point.table != table {
+                    return invalid(table, format!("point of table:{} is mixed", point.table));
+                }
+                if point.fields.is_empty() {
+                    return invalid(table, "fields of point are empty".to_string());
+                }
+
+                let tags = point.tags.iter().map(|(name, value)| (true, name, value));
+                let fields = point
+                    .fields
+                    .iter()
+                    .map(|(name, value)| (false, name, value));
+                for (is_tag, name, value) in tags.chain(fields) {
+                    if is_reserved_column_name(name) {
+                        return invalid(table, format!("column:{name} is reserved"));
+                    }
+                    if value.is_null() {
+                        continue;
+                    }
+                    let data_type = value.data_type();
+                    match columns.insert(name, (is_tag, data_type)) {
+                        Some((was_tag, _)) if was_tag != is_tag => {
+                            return invalid(table, format!("column:{name} is both tag and field"));
+                        }
+                        Some((_, prev_type)) if prev_type != data_type => {
+                            return invalid(
+                                table,
+                                format!("column:{name} has types {prev_type:?} and {data_type:?}"),
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The length of the protobuf message of the request, which is checked
+    /// against the `max_send_msg_len` of the [`RpcConfig`].
+    ///
+    /// [`RpcConfig`]: crate::RpcConfig
+    pub fn encoded_len(&self, database: &str) -> usize {
+        let req_pb = WriteRequestPb {
+            context: Some(RequestContextPb {
+                database: database.to_string(),
+            }),
+            table_requests: WriteTableRequestPbsBuilder(self.clone()).build(),
+        };
+        req_pb.encoded_len()
+    }
+
+    /// Summary of the request for logging, see [`WriteSummaryFormatter`].
+    pub fn summary(&self) -> WriteSummaryFormatter<'_> {
+        WriteSummaryFormatter {
+            req: self,
+            verbose: false,
+        }
+    }
+
     /// Add one point to the request.
     pub fn add_point(&mut self, point: Point) -> &mut Self {
         let points = self.point_groups.entry(point.table.clone()).or_default();
@@ -55,23 +229,23 @@ pub mod pb_builder {
 
     use crate::model::{
         value::{TimestampMs, Value},
-        write::{point::Point, Request},
+        write::{point::Point, series_key::SeriesKey, Request, WriteOrder},
     };
 
-    type TagsKey = Vec<u8>;
-
     /// Used to build [`WriteRequestPb`](WriteTableRequestPb) from [Request].
     pub struct WriteTableRequestPbsBuilder(pub Request);
 
     impl WriteTableRequestPbsBuilder {
         pub fn build(self) -> Vec<WriteTableRequestPb> {
             // Partition points by table.
+            let order = self.0.order;
             let point_group = self.0.point_groups;
 
             // Build pb.
             let mut table_request_pbs = Vec::with_capacity(point_group.len());
             for (table, points) in point_group {
-                let write_table_request_pb_builder = TableRequestPbBuilder::new(table, points);
+                let write_table_request_pb_builder =
+                    TableRequestPbBuilder::new(table, points, order);
                 let write_table_request_pb = write_table_request_pb_builder.build();
                 table_request_pbs.push(write_table_request_pb);
             }
@@ -82,40 +256,63 @@ pub mod pb_builder {
 
     struct TableRequestPbBuilder {
         table: String,
-        series_entires: Vec<SeriesEntry>,
+        series_entries: Vec<SeriesEntry>,
+        // The max number of the tags and fields in one point, which are the lower
+        // bounds of the distinct names and used to pre-size the dictionaries.
+        //
+        // The names can't be inlined in the pb, so the dictionaries are always
+        // built even for the tiny requests.
+        max_tags_num: usize,
+        max_fields_num: usize,
     }
 
     impl TableRequestPbBuilder {
-        pub fn new(table: String, points: Vec<Point>) -> Self {
-            // Partition points according to tags and build [WriteSeriesEntry].
-            let mut series_entries_by_tags = HashMap::new();
+        pub fn new(table: String, points: Vec<Point>, order: WriteOrder) -> Self {
+            // Partition points according to tags and build [WriteSeriesEntry], the
+            // series are kept in the order of their first appearance.
+            let mut series_idx_by_tags = HashMap::new();
+            let mut series_entries: Vec<SeriesEntry> = Vec::new();
+            let mut max_tags_num = 0;
+            let mut max_fields_num = 0;
             for point in points {
                 assert_eq!(point.table, table);
-                let tags_key = make_tags_key(&point.tags);
-                let series_entry =
-                    series_entries_by_tags
-                        .entry(tags_key)
-                        .or_insert_with(|| SeriesEntry {
-                            tags: point.tags,
-                            ts_fields: BTreeMap::new(),
-                        });
-                series_entry.ts_fields.insert(point.timestamp, point.fields);
+                max_tags_num = max_tags_num.max(point.tags.len());
+                max_fields_num = max_fields_num.max(point.fields.len());
+                let tags_key = SeriesKey::encode(&point.tags);
+                let series_idx = *series_idx_by_tags.entry(tags_key).or_insert_with(|| {
+                    series_entries.push(SeriesEntry {
+                        tags: point.tags,
+                        ts_fields: Vec::new(),
+                    });
+                    series_entries.len() - 1
+                });
+                series_entries[series_idx]
+                    .ts_fields
+                    .push((point.timestamp, point.fields));
             }
 
-            // Flatten the write series entires.
-            let series_entires = series_entries_by_tags.into_values().collect();
+            // Sort the field groups by timestamp and keep the last one for the same
+            // timestamp, unless the insertion order is required.
+            if order == WriteOrder::Timestamp {
+                for entry in &mut series_entries {
+                    let ts_fields: BTreeMap<_, _> = entry.ts_fields.drain(..).collect();
+                    entry.ts_fields.extend(ts_fields);
+                }
+            }
 
             Self {
                 table,
-                series_entires,
+                series_entries,
+                max_tags_num,
+                max_fields_num,
             }
         }
 
         pub fn build(self) -> WriteTableRequestPb {
-            let mut tags_dict = NameDict::new();
-            let mut fields_dict = NameDict::new();
-            let mut wirte_entries_pb = Vec::with_capacity(self.series_entires.len());
-            for entry in self.series_entires {
+            let mut tags_dict = NameDict::with_capacity(self.max_tags_num);
+            let mut fields_dict = NameDict::with_capacity(self.max_fields_num);
+            let mut wirte_entries_pb = Vec::with_capacity(self.series_entries.len());
+            for entry in self.series_entries {
                 wirte_entries_pb.push(Self::build_series_entry(
                     &mut tags_dict,
                     &mut fields_dict,
@@ -161,7 +358,7 @@ pub mod pb_builder {
 
         fn build_ts_fields(
             fields_dict: &mut NameDict,
-            ts_fields: BTreeMap<TimestampMs, Fields>,
+            ts_fields: Vec<(TimestampMs, Fields)>,
         ) -> Vec<FieldGroupPb> {
             if ts_fields.is_empty() {
                 return Vec::new();
@@ -194,7 +391,7 @@ pub mod pb_builder {
     #[derive(Clone, Default, Debug)]
     pub struct SeriesEntry {
         tags: BTreeMap<String, Value>,
-        ts_fields: BTreeMap<TimestampMs, Fields>,
+        ts_fields: Vec<(TimestampMs, Fields)>,
     }
 
     type Fields = BTreeMap<String, Value>;
@@ -206,9 +403,9 @@ pub mod pb_builder {
     }
 
     impl NameDict {
-        fn new() -> Self {
+        fn with_capacity(capacity: usize) -> Self {
             NameDict {
-                dict: HashMap::new(),
+                dict: HashMap::with_capacity(capacity),
                 name_idx: 0,
             }
         }
@@ -229,16 +426,6 @@ pub mod pb_builder {
             ordered
         }
     }
-
-    pub fn make_tags_key(tags: &BTreeMap<String, Value>) -> TagsKey {
-        let mut series_key = Vec::default();
-        for (name, val) in tags {
-            series_key.extend(name.as_bytes());
-            series_key.extend_from_slice(&val.to_bytes());
-        }
-
-        series_key
-    }
 }
 
 #[cfg(test)]
@@ -247,13 +434,13 @@ mod test {
 
     use chrono::Local;
 
-    use super::pb_builder::make_tags_key;
     use crate::model::{
         value::Value,
         write::{
             point::{Point, PointBuilder},
             request::pb_builder::WriteTableRequestPbsBuilder,
-            Request,
+            series_key::SeriesKey,
+            Request, WriteOrder,
         },
     };
 
@@ -397,10 +584,147 @@ mod test {
         assert_eq!(points, expected_points);
     }
 
+    #[test]
+    fn test_build_write_table_in_insertion_order() {
+        let make_point = |tag: &str, ts: i64, field: i32| {
+            PointBuilder::new("test_table")
+                .timestamp(ts)
+                .tag("tag", Value::String(tag.to_string()))
+                .field("field", Value::Int32(field))
+                .build()
+                .unwrap()
+        };
+        let points = vec![
+            make_point("b", 3, 0),
+            make_point("a", 2, 1),
+            make_point("b", 1, 2),
+            make_point("b", 3, 3),
+        ];
+
+        let collect_series = |order: WriteOrder| {
+            let mut write_req = Request::default();
+            write_req.set_order(order).add_points(points.clone());
+            let table_requests = WriteTableRequestPbsBuilder(write_req).build();
+            assert_eq!(table_requests.len(), 1);
+            table_requests[0]
+                .entries
+                .iter()
+                .map(|entry| {
+                    let tag = Value::from(entry.tags[0].value.clone().unwrap());
+                    let field_groups = entry
+                        .field_groups
+                        .iter()
+                        .map(|group| {
+                            let field = Value::from(group.fields[0].value.clone().unwrap());
+                            (group.timestamp, field.as_i32().unwrap())
+                        })
+                        .collect::<Vec<_>>();
+                    (tag.as_str().unwrap(), field_groups)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            collect_series(WriteOrder::Insertion),
+            vec![
+                ("b".to_string(), vec![(3, 0), (1, 2), (3, 3)]),
+                ("a".to_string(), vec![(2, 1)]),
+            ]
+        );
+        assert_eq!(
+            collect_series(WriteOrder::Timestamp),
+            vec![
+                ("b".to_string(), vec![(1, 2), (3, 3)]),
+                ("a".to_string(), vec![(2, 1)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let point = |tags: Vec<(&str, Value)>, fields: Vec<(&str, Value)>| {
+            let mut builder = PointBuilder::new("t").timestamp(1);
+            for (name, value) in tags {
+                builder = builder.tag(name, value);
+            }
+            for (name, value) in fields {
+                builder = builder.field(name, value);
+            }
+            builder.build().unwrap()
+        };
+        let validate = |points: Vec<Point>| {
+            let mut req = Request::default();
+            req.add_points(points);
+            req.validate()
+        };
+
+        let host = ("host", Value::String("a".to_string()));
+        let v = ("v", Value::Double(1.0));
+        assert!(validate(vec![
+            point(vec![host.clone()], vec![v.clone()]),
+            point(
+                vec![("host", Value::Null)],
+                vec![v.clone(), ("v2", Value::Int32(1))]
+            ),
+        ])
+        .is_ok());
+        // Inconsistent types.
+        assert!(validate(vec![
+            point(vec![host.clone()], vec![v.clone()]),
+            point(vec![host.clone()], vec![("v", Value::Int32(1))]),
+        ])
+        .is_err());
+        // Both tag and field.
+        assert!(validate(vec![
+            point(vec![host.clone()], vec![v.clone()]),
+            point(vec![], vec![("host", Value::String("b".to_string()))]),
+        ])
+        .is_err());
+        // Reserved names and empty fields of the points built by hand.
+        let mut reserved = point(vec![], vec![v.clone()]);
+        reserved.tags.insert("tsid".to_string(), Value::UInt64(1));
+        assert!(validate(vec![reserved]).is_err());
+        let mut empty = point(vec![host], vec![v]);
+        empty.fields.clear();
+        assert!(validate(vec![empty]).is_err());
+    }
+
+    #[test]
+    fn test_split_by_tables() {
+        let mut write_req = Request::default();
+        write_req.set_order(WriteOrder::Insertion);
+        for table in ["t1", "t2", "t3", "t4", "t5"] {
+            write_req.add_point(
+                PointBuilder::new(table)
+                    .timestamp(0)
+                    .field("field", Value::Int32(0))
+                    .build()
+                    .unwrap(),
+            );
+        }
+
+        let reqs = write_req.clone().split_by_tables(2);
+        assert_eq!(
+            reqs.iter()
+                .map(|req| req.point_groups.len())
+                .collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        assert!(reqs.iter().all(|req| req.order == WriteOrder::Insertion));
+        let mut tables = reqs
+            .into_iter()
+            .flat_map(|req| req.point_groups.into_keys())
+            .collect::<Vec<_>>();
+        tables.sort();
+        assert_eq!(tables, vec!["t1", "t2", "t3", "t4", "t5"]);
+
+        assert_eq!(write_req.split_by_tables(5).len(), 1);
+    }
+
     fn make_cmp_key(point: &Point) -> (Vec<u8>, i64) {
         let mut series_key = point.table.as_bytes().to_vec();
-        let tagks_key = make_tags_key(&point.tags);
-        series_key.extend(tagks_key);
+        let tagks_key = SeriesKey::encode(&point.tags);
+        series_key.extend_from_slice(tagks_key.as_bytes());
 
         (series_key, point.timestamp)
     }
@@ -409,10 +733,10 @@ mod test {
         points.sort_by(|point1, point2| {
             let mut series_key1 = point1.table.as_bytes().to_vec();
             let mut series_key2 = point2.table.as_bytes().to_vec();
-            let tagks_key1 = make_tags_key(&point1.tags);
-            let tagks_key2 = make_tags_key(&point2.tags);
-            series_key1.extend(tagks_key1);
-            series_key2.extend(tagks_key2);
+            let tagks_key1 = SeriesKey::encode(&point1.tags);
+            let tagks_key2 = SeriesKey::encode(&point2.tags);
+            series_key1.extend_from_slice(tagks_key1.as_bytes());
+            series_key2.extend_from_slice(tagks_key2.as_bytes());
             let cmp_key1 = (series_key1, point1.timestamp);
             let cmp_key2 = (series_key2, point2.timestamp);
 
@@ -0,0 +1,302 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Client-side sampling of the points before they are written, which is useful
+//! for the users writing a downsampled tier alongside the raw data.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use dashmap::DashMap;
+
+use crate::model::{
+    value::{TimestampMs, Value},
+    write::{point::Point, series_key::SeriesKey, Request},
+};
+
+/// The policy to sample the points of one table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SamplingPolicy {
+    /// Keep the first point in every `n` points of each series.
+    ///
+    /// The counting is across requests, and `0` or `1` keeps all the points.
+    /// The counters are reset once too many series are counted, so the memory
+    /// of the high-cardinality tables is bounded.
+    KeepOneInN(u64),
+    /// Aggregate the points of each series in every window into one point
+    /// with the timestamp of the window start.
+    ///
+    /// The float fields are averaged and the others take the last value. The
+    /// aggregation is done within one request, so the points of one window
+    /// should be written in the same request.
+    AggregateWindow { window_ms: i64 },
+}
+
+/// The max number of the series counted by the `KeepOneInN` policies.
+const MAX_COUNTED_SERIES: usize = 100_000;
+
+/// Sampler applying the [`SamplingPolicy`] of each table to the write
+/// requests, and the tables without policy are kept untouched.
+#[derive(Debug)]
+pub struct WriteSampler {
+    policies: HashMap<String, SamplingPolicy>,
+    // Counters of the `KeepOneInN` policy, keyed by table and series.
+    counters: DashMap<(String, SeriesKey), AtomicU64>,
+    max_counted_series: usize,
+}
+
+impl Default for WriteSampler {
+    fn default() -> Self {
+        Self {
+            policies: HashMap::new(),
+            counters: DashMap::new(),
+            max_counted_series: MAX_COUNTED_SERIES,
+        }
+    }
+}
+
+impl WriteSampler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the [`SamplingPolicy`] for the `table`.
+    pub fn policy(mut self, table: impl Into<String>, policy: SamplingPolicy) -> Self {
+        self.policies.insert(table.into(), policy);
+        self
+    }
+
+    /// Whether there is any policy for the tables in the request.
+    pub fn should_sample(&self, req: &Request) -> bool {
+        req.point_groups
+            .keys()
+            .any(|table| self.policies.contains_key(table))
+    }
+
+    /// Sample the points in the request.
+    pub fn sample(&self, req: &Request) -> Request {
+        self.sample_impl(req, true)
+    }
+
+    /// Sample the points in the request as [`sample`](Self::sample) without
+    /// counting them, e.g. for the dry run of the write.
+    pub(crate) fn preview(&self, req: &Request) -> Request {
+        self.sample_impl(req, false)
+    }
+
+    fn sample_impl(&self, req: &Request, count: bool) -> Request {
+        let mut sampled = Request {
+            order: req.order,
+            ..Default::default()
+        };
+        for (table, points) in &req.point_groups {
+            let points = match self.policies.get(table) {
+                Some(SamplingPolicy::KeepOneInN(n)) if *n > 1 => {
+                    self.keep_one_in_n(points, *n, count)
+                }
+                Some(SamplingPolicy::AggregateWindow { window_ms }) if *window_ms > 0 => {
+                    aggregate_window(points, *window_ms)
+                }
+                _ => points.clone(),
+            };
+            if !points.is_empty() {
+                sampled.point_groups.insert(table.clone(), points);
+            }
+        }
+
+        sampled
+    }
+
+    fn keep_one_in_n(&self, points: &[Point], n: u64, count: bool) -> Vec<Point> {
+        // The counting restarts rather than growing with the cardinality.
+        if count && self.counters.len() >= self.max_counted_series {
+            self.counters.clear();
+        }
+
+        // The counts of the preview start from the counters without changing
+        // them.
+        let mut previewed = HashMap::new();
+        points
+            .iter()
+            .filter(|point| {
+                let key = (point.table.clone(), SeriesKey::encode(&point.tags));
+                let prev = match count {
+                    true => self
+                        .counters
+                        .entry(key)
+                        .or_default()
+                        .fetch_add(1, Ordering::Relaxed),
+                    false => {
+                        let prev = previewed.entry(key).or_insert_with_key(|key| {
+                            self.counters
+                                .get(key)
+                                .map_or(0, |counter| counter.load(Ordering::Relaxed))
+                        });
+                        *prev += 1;
+                        *prev - 1
+                    }
+                };
+                prev.is_multiple_of(n)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+fn aggregate_window(points: &[Point], window_ms: i64) -> Vec<Point> {
+    struct Window {
+        point: Point,
+        // Sum and count of the float fields.
+        float_sums: BTreeMap<String, (f64, usize)>,
+    }
+
+    // Keep the windows in the order of their first appearance.
+    let mut window_idx_by_key = HashMap::new();
+    let mut windows: Vec<Window> = Vec::new();
+    for point in points {
+        let window_start: TimestampMs = point.timestamp - point.timestamp.rem_euclid(window_ms);
+        let key = (SeriesKey::encode(&point.tags), window_start);
+        let idx = *window_idx_by_key.entry(key).or_insert_with(|| {
+            windows.push(Window {
+                point: Point {
+                    table: point.table.clone(),
+                    timestamp: window_start,
+                    tags: point.tags.clone(),
+                    fields: BTreeMap::new(),
+                },
+                float_sums: BTreeMap::new(),
+            });
+            windows.len() - 1
+        });
+
+        let window = &mut windows[idx];
+        for (name, value) in &point.fields {
+            match value {
+                Value::Double(_) | Value::Float(_) => {
+                    let sum = window.float_sums.entry(name.clone()).or_default();
+                    sum.0 += value.as_f64().unwrap();
+                    sum.1 += 1;
+                }
+                _ => {
+                    window.point.fields.insert(name.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    windows
+        .into_iter()
+        .map(|mut window| {
+            for (name, (sum, count)) in window.float_sums {
+                let mean = Value::Double(sum / count as f64);
+                window.point.fields.insert(name, mean);
+            }
+            window.point
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::write::point::PointBuilder;
+
+    fn make_point(table: &str, host: &str, ts: i64, cpu: f64) -> Point {
+        PointBuilder::new(table)
+            .timestamp(ts)
+            .tag("host", Value::String(host.to_string()))
+            .field("cpu", Value::Double(cpu))
+            .field("status", Value::Int32(ts as i32))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_keep_one_in_n() {
+        let sampler = WriteSampler::new().policy("t1", SamplingPolicy::KeepOneInN(2));
+        let mut req = Request::default();
+        req.add_points(vec![
+            make_point("t1", "a", 1, 0.0),
+            make_point("t1", "a", 2, 0.0),
+            make_point("t1", "b", 3, 0.0),
+            make_point("t1", "a", 4, 0.0),
+            make_point("t2", "a", 5, 0.0),
+        ]);
+
+        let sampled = sampler.sample(&req);
+        let timestamps = |table: &str| -> Vec<i64> {
+            sampled.point_groups[table]
+                .iter()
+                .map(|point| point.timestamp)
+                .collect()
+        };
+        assert_eq!(timestamps("t1"), vec![1, 3, 4]);
+        assert_eq!(timestamps("t2"), vec![5]);
+
+        // The counting continues in the next request.
+        let mut req = Request::default();
+        req.add_point(make_point("t1", "a", 6, 0.0));
+        assert!(sampler.sample(&req).point_groups.is_empty());
+
+        // The preview doesn't count the points.
+        let mut req = Request::default();
+        req.add_points(vec![
+            make_point("t1", "a", 7, 0.0),
+            make_point("t1", "a", 8, 0.0),
+        ]);
+        assert_eq!(sampler.preview(&req).point_groups["t1"].len(), 1);
+        assert_eq!(sampler.preview(&req).point_groups["t1"][0].timestamp, 7);
+        assert_eq!(sampler.sample(&req).point_groups["t1"][0].timestamp, 7);
+    }
+
+    #[test]
+    fn test_max_counted_series() {
+        let mut sampler = WriteSampler::new().policy("t1", SamplingPolicy::KeepOneInN(2));
+        sampler.max_counted_series = 2;
+        for host in ["a", "b", "c"] {
+            let mut req = Request::default();
+            req.add_point(make_point("t1", host, 1, 0.0));
+            sampler.sample(&req);
+        }
+        assert_eq!(sampler.counters.len(), 1);
+    }
+
+    #[test]
+    fn test_aggregate_window() {
+        let sampler =
+            WriteSampler::new().policy("t1", SamplingPolicy::AggregateWindow { window_ms: 10 });
+        let mut req = Request::default();
+        req.add_points(vec![
+            make_point("t1", "a", 11, 1.0),
+            make_point("t1", "b", 12, 5.0),
+            make_point("t1", "a", 15, 3.0),
+            make_point("t1", "a", 21, 7.0),
+        ]);
+
+        let sampled = sampler.sample(&req);
+        let points = &sampled.point_groups["t1"];
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].timestamp, 10);
+        assert_eq!(points[0].fields["cpu"], Value::Double(2.0));
+        assert_eq!(points[0].fields["status"], Value::Int32(15));
+        assert_eq!(points[1].tags["host"], Value::String("b".to_string()));
+        assert_eq!(points[2].timestamp, 20);
+        assert_eq!(points[2].fields["cpu"], Value::Double(7.0));
+    }
+}
@@ -0,0 +1,141 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The stable binary encoding of the series identified by the tags.
+
+use std::collections::BTreeMap;
+
+use crate::model::value::{DataType, Value};
+
+/// The version of the [`SeriesKey`] encoding, which is the first byte of every
+/// encoded key and will be bumped once the encoding changes.
+pub const SERIES_KEY_VERSION: u8 = 1;
+
+/// The encoded key of a series, which is stable across the releases of the
+/// same [`SERIES_KEY_VERSION`] and can be used to shard the series.
+///
+/// The tags are encoded in the order of their names, and every tag is encoded
+/// as:
+/// ```plaintext
+/// | name len (u32 le) | name | type tag (u8) | value len (u32 le) | value |
+/// ```
+/// where the value is encoded by [`Value::to_bytes`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SeriesKey(Vec<u8>);
+
+impl SeriesKey {
+    /// Encode the tags of a series.
+    pub fn encode(tags: &BTreeMap<String, Value>) -> Self {
+        let mut buf = vec![SERIES_KEY_VERSION];
+        for (name, value) in tags {
+            let value_bytes = value.to_bytes();
+            buf.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            buf.extend_from_slice(name.as_bytes());
+            buf.push(type_tag(value.data_type()));
+            buf.extend_from_slice(&(value_bytes.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&value_bytes);
+        }
+
+        Self(buf)
+    }
+
+    /// The version of the encoding.
+    pub fn version(&self) -> u8 {
+        self.0[0]
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The stable 64-bit FNV-1a hash of the key, which won't change across
+    /// processes or platforms unlike the [`std::hash::Hash`].
+    pub fn hash64(&self) -> u64 {
+        const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const PRIME: u64 = 0x100000001b3;
+
+        self.0.iter().fold(OFFSET_BASIS, |hash, b| {
+            (hash ^ *b as u64).wrapping_mul(PRIME)
+        })
+    }
+}
+
+/// The type tags are part of the encoding, so they are listed explicitly
+/// instead of relying on the order of the [`DataType`] variants.
+fn type_tag(data_type: DataType) -> u8 {
+    match data_type {
+        DataType::Null => 0,
+        DataType::Timestamp => 1,
+        DataType::Double => 2,
+        DataType::Float => 3,
+        DataType::Varbinary => 4,
+        DataType::String => 5,
+        DataType::UInt64 => 6,
+        DataType::UInt32 => 7,
+        DataType::UInt16 => 8,
+        DataType::UInt8 => 9,
+        DataType::Int64 => 10,
+        DataType::Int32 => 11,
+        DataType::Int16 => 12,
+        DataType::Int8 => 13,
+        DataType::Boolean => 14,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{SeriesKey, SERIES_KEY_VERSION};
+    use crate::model::value::Value;
+
+    fn make_tags(tags: &[(&str, Value)]) -> BTreeMap<String, Value> {
+        tags.iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_series_key_without_collision() {
+        let key1 = SeriesKey::encode(&make_tags(&[("ab", Value::String("c".to_string()))]));
+        let key2 = SeriesKey::encode(&make_tags(&[("a", Value::String("bc".to_string()))]));
+        assert_ne!(key1, key2);
+
+        let key1 = SeriesKey::encode(&make_tags(&[("a", Value::Int64(1))]));
+        let key2 = SeriesKey::encode(&make_tags(&[("a", Value::UInt64(1))]));
+        let key3 = SeriesKey::encode(&make_tags(&[("a", Value::Timestamp(1))]));
+        assert_ne!(key1, key2);
+        assert_ne!(key1, key3);
+        assert_eq!(key1.version(), SERIES_KEY_VERSION);
+    }
+
+    #[test]
+    fn test_series_key_stable() {
+        let key = SeriesKey::encode(&make_tags(&[
+            ("host", Value::String("a".to_string())),
+            ("id", Value::UInt8(7)),
+        ]));
+        assert_eq!(
+            key.as_bytes(),
+            &[
+                1, 4, 0, 0, 0, b'h', b'o', b's', b't', 5, 1, 0, 0, 0, b'a', 2, 0, 0, 0, b'i', b'd',
+                9, 1, 0, 0, 0, 7
+            ]
+        );
+        assert_eq!(key.hash64(), 0xfc1eabaee000c317);
+    }
+}
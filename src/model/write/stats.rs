@@ -0,0 +1,187 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Per-table statistics of the written points, which helps to locate the
+//! tables dominating the ingestion when the load of the cluster is uneven.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+
+use crate::model::{value::Value, write::Request};
+
+#[derive(Debug, Default)]
+struct TableCounters {
+    points: AtomicU64,
+    bytes: AtomicU64,
+    flushes: AtomicU64,
+}
+
+/// The statistics of the writes of one table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableWriteStats {
+    pub table: String,
+    /// The number of the written points.
+    pub points: u64,
+    /// The estimated size of the written points in bytes.
+    pub bytes: u64,
+    /// The number of the write requests containing the table.
+    pub flushes: u64,
+    /// The share of the points of the table in all the written points.
+    pub point_ratio: f64,
+}
+
+/// Recorder of the per-table point counts and bytes of the writes.
+///
+/// The recorder is shared with the client by
+/// [`Builder::write_stats`](crate::Builder::write_stats), and every write is
+/// recorded automatically.
+#[derive(Debug, Default)]
+pub struct WriteStats {
+    tables: DashMap<String, TableCounters>,
+}
+
+impl WriteStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the points in the request.
+    pub fn record(&self, req: &Request) {
+        for (table, points) in &req.point_groups {
+            if points.is_empty() {
+                continue;
+            }
+
+            let bytes: usize = points
+                .iter()
+                .map(|point| {
+                    let tags_size: usize = point
+                        .tags
+                        .iter()
+                        .map(|(name, value)| name.len() + value_size(value))
+                        .sum();
+                    let fields_size: usize = point
+                        .fields
+                        .iter()
+                        .map(|(name, value)| name.len() + value_size(value))
+                        .sum();
+                    std::mem::size_of::<i64>() + tags_size + fields_size
+                })
+                .sum();
+
+            let counters = match self.tables.get(table) {
+                Some(counters) => counters,
+                None => self.tables.entry(table.clone()).or_default().downgrade(),
+            };
+            counters
+                .points
+                .fetch_add(points.len() as u64, Ordering::Relaxed);
+            counters.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+            counters.flushes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The `k` tables with the most written points, in descending order.
+    pub fn top_k(&self, k: usize) -> Vec<TableWriteStats> {
+        let mut stats = self
+            .tables
+            .iter()
+            .map(|entry| TableWriteStats {
+                table: entry.key().clone(),
+                points: entry.points.load(Ordering::Relaxed),
+                bytes: entry.bytes.load(Ordering::Relaxed),
+                flushes: entry.flushes.load(Ordering::Relaxed),
+                point_ratio: 0.0,
+            })
+            .collect::<Vec<_>>();
+
+        let total_points: u64 = stats.iter().map(|s| s.points).sum();
+        if total_points > 0 {
+            for s in &mut stats {
+                s.point_ratio = s.points as f64 / total_points as f64;
+            }
+        }
+        stats.sort_by(|a, b| b.points.cmp(&a.points).then_with(|| a.table.cmp(&b.table)));
+        stats.truncate(k);
+
+        stats
+    }
+
+    /// Clear the recorded statistics, e.g. after every report period.
+    pub fn reset(&self) {
+        self.tables.clear();
+    }
+}
+
+fn value_size(value: &Value) -> usize {
+    match value {
+        Value::Null => 0,
+        Value::Varbinary(v) => v.len(),
+        Value::String(v) => v.len(),
+        Value::Timestamp(_) | Value::Double(_) | Value::UInt64(_) | Value::Int64(_) => 8,
+        Value::Float(_) | Value::UInt32(_) | Value::Int32(_) => 4,
+        Value::UInt16(_) | Value::Int16(_) => 2,
+        Value::UInt8(_) | Value::Int8(_) | Value::Boolean(_) => 1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::WriteStats;
+    use crate::model::{
+        value::Value,
+        write::{point::PointBuilder, Request},
+    };
+
+    fn make_request(tables: &[(&str, usize)]) -> Request {
+        let mut req = Request::default();
+        for (table, num_points) in tables {
+            for ts in 0..*num_points {
+                req.add_point(
+                    PointBuilder::new(*table)
+                        .timestamp(ts as i64)
+                        .tag("host", Value::String("a".to_string()))
+                        .field("value", Value::Double(0.5))
+                        .build()
+                        .unwrap(),
+                );
+            }
+        }
+        req
+    }
+
+    #[test]
+    fn test_top_k() {
+        let stats = WriteStats::new();
+        stats.record(&make_request(&[("t1", 1), ("t2", 6)]));
+        stats.record(&make_request(&[("t2", 2), ("t3", 1)]));
+
+        let top = stats.top_k(2);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].table, "t2");
+        assert_eq!(top[0].points, 8);
+        assert_eq!(top[0].flushes, 2);
+        // timestamp(8) + "host"(4) + "a"(1) + "value"(5) + double(8)
+        assert_eq!(top[0].bytes, 8 * 26);
+        assert_eq!(top[0].point_ratio, 0.8);
+        assert_eq!(top[1].table, "t1");
+
+        stats.reset();
+        assert!(stats.top_k(2).is_empty());
+    }
+}
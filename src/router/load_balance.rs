@@ -0,0 +1,170 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Policies to choose among the replicas serving the same table.
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use dashmap::DashMap;
+
+use crate::{model::route::Endpoint, util::random_fraction};
+
+/// Policy choosing one of the replicas of a table, which are reported by
+/// [`Router::replicas`](crate::Router::replicas) of the custom router.
+pub trait LoadBalancePolicy: Send + Sync {
+    /// Select the index of the endpoint among the non-empty `candidates`
+    /// serving the `table`.
+    fn select(&self, table: &str, candidates: &[Endpoint]) -> usize;
+
+    /// Observe the latency of a call to the `endpoint`, and `ok` tells whether
+    /// the call succeeded.
+    fn observe(&self, _endpoint: &Endpoint, _latency: Duration, _ok: bool) {}
+}
+
+/// Select the endpoints in turn.
+#[derive(Debug, Default)]
+pub struct RoundRobinPolicy {
+    next: AtomicUsize,
+}
+
+impl LoadBalancePolicy for RoundRobinPolicy {
+    fn select(&self, _table: &str, candidates: &[Endpoint]) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed) % candidates.len()
+    }
+}
+
+/// Select the endpoints randomly.
+#[derive(Debug, Default)]
+pub struct RandomPolicy;
+
+impl LoadBalancePolicy for RandomPolicy {
+    fn select(&self, _table: &str, candidates: &[Endpoint]) -> usize {
+        let idx = (random_fraction() * candidates.len() as f64) as usize;
+        idx.min(candidates.len() - 1)
+    }
+}
+
+/// Select the endpoint of the lowest latency, which is the exponentially
+/// weighted moving average of the observed latencies.
+///
+/// The endpoints never observed are preferred so that every endpoint is
+/// probed, and a failed call is observed as the `failure_penalty`.
+#[derive(Debug)]
+pub struct LatencyAwarePolicy {
+    /// Weight of the latest latency in the moving average, in (0, 1].
+    alpha: f64,
+    failure_penalty: Duration,
+    latencies: DashMap<Endpoint, f64>,
+}
+
+impl Default for LatencyAwarePolicy {
+    fn default() -> Self {
+        Self::new(0.3, Duration::from_secs(1))
+    }
+}
+
+impl LatencyAwarePolicy {
+    pub fn new(alpha: f64, failure_penalty: Duration) -> Self {
+        assert!(alpha > 0.0 && alpha <= 1.0, "alpha should be in (0, 1]");
+        Self {
+            alpha,
+            failure_penalty,
+            latencies: DashMap::new(),
+        }
+    }
+
+    /// The average latency of the `endpoint` if observed.
+    pub fn latency(&self, endpoint: &Endpoint) -> Option<Duration> {
+        self.latencies
+            .get(endpoint)
+            .map(|latency| Duration::from_secs_f64(*latency))
+    }
+}
+
+impl LoadBalancePolicy for LatencyAwarePolicy {
+    fn select(&self, _table: &str, candidates: &[Endpoint]) -> usize {
+        let latency = |endpoint: &Endpoint| self.latencies.get(endpoint).map_or(0.0, |l| *l);
+        candidates
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| latency(a).total_cmp(&latency(b)))
+            .map(|(idx, _)| idx)
+            .unwrap_or(0)
+    }
+
+    fn observe(&self, endpoint: &Endpoint, latency: Duration, ok: bool) {
+        let sample = if ok {
+            latency
+        } else {
+            latency.max(self.failure_penalty)
+        }
+        .as_secs_f64();
+        self.latencies
+            .entry(endpoint.clone())
+            .and_modify(|avg| *avg += self.alpha * (sample - *avg))
+            .or_insert(sample);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{LatencyAwarePolicy, LoadBalancePolicy, RandomPolicy, RoundRobinPolicy};
+    use crate::model::route::Endpoint;
+
+    fn endpoints() -> Vec<Endpoint> {
+        (1..=3)
+            .map(|i| Endpoint::new(format!("192.168.0.{i}"), 8831))
+            .collect()
+    }
+
+    #[test]
+    fn test_round_robin_and_random() {
+        let candidates = endpoints();
+        let policy = RoundRobinPolicy::default();
+        let selected: Vec<_> = (0..4).map(|_| policy.select("t", &candidates)).collect();
+        assert_eq!(selected, vec![0, 1, 2, 0]);
+
+        let policy = RandomPolicy;
+        assert!((0..100).all(|_| policy.select("t", &candidates) < candidates.len()));
+    }
+
+    #[test]
+    fn test_latency_aware() {
+        let candidates = endpoints();
+        let policy = LatencyAwarePolicy::new(0.5, Duration::from_secs(1));
+        policy.observe(&candidates[0], Duration::from_millis(10), true);
+        policy.observe(&candidates[1], Duration::from_millis(20), true);
+        // The never observed endpoint is probed first.
+        assert_eq!(policy.select("t", &candidates), 2);
+
+        policy.observe(&candidates[2], Duration::from_millis(5), false);
+        assert_eq!(policy.select("t", &candidates), 0);
+        assert_eq!(policy.latency(&candidates[2]), Some(Duration::from_secs(1)));
+
+        policy.observe(&candidates[0], Duration::from_millis(50), true);
+        assert_eq!(
+            policy.latency(&candidates[0]),
+            Some(Duration::from_millis(30))
+        );
+        assert_eq!(policy.select("t", &candidates), 1);
+    }
+}
@@ -0,0 +1,115 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+
+use crate::{model::route::Endpoint, router::Router, rpc_client::RpcContext, Result};
+
+/// Router used for testing, which returns the scripted results in order and
+/// records the calls for assertions.
+///
+/// It's available with the `test-util` feature, for testing the custom routing
+/// and load balance policies.
+///
+/// When no scripted result is left, the tables are routed by the static route
+/// table, and tables not in it have no endpoint.
+#[derive(Default)]
+pub struct MockRouter {
+    route_table: HashMap<String, Endpoint>,
+    replicas: HashMap<String, Vec<Endpoint>>,
+    scripted: Mutex<VecDeque<Result<Vec<Option<Endpoint>>>>>,
+    route_calls: Mutex<Vec<Vec<String>>>,
+    evictions: Mutex<Vec<String>>,
+}
+
+impl MockRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a static route of the `table`.
+    pub fn with_route(mut self, table: impl Into<String>, endpoint: Endpoint) -> Self {
+        self.route_table.insert(table.into(), endpoint);
+        self
+    }
+
+    /// Add the replicas of the `table`.
+    pub fn with_replicas(mut self, table: impl Into<String>, replicas: Vec<Endpoint>) -> Self {
+        self.replicas.insert(table.into(), replicas);
+        self
+    }
+
+    /// Script the result of the next unscripted `route` call.
+    pub fn push_result(&self, result: Result<Vec<Option<Endpoint>>>) {
+        self.scripted.lock().unwrap().push_back(result);
+    }
+
+    /// The tables of every `route` call.
+    pub fn route_calls(&self) -> Vec<Vec<String>> {
+        self.route_calls.lock().unwrap().clone()
+    }
+
+    /// The tables evicted so far.
+    pub fn evictions(&self) -> Vec<String> {
+        self.evictions.lock().unwrap().clone()
+    }
+
+    pub fn assert_route_call_count(&self, expected: usize) {
+        assert_eq!(
+            self.route_calls.lock().unwrap().len(),
+            expected,
+            "unexpected number of route calls"
+        );
+    }
+
+    /// Assert the evicted tables regardless of the order.
+    pub fn assert_evicted(&self, expected: &[&str]) {
+        let mut evictions = self.evictions();
+        evictions.sort();
+        let mut expected = expected.to_vec();
+        expected.sort();
+        assert_eq!(evictions, expected, "unexpected evictions");
+    }
+}
+
+#[async_trait]
+impl Router for MockRouter {
+    async fn route(&self, tables: &[String], _ctx: &RpcContext) -> Result<Vec<Option<Endpoint>>> {
+        self.route_calls.lock().unwrap().push(tables.to_vec());
+        if let Some(result) = self.scripted.lock().unwrap().pop_front() {
+            return result;
+        }
+
+        Ok(tables
+            .iter()
+            .map(|table| self.route_table.get(table).cloned())
+            .collect())
+    }
+
+    fn evict(&self, tables: &[String]) {
+        self.evictions.lock().unwrap().extend_from_slice(tables);
+    }
+
+    fn replicas(&self, table: &str) -> Vec<Endpoint> {
+        self.replicas.get(table).cloned().unwrap_or_default()
+    }
+}
@@ -0,0 +1,580 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+mod load_balance;
+#[cfg(any(test, feature = "test-util"))]
+mod mock_router;
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use horaedbproto::storage::{self, RouteRequest};
+pub use load_balance::{LatencyAwarePolicy, LoadBalancePolicy, RandomPolicy, RoundRobinPolicy};
+#[cfg(any(test, feature = "test-util"))]
+pub use mock_router::MockRouter;
+use tokio::time::MissedTickBehavior;
+
+use crate::{
+    db_client::ClientMetrics,
+    errors::Result,
+    model::route::Endpoint,
+    rpc_client::{RpcClient, RpcContext},
+    Error,
+};
+
+/// The max tables routed by one rpc of the prefetching.
+const PREFETCH_BATCH_SIZE: usize = 1024;
+
+/// Used to route tables to endpoints.
+///
+/// The routes are asked from the server by default, and a custom router can
+/// be set by [`Builder::router`](crate::Builder::router).
+#[async_trait]
+pub trait Router: Send + Sync {
+    /// The endpoints of the `tables` in order in the database of the `ctx`,
+    /// which is always set, and None means no endpoint serves the table.
+    async fn route(&self, tables: &[String], ctx: &RpcContext) -> Result<Vec<Option<Endpoint>>>;
+
+    /// Forget the routes of the `tables`, which are found stale by the failed
+    /// requests.
+    fn evict(&self, tables: &[String]);
+
+    /// Forget the routes of all the tables, e.g. after the cluster is
+    /// rebalanced.
+    ///
+    /// There is nothing cached by default.
+    fn clear(&self) {}
+
+    /// All the endpoints serving the `table`, among which the requests are
+    /// balanced by the [`LoadBalancePolicy`] and the queries are hedged.
+    ///
+    /// The route response of the server carries one endpoint per table, so
+    /// only the custom routers knowing the placement of the replicas can
+    /// report them, and none is reported by default.
+    fn replicas(&self, _table: &str) -> Vec<Endpoint> {
+        Vec::new()
+    }
+
+    /// Route the `tables` in advance to warm the cache, e.g. at the startup.
+    async fn prefetch(&self, tables: &[String], ctx: &RpcContext) -> Result<()> {
+        self.route(tables, ctx).await.map(|_| ())
+    }
+}
+
+#[async_trait]
+impl<T: Router + ?Sized> Router for Arc<T> {
+    async fn route(&self, tables: &[String], ctx: &RpcContext) -> Result<Vec<Option<Endpoint>>> {
+        self.as_ref().route(tables, ctx).await
+    }
+
+    fn evict(&self, tables: &[String]) {
+        self.as_ref().evict(tables)
+    }
+
+    fn clear(&self) {
+        self.as_ref().clear()
+    }
+
+    fn replicas(&self, table: &str) -> Vec<Endpoint> {
+        self.as_ref().replicas(table)
+    }
+
+    async fn prefetch(&self, tables: &[String], ctx: &RpcContext) -> Result<()> {
+        self.as_ref().prefetch(tables, ctx).await
+    }
+}
+
+/// Config of the background refreshing of the routes of the hot tables, see
+/// [`Builder::route_refresh`](crate::Builder::route_refresh).
+#[derive(Debug, Clone)]
+pub struct RouteRefreshConfig {
+    /// The interval of the refreshing.
+    ///
+    /// Default value is 30s.
+    pub interval: Duration,
+    /// The tables routed within the window are hot.
+    ///
+    /// Default value is 5min.
+    pub hot_window: Duration,
+}
+
+impl Default for RouteRefreshConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            hot_window: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Implementation for [`Router`].
+///
+/// There is cache in [`RouterImpl`], it will return endpoints in cache first.
+/// If returned endpoints is outdated, you should call [`evict`] to remove them.
+/// And [`RouterImpl`] will fetch new endpoints when you call ['route'] again.
+///
+/// The route response carries one endpoint per table, so no replica is
+/// reported by [`replicas`](Router::replicas).
+///
+/// The cached endpoints expire after the ttl if set by
+/// [`with_route_ttl`](RouterImpl::with_route_ttl), so the tables migrated to
+/// other nodes are routed again without failing the requests first, and the
+/// routes of the hot tables can be refreshed in the background by
+/// [`spawn_refresh`](RouterImpl::spawn_refresh) to keep the route rpcs off the
+/// requests.
+///
+/// [`route`]: RouterImpl::route
+/// [`evict`]: RouterImpl::evict
+pub struct RouterImpl {
+    default_endpoint: Endpoint,
+    cache: DashMap<String, CachedRoute>,
+    rpc_client: Arc<dyn RpcClient>,
+    route_ttl: Option<Duration>,
+    metrics: Arc<ClientMetrics>,
+}
+
+/// The cached endpoint of a table.
+struct CachedRoute {
+    endpoint: Endpoint,
+    expires_at: Option<Instant>,
+    /// The database the table is routed in.
+    database: String,
+    /// When the table is routed last time, by which the hot tables are
+    /// refreshed in the background.
+    last_used: Instant,
+}
+
+impl CachedRoute {
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Instant::now())
+    }
+}
+
+impl RouterImpl {
+    pub fn new(default_endpoint: Endpoint, rpc_client: Arc<dyn RpcClient>) -> Self {
+        Self {
+            default_endpoint,
+            cache: DashMap::new(),
+            rpc_client,
+            route_ttl: None,
+            metrics: Arc::default(),
+        }
+    }
+
+    /// Record the routing in the `metrics` of the client.
+    pub(crate) fn with_metrics(mut self, metrics: Arc<ClientMetrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Expire the cached endpoints of the tables after the `ttl`.
+    pub fn with_route_ttl(mut self, ttl: Duration) -> Self {
+        self.route_ttl = Some(ttl);
+        self
+    }
+
+    /// The cached endpoint of the `table` which is not expired.
+    #[cfg(test)]
+    fn cached(&self, table: &str) -> Option<Endpoint> {
+        let cached = self.cache.get(table)?;
+        (!cached.is_expired()).then(|| cached.endpoint.clone())
+    }
+
+    /// Fetch the endpoints of the `tables` in the database of the `ctx` from
+    /// the server, and cache them.
+    ///
+    /// The tables without any endpoint are absent in the result.
+    async fn fetch(
+        &self,
+        ctx: &RpcContext,
+        tables: Vec<String>,
+    ) -> Result<HashMap<String, Endpoint>> {
+        let database = ctx.database.clone().unwrap();
+        let req_ctx = storage::RequestContext {
+            database: database.clone(),
+        };
+        let req = RouteRequest {
+            context: Some(req_ctx),
+            tables,
+        };
+        let start = Instant::now();
+        let resp = self.rpc_client.route(ctx, req).await;
+        self.metrics.record_route_rpc(start.elapsed());
+        let resp = resp?;
+
+        let mut routed: HashMap<String, Endpoint> = HashMap::new();
+        for route in resp.routes {
+            // Endpoint may be none, and not cache it when it is none.
+            let Some(endpoint) = route.endpoint else {
+                continue;
+            };
+            routed.insert(route.table, endpoint.into());
+        }
+
+        let now = Instant::now();
+        for (table, endpoint) in &routed {
+            // The refreshing doesn't make the table hot.
+            let last_used = self.cache.get(table).map_or(now, |cached| cached.last_used);
+            let route = CachedRoute {
+                endpoint: endpoint.clone(),
+                expires_at: self.route_ttl.map(|ttl| now + ttl),
+                database: database.clone(),
+                last_used,
+            };
+            self.cache.insert(table.clone(), route);
+        }
+
+        Ok(routed)
+    }
+
+    /// Spawn the task refreshing the routes of the hot tables, i.e. the ones
+    /// routed within the `config.hot_window`, every `config.interval` until
+    /// the router is dropped.
+    pub fn spawn_refresh(self: &Arc<Self>, config: RouteRefreshConfig) {
+        let router = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(config.interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // The first tick completes immediately.
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(router) = router.upgrade() else {
+                    return;
+                };
+                router.refresh(config.hot_window).await;
+            }
+        });
+    }
+
+    /// Refresh the routes of the tables routed within the `hot_window`, and
+    /// the tables without any endpoint any more are evicted.
+    async fn refresh(&self, hot_window: Duration) {
+        let mut hot_tables: HashMap<String, Vec<String>> = HashMap::new();
+        for cached in self.cache.iter() {
+            if cached.last_used.elapsed() <= hot_window {
+                hot_tables
+                    .entry(cached.database.clone())
+                    .or_default()
+                    .push(cached.key().clone());
+            }
+        }
+
+        for (database, tables) in hot_tables {
+            let ctx = RpcContext::default().database(database);
+            // The stale routes are kept on failure, and evicted by the failed
+            // requests as before.
+            let Ok(routed) = self.fetch(&ctx, tables.clone()).await else {
+                continue;
+            };
+            let unrouted: Vec<_> = tables
+                .into_iter()
+                .filter(|table| !routed.contains_key(table))
+                .collect();
+            self.evict(&unrouted);
+        }
+    }
+}
+
+#[async_trait]
+impl Router for RouterImpl {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "horaedb.route", skip_all, fields(tables = tables.len()))
+    )]
+    async fn route(&self, tables: &[String], ctx: &RpcContext) -> Result<Vec<Option<Endpoint>>> {
+        assert!(ctx.database.is_some());
+
+        let mut target_endpoints = vec![Some(self.default_endpoint.clone()); tables.len()];
+
+        // Find from cache firstly and collect misses.
+        let misses = {
+            let mut misses = HashMap::new();
+            for (idx, table) in tables.iter().enumerate() {
+                match self.cache.get_mut(table) {
+                    Some(mut cached) if !cached.is_expired() => {
+                        cached.last_used = Instant::now();
+                        target_endpoints[idx] = Some(cached.endpoint.clone());
+                    }
+
+                    _ => {
+                        misses.insert(table.clone(), idx);
+                    }
+                }
+            }
+            misses
+        };
+
+        let hits = tables.len() - misses.len();
+        self.metrics
+            .record_route_lookups(hits as u64, misses.len() as u64);
+        if misses.is_empty() {
+            return Ok(target_endpoints);
+        }
+
+        // Get endpoints of misses from remote, which are cached.
+        let miss_tables = misses.keys().cloned().collect();
+        let routed = self.fetch(ctx, miss_tables).await?;
+
+        // Fill miss endpoint.
+        for (table, endpoint) in routed {
+            // Impossible to get none.
+            let idx = misses
+                .get(&table)
+                .ok_or_else(|| Error::Unknown(format!("Unknown table:{table} in response")))?;
+            target_endpoints[*idx] = Some(endpoint);
+        }
+
+        Ok(target_endpoints)
+    }
+
+    fn evict(&self, tables: &[String]) {
+        let evicted = tables
+            .iter()
+            .filter(|e| self.cache.remove(e.as_str()).is_some())
+            .count();
+        self.metrics.record_route_evictions(evicted as u64);
+    }
+
+    fn clear(&self) {
+        self.metrics.record_route_evictions(self.cache.len() as u64);
+        self.cache.clear();
+    }
+
+    /// Route the `tables` in batches, so a large set of tables doesn't make a
+    /// huge route rpc.
+    async fn prefetch(&self, tables: &[String], ctx: &RpcContext) -> Result<()> {
+        for tables in tables.chunks(PREFETCH_BATCH_SIZE) {
+            self.route(tables, ctx).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use dashmap::DashMap;
+
+    use super::{RouteRefreshConfig, Router, RouterImpl, PREFETCH_BATCH_SIZE};
+    use crate::{
+        db_client::ClientMetrics,
+        model::route::Endpoint,
+        rpc_client::{MockRpcClient, RpcContext},
+    };
+
+    #[tokio::test]
+    async fn test_basic_flow() {
+        // Init mock route table
+        let table1 = "table1".to_string();
+        let table2 = "table2".to_string();
+        let table3 = "table3".to_string();
+        let table4 = "table4".to_string();
+        let endpoint1 = Endpoint::new("192.168.0.1".to_string(), 11);
+        let endpoint2 = Endpoint::new("192.168.0.2".to_string(), 12);
+        let endpoint3 = Endpoint::new("192.168.0.3".to_string(), 13);
+        let endpoint4 = Endpoint::new("192.168.0.4".to_string(), 14);
+        let default_endpoint = Endpoint::new("192.168.0.5".to_string(), 15);
+
+        // Init mock client with route1 and route2
+        let route_table = Arc::new(DashMap::default());
+        let mock_rpc_client = MockRpcClient {
+            route_table: route_table.clone(),
+            ..Default::default()
+        };
+        mock_rpc_client
+            .route_table
+            .insert(table1.clone(), endpoint1.clone());
+        mock_rpc_client
+            .route_table
+            .insert(table2.clone(), endpoint2.clone());
+
+        // Follow these steps to check wether cache is used or not:
+        // route --> change route_table --> route again.
+        let ctx = RpcContext {
+            database: Some("db".to_string()),
+            timeout: None,
+            ..Default::default()
+        };
+        let tables = vec![table1.clone(), table2.clone()];
+        let route_client = RouterImpl::new(default_endpoint.clone(), Arc::new(mock_rpc_client));
+        let route_res1 = route_client.route(&tables, &ctx).await.unwrap();
+        assert_eq!(&endpoint1, route_res1.first().unwrap().as_ref().unwrap());
+        assert_eq!(&endpoint2, route_res1.get(1).unwrap().as_ref().unwrap());
+
+        route_table.insert(table1.clone(), endpoint3.clone());
+        route_table.insert(table2.clone(), endpoint4.clone());
+
+        let route_res2 = route_client.route(&tables, &ctx).await.unwrap();
+        assert_eq!(&endpoint1, route_res2.first().unwrap().as_ref().unwrap());
+        assert_eq!(&endpoint2, route_res2.get(1).unwrap().as_ref().unwrap());
+
+        route_client.evict(&[table1.clone(), table2.clone()]);
+
+        let route_res3 = route_client.route(&tables, &ctx).await.unwrap();
+        assert_eq!(&endpoint3, route_res3.first().unwrap().as_ref().unwrap());
+        assert_eq!(&endpoint4, route_res3.get(1).unwrap().as_ref().unwrap());
+
+        let route_res4 = route_client.route(&[table3, table4], &ctx).await.unwrap();
+        assert_eq!(
+            &default_endpoint,
+            route_res4.first().unwrap().as_ref().unwrap()
+        );
+        assert_eq!(
+            &default_endpoint,
+            route_res4.get(1).unwrap().as_ref().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_route_ttl() {
+        let table = "table1".to_string();
+        let endpoint1 = Endpoint::new("192.168.0.1".to_string(), 11);
+        let endpoint2 = Endpoint::new("192.168.0.2".to_string(), 12);
+        let default_endpoint = Endpoint::new("192.168.0.5".to_string(), 15);
+        let mock_rpc_client = MockRpcClient::default();
+        let route_table = mock_rpc_client.route_table.clone();
+        route_table.insert(table.clone(), endpoint1.clone());
+        let ctx = RpcContext::default().database("db".to_string());
+        let tables = vec![table.clone()];
+
+        let router = RouterImpl::new(default_endpoint, Arc::new(mock_rpc_client))
+            .with_route_ttl(Duration::from_millis(50));
+        assert_eq!(
+            router.route(&tables, &ctx).await.unwrap(),
+            vec![Some(endpoint1.clone())]
+        );
+
+        // The table is migrated, and routed again once the cache expires.
+        route_table.insert(table.clone(), endpoint2.clone());
+        assert_eq!(
+            router.route(&tables, &ctx).await.unwrap(),
+            vec![Some(endpoint1)]
+        );
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(router.cached(&table).is_none());
+        assert_eq!(
+            router.route(&tables, &ctx).await.unwrap(),
+            vec![Some(endpoint2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_route_refresh() {
+        let (hot, cold, dropped) = ("hot".to_string(), "cold".to_string(), "dropped".to_string());
+        let endpoint1 = Endpoint::new("192.168.0.1".to_string(), 11);
+        let endpoint2 = Endpoint::new("192.168.0.2".to_string(), 12);
+        let default_endpoint = Endpoint::new("192.168.0.5".to_string(), 15);
+        let mock_rpc_client = MockRpcClient::default();
+        let route_table = mock_rpc_client.route_table.clone();
+        for table in [&hot, &cold, &dropped] {
+            route_table.insert(table.clone(), endpoint1.clone());
+        }
+        let ctx = RpcContext::default().database("db".to_string());
+
+        let router = Arc::new(RouterImpl::new(default_endpoint, Arc::new(mock_rpc_client)));
+        router
+            .route(std::slice::from_ref(&cold), &ctx)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        router
+            .route(&[hot.clone(), dropped.clone()], &ctx)
+            .await
+            .unwrap();
+
+        // Only the routes of the hot tables are refreshed.
+        route_table.insert(hot.clone(), endpoint2.clone());
+        route_table.insert(cold.clone(), endpoint2.clone());
+        route_table.remove(&dropped);
+        router.refresh(Duration::from_millis(40)).await;
+        assert_eq!(router.cached(&hot), Some(endpoint2.clone()));
+        assert_eq!(router.cached(&cold), Some(endpoint1.clone()));
+        assert!(router.cached(&dropped).is_none());
+
+        // Refreshed by the background task.
+        router.spawn_refresh(RouteRefreshConfig {
+            interval: Duration::from_millis(10),
+            hot_window: Duration::from_secs(60),
+        });
+        route_table.insert(hot.clone(), endpoint1.clone());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(router.cached(&hot), Some(endpoint1));
+    }
+
+    #[tokio::test]
+    async fn test_prefetch() {
+        let endpoint = Endpoint::new("192.168.0.1".to_string(), 11);
+        let default_endpoint = Endpoint::new("192.168.0.5".to_string(), 15);
+        let mock_rpc_client = MockRpcClient::default();
+        let tables: Vec<_> = (0..PREFETCH_BATCH_SIZE + 1)
+            .map(|i| format!("table{i}"))
+            .collect();
+        for table in &tables {
+            mock_rpc_client
+                .route_table
+                .insert(table.clone(), endpoint.clone());
+        }
+        let ctx = RpcContext::default().database("db".to_string());
+
+        let router = RouterImpl::new(default_endpoint, Arc::new(mock_rpc_client));
+        router.prefetch(&tables, &ctx).await.unwrap();
+        assert!(tables
+            .iter()
+            .all(|table| router.cached(table) == Some(endpoint.clone())));
+    }
+
+    #[tokio::test]
+    async fn test_clear() {
+        let endpoint1 = Endpoint::new("192.168.0.1".to_string(), 11);
+        let endpoint2 = Endpoint::new("192.168.0.2".to_string(), 12);
+        let default_endpoint = Endpoint::new("192.168.0.5".to_string(), 15);
+        let mock_rpc_client = MockRpcClient::default();
+        let route_table = mock_rpc_client.route_table.clone();
+        let tables = vec!["table1".to_string(), "table2".to_string()];
+        for table in &tables {
+            route_table.insert(table.clone(), endpoint1.clone());
+        }
+        let ctx = RpcContext::default().database("db".to_string());
+
+        let metrics = Arc::new(ClientMetrics::default());
+        let router = RouterImpl::new(default_endpoint, Arc::new(mock_rpc_client))
+            .with_metrics(metrics.clone());
+        router.route(&tables, &ctx).await.unwrap();
+        router.route(&tables, &ctx).await.unwrap();
+
+        // All the tables are routed again after the rebalance.
+        for table in &tables {
+            route_table.insert(table.clone(), endpoint2.clone());
+        }
+        router.clear();
+        let endpoints = router.route(&tables, &ctx).await.unwrap();
+        assert_eq!(endpoints, vec![Some(endpoint2.clone()), Some(endpoint2)]);
+
+        let routes = metrics.snapshot().routes;
+        assert_eq!((routes.hits, routes.misses, routes.evictions), (2, 4, 2));
+        assert_eq!(routes.rpc_latency.count, 2);
+    }
+}
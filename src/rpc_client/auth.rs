@@ -0,0 +1,374 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Credentials attached to the requests as the grpc metadata.
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use tokio::sync::Mutex;
+use tonic::metadata::{Ascii, MetadataMap, MetadataValue};
+
+use crate::{
+    errors::{Error, Result},
+    rpc_client::RpcContext,
+    util::StatusCode,
+    Authorization,
+};
+
+/// The token is refreshed in advance of its expiration by the margin, so that
+/// it won't expire on the way to the server.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(10);
+
+/// Provide the credentials of every rpc as the grpc metadata, e.g. the HMAC
+/// signatures or the cloud IAM tokens required by the custom gateways.
+///
+/// The basic [`Authorization`] and the [`TokenProvider`] are built on it.
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Attach the credentials of the rpc named by the `method` to the
+    /// `metadata`, and the rpc fails with the returned error without being
+    /// sent.
+    async fn authorize(
+        &self,
+        method: &'static str,
+        ctx: &RpcContext,
+        metadata: &mut MetadataMap,
+    ) -> Result<()>;
+
+    /// Whether the credentials can be refreshed, and the rpc rejected as
+    /// unauthenticated by the server is retried once after
+    /// [`refresh`](AuthProvider::refresh) then.
+    fn is_refreshable(&self) -> bool {
+        false
+    }
+
+    /// Refresh the credentials as they are rejected by the server.
+    async fn refresh(&self) {}
+}
+
+/// The [`AuthProvider`] of the basic authorization.
+pub(crate) struct BasicAuth {
+    authorization: MetadataValue<Ascii>,
+}
+
+impl BasicAuth {
+    pub fn new(authorization: &Authorization) -> Result<Self> {
+        let mut buf =
+            Vec::with_capacity(authorization.username.len() + authorization.password.len() + 1);
+        buf.extend_from_slice(authorization.username.as_bytes());
+        buf.push(b':');
+        buf.extend_from_slice(authorization.password.as_bytes());
+        let auth = BASE64_STANDARD.encode(&buf);
+        let authorization = format!("Basic {}", auth)
+            .parse()
+            .context("invalid grpc metadata")?;
+
+        Ok(Self { authorization })
+    }
+}
+
+#[async_trait]
+impl AuthProvider for BasicAuth {
+    async fn authorize(
+        &self,
+        _method: &'static str,
+        _ctx: &RpcContext,
+        metadata: &mut MetadataMap,
+    ) -> Result<()> {
+        metadata.insert("authorization", self.authorization.clone());
+        Ok(())
+    }
+}
+
+/// The bearer token fetched by the [`TokenProvider`].
+#[derive(Debug, Clone)]
+pub struct BearerToken {
+    pub token: String,
+    /// The token is refreshed before the expiration if set, otherwise only
+    /// when it's rejected by the server.
+    pub expires_at: Option<SystemTime>,
+}
+
+impl BearerToken {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+            expires_at: None,
+        }
+    }
+
+    pub fn expires_at(mut self, expires_at: SystemTime) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+}
+
+/// Provide the bearer tokens attached to the requests as the
+/// `authorization: Bearer {token}` metadata.
+///
+/// The token is cached by the client, and fetched again once it expires or is
+/// rejected by the server.
+#[async_trait]
+pub trait TokenProvider: Send + Sync {
+    async fn token(&self) -> Result<BearerToken>;
+}
+
+struct CachedToken {
+    authorization: MetadataValue<Ascii>,
+    expires_at: Option<SystemTime>,
+}
+
+/// The [`AuthProvider`] caching the token of the [`TokenProvider`], which is
+/// shared by all the clients.
+pub(crate) struct TokenCache {
+    provider: Arc<dyn TokenProvider>,
+    cached: Mutex<Option<CachedToken>>,
+}
+
+impl TokenCache {
+    pub fn new(provider: Arc<dyn TokenProvider>) -> Self {
+        Self {
+            provider,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// The `authorization` metadata of the cached token, which is refreshed
+    /// first if it's absent or expiring.
+    async fn authorization(&self) -> Result<MetadataValue<Ascii>> {
+        // Hold the lock while refreshing, so that the concurrent calls don't
+        // fetch the token repeatedly.
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = &*cached {
+            let expiring = token
+                .expires_at
+                .is_some_and(|expires_at| SystemTime::now() + TOKEN_REFRESH_MARGIN >= expires_at);
+            if !expiring {
+                return Ok(token.authorization.clone());
+            }
+        }
+
+        let token = self.provider.token().await?;
+        let authorization: MetadataValue<Ascii> = format!("Bearer {}", token.token)
+            .parse()
+            .context("invalid bearer token")?;
+        *cached = Some(CachedToken {
+            authorization: authorization.clone(),
+            expires_at: token.expires_at,
+        });
+        Ok(authorization)
+    }
+}
+
+#[async_trait]
+impl AuthProvider for TokenCache {
+    async fn authorize(
+        &self,
+        _method: &'static str,
+        _ctx: &RpcContext,
+        metadata: &mut MetadataMap,
+    ) -> Result<()> {
+        metadata.insert("authorization", self.authorization().await?);
+        Ok(())
+    }
+
+    fn is_refreshable(&self) -> bool {
+        true
+    }
+
+    /// Drop the rejected token, and the new one is fetched by the next rpc.
+    async fn refresh(&self) {
+        *self.cached.lock().await = None;
+    }
+}
+
+/// The credentials of the clients of one factory.
+#[derive(Clone, Default)]
+pub(crate) struct Credentials {
+    provider: Option<Arc<dyn AuthProvider>>,
+}
+
+impl Credentials {
+    pub fn new(provider: Option<Arc<dyn AuthProvider>>) -> Self {
+        Self { provider }
+    }
+
+    pub async fn authorize(
+        &self,
+        method: &'static str,
+        ctx: &RpcContext,
+        metadata: &mut MetadataMap,
+    ) -> Result<()> {
+        match &self.provider {
+            Some(provider) => provider.authorize(method, ctx, metadata).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Make the call, and retry it once with the refreshed credentials if
+    /// they are rejected by the server.
+    pub async fn call<Req, T, Fut>(&self, req: Req, op: impl Fn(Req) -> Fut) -> Result<T>
+    where
+        Req: Clone,
+        Fut: Future<Output = Result<T>>,
+    {
+        let Some(provider) = self.provider.as_ref().filter(|p| p.is_refreshable()) else {
+            return op(req).await;
+        };
+
+        match op(req.clone()).await {
+            Err(e) if is_unauthenticated(&e) => {
+                provider.refresh().await;
+                op(req).await
+            }
+            result => result,
+        }
+    }
+}
+
+fn is_unauthenticated(err: &Error) -> bool {
+    match err {
+        Error::Rpc(status) => status.code() == tonic::Code::Unauthenticated,
+        Error::Server(e) => e.code == StatusCode::Unauthorized.as_u32(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, SystemTime},
+    };
+
+    use async_trait::async_trait;
+    use tonic::metadata::MetadataMap;
+
+    use super::{AuthProvider, BearerToken, Credentials, TokenCache, TokenProvider};
+    use crate::{rpc_client::RpcContext, Error, Result};
+
+    /// Provider of the tokens `token-{n}` expiring after `ttl`.
+    struct CountingProvider {
+        fetches: AtomicUsize,
+        ttl: Duration,
+    }
+
+    #[async_trait]
+    impl TokenProvider for CountingProvider {
+        async fn token(&self) -> Result<BearerToken> {
+            let n = self.fetches.fetch_add(1, Ordering::Relaxed);
+            Ok(BearerToken::new(format!("token-{n}")).expires_at(SystemTime::now() + self.ttl))
+        }
+    }
+
+    /// Sign every rpc by its method.
+    struct Signer;
+
+    #[async_trait]
+    impl AuthProvider for Signer {
+        async fn authorize(
+            &self,
+            method: &'static str,
+            _ctx: &RpcContext,
+            metadata: &mut MetadataMap,
+        ) -> Result<()> {
+            metadata.insert("x-signature", format!("signed-{method}").parse().unwrap());
+            Ok(())
+        }
+    }
+
+    fn make_credentials(ttl: Duration) -> (Arc<CountingProvider>, Credentials) {
+        let provider = Arc::new(CountingProvider {
+            fetches: AtomicUsize::new(0),
+            ttl,
+        });
+        let cache = TokenCache::new(provider.clone());
+        (provider, Credentials::new(Some(Arc::new(cache))))
+    }
+
+    async fn authorize(credentials: &Credentials, key: &str) -> String {
+        let mut metadata = MetadataMap::new();
+        credentials
+            .authorize("write", &RpcContext::default(), &mut metadata)
+            .await
+            .unwrap();
+        metadata.get(key).unwrap().to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_token_refresh() {
+        let (provider, credentials) = make_credentials(Duration::from_secs(3600));
+        for _ in 0..2 {
+            let authorization = authorize(&credentials, "authorization").await;
+            assert_eq!(authorization, "Bearer token-0");
+        }
+        assert_eq!(provider.fetches.load(Ordering::Relaxed), 1);
+
+        // The token expiring within the margin is refreshed on every call.
+        let (provider, credentials) = make_credentials(Duration::from_secs(1));
+        authorize(&credentials, "authorization").await;
+        let authorization = authorize(&credentials, "authorization").await;
+        assert_eq!(authorization, "Bearer token-1");
+        assert_eq!(provider.fetches.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_token_rejected() {
+        let (provider, credentials) = make_credentials(Duration::from_secs(3600));
+        let calls = AtomicUsize::new(0);
+        let result = credentials
+            .call((), |_| async {
+                let authorization = authorize(&credentials, "authorization").await;
+                calls.fetch_add(1, Ordering::Relaxed);
+                match authorization.as_str() {
+                    "Bearer token-0" => Err(Error::Rpc(tonic::Status::unauthenticated("expired"))),
+                    _ => Ok(()),
+                }
+            })
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        assert_eq!(provider.fetches.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_custom_auth_provider() {
+        let credentials = Credentials::new(Some(Arc::new(Signer)));
+        assert_eq!(authorize(&credentials, "x-signature").await, "signed-write");
+
+        // The credentials which can't be refreshed are not retried.
+        let calls = AtomicUsize::new(0);
+        let result = credentials
+            .call((), |_| async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err::<(), _>(Error::Rpc(tonic::Status::unauthenticated("bad signature")))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,269 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Fail over to the fallback endpoints once the endpoint in use is down.
+
+use std::{
+    future::Future,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+#[cfg(feature = "prom")]
+use horaedbproto::storage::{
+    PrometheusQueryRequest as PromQueryRequestPb, PrometheusQueryResponse as PromQueryResponsePb,
+};
+use horaedbproto::storage::{
+    RouteRequest as RouteRequestPb, RouteResponse as RouteResponsePb,
+    SqlQueryRequest as QueryRequestPb, SqlQueryResponse as QueryResponsePb,
+    WriteRequest as WriteRequestPb, WriteResponse as WriteResponsePb,
+};
+
+use crate::{
+    errors::{Error, Result},
+    rpc_client::{RpcClient, RpcClientFactory, RpcContext, SqlQueryStream},
+};
+
+/// Whether the server is down, and the call can be sent to another endpoint.
+///
+/// The timeouts are excluded because the call may have been handled.
+fn is_down(err: &Error) -> bool {
+    match err {
+        Error::Connect { .. } => true,
+        Error::Rpc(status) => status.code() == tonic::Code::Unavailable,
+        _ => false,
+    }
+}
+
+/// [`RpcClient`] calling the first endpoint available in order, and failing
+/// over to the following ones once it is down.
+///
+/// The endpoint failed over to is kept in use until it is down too.
+pub(crate) struct FallbackRpcClient<F: RpcClientFactory> {
+    factory: F,
+    endpoints: Vec<String>,
+    /// The index of the endpoint in use and its client.
+    current: RwLock<(usize, Arc<dyn RpcClient>)>,
+}
+
+impl<F: RpcClientFactory> FallbackRpcClient<F> {
+    /// Build the client of the first endpoint available, and fail with the
+    /// error of the last one if none is available.
+    pub async fn new(factory: F, endpoints: Vec<String>) -> Result<Self> {
+        assert!(!endpoints.is_empty());
+
+        let mut last_err = None;
+        for (idx, endpoint) in endpoints.iter().enumerate() {
+            match factory.build(endpoint.clone()).await {
+                Ok(client) => {
+                    return Ok(Self {
+                        factory,
+                        endpoints,
+                        current: RwLock::new((idx, client)),
+                    })
+                }
+                Err(e) if is_down(&e) => last_err = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_err.unwrap())
+    }
+
+    async fn call<T, Fut>(&self, op: impl Fn(Arc<dyn RpcClient>) -> Fut) -> Result<T>
+    where
+        Fut: Future<Output = Result<T>>,
+    {
+        let (idx, client) = self.current.read().unwrap().clone();
+        let mut err = match op(client).await {
+            Err(e) if is_down(&e) => e,
+            result => return result,
+        };
+
+        // The next build reconnects to the endpoint once it is back.
+        self.factory.evict(&self.endpoints[idx]);
+        for offset in 1..self.endpoints.len() {
+            let next = (idx + offset) % self.endpoints.len();
+            let endpoint = &self.endpoints[next];
+            let client = match self.factory.build(endpoint.clone()).await {
+                Ok(client) => client,
+                Err(e) => {
+                    err = e;
+                    continue;
+                }
+            };
+            *self.current.write().unwrap() = (next, client.clone());
+
+            match op(client).await {
+                Err(e) if is_down(&e) => err = e,
+                result => return result,
+            }
+        }
+
+        Err(err)
+    }
+}
+
+#[async_trait]
+impl<F: RpcClientFactory> RpcClient for FallbackRpcClient<F> {
+    async fn sql_query(&self, ctx: &RpcContext, req: QueryRequestPb) -> Result<QueryResponsePb> {
+        self.call(|client| {
+            let req = req.clone();
+            async move { client.sql_query(ctx, req).await }
+        })
+        .await
+    }
+
+    async fn write(&self, ctx: &RpcContext, req: WriteRequestPb) -> Result<WriteResponsePb> {
+        self.call(|client| {
+            let req = req.clone();
+            async move { client.write(ctx, req).await }
+        })
+        .await
+    }
+
+    async fn route(&self, ctx: &RpcContext, req: RouteRequestPb) -> Result<RouteResponsePb> {
+        self.call(|client| {
+            let req = req.clone();
+            async move { client.route(ctx, req).await }
+        })
+        .await
+    }
+
+    /// Only the failure of opening the stream is handled.
+    async fn sql_query_stream(
+        &self,
+        ctx: &RpcContext,
+        req: QueryRequestPb,
+    ) -> Result<SqlQueryStream> {
+        self.call(|client| {
+            let req = req.clone();
+            async move { client.sql_query_stream(ctx, req).await }
+        })
+        .await
+    }
+
+    #[cfg(feature = "prom")]
+    async fn prom_query(
+        &self,
+        ctx: &RpcContext,
+        req: PromQueryRequestPb,
+    ) -> Result<PromQueryResponsePb> {
+        self.call(|client| {
+            let req = req.clone();
+            async move { client.prom_query(ctx, req).await }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashSet,
+        sync::{Arc, Mutex},
+    };
+
+    use async_trait::async_trait;
+    use horaedbproto::storage::{
+        RouteRequest as RouteRequestPb, RouteResponse as RouteResponsePb,
+        SqlQueryRequest as QueryRequestPb, SqlQueryResponse as QueryResponsePb,
+        WriteRequest as WriteRequestPb, WriteResponse as WriteResponsePb,
+    };
+
+    use super::FallbackRpcClient;
+    use crate::{
+        errors::{Error, Result},
+        rpc_client::{RpcClient, RpcClientFactory, RpcContext},
+    };
+
+    /// Client reporting the number of its endpoint as the successful writes.
+    struct EndpointClient {
+        endpoint: String,
+        down: Arc<Mutex<HashSet<String>>>,
+    }
+
+    #[async_trait]
+    impl RpcClient for EndpointClient {
+        async fn sql_query(&self, _: &RpcContext, _: QueryRequestPb) -> Result<QueryResponsePb> {
+            unimplemented!()
+        }
+
+        async fn write(&self, _: &RpcContext, _: WriteRequestPb) -> Result<WriteResponsePb> {
+            if self.down.lock().unwrap().contains(&self.endpoint) {
+                return Err(Error::Rpc(tonic::Status::unavailable("down")));
+            }
+            Ok(WriteResponsePb {
+                success: self.endpoint.parse().unwrap(),
+                ..Default::default()
+            })
+        }
+
+        async fn route(&self, _: &RpcContext, _: RouteRequestPb) -> Result<RouteResponsePb> {
+            unimplemented!()
+        }
+    }
+
+    /// Factory failing to connect to the endpoints being down.
+    #[derive(Default)]
+    struct EndpointFactory {
+        down: Arc<Mutex<HashSet<String>>>,
+    }
+
+    #[async_trait]
+    impl RpcClientFactory for EndpointFactory {
+        async fn build(&self, endpoint: String) -> Result<Arc<dyn RpcClient>> {
+            if self.down.lock().unwrap().contains(&endpoint) {
+                return Err(Error::Connect {
+                    addr: endpoint,
+                    source: "refused".into(),
+                });
+            }
+            Ok(Arc::new(EndpointClient {
+                endpoint,
+                down: self.down.clone(),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback() {
+        let factory = EndpointFactory::default();
+        let down = factory.down.clone();
+        down.lock().unwrap().insert("1".to_string());
+        let endpoints = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+        let client = FallbackRpcClient::new(factory, endpoints).await.unwrap();
+
+        let ctx = RpcContext::default();
+        let write = || client.write(&ctx, WriteRequestPb::default());
+        assert_eq!(write().await.unwrap().success, 2);
+
+        // Fail over to the next endpoint once the one in use is down.
+        down.lock().unwrap().insert("2".to_string());
+        assert_eq!(write().await.unwrap().success, 3);
+        down.lock().unwrap().remove("1");
+        assert_eq!(write().await.unwrap().success, 3);
+
+        // Wrap around to the first endpoint.
+        down.lock().unwrap().insert("3".to_string());
+        assert_eq!(write().await.unwrap().success, 1);
+
+        down.lock().unwrap().insert("1".to_string());
+        let err = write().await.unwrap_err();
+        assert!(err.is_unavailable());
+    }
+}
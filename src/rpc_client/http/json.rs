@@ -0,0 +1,220 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Decode the json responses of the HTTP sql endpoint.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, NullArray, StringBuilder},
+    datatypes::{DataType, Field, Schema},
+    ipc::writer::StreamWriter,
+    record_batch::RecordBatch,
+};
+use horaedbproto::storage::{
+    arrow_payload::Compression, sql_query_response::Output as OutputPb, ArrowPayload,
+    SqlQueryResponse as QueryResponsePb,
+};
+use serde_json::{Map, Value as JsonValue};
+
+use crate::{Error, Result};
+
+/// Decode the response of the sql endpoint, which is either
+/// `{"affected_rows": n}` or `{"rows": [{column: value}]}`, into the grpc
+/// response carrying the rows as an uncompressed arrow batch.
+///
+/// The column types are inferred from the json values, so the integers are
+/// decoded as `Int64` (including the timestamps), the other numbers as
+/// `Float64`, and the nested values as their json text.
+pub(crate) fn decode_sql_response(body: &[u8]) -> Result<QueryResponsePb> {
+    let resp: Map<String, JsonValue> = serde_json::from_slice(body)
+        .map_err(|e| Error::Client(format!("invalid sql response, err:{e}")))?;
+
+    let output = if let Some(affected_rows) = resp.get("affected_rows") {
+        let affected_rows = affected_rows
+            .as_u64()
+            .ok_or_else(|| Error::Client(format!("invalid affected_rows:{affected_rows}")))?;
+        OutputPb::AffectedRows(affected_rows as u32)
+    } else if let Some(JsonValue::Array(rows)) = resp.get("rows") {
+        let record_batches = match encode_rows(rows)? {
+            Some(batch) => vec![batch],
+            None => Vec::new(),
+        };
+        let mut payload = ArrowPayload {
+            record_batches,
+            ..Default::default()
+        };
+        payload.set_compression(Compression::None);
+        OutputPb::Arrow(payload)
+    } else {
+        return Err(Error::Client(
+            "sql response has neither affected_rows nor rows".to_string(),
+        ));
+    };
+
+    Ok(QueryResponsePb {
+        header: None,
+        output: Some(output),
+    })
+}
+
+/// Encode the rows into an arrow ipc stream of one batch.
+fn encode_rows(rows: &[JsonValue]) -> Result<Option<Vec<u8>>> {
+    let rows = rows
+        .iter()
+        .map(|row| {
+            row.as_object()
+                .ok_or_else(|| Error::Client(format!("invalid row:{row}")))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if rows.is_empty() {
+        return Ok(None);
+    }
+
+    // The columns are in the order of their first appearance.
+    let mut columns: Vec<&str> = Vec::new();
+    for row in &rows {
+        for name in row.keys() {
+            if !columns.contains(&name.as_str()) {
+                columns.push(name);
+            }
+        }
+    }
+
+    let mut fields = Vec::with_capacity(columns.len());
+    let mut arrays = Vec::with_capacity(columns.len());
+    for name in columns {
+        let values: Vec<_> = rows.iter().map(|row| row.get(name)).collect();
+        let array = build_array(&values);
+        fields.push(Field::new(name, array.data_type().clone(), true));
+        arrays.push(array);
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), arrays)
+        .map_err(|e| Error::DecodeArrowPayload(Box::new(e)))?;
+    let mut writer = StreamWriter::try_new(Vec::new(), &schema)
+        .map_err(|e| Error::DecodeArrowPayload(Box::new(e)))?;
+    writer
+        .write(&batch)
+        .and_then(|_| writer.finish())
+        .map_err(|e| Error::DecodeArrowPayload(Box::new(e)))?;
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| Error::DecodeArrowPayload(Box::new(e)))?;
+
+    Ok(Some(bytes))
+}
+
+fn infer_type(values: &[Option<&JsonValue>]) -> DataType {
+    let mut data_type = DataType::Null;
+    for value in values.iter().flatten() {
+        let value_type = match value {
+            JsonValue::Null => continue,
+            JsonValue::Bool(_) => DataType::Boolean,
+            JsonValue::Number(n) if n.is_i64() => DataType::Int64,
+            JsonValue::Number(_) => DataType::Float64,
+            _ => DataType::Utf8,
+        };
+        data_type = match (&data_type, &value_type) {
+            (DataType::Null, _) => value_type,
+            (a, b) if a == b => continue,
+            (DataType::Int64, DataType::Float64) | (DataType::Float64, DataType::Int64) => {
+                DataType::Float64
+            }
+            _ => DataType::Utf8,
+        };
+    }
+    data_type
+}
+
+fn build_array(values: &[Option<&JsonValue>]) -> ArrayRef {
+    match infer_type(values) {
+        DataType::Boolean => {
+            let mut builder = BooleanBuilder::with_capacity(values.len());
+            for value in values {
+                builder.append_option(value.and_then(JsonValue::as_bool));
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Int64 => {
+            let mut builder = Int64Builder::with_capacity(values.len());
+            for value in values {
+                builder.append_option(value.and_then(JsonValue::as_i64));
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Float64 => {
+            let mut builder = Float64Builder::with_capacity(values.len());
+            for value in values {
+                builder.append_option(value.and_then(JsonValue::as_f64));
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Utf8 => {
+            let mut builder = StringBuilder::new();
+            for value in values {
+                match value {
+                    None | Some(JsonValue::Null) => builder.append_null(),
+                    Some(JsonValue::String(s)) => builder.append_value(s),
+                    Some(other) => builder.append_value(other.to_string()),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        _ => Arc::new(NullArray::new(values.len())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use horaedbproto::storage::sql_query_response::Output as OutputPb;
+
+    use super::decode_sql_response;
+    use crate::model::{sql_query::Response, value::Value};
+
+    #[test]
+    fn test_decode_sql_response() {
+        let resp = decode_sql_response(br#"{"affected_rows": 3}"#).unwrap();
+        assert_eq!(resp.output, Some(OutputPb::AffectedRows(3)));
+
+        let body = br#"{"rows": [
+            {"t": 1700000000000, "host": "a", "value": 1, "ok": true, "note": null},
+            {"t": 1700000000001, "host": "b", "value": 2.5, "ok": false, "note": null}
+        ]}"#;
+        let resp = Response::try_from(decode_sql_response(body).unwrap()).unwrap();
+        assert_eq!(resp.rows.len(), 2);
+        let row = &resp.rows[1];
+        let names: Vec<_> = row.columns().iter().map(|c| c.name()).collect();
+        assert_eq!(names, vec!["t", "host", "value", "ok", "note"]);
+        assert_eq!(
+            row.column("t").unwrap().value(),
+            &Value::Int64(1700000000001)
+        );
+        assert_eq!(
+            row.column("host").unwrap().value(),
+            &Value::String("b".to_string())
+        );
+        assert_eq!(row.column("value").unwrap().value(), &Value::Double(2.5));
+        assert_eq!(row.column("ok").unwrap().value(), &Value::Boolean(false));
+        assert_eq!(row.column("note").unwrap().value(), &Value::Null);
+
+        let resp = Response::try_from(decode_sql_response(br#"{"rows": []}"#).unwrap()).unwrap();
+        assert!(resp.rows.is_empty());
+        assert!(decode_sql_response(b"{}").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Encode the write requests into the InfluxDB line protocol.
+
+use std::fmt::Write;
+
+use horaedbproto::storage::{value, Value as ValuePb, WriteRequest as WriteRequestPb};
+
+use crate::{Error, Result};
+
+/// Encode the write request into the InfluxDB line protocol with the timestamps
+/// in milliseconds, and return the lines and the number of the points.
+///
+/// The tag values are written as strings because the protocol has no typed
+/// tags, and the null tags and fields are omitted.
+pub(crate) fn encode_write_request(req: &WriteRequestPb) -> Result<(String, u32)> {
+    let mut lines = String::new();
+    let mut points = 0;
+    for table_req in &req.table_requests {
+        let measurement = escape(&table_req.table, &[',', ' ']);
+        for entry in &table_req.entries {
+            let mut series = measurement.clone();
+            for tag in &entry.tags {
+                let name = name_of(&table_req.tag_names, tag.name_index)?;
+                let Some(value) = tag_value(tag.value.as_ref())? else {
+                    continue;
+                };
+                write!(
+                    series,
+                    ",{}={}",
+                    escape(name, &[',', '=', ' ']),
+                    escape(&value, &[',', '=', ' '])
+                )
+                .unwrap();
+            }
+
+            for field_group in &entry.field_groups {
+                let mut fields = String::new();
+                for field in &field_group.fields {
+                    let name = name_of(&table_req.field_names, field.name_index)?;
+                    let Some(value) = field_value(field.value.as_ref())? else {
+                        continue;
+                    };
+                    if !fields.is_empty() {
+                        fields.push(',');
+                    }
+                    write!(fields, "{}={}", escape(name, &[',', '=', ' ']), value).unwrap();
+                }
+                if fields.is_empty() {
+                    return Err(Error::Client(format!(
+                        "point of table:{} at timestamp:{} has no field",
+                        table_req.table, field_group.timestamp
+                    )));
+                }
+
+                writeln!(lines, "{series} {fields} {}", field_group.timestamp).unwrap();
+                points += 1;
+            }
+        }
+    }
+
+    Ok((lines, points))
+}
+
+fn name_of(names: &[String], index: u32) -> Result<&str> {
+    names
+        .get(index as usize)
+        .map(String::as_str)
+        .ok_or_else(|| Error::Client(format!("name index:{index} is out of range")))
+}
+
+fn escape(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn tag_value(value: Option<&ValuePb>) -> Result<Option<String>> {
+    let Some(value) = value.and_then(|v| v.value.as_ref()) else {
+        return Ok(None);
+    };
+    let value = match value {
+        value::Value::StringValue(v) => v.clone(),
+        value::Value::Float64Value(v) => v.to_string(),
+        value::Value::Float32Value(v) => v.to_string(),
+        value::Value::Int64Value(v) | value::Value::TimestampValue(v) => v.to_string(),
+        value::Value::Int32Value(v) | value::Value::Int16Value(v) | value::Value::Int8Value(v) => {
+            v.to_string()
+        }
+        value::Value::Uint64Value(v) => v.to_string(),
+        value::Value::Uint32Value(v)
+        | value::Value::Uint16Value(v)
+        | value::Value::Uint8Value(v) => v.to_string(),
+        value::Value::BoolValue(v) => v.to_string(),
+        value::Value::VarbinaryValue(_) => {
+            return Err(Error::Client(
+                "varbinary tag is not supported by the http transport".to_string(),
+            ))
+        }
+    };
+
+    // The empty tag values are not allowed by the protocol.
+    Ok((!value.is_empty()).then_some(value))
+}
+
+fn field_value(value: Option<&ValuePb>) -> Result<Option<String>> {
+    let Some(value) = value.and_then(|v| v.value.as_ref()) else {
+        return Ok(None);
+    };
+    let value = match value {
+        value::Value::StringValue(v) => format!("\"{}\"", escape(v, &['"', '\\'])),
+        value::Value::Float64Value(v) => v.to_string(),
+        value::Value::Float32Value(v) => v.to_string(),
+        value::Value::Int64Value(v) | value::Value::TimestampValue(v) => format!("{v}i"),
+        value::Value::Int32Value(v) | value::Value::Int16Value(v) | value::Value::Int8Value(v) => {
+            format!("{v}i")
+        }
+        value::Value::Uint64Value(v) => format!("{v}u"),
+        value::Value::Uint32Value(v)
+        | value::Value::Uint16Value(v)
+        | value::Value::Uint8Value(v) => format!("{v}u"),
+        value::Value::BoolValue(v) => v.to_string(),
+        value::Value::VarbinaryValue(_) => {
+            return Err(Error::Client(
+                "varbinary field is not supported by the http transport".to_string(),
+            ))
+        }
+    };
+
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod test {
+    use horaedbproto::storage::WriteRequest as WriteRequestPb;
+
+    use super::encode_write_request;
+    use crate::model::{
+        value::Value,
+        write::{point::PointBuilder, Request as WriteRequest, WriteTableRequestPbsBuilder},
+    };
+
+    fn encode(req: WriteRequest) -> crate::Result<(String, u32)> {
+        let req_pb = WriteRequestPb {
+            context: None,
+            table_requests: WriteTableRequestPbsBuilder(req).build(),
+        };
+        encode_write_request(&req_pb)
+    }
+
+    #[test]
+    fn test_encode_write_request() {
+        let mut req = WriteRequest::default();
+        for (ts, value) in [(1, 1.5), (2, 2.0)] {
+            req.add_point(
+                PointBuilder::new("cpu usage")
+                    .timestamp(ts)
+                    .tag("host", Value::String("a,b".to_string()))
+                    .tag("idc", Value::Int32(3))
+                    .field("value", Value::Double(value))
+                    .field("msg", Value::String("say \"hi\"".to_string()))
+                    .field("count", Value::UInt64(7))
+                    .field("ok", Value::Boolean(true))
+                    .build()
+                    .unwrap(),
+            );
+        }
+
+        let (lines, points) = encode(req).unwrap();
+        assert_eq!(points, 2);
+        let lines: Vec<_> = lines.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                r#"cpu\ usage,host=a\,b,idc=3 count=7u,msg="say \"hi\"",ok=true,value=1.5 1"#,
+                r#"cpu\ usage,host=a\,b,idc=3 count=7u,msg="say \"hi\"",ok=true,value=2 2"#,
+            ]
+        );
+
+        let mut req = WriteRequest::default();
+        req.add_point(
+            PointBuilder::new("t")
+                .timestamp(1)
+                .field("bin", Value::Varbinary(vec![1]))
+                .build()
+                .unwrap(),
+        );
+        assert!(encode(req).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! [`RpcClient`] over the HTTP endpoints of the server, for the environments
+//! blocking grpc.
+
+mod json;
+mod line_protocol;
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use horaedbproto::storage::{
+    RouteRequest as RouteRequestPb, RouteResponse as RouteResponsePb,
+    SqlQueryRequest as QueryRequestPb, SqlQueryResponse as QueryResponsePb,
+    WriteRequest as WriteRequestPb, WriteResponse as WriteResponsePb,
+};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    RequestBuilder,
+};
+use tonic::metadata::{KeyAndValueRef, MetadataMap};
+
+use crate::{
+    config::RpcConfig,
+    errors::{Error, Result, ServerError},
+    rpc_client::{
+        auth::{AuthProvider, Credentials},
+        rpc_client_impl::fill_metadata,
+        ObservedRpcClient, RequestInterceptor, RequestObserver, RpcClient, RpcClientFactory,
+        RpcContext,
+    },
+};
+
+/// The header carrying the database (schema) of the request.
+const SCHEMA_HEADER: &str = "x-horaedb-schema";
+
+struct HttpRpcClient {
+    client: reqwest::Client,
+    base_url: String,
+    default_read_timeout: Duration,
+    default_write_timeout: Duration,
+    credentials: Credentials,
+    interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    forward_priority: bool,
+}
+
+impl HttpRpcClient {
+    /// Set the timeout and the headers in the same way as the grpc metadata.
+    async fn make_request(
+        &self,
+        method: &'static str,
+        ctx: &RpcContext,
+        req: RequestBuilder,
+        default_timeout: Duration,
+    ) -> Result<RequestBuilder> {
+        let timeout = ctx.remaining_timeout(method, default_timeout)?;
+        let mut metadata = MetadataMap::new();
+        fill_metadata(
+            method,
+            ctx,
+            &self.credentials,
+            &self.interceptors,
+            self.forward_priority,
+            &mut metadata,
+        )
+        .await?;
+
+        let mut headers = HeaderMap::with_capacity(metadata.len() + 1);
+        for entry in metadata.iter() {
+            // The binary metadata can't be sent as the http headers.
+            if let KeyAndValueRef::Ascii(key, value) = entry {
+                let key = HeaderName::from_bytes(key.as_str().as_bytes())
+                    .map_err(|e| Error::Client(format!("invalid header key:{key}, err:{e}")))?;
+                let value = HeaderValue::from_bytes(value.as_encoded_bytes())
+                    .map_err(|e| Error::Client(format!("invalid header value, err:{e}")))?;
+                headers.insert(key, value);
+            }
+        }
+        if let Some(database) = &ctx.database {
+            let value = HeaderValue::from_str(database)
+                .map_err(|e| Error::Client(format!("invalid database:{database}, err:{e}")))?;
+            headers.insert(SCHEMA_HEADER, value);
+        }
+
+        Ok(req.timeout(timeout).headers(headers))
+    }
+
+    async fn send(&self, req: RequestBuilder) -> Result<Vec<u8>> {
+        let resp = req.send().await.map_err(|e| self.map_send_error(e))?;
+        let status = resp.status();
+        let body = resp.bytes().await.map_err(|e| self.map_send_error(e))?;
+        if !status.is_success() {
+            return Err(Error::Server(ServerError {
+                code: status.as_u16() as u32,
+                msg: String::from_utf8_lossy(&body).into_owned(),
+            }));
+        }
+
+        Ok(body.to_vec())
+    }
+
+    /// Map the transport errors to the grpc ones of the same meaning.
+    fn map_send_error(&self, e: reqwest::Error) -> Error {
+        if e.is_connect() {
+            Error::Connect {
+                addr: self.base_url.clone(),
+                source: Box::new(e),
+            }
+        } else if e.is_timeout() {
+            Error::Rpc(tonic::Status::deadline_exceeded(e.to_string()))
+        } else {
+            Error::Rpc(tonic::Status::unavailable(e.to_string()))
+        }
+    }
+}
+
+#[async_trait]
+impl RpcClient for HttpRpcClient {
+    async fn sql_query(&self, ctx: &RpcContext, req: QueryRequestPb) -> Result<QueryResponsePb> {
+        let body = serde_json::json!({ "query": req.sql }).to_string();
+        let resp = self
+            .credentials
+            .call(body, |body| async move {
+                let http_req = self
+                    .client
+                    .post(format!("{}/sql", self.base_url))
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body);
+                let http_req = self
+                    .make_request("sql_query", ctx, http_req, self.default_read_timeout)
+                    .await?;
+                self.send(http_req).await
+            })
+            .await?;
+
+        json::decode_sql_response(&resp)
+    }
+
+    async fn write(&self, ctx: &RpcContext, req: WriteRequestPb) -> Result<WriteResponsePb> {
+        let (lines, points) = line_protocol::encode_write_request(&req)?;
+        let database = ctx.database.as_deref().unwrap_or_default();
+        self.credentials
+            .call(lines, |lines| async move {
+                let http_req = self
+                    .client
+                    .post(format!("{}/influxdb/v1/write", self.base_url))
+                    .query(&[("db", database), ("precision", "ms")])
+                    .body(lines);
+                let http_req = self
+                    .make_request("write", ctx, http_req, self.default_write_timeout)
+                    .await?;
+                self.send(http_req).await
+            })
+            .await?;
+
+        Ok(WriteResponsePb {
+            header: None,
+            success: points,
+            failed: 0,
+        })
+    }
+
+    /// The tables can't be routed by the http transport, which only works in
+    /// the `Proxy` mode.
+    async fn route(&self, _ctx: &RpcContext, _req: RouteRequestPb) -> Result<RouteResponsePb> {
+        Ok(RouteResponsePb::default())
+    }
+
+    /// Check the server by a trivial query, as no table is routed.
+    async fn health_check(&self, ctx: &RpcContext) -> Result<()> {
+        let req = QueryRequestPb {
+            sql: "SELECT 1".to_string(),
+            ..Default::default()
+        };
+        self.sql_query(ctx, req).await.map(|_| ())
+    }
+}
+
+/// Factory of the [`RpcClient`]s over the HTTP endpoints, and the endpoint
+/// should be the HTTP one of the server, e.g. `127.0.0.1:5440`.
+pub struct HttpRpcClientFactory {
+    rpc_config: RpcConfig,
+    credentials: Credentials,
+    interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    observers: Arc<[Arc<dyn RequestObserver>]>,
+}
+
+impl HttpRpcClientFactory {
+    pub fn new(
+        rpc_config: RpcConfig,
+        auth_provider: Option<Arc<dyn AuthProvider>>,
+        interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    ) -> Self {
+        Self {
+            rpc_config,
+            credentials: Credentials::new(auth_provider),
+            interceptors,
+            observers: Arc::new([]),
+        }
+    }
+
+    /// Notify the `observers` around every rpc sent by the built clients.
+    pub fn with_observers(mut self, observers: Vec<Arc<dyn RequestObserver>>) -> Self {
+        self.observers = observers.into();
+        self
+    }
+
+    #[cfg(feature = "tls")]
+    fn config_tls(
+        &self,
+        endpoint: &str,
+        builder: reqwest::ClientBuilder,
+    ) -> Result<reqwest::ClientBuilder> {
+        let Some(tls) = &self.rpc_config.tls else {
+            return Ok(builder);
+        };
+
+        let builder = builder.use_rustls_tls();
+        match &tls.ca_pem {
+            Some(ca_pem) => {
+                let cert = reqwest::Certificate::from_pem(ca_pem).map_err(|e| Error::Connect {
+                    addr: endpoint.to_string(),
+                    source: Box::new(e),
+                })?;
+                Ok(builder.add_root_certificate(cert))
+            }
+            None => Ok(builder),
+        }
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn config_tls(
+        &self,
+        endpoint: &str,
+        builder: reqwest::ClientBuilder,
+    ) -> Result<reqwest::ClientBuilder> {
+        match self.rpc_config.tls {
+            Some(_) => Err(Error::Connect {
+                addr: endpoint.to_string(),
+                source: "tls is configured but the `tls` feature is not enabled".into(),
+            }),
+            None => Ok(builder),
+        }
+    }
+}
+
+#[async_trait]
+impl RpcClientFactory for HttpRpcClientFactory {
+    async fn build(&self, endpoint: String) -> Result<Arc<dyn RpcClient>> {
+        let rpc_config = self.rpc_config.for_endpoint(&endpoint);
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(rpc_config.connect_timeout)
+            .tcp_nodelay(rpc_config.tcp_nodelay)
+            .local_address(rpc_config.local_address);
+        // The http2 keepalive is replaced by the TCP one if not set explicitly.
+        let tcp_keepalive = rpc_config.tcp_keepalive.or(rpc_config
+            .keep_alive_while_idle
+            .then_some(rpc_config.keep_alive_interval));
+        if let Some(tcp_keepalive) = tcp_keepalive {
+            builder = builder.tcp_keepalive(tcp_keepalive);
+        }
+        let client = self
+            .config_tls(&endpoint, builder)?
+            .build()
+            .map_err(|e| Error::Connect {
+                addr: endpoint.clone(),
+                source: Box::new(e),
+            })?;
+
+        let scheme = match rpc_config.tls {
+            Some(_) => "https",
+            None => "http",
+        };
+        let client = HttpRpcClient {
+            client,
+            base_url: format!("{scheme}://{endpoint}"),
+            default_read_timeout: rpc_config.default_sql_query_timeout,
+            default_write_timeout: rpc_config.default_write_timeout,
+            credentials: self.credentials.clone(),
+            interceptors: self.interceptors.clone(),
+            forward_priority: rpc_config.forward_priority,
+        };
+        Ok(ObservedRpcClient::wrap(
+            Arc::new(client),
+            endpoint,
+            &self.observers,
+        ))
+    }
+}
@@ -0,0 +1,49 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use tonic::metadata::MetadataMap;
+
+use crate::{errors::Result, rpc_client::RpcContext};
+
+/// Intercept every outgoing rpc to attach custom grpc metadata, e.g.
+/// signatures or tenant headers.
+///
+/// The call fails with the returned error without being sent to the server.
+pub trait RequestInterceptor: Send + Sync {
+    /// Intercept the rpc named by the `method`, i.e. `sql_query`, `write` or
+    /// `route`.
+    fn intercept(
+        &self,
+        method: &'static str,
+        ctx: &RpcContext,
+        metadata: &mut MetadataMap,
+    ) -> Result<()>;
+}
+
+impl<F> RequestInterceptor for F
+where
+    F: Fn(&'static str, &RpcContext, &mut MetadataMap) -> Result<()> + Send + Sync,
+{
+    fn intercept(
+        &self,
+        method: &'static str,
+        ctx: &RpcContext,
+        metadata: &mut MetadataMap,
+    ) -> Result<()> {
+        self(method, ctx, metadata)
+    }
+}
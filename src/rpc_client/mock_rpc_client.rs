@@ -15,10 +15,17 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::sync::Arc;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use dashmap::DashMap;
+use futures::{stream, StreamExt};
 use horaedbproto::storage::{
     Endpoint as EndpointPb, Route as RoutePb, RouteRequest as RouteRequestPb,
     RouteResponse as RouteResponsePb, SqlQueryRequest as QueryRequestPb,
@@ -28,23 +35,59 @@ use horaedbproto::storage::{
 
 use crate::{
     model::route::Endpoint,
-    rpc_client::{RpcClient, RpcContext},
+    rpc_client::{RpcClient, RpcClientFactory, RpcContext, SqlQueryStream},
     Result,
 };
 
 /// Rpc client used for testing.
+///
+/// The write always succeeds and reports every field group as a written row,
+/// and the query returns the first of the `query_responses` after the delay of
+/// the endpoint.
+#[derive(Default)]
 pub struct MockRpcClient {
+    pub endpoint: String,
     pub route_table: Arc<DashMap<String, Endpoint>>,
+    pub write_calls: Arc<AtomicUsize>,
+    /// The responses returned by the streaming query in order.
+    pub query_responses: Arc<Mutex<Vec<QueryResponsePb>>>,
+    pub query_delays: Arc<DashMap<String, Duration>>,
 }
 
 #[async_trait]
 impl RpcClient for MockRpcClient {
     async fn sql_query(&self, _ctx: &RpcContext, _req: QueryRequestPb) -> Result<QueryResponsePb> {
-        todo!()
+        let delay = self.query_delays.get(&self.endpoint).map(|d| *d.value());
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+        let responses = self.query_responses.lock().unwrap();
+        Ok(responses.first().cloned().unwrap_or_default())
     }
 
-    async fn write(&self, _ctx: &RpcContext, _req: WriteRequestPb) -> Result<WriteResponsePb> {
-        todo!()
+    async fn write(&self, _ctx: &RpcContext, req: WriteRequestPb) -> Result<WriteResponsePb> {
+        self.write_calls.fetch_add(1, Ordering::Relaxed);
+        let success = req
+            .table_requests
+            .iter()
+            .flat_map(|table_req| &table_req.entries)
+            .map(|entry| entry.field_groups.len() as u32)
+            .sum();
+
+        Ok(WriteResponsePb {
+            header: None,
+            success,
+            failed: 0,
+        })
+    }
+
+    async fn sql_query_stream(
+        &self,
+        _ctx: &RpcContext,
+        _req: QueryRequestPb,
+    ) -> Result<SqlQueryStream> {
+        let responses = self.query_responses.lock().unwrap().clone();
+        Ok(stream::iter(responses.into_iter().map(Ok)).boxed())
     }
 
     async fn route(&self, _ctx: &RpcContext, req: RouteRequestPb) -> Result<RouteResponsePb> {
@@ -53,18 +96,14 @@ impl RpcClient for MockRpcClient {
             .tables
             .iter()
             .filter_map(|m| {
-                let endpoint = match route_tables.get(m.as_str()) {
-                    Some(v) => v.value().clone(),
-                    None => return None,
-                };
-                let mut route_pb = RoutePb::default();
-                let endpoint_pb = EndpointPb {
-                    ip: endpoint.addr,
-                    port: endpoint.port,
-                };
-                route_pb.table.clone_from(m);
-                route_pb.endpoint = Some(endpoint_pb);
-                Some(route_pb)
+                let endpoint = route_tables.get(m.as_str())?.value().clone();
+                Some(RoutePb {
+                    table: m.clone(),
+                    endpoint: Some(EndpointPb {
+                        ip: endpoint.addr,
+                        port: endpoint.port,
+                    }),
+                })
             })
             .collect();
         let route_resp = RouteResponsePb {
@@ -74,3 +113,30 @@ impl RpcClient for MockRpcClient {
         Ok(route_resp)
     }
 }
+
+/// Rpc client factory used for testing, which builds [`MockRpcClient`]s
+/// sharing the same route table and write counter.
+#[derive(Default)]
+pub struct MockRpcClientFactory {
+    pub route_table: Arc<DashMap<String, Endpoint>>,
+    pub write_calls: Arc<AtomicUsize>,
+    pub query_responses: Arc<Mutex<Vec<QueryResponsePb>>>,
+    /// The delays of the queries to the endpoints.
+    pub query_delays: Arc<DashMap<String, Duration>>,
+    /// The number of the clients built.
+    pub builds: AtomicUsize,
+}
+
+#[async_trait]
+impl RpcClientFactory for MockRpcClientFactory {
+    async fn build(&self, endpoint: String) -> Result<Arc<dyn RpcClient>> {
+        self.builds.fetch_add(1, Ordering::Relaxed);
+        Ok(Arc::new(MockRpcClient {
+            endpoint,
+            route_table: self.route_table.clone(),
+            write_calls: self.write_calls.clone(),
+            query_responses: self.query_responses.clone(),
+            query_delays: self.query_delays.clone(),
+        }))
+    }
+}
@@ -15,28 +15,133 @@
 // specific language governing permissions and limitations
 // under the License.
 
+mod auth;
+mod fallback;
+#[cfg(feature = "http")]
+mod http;
+mod interceptor;
+#[cfg(test)]
 mod mock_rpc_client;
+mod observer;
+mod reconnect;
+mod resolve;
 mod rpc_client_impl;
+mod runtime;
+mod session;
+#[cfg(feature = "otel")]
+mod trace;
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
+pub use auth::{AuthProvider, BearerToken, TokenProvider};
+pub(crate) use auth::{BasicAuth, TokenCache};
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+#[cfg(feature = "prom")]
+use horaedbproto::storage::{
+    PrometheusQueryRequest as PromQueryRequestPb, PrometheusQueryResponse as PromQueryResponsePb,
+};
 use horaedbproto::storage::{
-    RouteRequest as RouteRequestPb, RouteResponse as RouteResponsePb,
-    SqlQueryRequest as QueryRequestPb, SqlQueryResponse as QueryResponsePb,
-    WriteRequest as WriteRequestPb, WriteResponse as WriteResponsePb,
+    RequestContext as RequestContextPb, RouteRequest as RouteRequestPb,
+    RouteResponse as RouteResponsePb, SqlQueryRequest as QueryRequestPb,
+    SqlQueryResponse as QueryResponsePb, WriteRequest as WriteRequestPb,
+    WriteResponse as WriteResponsePb,
 };
+#[cfg(feature = "http")]
+pub use http::HttpRpcClientFactory;
+pub use interceptor::RequestInterceptor;
 #[cfg(test)]
-pub use mock_rpc_client::MockRpcClient;
+pub use mock_rpc_client::{MockRpcClient, MockRpcClientFactory};
+pub(crate) use observer::ObservedRpcClient;
+pub use observer::RequestObserver;
+pub(crate) use rpc_client_impl::EndpointHook;
 pub use rpc_client_impl::RpcClientImplFactory;
+pub(crate) use runtime::DedicatedRuntime;
+pub use session::SessionSettings;
+use tonic::transport::Channel;
+
+use crate::errors::{Error, Result};
+
+/// The stream of the responses of a streaming query.
+pub type SqlQueryStream = BoxStream<'static, Result<QueryResponsePb>>;
+
+/// The priority of the call, which orders the calls waiting for the
+/// concurrency limit of the client, see
+/// [`Builder::max_concurrent_calls`](crate::Builder::max_concurrent_calls), and
+/// is sent to the server as a scheduling hint unless disabled by the
+/// [`RpcConfig`].
+///
+/// [`RpcConfig`]: crate::RpcConfig
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// The bulk calls, e.g. the backfills, which yield to the others.
+    Low,
+    Normal,
+    /// The interactive calls.
+    High,
+}
 
-use crate::errors::Result;
+impl Priority {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
 
 /// Context for rpc request.
 #[derive(Clone, Debug, Default)]
 pub struct RpcContext {
     pub database: Option<String>,
+    /// The timeout of every rpc of the call, which is also sent to the server
+    /// as the `grpc-timeout` header so that the server can abort the work once
+    /// the client has given up.
     pub timeout: Option<Duration>,
+    /// The absolute deadline of the whole call, which may consist of multiple
+    /// rpcs, e.g. the routing and the split writes.
+    ///
+    /// The timeout of every rpc is bounded by the remaining time until the
+    /// deadline, and the rpc fails without being sent once it is exceeded.
+    pub deadline: Option<Instant>,
+    /// The number of the attempts made before for the same call.
+    ///
+    /// It is sent to the server along with the request, and a call with
+    /// non-zero attempt is regarded as a retry, which won't be retried again
+    /// by the client to avoid multiplying the retries.
+    pub attempt: u32,
+    /// Override the compression of the call if set.
+    ///
+    /// `false` disables the compression of both the request and the response,
+    /// and `true` enables it by the algorithm in the [`RpcConfig`] (gzip if
+    /// not configured).
+    ///
+    /// [`RpcConfig`]: crate::RpcConfig
+    pub compression: Option<bool>,
+    /// The custom grpc metadata sent along with the request, e.g. trace ids.
+    ///
+    /// The keys should be valid ascii metadata keys, and the keys reserved by
+    /// the client (e.g. `authorization` and `x-horaedb-priority`) are not
+    /// allowed.
+    pub headers: HashMap<String, String>,
+    /// The priority of the call, which is [`Priority::Normal`] if not set.
+    pub priority: Option<Priority>,
+    /// Override whether the result of the query is cached if set, which takes
+    /// effect only if the cache is configured by
+    /// [`Builder::query_cache_config`](crate::Builder::query_cache_config).
+    pub cache: Option<bool>,
+    /// Hedge the read-only query by the
+    /// [`HedgingConfig`](crate::HedgingConfig) of the client, which is
+    /// disabled by default.
+    pub hedge: bool,
 }
 
 impl RpcContext {
@@ -49,12 +154,135 @@ impl RpcContext {
         self.timeout = Some(timeout);
         self
     }
+
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn compression(mut self, enabled: bool) -> Self {
+        self.compression = Some(enabled);
+        self
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn cache(mut self, enabled: bool) -> Self {
+        self.cache = Some(enabled);
+        self
+    }
+
+    pub fn hedge(mut self, enabled: bool) -> Self {
+        self.hedge = enabled;
+        self
+    }
+
+    /// Override the [`SessionSettings`] of the client by the setting `name` of
+    /// the call.
+    pub fn setting(self, name: &str, value: impl Into<String>) -> Self {
+        self.header(session::setting_metadata_key(name), value)
+    }
+
+    /// Build the context for the next attempt of the call, which should be
+    /// used by the retry loops outside the client.
+    pub fn next_attempt(&self) -> Self {
+        Self {
+            attempt: self.attempt + 1,
+            ..self.clone()
+        }
+    }
+
+    /// The timeout of the rpc bounded by the remaining time until the deadline,
+    /// and fail if the deadline is exceeded.
+    pub(crate) fn remaining_timeout(
+        &self,
+        method: &'static str,
+        default_timeout: Duration,
+    ) -> Result<Duration> {
+        let timeout = self.timeout.unwrap_or(default_timeout);
+        let Some(deadline) = self.deadline else {
+            return Ok(timeout);
+        };
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::Rpc(tonic::Status::deadline_exceeded(format!(
+                "deadline of {method} is exceeded before sending"
+            ))));
+        }
+        Ok(timeout.min(remaining))
+    }
+
+    /// Whether the call is a retry of the previous attempts.
+    #[inline]
+    pub fn is_retry(&self) -> bool {
+        self.attempt > 0
+    }
 }
+
+/// The error of the rpc not supported by the [`RpcClient`].
+#[cfg_attr(not(feature = "prom"), allow(dead_code))]
+pub(crate) fn unimplemented_rpc(method: &'static str) -> Error {
+    Error::Rpc(tonic::Status::unimplemented(format!(
+        "{method} is not supported by the client"
+    )))
+}
+
+/// The rpcs to the server.
+///
+/// The rpcs added after the initial ones have the default implementations
+/// failing with the `Unimplemented` status, so that they can be added behind
+/// the feature flags without breaking the existing implementors.
 #[async_trait]
 pub trait RpcClient: Send + Sync {
     async fn sql_query(&self, ctx: &RpcContext, req: QueryRequestPb) -> Result<QueryResponsePb>;
     async fn write(&self, ctx: &RpcContext, req: WriteRequestPb) -> Result<WriteResponsePb>;
     async fn route(&self, ctx: &RpcContext, req: RouteRequestPb) -> Result<RouteResponsePb>;
+
+    /// Query by the server-streaming rpc, and the responses are received one
+    /// by one from the returned stream.
+    ///
+    /// The whole response of [`sql_query`](RpcClient::sql_query) is returned
+    /// as the only item by default.
+    async fn sql_query_stream(
+        &self,
+        ctx: &RpcContext,
+        req: QueryRequestPb,
+    ) -> Result<SqlQueryStream> {
+        let resp = self.sql_query(ctx, req).await?;
+        Ok(stream::once(async { Ok(resp) }).boxed())
+    }
+
+    /// Check whether the server is alive by a trivial route request of no
+    /// table.
+    async fn health_check(&self, ctx: &RpcContext) -> Result<()> {
+        let req = RouteRequestPb {
+            context: Some(RequestContextPb {
+                database: ctx.database.clone().unwrap_or_default(),
+            }),
+            tables: Vec::new(),
+        };
+        self.route(ctx, req).await.map(|_| ())
+    }
+
+    /// Query by the PromQL expression.
+    #[cfg(feature = "prom")]
+    async fn prom_query(
+        &self,
+        ctx: &RpcContext,
+        req: PromQueryRequestPb,
+    ) -> Result<PromQueryResponsePb> {
+        let _ = (ctx, req);
+        Err(unimplemented_rpc("prom_query"))
+    }
 }
 
 #[async_trait]
@@ -64,4 +292,28 @@ pub trait RpcClientFactory: Send + Sync {
     /// It may fail because of invalid endpoint. Any caller calls this method
     /// should handle the potential error.
     async fn build(&self, endpoint: String) -> Result<Arc<dyn RpcClient>>;
+
+    /// Evict the connection to the `endpoint` cached by the factory if any, so
+    /// that the next build reconnects to it.
+    fn evict(&self, _endpoint: &str) {}
+}
+
+/// Provider of the pre-built [`Channel`]s, for the users managing the channels
+/// by themselves, e.g. with custom balancers or middlewares.
+#[async_trait]
+pub trait ChannelProvider: Send + Sync {
+    /// Provide the channel to the `endpoint` in the form: `{ip_addr}:{port}`.
+    async fn channel(&self, endpoint: &str) -> Result<Channel>;
+}
+
+/// The channels keyed by the endpoints, and the endpoints not in the map fail
+/// to connect.
+#[async_trait]
+impl ChannelProvider for HashMap<String, Channel> {
+    async fn channel(&self, endpoint: &str) -> Result<Channel> {
+        self.get(endpoint).cloned().ok_or_else(|| Error::Connect {
+            addr: endpoint.to_string(),
+            source: "no channel is provided for the endpoint".into(),
+        })
+    }
 }
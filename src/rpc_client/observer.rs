@@ -0,0 +1,217 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+#[cfg(feature = "prom")]
+use horaedbproto::storage::{
+    PrometheusQueryRequest as PromQueryRequestPb, PrometheusQueryResponse as PromQueryResponsePb,
+};
+use horaedbproto::storage::{
+    RouteRequest as RouteRequestPb, RouteResponse as RouteResponsePb,
+    SqlQueryRequest as QueryRequestPb, SqlQueryResponse as QueryResponsePb,
+    WriteRequest as WriteRequestPb, WriteResponse as WriteResponsePb,
+};
+
+use crate::{
+    errors::{Error, Result},
+    rpc_client::{RpcClient, RpcContext, SqlQueryStream},
+};
+
+/// Observe every rpc sent to the servers, e.g. for the custom logging, metrics
+/// and auditing.
+///
+/// Every attempt of the retried calls is observed separately, and the rpcs
+/// rejected before being sent, e.g. by the [`RequestInterceptor`], are
+/// observed too.
+///
+/// [`RequestInterceptor`]: crate::RequestInterceptor
+pub trait RequestObserver: Send + Sync {
+    /// Called before the rpc named by the `method`, i.e. `sql_query`, `write`,
+    /// `route`, `sql_query_stream` or `prom_query`, is sent to the `endpoint`.
+    fn on_request_start(&self, method: &'static str, endpoint: &str, ctx: &RpcContext) {
+        let _ = (method, endpoint, ctx);
+    }
+
+    /// Called after the rpc is finished in `elapsed`, and only the opening of
+    /// the stream is observed for the `sql_query_stream`.
+    fn on_request_end(
+        &self,
+        method: &'static str,
+        endpoint: &str,
+        ctx: &RpcContext,
+        elapsed: Duration,
+        result: std::result::Result<(), &Error>,
+    ) {
+        let _ = (method, endpoint, ctx, elapsed, result);
+    }
+}
+
+/// [`RpcClient`] notifying the observers around the rpcs of the inner client.
+pub(crate) struct ObservedRpcClient {
+    inner: Arc<dyn RpcClient>,
+    endpoint: String,
+    observers: Arc<[Arc<dyn RequestObserver>]>,
+}
+
+impl ObservedRpcClient {
+    /// Wrap the `inner` client of the `endpoint` if there is any observer.
+    pub fn wrap(
+        inner: Arc<dyn RpcClient>,
+        endpoint: String,
+        observers: &Arc<[Arc<dyn RequestObserver>]>,
+    ) -> Arc<dyn RpcClient> {
+        if observers.is_empty() {
+            return inner;
+        }
+
+        Arc::new(Self {
+            inner,
+            endpoint,
+            observers: observers.clone(),
+        })
+    }
+
+    async fn observe<T>(
+        &self,
+        method: &'static str,
+        ctx: &RpcContext,
+        rpc: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        for observer in self.observers.iter() {
+            observer.on_request_start(method, &self.endpoint, ctx);
+        }
+        let start = Instant::now();
+        let result = rpc.await;
+        let elapsed = start.elapsed();
+        for observer in self.observers.iter() {
+            let result = result.as_ref().map(|_| ());
+            observer.on_request_end(method, &self.endpoint, ctx, elapsed, result);
+        }
+
+        result
+    }
+}
+
+#[async_trait]
+impl RpcClient for ObservedRpcClient {
+    async fn sql_query(&self, ctx: &RpcContext, req: QueryRequestPb) -> Result<QueryResponsePb> {
+        self.observe("sql_query", ctx, self.inner.sql_query(ctx, req))
+            .await
+    }
+
+    async fn write(&self, ctx: &RpcContext, req: WriteRequestPb) -> Result<WriteResponsePb> {
+        self.observe("write", ctx, self.inner.write(ctx, req)).await
+    }
+
+    async fn route(&self, ctx: &RpcContext, req: RouteRequestPb) -> Result<RouteResponsePb> {
+        self.observe("route", ctx, self.inner.route(ctx, req)).await
+    }
+
+    async fn sql_query_stream(
+        &self,
+        ctx: &RpcContext,
+        req: QueryRequestPb,
+    ) -> Result<SqlQueryStream> {
+        self.observe(
+            "sql_query_stream",
+            ctx,
+            self.inner.sql_query_stream(ctx, req),
+        )
+        .await
+    }
+
+    #[cfg(feature = "prom")]
+    async fn prom_query(
+        &self,
+        ctx: &RpcContext,
+        req: PromQueryRequestPb,
+    ) -> Result<PromQueryResponsePb> {
+        self.observe("prom_query", ctx, self.inner.prom_query(ctx, req))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use horaedbproto::storage::{RouteRequest as RouteRequestPb, WriteRequest as WriteRequestPb};
+
+    use super::{ObservedRpcClient, RequestObserver};
+    use crate::{
+        errors::Error,
+        rpc_client::{MockRpcClient, RpcContext},
+    };
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl RequestObserver for RecordingObserver {
+        fn on_request_start(&self, method: &'static str, endpoint: &str, _: &RpcContext) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("start {method} {endpoint}"));
+        }
+
+        fn on_request_end(
+            &self,
+            method: &'static str,
+            endpoint: &str,
+            _: &RpcContext,
+            _: Duration,
+            result: Result<(), &Error>,
+        ) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("end {method} {endpoint} {}", result.is_ok()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observed_rpc_client() {
+        let observer = Arc::new(RecordingObserver::default());
+        let observers: Arc<[Arc<dyn RequestObserver>]> = Arc::new([observer.clone() as _]);
+        let client = ObservedRpcClient::wrap(
+            Arc::new(MockRpcClient::default()),
+            "127.0.0.1:8831".to_string(),
+            &observers,
+        );
+
+        let ctx = RpcContext::default();
+        client.route(&ctx, RouteRequestPb::default()).await.unwrap();
+        client.write(&ctx, WriteRequestPb::default()).await.unwrap();
+        let events = observer.events.lock().unwrap().clone();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], "start route 127.0.0.1:8831");
+        assert_eq!(events[1], "end route 127.0.0.1:8831 true");
+        assert_eq!(events[2], "start write 127.0.0.1:8831");
+        assert_eq!(events[3], "end write 127.0.0.1:8831 true");
+    }
+}
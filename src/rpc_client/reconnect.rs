@@ -0,0 +1,371 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Rebuild the broken connections with the exponential backoff.
+
+use std::{
+    future::Future,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+#[cfg(feature = "prom")]
+use horaedbproto::storage::{
+    PrometheusQueryRequest as PromQueryRequestPb, PrometheusQueryResponse as PromQueryResponsePb,
+};
+use horaedbproto::storage::{
+    RouteRequest as RouteRequestPb, RouteResponse as RouteResponsePb,
+    SqlQueryRequest as QueryRequestPb, SqlQueryResponse as QueryResponsePb,
+    WriteRequest as WriteRequestPb, WriteResponse as WriteResponsePb,
+};
+
+use crate::{
+    config::ReconnectPolicy,
+    db_client::REQUEST_ID_METADATA_KEY,
+    errors::{Error, Result},
+    rpc_client::{RpcClient, RpcClientFactory, RpcContext, SqlQueryStream},
+};
+
+/// [`RpcClient`] rebuilding the client by the factory once the connection is
+/// found broken, and re-sending the failed call on the rebuilt one if it's
+/// safe to.
+pub(crate) struct ReconnectingRpcClient<F: RpcClientFactory> {
+    factory: F,
+    endpoint: String,
+    policy: ReconnectPolicy,
+    /// The status codes of the broken connections.
+    codes: Vec<tonic::Code>,
+    client: RwLock<Arc<dyn RpcClient>>,
+}
+
+impl<F: RpcClientFactory> ReconnectingRpcClient<F> {
+    pub fn new(
+        factory: F,
+        endpoint: String,
+        policy: ReconnectPolicy,
+        codes: Vec<tonic::Code>,
+        client: Arc<dyn RpcClient>,
+    ) -> Self {
+        Self {
+            factory,
+            endpoint,
+            policy,
+            codes,
+            client: RwLock::new(client),
+        }
+    }
+
+    fn is_broken(&self, err: &Error) -> bool {
+        matches!(err, Error::Rpc(status) if self.codes.contains(&status.code()))
+    }
+
+    /// Call `op` by the client, and re-send the `req` on the rebuilt client if
+    /// the connection is found broken and the `req` is `resendable`.
+    ///
+    /// The retries of the callers are not re-sent to avoid multiplying the
+    /// retries, and only their connections are rebuilt. The `req` is only
+    /// copied for the calls which may be re-sent, and the re-sent ones are the
+    /// next attempts of the call.
+    async fn call<Req, T, Fut>(
+        &self,
+        ctx: &RpcContext,
+        req: Req,
+        resendable: bool,
+        op: impl Fn(Arc<dyn RpcClient>, RpcContext, Req) -> Fut,
+    ) -> Result<T>
+    where
+        Req: Clone,
+        Fut: Future<Output = Result<T>>,
+    {
+        let retained = (resendable && !ctx.is_retry()).then(|| req.clone());
+        let client = self.client.read().unwrap().clone();
+        let err = match op(client, ctx.clone(), req).await {
+            Err(e) if self.is_broken(&e) => e,
+            result => return result,
+        };
+        let Some(req) = retained else {
+            // Only the connection is rebuilt, for the following calls.
+            self.rebuild().await;
+            return Err(err);
+        };
+
+        let mut ctx = ctx.clone();
+        for attempt in 0..self.policy.max_attempts {
+            tokio::time::sleep(self.policy.backoff(attempt)).await;
+            // Keep the original error if the client can't be rebuilt.
+            let Some(client) = self.rebuild().await else {
+                continue;
+            };
+
+            ctx = ctx.next_attempt();
+            match op(client, ctx.clone(), req.clone()).await {
+                Err(e) if self.is_broken(&e) => continue,
+                result => return result,
+            }
+        }
+
+        Err(err)
+    }
+
+    async fn rebuild(&self) -> Option<Arc<dyn RpcClient>> {
+        self.factory.evict(&self.endpoint);
+        let client = self.factory.build(self.endpoint.clone()).await.ok()?;
+        *self.client.write().unwrap() = client.clone();
+        Some(client)
+    }
+}
+
+#[async_trait]
+impl<F: RpcClientFactory> RpcClient for ReconnectingRpcClient<F> {
+    async fn sql_query(&self, ctx: &RpcContext, req: QueryRequestPb) -> Result<QueryResponsePb> {
+        self.call(ctx, req, true, |client, ctx, req| async move {
+            client.sql_query(&ctx, req).await
+        })
+        .await
+    }
+
+    /// The write is only re-sent with the request id of
+    /// [`WriteRetryMode::RequestId`], which the server deduplicates by, so
+    /// the write possibly received by the server isn't duplicated.
+    ///
+    /// [`WriteRetryMode::RequestId`]: crate::WriteRetryMode::RequestId
+    async fn write(&self, ctx: &RpcContext, req: WriteRequestPb) -> Result<WriteResponsePb> {
+        let resendable = ctx.headers.contains_key(REQUEST_ID_METADATA_KEY);
+        self.call(ctx, req, resendable, |client, ctx, req| async move {
+            client.write(&ctx, req).await
+        })
+        .await
+    }
+
+    async fn route(&self, ctx: &RpcContext, req: RouteRequestPb) -> Result<RouteResponsePb> {
+        self.call(ctx, req, true, |client, ctx, req| async move {
+            client.route(&ctx, req).await
+        })
+        .await
+    }
+
+    /// Only the failure of opening the stream is handled.
+    async fn sql_query_stream(
+        &self,
+        ctx: &RpcContext,
+        req: QueryRequestPb,
+    ) -> Result<SqlQueryStream> {
+        self.call(ctx, req, true, |client, ctx, req| async move {
+            client.sql_query_stream(&ctx, req).await
+        })
+        .await
+    }
+
+    #[cfg(feature = "prom")]
+    async fn prom_query(
+        &self,
+        ctx: &RpcContext,
+        req: PromQueryRequestPb,
+    ) -> Result<PromQueryResponsePb> {
+        self.call(ctx, req, true, |client, ctx, req| async move {
+            client.prom_query(&ctx, req).await
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use horaedbproto::storage::{
+        sql_query_response::Output as OutputPb, RouteRequest as RouteRequestPb,
+        RouteResponse as RouteResponsePb, SqlQueryRequest as QueryRequestPb,
+        SqlQueryResponse as QueryResponsePb, WriteRequest as WriteRequestPb,
+        WriteResponse as WriteResponsePb,
+    };
+
+    use super::ReconnectingRpcClient;
+    use crate::{
+        config::ReconnectPolicy,
+        db_client::REQUEST_ID_METADATA_KEY,
+        errors::{Error, Result},
+        rpc_client::{RpcClient, RpcClientFactory, RpcContext},
+    };
+
+    /// Client whose queries and writes always fail if it's broken.
+    struct FlakyClient {
+        broken: bool,
+    }
+
+    impl FlakyClient {
+        fn check(&self) -> Result<()> {
+            match self.broken {
+                true => Err(Error::Rpc(tonic::Status::unavailable("broken pipe"))),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl RpcClient for FlakyClient {
+        async fn sql_query(&self, ctx: &RpcContext, _: QueryRequestPb) -> Result<QueryResponsePb> {
+            self.check()?;
+            Ok(QueryResponsePb {
+                output: Some(OutputPb::AffectedRows(ctx.attempt)),
+                ..Default::default()
+            })
+        }
+
+        async fn write(&self, _: &RpcContext, _: WriteRequestPb) -> Result<WriteResponsePb> {
+            self.check()?;
+            Ok(WriteResponsePb {
+                success: 1,
+                ..Default::default()
+            })
+        }
+
+        async fn route(&self, _: &RpcContext, _: RouteRequestPb) -> Result<RouteResponsePb> {
+            Err(Error::Rpc(tonic::Status::invalid_argument("bad request")))
+        }
+    }
+
+    /// Factory building the broken clients until `broken_builds` is reached.
+    #[derive(Default)]
+    struct FlakyFactory {
+        broken_builds: usize,
+        builds: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl RpcClientFactory for FlakyFactory {
+        async fn build(&self, _: String) -> Result<Arc<dyn RpcClient>> {
+            let builds = self.builds.fetch_add(1, Ordering::Relaxed);
+            Ok(Arc::new(FlakyClient {
+                broken: builds < self.broken_builds,
+            }))
+        }
+    }
+
+    fn make_client(
+        broken_builds: usize,
+        codes: Vec<tonic::Code>,
+    ) -> ReconnectingRpcClient<FlakyFactory> {
+        let policy = ReconnectPolicy {
+            max_attempts: 2,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+            jitter: true,
+        };
+        let factory = FlakyFactory {
+            broken_builds,
+            ..Default::default()
+        };
+        let client = Arc::new(FlakyClient { broken: true });
+        ReconnectingRpcClient::new(factory, "127.0.0.1:8831".to_string(), policy, codes, client)
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let ctx = RpcContext::default();
+
+        let client = make_client(1, vec![tonic::Code::Unavailable]);
+        let resp = client
+            .sql_query(&ctx, QueryRequestPb::default())
+            .await
+            .unwrap();
+        // The query is re-sent twice as the next attempts.
+        assert_eq!(resp.output, Some(OutputPb::AffectedRows(2)));
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 2);
+        // The rebuilt client is kept for the following calls.
+        client
+            .sql_query(&ctx, QueryRequestPb::default())
+            .await
+            .unwrap();
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 2);
+
+        // The retries of the caller only rebuild the connection.
+        let client = make_client(1, vec![tonic::Code::Unavailable]);
+        let err = client
+            .sql_query(&ctx.next_attempt(), QueryRequestPb::default())
+            .await
+            .unwrap_err();
+        assert!(err.is_unavailable());
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 1);
+
+        let client = make_client(usize::MAX, vec![tonic::Code::Unavailable]);
+        let err = client
+            .sql_query(&ctx, QueryRequestPb::default())
+            .await
+            .unwrap_err();
+        assert!(err.is_unavailable());
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 2);
+
+        // The errors other than the broken connection are not retried.
+        let err = client
+            .route(&ctx, RouteRequestPb::default())
+            .await
+            .unwrap_err();
+        assert!(!err.is_unavailable());
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 2);
+
+        // Only the configured codes are retried.
+        let client = make_client(1, vec![tonic::Code::Internal]);
+        let err = client
+            .sql_query(&ctx, QueryRequestPb::default())
+            .await
+            .unwrap_err();
+        assert!(err.is_unavailable());
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_resend_write() {
+        // The write without the request id isn't re-sent, but the connection
+        // is rebuilt for the following writes.
+        let client = make_client(0, vec![tonic::Code::Unavailable]);
+        let ctx = RpcContext::default();
+        let err = client
+            .write(&ctx, WriteRequestPb::default())
+            .await
+            .unwrap_err();
+        assert!(err.is_unavailable());
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 1);
+        client.write(&ctx, WriteRequestPb::default()).await.unwrap();
+
+        let client = make_client(1, vec![tonic::Code::Unavailable]);
+        let ctx = RpcContext::default().header(REQUEST_ID_METADATA_KEY, "id");
+        let resp = client.write(&ctx, WriteRequestPb::default()).await.unwrap();
+        assert_eq!(resp.success, 1);
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 2);
+    }
+
+    #[cfg(feature = "prom")]
+    #[tokio::test]
+    async fn test_unimplemented_rpc() {
+        let client = make_client(1, vec![tonic::Code::Unavailable]);
+        let req = horaedbproto::storage::PrometheusQueryRequest::default();
+        let err = client
+            .prom_query(&RpcContext::default(), req)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Rpc(status) if status.code() == tonic::Code::Unimplemented));
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,363 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Re-resolve the host names of the endpoints and rebuild the connections once
+//! the resolved addresses change.
+
+use std::{
+    future::Future,
+    net::SocketAddr,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+#[cfg(feature = "prom")]
+use horaedbproto::storage::{
+    PrometheusQueryRequest as PromQueryRequestPb, PrometheusQueryResponse as PromQueryResponsePb,
+};
+use horaedbproto::storage::{
+    RouteRequest as RouteRequestPb, RouteResponse as RouteResponsePb,
+    SqlQueryRequest as QueryRequestPb, SqlQueryResponse as QueryResponsePb,
+    WriteRequest as WriteRequestPb, WriteResponse as WriteResponsePb,
+};
+
+use crate::{
+    errors::{Error, Result},
+    rpc_client::{RpcClient, RpcClientFactory, RpcContext, SqlQueryStream},
+};
+
+/// Resolver of the endpoints in the form: `{host}:{port}`.
+#[async_trait]
+pub(crate) trait Resolve: Send + Sync {
+    async fn resolve(&self, endpoint: &str) -> Result<Vec<SocketAddr>>;
+}
+
+/// [`Resolve`] by the system resolver.
+pub(crate) struct DnsResolver;
+
+#[async_trait]
+impl Resolve for DnsResolver {
+    async fn resolve(&self, endpoint: &str) -> Result<Vec<SocketAddr>> {
+        let addrs = tokio::net::lookup_host(endpoint)
+            .await
+            .map_err(|e| Error::Connect {
+                addr: endpoint.to_string(),
+                source: Box::new(e),
+            })?;
+        Ok(addrs.collect())
+    }
+}
+
+/// Whether the endpoint is a host name to be resolved rather than an ip
+/// address.
+pub(crate) fn is_host_name(endpoint: &str) -> bool {
+    endpoint.parse::<SocketAddr>().is_err()
+}
+
+struct Resolved {
+    /// The sorted addresses resolved last time.
+    addrs: Vec<SocketAddr>,
+    resolved_at: Instant,
+}
+
+/// [`RpcClient`] re-resolving the host name of the endpoint on the interval or
+/// when the server is unavailable, and rebuilding the client by the factory
+/// once the resolved addresses change.
+pub(crate) struct ResolvingRpcClient<F: RpcClientFactory> {
+    factory: F,
+    endpoint: String,
+    interval: Duration,
+    resolver: Arc<dyn Resolve>,
+    resolved: Mutex<Resolved>,
+    client: RwLock<Arc<dyn RpcClient>>,
+}
+
+impl<F: RpcClientFactory> ResolvingRpcClient<F> {
+    pub async fn new(
+        factory: F,
+        endpoint: String,
+        interval: Duration,
+        resolver: Arc<dyn Resolve>,
+        client: Arc<dyn RpcClient>,
+    ) -> Self {
+        // The addresses failed to be resolved are regarded as changed next time.
+        let addrs = Self::sorted(resolver.resolve(&endpoint).await.unwrap_or_default());
+        Self {
+            factory,
+            endpoint,
+            interval,
+            resolver,
+            resolved: Mutex::new(Resolved {
+                addrs,
+                resolved_at: Instant::now(),
+            }),
+            client: RwLock::new(client),
+        }
+    }
+
+    fn sorted(mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        addrs.sort();
+        addrs.dedup();
+        addrs
+    }
+
+    /// Re-resolve the endpoint if `force` or the interval is elapsed, and
+    /// return whether the client is rebuilt.
+    async fn refresh(&self, force: bool) -> bool {
+        {
+            let mut resolved = self.resolved.lock().unwrap();
+            if !force && resolved.resolved_at.elapsed() < self.interval {
+                return false;
+            }
+            // Only one of the concurrent calls resolves the endpoint.
+            resolved.resolved_at = Instant::now();
+        }
+
+        // Keep the current client if the endpoint can't be resolved.
+        let Ok(addrs) = self.resolver.resolve(&self.endpoint).await else {
+            return false;
+        };
+        let addrs = Self::sorted(addrs);
+        if addrs.is_empty() || self.resolved.lock().unwrap().addrs == addrs {
+            return false;
+        }
+
+        self.factory.evict(&self.endpoint);
+        let Ok(client) = self.factory.build(self.endpoint.clone()).await else {
+            return false;
+        };
+        *self.client.write().unwrap() = client;
+        self.resolved.lock().unwrap().addrs = addrs;
+        true
+    }
+
+    async fn call<T, Fut>(&self, op: impl Fn(Arc<dyn RpcClient>) -> Fut) -> Result<T>
+    where
+        Fut: Future<Output = Result<T>>,
+    {
+        self.refresh(false).await;
+
+        let client = self.client.read().unwrap().clone();
+        match op(client).await {
+            // Retry on the rebuilt client if the addresses are changed.
+            Err(e) if e.is_unavailable() => {
+                if !self.refresh(true).await {
+                    return Err(e);
+                }
+                let client = self.client.read().unwrap().clone();
+                op(client).await
+            }
+            result => result,
+        }
+    }
+}
+
+#[async_trait]
+impl<F: RpcClientFactory> RpcClient for ResolvingRpcClient<F> {
+    async fn sql_query(&self, ctx: &RpcContext, req: QueryRequestPb) -> Result<QueryResponsePb> {
+        self.call(|client| {
+            let req = req.clone();
+            async move { client.sql_query(ctx, req).await }
+        })
+        .await
+    }
+
+    async fn write(&self, ctx: &RpcContext, req: WriteRequestPb) -> Result<WriteResponsePb> {
+        self.call(|client| {
+            let req = req.clone();
+            async move { client.write(ctx, req).await }
+        })
+        .await
+    }
+
+    async fn route(&self, ctx: &RpcContext, req: RouteRequestPb) -> Result<RouteResponsePb> {
+        self.call(|client| {
+            let req = req.clone();
+            async move { client.route(ctx, req).await }
+        })
+        .await
+    }
+
+    /// Only the failure of opening the stream is handled.
+    async fn sql_query_stream(
+        &self,
+        ctx: &RpcContext,
+        req: QueryRequestPb,
+    ) -> Result<SqlQueryStream> {
+        self.call(|client| {
+            let req = req.clone();
+            async move { client.sql_query_stream(ctx, req).await }
+        })
+        .await
+    }
+
+    #[cfg(feature = "prom")]
+    async fn prom_query(
+        &self,
+        ctx: &RpcContext,
+        req: PromQueryRequestPb,
+    ) -> Result<PromQueryResponsePb> {
+        self.call(|client| {
+            let req = req.clone();
+            async move { client.prom_query(ctx, req).await }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use horaedbproto::storage::{
+        RouteRequest as RouteRequestPb, RouteResponse as RouteResponsePb,
+        SqlQueryRequest as QueryRequestPb, SqlQueryResponse as QueryResponsePb,
+        WriteRequest as WriteRequestPb, WriteResponse as WriteResponsePb,
+    };
+
+    use super::{is_host_name, Resolve, ResolvingRpcClient};
+    use crate::{
+        errors::{Error, Result},
+        rpc_client::{RpcClient, RpcClientFactory, RpcContext},
+    };
+
+    /// Resolver returning the address set by the test.
+    #[derive(Default)]
+    struct FakeResolver {
+        addr: Mutex<Option<SocketAddr>>,
+    }
+
+    impl FakeResolver {
+        fn set(&self, addr: &str) {
+            *self.addr.lock().unwrap() = Some(addr.parse().unwrap());
+        }
+    }
+
+    #[async_trait]
+    impl Resolve for FakeResolver {
+        async fn resolve(&self, _: &str) -> Result<Vec<SocketAddr>> {
+            Ok(self.addr.lock().unwrap().iter().cloned().collect())
+        }
+    }
+
+    /// Client whose writes succeed if it's built for the current address.
+    struct PinnedClient {
+        addr: Option<SocketAddr>,
+        resolver: Arc<FakeResolver>,
+    }
+
+    #[async_trait]
+    impl RpcClient for PinnedClient {
+        async fn sql_query(&self, _: &RpcContext, _: QueryRequestPb) -> Result<QueryResponsePb> {
+            unimplemented!()
+        }
+
+        async fn write(&self, _: &RpcContext, _: WriteRequestPb) -> Result<WriteResponsePb> {
+            if self.addr != *self.resolver.addr.lock().unwrap() {
+                return Err(Error::Rpc(tonic::Status::unavailable("stale address")));
+            }
+            Ok(WriteResponsePb::default())
+        }
+
+        async fn route(&self, _: &RpcContext, _: RouteRequestPb) -> Result<RouteResponsePb> {
+            unimplemented!()
+        }
+    }
+
+    struct PinnedFactory {
+        resolver: Arc<FakeResolver>,
+        builds: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl RpcClientFactory for PinnedFactory {
+        async fn build(&self, _: String) -> Result<Arc<dyn RpcClient>> {
+            self.builds.fetch_add(1, Ordering::Relaxed);
+            Ok(Arc::new(PinnedClient {
+                addr: *self.resolver.addr.lock().unwrap(),
+                resolver: self.resolver.clone(),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_re_resolve() {
+        assert!(is_host_name("horaedb.example.com:8831"));
+        assert!(!is_host_name("127.0.0.1:8831"));
+
+        let resolver = Arc::new(FakeResolver::default());
+        resolver.set("10.0.0.1:8831");
+        let factory = PinnedFactory {
+            resolver: resolver.clone(),
+            builds: AtomicUsize::new(0),
+        };
+        let initial = factory.build(String::new()).await.unwrap();
+        let client = ResolvingRpcClient::new(
+            factory,
+            "horaedb:8831".to_string(),
+            Duration::from_secs(3600),
+            resolver.clone(),
+            initial,
+        )
+        .await;
+        let ctx = RpcContext::default();
+        client.write(&ctx, WriteRequestPb::default()).await.unwrap();
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 1);
+
+        // The failure triggers the re-resolution before the interval elapses.
+        resolver.set("10.0.0.2:8831");
+        client.write(&ctx, WriteRequestPb::default()).await.unwrap();
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 2);
+        client.write(&ctx, WriteRequestPb::default()).await.unwrap();
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_interval() {
+        let resolver = Arc::new(FakeResolver::default());
+        resolver.set("10.0.0.1:8831");
+        let factory = PinnedFactory {
+            resolver: resolver.clone(),
+            builds: AtomicUsize::new(0),
+        };
+        let initial = factory.build(String::new()).await.unwrap();
+        let client = ResolvingRpcClient::new(
+            factory,
+            "horaedb:8831".to_string(),
+            Duration::ZERO,
+            resolver.clone(),
+            initial,
+        )
+        .await;
+
+        // The client is rebuilt by the periodic re-resolution only if the
+        // addresses change.
+        assert!(!client.refresh(false).await);
+        resolver.set("10.0.0.2:8831");
+        assert!(client.refresh(false).await);
+        assert_eq!(client.factory.builds.load(Ordering::Relaxed), 2);
+    }
+}
@@ -15,11 +15,15 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use anyhow::Context;
 use async_trait::async_trait;
-use base64::{prelude::BASE64_STANDARD, Engine};
+use dashmap::DashMap;
+use futures::StreamExt;
+#[cfg(feature = "prom")]
+use horaedbproto::storage::{
+    PrometheusQueryRequest as PromQueryRequestPb, PrometheusQueryResponse as PromQueryResponsePb,
+};
 use horaedbproto::{
     common::ResponseHeader,
     storage::{
@@ -28,42 +32,207 @@ use horaedbproto::{
         WriteRequest as WriteRequestPb, WriteResponse as WriteResponsePb,
     },
 };
+use hyper::client::HttpConnector;
+use prost::Message;
+use tokio::sync::{OnceCell, OwnedSemaphorePermit, Semaphore};
+#[cfg(feature = "tls")]
+use tonic::transport::{Certificate, ClientTlsConfig};
 use tonic::{
-    metadata::{Ascii, MetadataValue},
+    codec::CompressionEncoding,
+    metadata::{Ascii, MetadataKey, MetadataMap, MetadataValue},
     transport::{Channel, Endpoint},
     Request,
 };
 
 use crate::{
-    config::RpcConfig,
+    config::{Compression, ReconnectPolicy, RpcConfig},
     errors::{Error, Result, ServerError},
-    rpc_client::{RpcClient, RpcClientFactory, RpcContext},
+    rpc_client::{
+        auth::{AuthProvider, Credentials},
+        fallback::FallbackRpcClient,
+        reconnect::ReconnectingRpcClient,
+        resolve::{is_host_name, DnsResolver, ResolvingRpcClient},
+        runtime::DedicatedRuntime,
+        ChannelProvider, ObservedRpcClient, RequestInterceptor, RequestObserver, RpcClient,
+        RpcClientFactory, RpcContext, SqlQueryStream,
+    },
     util::is_ok,
-    Authorization,
 };
 
+/// The hook customizing the tonic [`Endpoint`] before connecting.
+pub(crate) type EndpointHook = Arc<dyn Fn(Endpoint) -> Endpoint + Send + Sync>;
+
+/// The metadata key of the attempt number (starting from 1) of the request.
+const ATTEMPT_METADATA_KEY: &str = "x-horaedb-attempt";
+/// The metadata key of the [`Priority`](crate::Priority) of the request.
+const PRIORITY_METADATA_KEY: &str = "x-horaedb-priority";
+/// The metadata keys set by the client, which can't be set by the headers of
+/// the [`RpcContext`].
+const RESERVED_METADATA_KEYS: [&str; 3] =
+    ["authorization", ATTEMPT_METADATA_KEY, PRIORITY_METADATA_KEY];
+
+/// Fill the metadata of the request by the credentials, the `ctx` and the
+/// interceptors, and the priority of the `ctx` is filled if
+/// `forward_priority`.
+pub(crate) async fn fill_metadata(
+    method: &'static str,
+    ctx: &RpcContext,
+    credentials: &Credentials,
+    interceptors: &[Arc<dyn RequestInterceptor>],
+    forward_priority: bool,
+    metadata: &mut MetadataMap,
+) -> Result<()> {
+    credentials.authorize(method, ctx, metadata).await?;
+    metadata.insert(ATTEMPT_METADATA_KEY, (ctx.attempt + 1).into());
+    if let Some(priority) = ctx.priority.filter(|_| forward_priority) {
+        metadata.insert(
+            PRIORITY_METADATA_KEY,
+            MetadataValue::from_static(priority.as_str()),
+        );
+    }
+    for (key, value) in &ctx.headers {
+        let key = MetadataKey::<Ascii>::from_bytes(key.as_bytes())
+            .map_err(|e| Error::Client(format!("invalid header key:{key}, err:{e}")))?;
+        if RESERVED_METADATA_KEYS.contains(&key.as_str()) {
+            return Err(Error::Client(format!("header key:{key} is reserved")));
+        }
+        let value: MetadataValue<Ascii> = value
+            .parse()
+            .map_err(|e| Error::Client(format!("invalid header value of key:{key}, err:{e}")))?;
+        metadata.insert(key, value);
+    }
+    #[cfg(feature = "otel")]
+    crate::rpc_client::trace::inject_trace_context(metadata);
+    for interceptor in interceptors {
+        interceptor.intercept(method, ctx, metadata)?;
+    }
+
+    Ok(())
+}
+
 struct RpcClientImpl {
     channel: Channel,
     default_read_timeout: Duration,
     default_write_timeout: Duration,
-    metadata: Option<MetadataValue<Ascii>>,
+    send_compressed: Option<Compression>,
+    accept_compressed: Option<Compression>,
+    /// The limits of the message lengths, which are checked by the client
+    /// because the tonic codec doesn't support them, so the responses are
+    /// checked after being decoded.
+    max_send_msg_len: Option<usize>,
+    max_recv_msg_len: Option<usize>,
+    credentials: Credentials,
+    interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    forward_priority: bool,
+    /// Limit of the concurrent rpcs to the endpoint, shared by the clients of
+    /// the same endpoint.
+    in_flight: Option<Arc<Semaphore>>,
 }
 
 impl RpcClientImpl {
     fn new(
         channel: Channel,
-        default_read_timeout: Duration,
-        default_write_timeout: Duration,
-        metadata: Option<MetadataValue<Ascii>>,
+        rpc_config: &RpcConfig,
+        credentials: Credentials,
+        interceptors: Vec<Arc<dyn RequestInterceptor>>,
     ) -> Self {
         Self {
             channel,
-            default_read_timeout,
-            default_write_timeout,
-            metadata,
+            default_read_timeout: rpc_config.default_sql_query_timeout,
+            default_write_timeout: rpc_config.default_write_timeout,
+            send_compressed: rpc_config.send_compressed,
+            accept_compressed: rpc_config.accept_compressed,
+            // The negative limits mean unlimited.
+            max_send_msg_len: usize::try_from(rpc_config.max_send_msg_len).ok(),
+            max_recv_msg_len: usize::try_from(rpc_config.max_recv_msg_len).ok(),
+            credentials,
+            interceptors,
+            forward_priority: rpc_config.forward_priority,
+            in_flight: None,
         }
     }
 
+    fn with_in_flight_limit(mut self, in_flight: Option<Arc<Semaphore>>) -> Self {
+        self.in_flight = in_flight;
+        self
+    }
+
+    /// Wait for the permit of sending the rpc if the in-flight rpcs are
+    /// limited, and the waiting is bounded by the timeout of the rpc.
+    async fn acquire(
+        &self,
+        method: &'static str,
+        ctx: &RpcContext,
+        default_timeout: Duration,
+    ) -> Result<Option<OwnedSemaphorePermit>> {
+        let Some(in_flight) = &self.in_flight else {
+            return Ok(None);
+        };
+
+        let timeout = ctx.remaining_timeout(method, default_timeout)?;
+        match tokio::time::timeout(timeout, in_flight.clone().acquire_owned()).await {
+            Ok(permit) => Ok(Some(permit.expect("in-flight semaphore is never closed"))),
+            Err(_) => Err(Error::Rpc(tonic::Status::deadline_exceeded(format!(
+                "too many in-flight rpcs, method:{method}"
+            )))),
+        }
+    }
+
+    /// The compressions of the request and response, which may be overridden
+    /// by the `ctx`.
+    fn compressions(&self, ctx: &RpcContext) -> (Option<Compression>, Option<Compression>) {
+        match ctx.compression {
+            None => (self.send_compressed, self.accept_compressed),
+            Some(false) => (None, None),
+            Some(true) => (
+                self.send_compressed.or(Some(Compression::Gzip)),
+                self.accept_compressed.or(Some(Compression::Gzip)),
+            ),
+        }
+    }
+
+    fn make_client(&self, ctx: &RpcContext) -> StorageServiceClient<Channel> {
+        let (send_compressed, accept_compressed) = self.compressions(ctx);
+        let mut client = StorageServiceClient::new(self.channel.clone());
+        if let Some(compression) = send_compressed {
+            client = client.send_compressed(Self::compression_encoding(compression));
+        }
+        if let Some(compression) = accept_compressed {
+            client = client.accept_compressed(Self::compression_encoding(compression));
+        }
+
+        client
+    }
+
+    #[inline]
+    fn compression_encoding(compression: Compression) -> CompressionEncoding {
+        match compression {
+            Compression::Gzip => CompressionEncoding::Gzip,
+        }
+    }
+
+    /// Check the encoded length of the message against the `limit` named by
+    /// `limit_name`.
+    fn check_msg_len(
+        method: &'static str,
+        msg: &impl Message,
+        limit: Option<usize>,
+        limit_name: &'static str,
+    ) -> Result<()> {
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+
+        let len = msg.encoded_len();
+        if len > limit {
+            return Err(Error::Client(format!(
+                "message of {method} is {len} bytes, exceeding the {limit_name}:{limit}"
+            )));
+        }
+
+        Ok(())
+    }
+
     fn check_status(header: ResponseHeader) -> Result<()> {
         if !is_ok(header.code) {
             return Err(Error::Server(ServerError {
@@ -75,142 +244,757 @@ impl RpcClientImpl {
         Ok(())
     }
 
-    fn make_request<T>(&self, ctx: &RpcContext, req: T, default_timeout: Duration) -> Request<T> {
-        let timeout = ctx.timeout.unwrap_or(default_timeout);
+    async fn make_request<T: Message>(
+        &self,
+        method: &'static str,
+        ctx: &RpcContext,
+        req: T,
+        default_timeout: Duration,
+    ) -> Result<Request<T>> {
+        Self::check_msg_len(method, &req, self.max_send_msg_len, "max_send_msg_len")?;
         let mut req = Request::new(req);
-        req.set_timeout(timeout);
-        if let Some(md) = &self.metadata {
-            req.metadata_mut().insert("authorization", md.clone());
-        }
-        req
+        req.set_timeout(ctx.remaining_timeout(method, default_timeout)?);
+        fill_metadata(
+            method,
+            ctx,
+            &self.credentials,
+            &self.interceptors,
+            self.forward_priority,
+            req.metadata_mut(),
+        )
+        .await?;
+
+        Ok(req)
     }
 
-    fn make_query_request<T>(&self, ctx: &RpcContext, req: T) -> Request<T> {
-        self.make_request(ctx, req, self.default_read_timeout)
+    async fn make_query_request<T: Message>(&self, ctx: &RpcContext, req: T) -> Result<Request<T>> {
+        self.make_request("sql_query", ctx, req, self.default_read_timeout)
+            .await
     }
 
-    fn make_write_request<T>(&self, ctx: &RpcContext, req: T) -> Request<T> {
-        self.make_request(ctx, req, self.default_write_timeout)
+    async fn make_write_request<T: Message>(&self, ctx: &RpcContext, req: T) -> Result<Request<T>> {
+        self.make_request("write", ctx, req, self.default_write_timeout)
+            .await
     }
 }
 
 #[async_trait]
 impl RpcClient for RpcClientImpl {
     async fn sql_query(&self, ctx: &RpcContext, req: SqlQueryRequest) -> Result<SqlQueryResponse> {
-        let mut client = StorageServiceClient::<Channel>::new(self.channel.clone());
+        let _permit = self
+            .acquire("sql_query", ctx, self.default_read_timeout)
+            .await?;
+        self.credentials
+            .call(req, |req| async move {
+                let mut client = self.make_client(ctx);
 
-        let resp = client
-            .sql_query(self.make_query_request(ctx, req))
-            .await
-            .map_err(Error::Rpc)?;
-        let mut resp = resp.into_inner();
+                let resp = client
+                    .sql_query(self.make_query_request(ctx, req).await?)
+                    .await
+                    .map_err(Error::Rpc)?;
+                let mut resp = resp.into_inner();
+                Self::check_msg_len(
+                    "sql_query",
+                    &resp,
+                    self.max_recv_msg_len,
+                    "max_recv_msg_len",
+                )?;
 
-        if let Some(header) = resp.header.take() {
-            Self::check_status(header)?;
-        }
+                if let Some(header) = resp.header.take() {
+                    Self::check_status(header)?;
+                }
 
-        Ok(resp)
+                Ok(resp)
+            })
+            .await
     }
 
     async fn write(&self, ctx: &RpcContext, req: WriteRequestPb) -> Result<WriteResponsePb> {
-        let mut client = StorageServiceClient::<Channel>::new(self.channel.clone());
+        let _permit = self
+            .acquire("write", ctx, self.default_write_timeout)
+            .await?;
+        self.credentials
+            .call(req, |req| async move {
+                let mut client = self.make_client(ctx);
 
-        let resp = client
-            .write(self.make_write_request(ctx, req))
-            .await
-            .map_err(Error::Rpc)?;
-        let mut resp = resp.into_inner();
+                let resp = client
+                    .write(self.make_write_request(ctx, req).await?)
+                    .await
+                    .map_err(Error::Rpc)?;
+                let mut resp = resp.into_inner();
+                Self::check_msg_len("write", &resp, self.max_recv_msg_len, "max_recv_msg_len")?;
 
-        if let Some(header) = resp.header.take() {
-            Self::check_status(header)?;
-        }
+                if let Some(header) = resp.header.take() {
+                    Self::check_status(header)?;
+                }
 
-        Ok(resp)
+                Ok(resp)
+            })
+            .await
     }
 
     async fn route(&self, ctx: &RpcContext, req: RouteRequestPb) -> Result<RouteResponsePb> {
-        let mut client = StorageServiceClient::<Channel>::new(self.channel.clone());
+        let _permit = self
+            .acquire("route", ctx, self.default_write_timeout)
+            .await?;
+        self.credentials
+            .call(req, |req| async move {
+                let mut client = self.make_client(ctx);
 
-        // use the write timeout for the route request.
-        let route_req = self.make_request(ctx, req, self.default_write_timeout);
-        let resp = client.route(route_req).await.map_err(Error::Rpc)?;
-        let mut resp = resp.into_inner();
+                // use the write timeout for the route request.
+                let route_req = self
+                    .make_request("route", ctx, req, self.default_write_timeout)
+                    .await?;
+                let resp = client.route(route_req).await.map_err(Error::Rpc)?;
+                let mut resp = resp.into_inner();
+                Self::check_msg_len("route", &resp, self.max_recv_msg_len, "max_recv_msg_len")?;
 
-        if let Some(header) = resp.header.take() {
-            Self::check_status(header)?;
-        }
+                if let Some(header) = resp.header.take() {
+                    Self::check_status(header)?;
+                }
+
+                Ok(resp)
+            })
+            .await
+    }
 
-        Ok(resp)
+    #[cfg(feature = "prom")]
+    async fn prom_query(
+        &self,
+        ctx: &RpcContext,
+        req: PromQueryRequestPb,
+    ) -> Result<PromQueryResponsePb> {
+        let _permit = self
+            .acquire("prom_query", ctx, self.default_read_timeout)
+            .await?;
+        self.credentials
+            .call(req, |req| async move {
+                let mut client = self.make_client(ctx);
+
+                let prom_req = self
+                    .make_request("prom_query", ctx, req, self.default_read_timeout)
+                    .await?;
+                let resp = client.prom_query(prom_req).await.map_err(Error::Rpc)?;
+                let mut resp = resp.into_inner();
+                Self::check_msg_len(
+                    "prom_query",
+                    &resp,
+                    self.max_recv_msg_len,
+                    "max_recv_msg_len",
+                )?;
+
+                if let Some(header) = resp.header.take() {
+                    Self::check_status(header)?;
+                }
+
+                Ok(resp)
+            })
+            .await
+    }
+
+    async fn sql_query_stream(
+        &self,
+        ctx: &RpcContext,
+        req: SqlQueryRequest,
+    ) -> Result<SqlQueryStream> {
+        // The permit is held until the stream is dropped.
+        let permit = self
+            .acquire("sql_query_stream", ctx, self.default_read_timeout)
+            .await?;
+        let resp = self
+            .credentials
+            .call(req, |req| async move {
+                let mut client = self.make_client(ctx);
+
+                let query_req = self
+                    .make_request("sql_query_stream", ctx, req, self.default_read_timeout)
+                    .await?;
+                client.stream_sql_query(query_req).await.map_err(Error::Rpc)
+            })
+            .await?;
+        let max_recv_msg_len = self.max_recv_msg_len;
+        let stream = resp.into_inner().map(move |resp| {
+            let _permit = &permit;
+            let mut resp = resp.map_err(Error::Rpc)?;
+            Self::check_msg_len(
+                "sql_query_stream",
+                &resp,
+                max_recv_msg_len,
+                "max_recv_msg_len",
+            )?;
+            if let Some(header) = resp.header.take() {
+                Self::check_status(header)?;
+            }
+            Ok(resp)
+        });
+
+        Ok(stream.boxed())
     }
 }
 
+#[derive(Clone)]
 pub struct RpcClientImplFactory {
     rpc_config: RpcConfig,
-    authorization: Option<Authorization>,
+    credentials: Credentials,
+    interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    channel_provider: Option<Arc<dyn ChannelProvider>>,
+    endpoint_hook: Option<EndpointHook>,
+    runtime: Option<Arc<DedicatedRuntime>>,
+    observers: Arc<[Arc<dyn RequestObserver>]>,
+    /// The endpoints failed over to by the clients of the endpoints.
+    fallback_endpoints: HashMap<String, Vec<String>>,
+    /// The channels shared by the clients built for the same endpoint, which
+    /// is also shared by the clones of the factory.
+    channels: Arc<DashMap<String, Arc<OnceCell<Channel>>>>,
+    /// The limits of the in-flight rpcs of the endpoints, which are kept
+    /// across the reconnections.
+    in_flight_limits: Arc<DashMap<String, Arc<Semaphore>>>,
 }
 
 impl RpcClientImplFactory {
-    pub fn new(rpc_config: RpcConfig, authorization: Option<Authorization>) -> Self {
+    pub fn new(
+        rpc_config: RpcConfig,
+        auth_provider: Option<Arc<dyn AuthProvider>>,
+        interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    ) -> Self {
         Self {
             rpc_config,
-            authorization,
+            credentials: Credentials::new(auth_provider),
+            interceptors,
+            channel_provider: None,
+            endpoint_hook: None,
+            runtime: None,
+            observers: Arc::new([]),
+            fallback_endpoints: HashMap::new(),
+            channels: Arc::new(DashMap::new()),
+            in_flight_limits: Arc::new(DashMap::new()),
         }
     }
 
+    /// Use the channels supplied by the [`ChannelProvider`] instead of the
+    /// ones built by the factory, and the connection related configs in the
+    /// [`RpcConfig`] are ignored then.
+    pub fn with_channel_provider(mut self, channel_provider: Arc<dyn ChannelProvider>) -> Self {
+        self.channel_provider = Some(channel_provider);
+        self
+    }
+
+    /// Customize the tonic [`Endpoint`] by the `hook` after applying the
+    /// [`RpcConfig`], which is ignored if the channel provider is set.
+    pub fn with_endpoint_hook(mut self, hook: EndpointHook) -> Self {
+        self.endpoint_hook = Some(hook);
+        self
+    }
+
+    /// Connect and drive the channels on the dedicated `runtime`.
+    pub(crate) fn with_runtime(mut self, runtime: Arc<DedicatedRuntime>) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Notify the `observers` around every rpc sent by the built clients.
+    pub fn with_observers(mut self, observers: Vec<Arc<dyn RequestObserver>>) -> Self {
+        self.observers = observers.into();
+        self
+    }
+
+    /// Fail over to the `fallbacks` in order once `endpoint` is down, and back
+    /// to `endpoint` once the last fallback is down.
+    pub fn with_fallback_endpoints(mut self, endpoint: String, fallbacks: Vec<String>) -> Self {
+        self.fallback_endpoints.insert(endpoint, fallbacks);
+        self
+    }
+
     #[inline]
-    fn make_endpoint_with_scheme(endpoint: &str) -> String {
-        format!("http://{endpoint}")
+    fn make_endpoint_with_scheme(&self, endpoint: &str) -> String {
+        match self.rpc_config.tls {
+            Some(_) => format!("https://{endpoint}"),
+            None => format!("http://{endpoint}"),
+        }
     }
-}
 
-#[async_trait]
-impl RpcClientFactory for RpcClientImplFactory {
-    /// The endpoint should be in the form: `{ip_addr}:{port}`.
-    async fn build(&self, endpoint: String) -> Result<Arc<dyn RpcClient>> {
-        let endpoint_with_scheme = Self::make_endpoint_with_scheme(&endpoint);
+    #[cfg(feature = "tls")]
+    fn config_tls(&self, endpoint: &str, configured_endpoint: Endpoint) -> Result<Endpoint> {
+        let Some(tls) = &self.rpc_config.tls else {
+            return Ok(configured_endpoint);
+        };
+
+        let mut tls_config = ClientTlsConfig::new();
+        if let Some(domain_name) = &tls.domain_name {
+            tls_config = tls_config.domain_name(domain_name);
+        }
+        if let Some(ca_pem) = &tls.ca_pem {
+            tls_config = tls_config.ca_certificate(Certificate::from_pem(ca_pem));
+        }
+        configured_endpoint
+            .tls_config(tls_config)
+            .map_err(|e| Error::Connect {
+                addr: endpoint.to_string(),
+                source: Box::new(e),
+            })
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn config_tls(&self, endpoint: &str, configured_endpoint: Endpoint) -> Result<Endpoint> {
+        match self.rpc_config.tls {
+            Some(_) => Err(Error::Connect {
+                addr: endpoint.to_string(),
+                source: "tls is configured but the `tls` feature is not enabled".into(),
+            }),
+            None => Ok(configured_endpoint),
+        }
+    }
+
+    /// Get the cached channel of the `endpoint`, or connect to it if not yet.
+    async fn cached_channel(&self, endpoint: String, rpc_config: &RpcConfig) -> Result<Channel> {
+        let cell = self.channels.entry(endpoint.clone()).or_default().clone();
+        cell.get_or_try_init(|| self.connect(endpoint, rpc_config))
+            .await
+            .cloned()
+    }
+
+    fn in_flight_limit(&self, endpoint: &str) -> Option<Arc<Semaphore>> {
+        let limit = self.rpc_config.max_in_flight_per_endpoint?;
+        let semaphore = self
+            .in_flight_limits
+            .entry(endpoint.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(limit)));
+        Some(semaphore.clone())
+    }
+
+    async fn connect(&self, endpoint: String, rpc_config: &RpcConfig) -> Result<Channel> {
+        let endpoint_with_scheme = self.make_endpoint_with_scheme(&endpoint);
         let configured_endpoint =
             Endpoint::from_shared(endpoint_with_scheme).map_err(|e| Error::Connect {
                 addr: endpoint.clone(),
                 source: Box::new(e),
             })?;
+        let configured_endpoint = self
+            .config_tls(&endpoint, configured_endpoint)?
+            .tcp_nodelay(rpc_config.tcp_nodelay)
+            .tcp_keepalive(rpc_config.tcp_keepalive);
 
-        let configured_endpoint = match self.rpc_config.keep_alive_while_idle {
+        let configured_endpoint = match rpc_config.keep_alive_while_idle {
             true => configured_endpoint
-                .connect_timeout(self.rpc_config.connect_timeout)
-                .keep_alive_timeout(self.rpc_config.keep_alive_timeout)
+                .connect_timeout(rpc_config.connect_timeout)
+                .keep_alive_timeout(rpc_config.keep_alive_timeout)
                 .keep_alive_while_idle(true)
-                .http2_keep_alive_interval(self.rpc_config.keep_alive_interval),
+                .http2_keep_alive_interval(rpc_config.keep_alive_interval),
             false => configured_endpoint
-                .connect_timeout(self.rpc_config.connect_timeout)
+                .connect_timeout(rpc_config.connect_timeout)
                 .keep_alive_while_idle(false),
         };
-        let channel = configured_endpoint
-            .connect()
+        let configured_endpoint = match &self.endpoint_hook {
+            Some(hook) => hook(configured_endpoint),
+            None => configured_endpoint,
+        };
+        let configured_endpoint = match &self.runtime {
+            Some(runtime) => runtime.configure(configured_endpoint),
+            None => configured_endpoint,
+        };
+        let local_connector = rpc_config.local_address.map(|addr| {
+            let mut connector = HttpConnector::new();
+            connector.enforce_http(false);
+            connector.set_nodelay(rpc_config.tcp_nodelay);
+            connector.set_keepalive(rpc_config.tcp_keepalive);
+            connector.set_local_address(Some(addr));
+            connector
+        });
+        let connect = async move {
+            match local_connector {
+                Some(connector) => configured_endpoint.connect_with_connector(connector).await,
+                None => configured_endpoint.connect().await,
+            }
+            .map_err(Into::into)
+        };
+        let result = match &self.runtime {
+            Some(runtime) => runtime.connect(connect).await,
+            None => connect.await,
+        };
+        result.map_err(|source| Error::Connect {
+            addr: endpoint,
+            source,
+        })
+    }
+}
+
+#[async_trait]
+impl RpcClientFactory for RpcClientImplFactory {
+    /// The endpoint should be in the form: `{ip_addr}:{port}`, and `https://`
+    /// is used if tls is configured.
+    async fn build(&self, endpoint: String) -> Result<Arc<dyn RpcClient>> {
+        if let Some(fallbacks) = self.fallback_endpoints.get(&endpoint) {
+            // Every endpoint is built by the factory without failover.
+            let mut base_factory = self.clone();
+            base_factory.fallback_endpoints.clear();
+            let endpoints = std::iter::once(endpoint)
+                .chain(fallbacks.iter().cloned())
+                .collect();
+            let client = FallbackRpcClient::new(base_factory, endpoints).await?;
+            return Ok(Arc::new(client));
+        }
+
+        // The channels supplied by the provider are resolved by the users.
+        if let Some(interval) = self.rpc_config.dns_refresh_interval {
+            if self.channel_provider.is_none() && is_host_name(&endpoint) {
+                // The changed addresses are rebuilt by the factory without
+                // re-resolution.
+                let mut base_factory = self.clone();
+                base_factory.rpc_config.dns_refresh_interval = None;
+                let client = base_factory.build(endpoint.clone()).await?;
+                let client = ResolvingRpcClient::new(
+                    base_factory,
+                    endpoint,
+                    interval,
+                    Arc::new(DnsResolver),
+                    client,
+                )
+                .await;
+                return Ok(Arc::new(client));
+            }
+        }
+
+        let client = self.build_client(endpoint.clone()).await?;
+        let policy = match &self.rpc_config.reconnect {
+            Some(policy) => policy.clone(),
+            None if !self.rpc_config.transport_retry_codes.is_empty() => ReconnectPolicy::once(),
+            None => return Ok(client),
+        };
+        // The broken clients are rebuilt by the factory without reconnection.
+        let mut base_factory = self.clone();
+        base_factory.rpc_config.reconnect = None;
+        base_factory.rpc_config.transport_retry_codes = Vec::new();
+        // The reconnection retries the unavailable servers if no code is set.
+        let codes = match self.rpc_config.transport_retry_codes.is_empty() {
+            true => vec![tonic::Code::Unavailable],
+            false => self.rpc_config.transport_retry_codes.clone(),
+        };
+        Ok(Arc::new(ReconnectingRpcClient::new(
+            base_factory,
+            endpoint,
+            policy,
+            codes,
+            client,
+        )))
+    }
+
+    fn evict(&self, endpoint: &str) {
+        self.channels.remove(endpoint);
+    }
+}
+
+impl RpcClientImplFactory {
+    async fn build_client(&self, endpoint: String) -> Result<Arc<dyn RpcClient>> {
+        let rpc_config = self.rpc_config.for_endpoint(&endpoint);
+        let in_flight = self.in_flight_limit(&endpoint);
+        let channel = match &self.channel_provider {
+            Some(provider) => provider.channel(&endpoint).await?,
+            None => self.cached_channel(endpoint.clone(), &rpc_config).await?,
+        };
+
+        let client = RpcClientImpl::new(
+            channel,
+            &rpc_config,
+            self.credentials.clone(),
+            self.interceptors.clone(),
+        )
+        .with_in_flight_limit(in_flight);
+        Ok(ObservedRpcClient::wrap(
+            Arc::new(client),
+            endpoint,
+            &self.observers,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use horaedbproto::storage::RouteRequest as RouteRequestPb;
+    use tonic::{metadata::MetadataMap, transport::Endpoint};
+
+    use super::{RpcClientImpl, RpcClientImplFactory, ATTEMPT_METADATA_KEY, PRIORITY_METADATA_KEY};
+    use crate::{
+        config::{Compression, RpcConfig},
+        errors::Result,
+        rpc_client::{auth::Credentials, Priority, RpcClientFactory, RpcContext},
+        Error,
+    };
+
+    #[tokio::test]
+    async fn test_request_interceptors() {
+        let channel = Endpoint::from_static("http://127.0.0.1:8831").connect_lazy();
+        let tenant = |_: &'static str, _: &RpcContext, md: &mut MetadataMap| -> Result<()> {
+            md.insert("x-tenant", "test".parse().unwrap());
+            Ok(())
+        };
+        let deny_route = |method: &'static str, _: &RpcContext, _: &mut MetadataMap| {
+            if method == "route" {
+                return Err(Error::Client("route is denied".to_string()));
+            }
+            Ok(())
+        };
+        let client = RpcClientImpl::new(
+            channel,
+            &RpcConfig::default(),
+            Credentials::default(),
+            vec![Arc::new(tenant), Arc::new(deny_route)],
+        );
+
+        let ctx = RpcContext::default()
+            .next_attempt()
+            .header("x-trace-id", "abc");
+        let req = client.make_write_request(&ctx, ()).await.unwrap();
+        assert_eq!(req.metadata().get("x-tenant").unwrap(), "test");
+        assert_eq!(req.metadata().get("x-trace-id").unwrap(), "abc");
+        assert_eq!(req.metadata().get(ATTEMPT_METADATA_KEY).unwrap(), "2");
+
+        for (key, value) in [
+            ("authorization", "x"),
+            (PRIORITY_METADATA_KEY, "high"),
+            ("invalid key", "x"),
+            ("k", "\n"),
+        ] {
+            let ctx = RpcContext::default().header(key, value);
+            let err = client.make_write_request(&ctx, ()).await.unwrap_err();
+            assert!(matches!(err, Error::Client(_)));
+        }
+
+        let err = client
+            .make_request("route", &ctx, (), Duration::from_secs(1))
             .await
-            .map_err(|e| Error::Connect {
-                addr: endpoint,
-                source: Box::new(e),
-            })?;
+            .unwrap_err();
+        assert!(matches!(err, Error::Client(_)));
+    }
+
+    #[tokio::test]
+    async fn test_priority_metadata() {
+        let ctx = RpcContext::default().priority(Priority::Low);
+        for forward_priority in [true, false] {
+            let channel = Endpoint::from_static("http://127.0.0.1:8831").connect_lazy();
+            let rpc_config = RpcConfig {
+                forward_priority,
+                ..Default::default()
+            };
+            let client =
+                RpcClientImpl::new(channel, &rpc_config, Credentials::default(), Vec::new());
+            let req = client.make_write_request(&ctx, ()).await.unwrap();
+            let priority = req.metadata().get(PRIORITY_METADATA_KEY);
+            assert_eq!(
+                priority.map(|v| v.to_str().unwrap()),
+                forward_priority.then_some("low")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_send_msg_len() {
+        let channel = Endpoint::from_static("http://127.0.0.1:8831").connect_lazy();
+        let rpc_config = RpcConfig {
+            max_send_msg_len: 16,
+            ..Default::default()
+        };
+        let client = RpcClientImpl::new(channel, &rpc_config, Credentials::default(), vec![]);
+
+        let ctx = RpcContext::default();
+        let req = RouteRequestPb {
+            context: None,
+            tables: vec!["t".to_string()],
+        };
+        client.make_write_request(&ctx, req).await.unwrap();
 
-        let metadata = if let Some(auth) = &self.authorization {
-            let mut buf = Vec::with_capacity(auth.username.len() + auth.password.len() + 1);
-            buf.extend_from_slice(auth.username.as_bytes());
-            buf.push(b':');
-            buf.extend_from_slice(auth.password.as_bytes());
-            let auth = BASE64_STANDARD.encode(&buf);
-            let metadata: MetadataValue<Ascii> = format!("Basic {}", auth)
-                .parse()
-                .context("invalid grpc metadata")?;
-
-            Some(metadata)
-        } else {
-            None
-        };
-        Ok(Arc::new(RpcClientImpl::new(
+        let req = RouteRequestPb {
+            context: None,
+            tables: vec!["a_table_with_long_name".to_string()],
+        };
+        let err = client.make_write_request(&ctx, req).await.unwrap_err();
+        assert!(err.to_string().contains("max_send_msg_len"));
+    }
+
+    #[tokio::test]
+    async fn test_deadline_propagation() {
+        let channel = Endpoint::from_static("http://127.0.0.1:8831").connect_lazy();
+        let client = RpcClientImpl::new(
             channel,
-            self.rpc_config.default_sql_query_timeout,
-            self.rpc_config.default_write_timeout,
-            metadata,
-        )))
+            &RpcConfig::default(),
+            Credentials::default(),
+            Vec::new(),
+        );
+        let client = &client;
+        let grpc_timeout_micros = |ctx: RpcContext| async move {
+            let req = client.make_write_request(&ctx, ()).await.unwrap();
+            let timeout = req
+                .metadata()
+                .get("grpc-timeout")
+                .unwrap()
+                .to_str()
+                .unwrap();
+            timeout.strip_suffix('u').unwrap().parse::<u64>().unwrap()
+        };
+
+        let ctx = RpcContext::default().timeout(Duration::from_secs(5));
+        assert_eq!(grpc_timeout_micros(ctx.clone()).await, 5_000_000);
+
+        // The remaining time until the deadline is shorter than the timeout.
+        let ctx = ctx.deadline(Instant::now() + Duration::from_secs(1));
+        let micros = grpc_timeout_micros(ctx).await;
+        assert!(micros > 0 && micros <= 1_000_000, "micros:{micros}");
+
+        let ctx = RpcContext::default().deadline(Instant::now());
+        let err = client.make_write_request(&ctx, ()).await.unwrap_err();
+        assert!(
+            matches!(err, Error::Rpc(status) if status.code() == tonic::Code::DeadlineExceeded)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compression_override() {
+        let channel = Endpoint::from_static("http://127.0.0.1:8831").connect_lazy();
+        let rpc_config = RpcConfig {
+            send_compressed: Some(Compression::Gzip),
+            ..Default::default()
+        };
+        let client = RpcClientImpl::new(channel, &rpc_config, Credentials::default(), Vec::new());
+
+        let ctx = RpcContext::default();
+        assert_eq!(client.compressions(&ctx), (Some(Compression::Gzip), None));
+        let ctx = RpcContext::default().compression(false);
+        assert_eq!(client.compressions(&ctx), (None, None));
+        let ctx = RpcContext::default().compression(true);
+        assert_eq!(
+            client.compressions(&ctx),
+            (Some(Compression::Gzip), Some(Compression::Gzip))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_channel_cache() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let accepted_clone = accepted.clone();
+        tokio::spawn(async move {
+            let mut conns = Vec::new();
+            while let Ok((conn, _)) = listener.accept().await {
+                accepted_clone.fetch_add(1, Ordering::Relaxed);
+                conns.push(conn);
+            }
+        });
+
+        let factory = RpcClientImplFactory::new(RpcConfig::default(), None, Vec::new());
+        factory.build(endpoint.clone()).await.unwrap();
+        // The clones of the factory share the cached channels.
+        factory.clone().build(endpoint.clone()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(accepted.load(Ordering::Relaxed), 1);
+
+        factory.evict(&endpoint);
+        factory.build(endpoint).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(accepted.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_local_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+
+        let rpc_config = RpcConfig {
+            local_address: Some("127.0.0.1".parse().unwrap()),
+            ..Default::default()
+        };
+        let factory = RpcClientImplFactory::new(rpc_config, None, Vec::new());
+        factory.build(endpoint.clone()).await.unwrap();
+
+        // The address not assigned to any local interface can't be bound.
+        let rpc_config = RpcConfig {
+            local_address: Some("192.0.2.1".parse().unwrap()),
+            ..Default::default()
+        };
+        let factory = RpcClientImplFactory::new(rpc_config, None, Vec::new());
+        let err = factory.build(endpoint).await.err().unwrap();
+        assert!(matches!(err, Error::Connect { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_limit() {
+        let rpc_config = RpcConfig {
+            max_in_flight_per_endpoint: Some(1),
+            ..Default::default()
+        };
+        let factory = RpcClientImplFactory::new(rpc_config.clone(), None, Vec::new());
+        let in_flight = factory.in_flight_limit("127.0.0.1:8831");
+        // The clients of the same endpoint share the limit.
+        assert!(Arc::ptr_eq(
+            in_flight.as_ref().unwrap(),
+            factory.in_flight_limit("127.0.0.1:8831").as_ref().unwrap()
+        ));
+
+        let channel = Endpoint::from_static("http://127.0.0.1:8831").connect_lazy();
+        let client = RpcClientImpl::new(channel, &rpc_config, Credentials::default(), Vec::new())
+            .with_in_flight_limit(in_flight);
+        let ctx = RpcContext::default().timeout(Duration::from_millis(10));
+        let permit = client
+            .acquire("write", &ctx, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(permit.is_some());
+
+        let err = client
+            .acquire("write", &ctx, Duration::from_secs(1))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::Rpc(status) if status.code() == tonic::Code::DeadlineExceeded)
+        );
+
+        drop(permit);
+        assert!(client
+            .acquire("write", &ctx, Duration::from_secs(1))
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_hook() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_clone = calls.clone();
+        let factory = RpcClientImplFactory::new(RpcConfig::default(), None, Vec::new())
+            .with_endpoint_hook(Arc::new(move |endpoint: Endpoint| {
+                calls_clone.fetch_add(1, Ordering::Relaxed);
+                endpoint.user_agent("test-agent").unwrap()
+            }));
+
+        factory.build(endpoint.clone()).await.unwrap();
+        factory.build(endpoint).await.unwrap();
+        // The hook is called once per connection.
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_channel_provider() {
+        let channel = Endpoint::from_static("http://127.0.0.1:8831").connect_lazy();
+        let channels = HashMap::from([("127.0.0.1:8831".to_string(), channel)]);
+        let factory = RpcClientImplFactory::new(RpcConfig::default(), None, Vec::new())
+            .with_channel_provider(Arc::new(channels));
+
+        assert!(factory.build("127.0.0.1:8831".to_string()).await.is_ok());
+        let err = factory
+            .build("127.0.0.1:8832".to_string())
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Connect { .. }));
     }
 }
@@ -0,0 +1,127 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The dedicated runtime driving the grpc connections.
+
+use std::{future::Future, sync::Arc};
+
+use tokio::runtime::{Builder, Handle, Runtime};
+use tonic::transport::{Channel, Endpoint};
+
+use crate::{Error, Result};
+
+const THREAD_NAME: &str = "horaedb-client-io";
+
+type ConnectResult = std::result::Result<Channel, Box<dyn std::error::Error + Send + Sync>>;
+
+/// The runtime owned by the client, which is shut down in the background once
+/// it is dropped, so that it can be dropped in the async context.
+pub(crate) struct DedicatedRuntime {
+    runtime: Option<Runtime>,
+}
+
+impl DedicatedRuntime {
+    pub fn new(thread_num: Option<usize>) -> Result<Self> {
+        let mut builder = Builder::new_multi_thread();
+        if let Some(thread_num) = thread_num {
+            builder.worker_threads(thread_num);
+        }
+        let runtime = builder
+            .thread_name(THREAD_NAME)
+            .enable_all()
+            .build()
+            .map_err(|e| {
+                Error::Client(format!("failed to build the dedicated runtime, err:{e}"))
+            })?;
+
+        Ok(Self {
+            runtime: Some(runtime),
+        })
+    }
+
+    fn handle(&self) -> &Handle {
+        // The runtime is only taken when dropped.
+        self.runtime.as_ref().unwrap().handle()
+    }
+
+    /// Spawn the tasks of the channel connected by the `endpoint` on the
+    /// runtime.
+    pub fn configure(self: &Arc<Self>, endpoint: Endpoint) -> Endpoint {
+        endpoint.executor(RuntimeExecutor(self.clone()))
+    }
+
+    /// Run the `connect` on the runtime.
+    pub async fn connect<F>(&self, connect: F) -> ConnectResult
+    where
+        F: Future<Output = ConnectResult> + Send + 'static,
+    {
+        match self.handle().spawn(connect).await {
+            Ok(result) => result,
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+}
+
+impl Drop for DedicatedRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// The executor spawning the tasks of the channels on the [`DedicatedRuntime`].
+#[derive(Clone)]
+struct RuntimeExecutor(Arc<DedicatedRuntime>);
+
+impl<F> hyper::rt::Executor<F> for RuntimeExecutor
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    fn execute(&self, fut: F) {
+        self.0.handle().spawn(fut);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, thread};
+
+    use tokio::sync::oneshot;
+    use tonic::transport::Endpoint;
+
+    use super::{DedicatedRuntime, RuntimeExecutor, THREAD_NAME};
+
+    #[tokio::test]
+    async fn test_dedicated_runtime() {
+        let runtime = Arc::new(DedicatedRuntime::new(Some(1)).unwrap());
+        let (tx, rx) = oneshot::channel();
+        hyper::rt::Executor::execute(&RuntimeExecutor(runtime.clone()), async move {
+            let _ = tx.send(thread::current().name().map(str::to_string));
+        });
+        assert_eq!(rx.await.unwrap().as_deref(), Some(THREAD_NAME));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let endpoint = runtime.configure(Endpoint::from_shared(format!("http://{addr}")).unwrap());
+        let connect = async move { endpoint.connect().await.map_err(Into::into) };
+        runtime.connect(connect).await.unwrap();
+
+        // The runtime can be dropped in the async context.
+        drop(runtime);
+    }
+}
@@ -0,0 +1,131 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Session-level settings forwarded with every rpc.
+
+use std::collections::BTreeMap;
+
+use tonic::metadata::{Ascii, MetadataKey, MetadataMap, MetadataValue};
+
+use crate::{
+    errors::{Error, Result},
+    rpc_client::{RequestInterceptor, RpcContext},
+};
+
+/// The prefix of the metadata keys of the settings.
+pub(crate) const SETTING_METADATA_PREFIX: &str = "x-horaedb-setting-";
+
+/// The metadata key of the setting `name`.
+pub(crate) fn setting_metadata_key(name: &str) -> String {
+    format!("{SETTING_METADATA_PREFIX}{}", name.to_ascii_lowercase())
+}
+
+/// The settings of the client session, e.g. the `timezone` of the queries,
+/// which are sent as the `x-horaedb-setting-<name>` metadata of every rpc
+/// instead of being embedded in every sql, see
+/// [`Builder::session_settings`](crate::Builder::session_settings).
+///
+/// The names are case-insensitive, and the settings of one call are
+/// overridden by [`RpcContext::setting`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionSettings {
+    settings: BTreeMap<String, String>,
+}
+
+impl SessionSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the setting `name` to the `value`.
+    pub fn set(mut self, name: impl AsRef<str>, value: impl Into<String>) -> Self {
+        self.settings
+            .insert(name.as_ref().to_ascii_lowercase(), value.into());
+        self
+    }
+
+    /// The value of the setting `name` if set.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.settings
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    /// The settings in the order of their names.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.settings
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty()
+    }
+}
+
+impl RequestInterceptor for SessionSettings {
+    fn intercept(
+        &self,
+        _method: &'static str,
+        _ctx: &RpcContext,
+        metadata: &mut MetadataMap,
+    ) -> Result<()> {
+        for (name, value) in &self.settings {
+            let key = MetadataKey::<Ascii>::from_bytes(setting_metadata_key(name).as_bytes())
+                .map_err(|e| Error::Client(format!("invalid setting name:{name}, err:{e}")))?;
+            // Overridden by the setting of the call.
+            if metadata.contains_key(&key) {
+                continue;
+            }
+            let value: MetadataValue<Ascii> = value
+                .parse()
+                .map_err(|e| Error::Client(format!("invalid value of setting:{name}, err:{e}")))?;
+            metadata.insert(key, value);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tonic::metadata::MetadataMap;
+
+    use super::SessionSettings;
+    use crate::rpc_client::{RequestInterceptor, RpcContext};
+
+    #[test]
+    fn test_session_settings() {
+        let settings = SessionSettings::new()
+            .set("TimeZone", "UTC")
+            .set("max_rows", "1000");
+        assert_eq!(settings.get("timezone"), Some("UTC"));
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert("x-horaedb-setting-max_rows", "10".parse().unwrap());
+        settings
+            .intercept("sql_query", &RpcContext::default(), &mut metadata)
+            .unwrap();
+        assert_eq!(metadata.get("x-horaedb-setting-timezone").unwrap(), "UTC");
+        // The setting of the call is kept.
+        assert_eq!(metadata.get("x-horaedb-setting-max_rows").unwrap(), "10");
+
+        let settings = SessionSettings::new().set("bad name", "v");
+        let result = settings.intercept("sql_query", &RpcContext::default(), &mut metadata);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,78 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Propagation of the OpenTelemetry trace context by the grpc metadata.
+
+use opentelemetry::{global, propagation::Injector, trace::TraceContextExt, Context};
+use tonic::metadata::{MetadataKey, MetadataMap, MetadataValue};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        // The invalid entries are skipped rather than failing the request.
+        let Ok(key) = MetadataKey::from_bytes(key.as_bytes()) else {
+            return;
+        };
+        if let Ok(value) = value.parse::<MetadataValue<_>>() {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+/// Inject the trace context of the current `tracing` span (or the current
+/// OpenTelemetry context if the span isn't traced) into the `metadata`, e.g.
+/// the `traceparent` by the W3C trace context propagator.
+pub(crate) fn inject_trace_context(metadata: &mut MetadataMap) {
+    let span_cx = tracing::Span::current().context();
+    let cx = if span_cx.span().span_context().is_valid() {
+        span_cx
+    } else {
+        Context::current()
+    };
+
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&cx, &mut MetadataInjector(metadata))
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use opentelemetry::propagation::Injector;
+    use tonic::metadata::MetadataMap;
+
+    use super::MetadataInjector;
+
+    #[test]
+    fn test_metadata_injector() {
+        let mut metadata = MetadataMap::new();
+        let mut injector = MetadataInjector(&mut metadata);
+        injector.set(
+            "traceparent",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".to_string(),
+        );
+        injector.set("invalid key", "x".to_string());
+        injector.set("tracestate", "\n".to_string());
+
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(
+            metadata.get("traceparent").unwrap(),
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+        );
+    }
+}
@@ -15,12 +15,18 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
 /// Server status code
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub enum StatusCode {
     Ok = 200,
     InvalidArgument = 400,
+    Unauthorized = 401,
     NotFound = 404,
     TooManyRequests = 429,
     InternalError = 500,
@@ -44,3 +50,39 @@ pub fn should_refresh(code: u32, msg: &str) -> bool {
         && msg.contains("Table")
         && msg.contains("not found")
 }
+
+/// A random fraction in `[0, 1)`, which is good enough for the jitters but
+/// not for any cryptographic usage.
+pub fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// A random id in hex, which is unique enough for identifying the requests.
+pub fn random_id() -> String {
+    format!("{:016x}", RandomState::new().build_hasher().finish())
+}
+
+/// Quote the identifier (e.g. table or column name) used in the sql.
+pub fn quote_ident(ident: &str) -> String {
+    format!("`{}`", ident.replace('`', "``"))
+}
+
+/// Quote the string literal used in the sql.
+pub fn quote_literal(literal: &str) -> String {
+    format!("'{}'", literal.replace('\'', "''"))
+}
+
+/// Run the future in the `tracing` span made of the rest arguments, and the
+/// future is run as it is without the `tracing` feature.
+macro_rules! traced {
+    ($fut:expr, $($span:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        let fut = tracing::Instrument::instrument($fut, tracing::info_span!($($span)+));
+        #[cfg(not(feature = "tracing"))]
+        let fut = $fut;
+        fut
+    }};
+}
+
+pub(crate) use traced;